@@ -0,0 +1,51 @@
+//! Capture build metadata for `GET /version` and `agcp upgrade`.
+
+use std::process::Command;
+
+/// Run a command and return its trimmed stdout, if it succeeded.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+fn main() {
+    let git_sha = command_output("git", &["rev-parse", "--short=12", "HEAD"])
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version =
+        command_output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+
+    // Honor SOURCE_DATE_EPOCH for reproducible builds (e.g. Nix)
+    let build_time = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        });
+
+    println!("cargo:rustc-env=AGCP_GIT_SHA={}", git_sha);
+    println!("cargo:rustc-env=AGCP_RUSTC_VERSION={}", rustc_version);
+    println!("cargo:rustc-env=AGCP_BUILD_TIME={}", build_time);
+
+    // Target triple, used by `agcp upgrade --yes` to pick the release asset
+    let target = std::env::var("TARGET").unwrap_or_default();
+    println!("cargo:rustc-env=AGCP_TARGET={}", target);
+
+    // Source tarballs have no .git, and a fresh clone may have no
+    // packed-refs (where `git gc` moves branch heads); pointing Cargo at a
+    // missing path would rerun this script on every build
+    for path in [".git/HEAD", ".git/refs/heads", ".git/packed-refs"] {
+        if std::path::Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
@@ -50,6 +51,10 @@ pub struct Account {
     /// Project ID for Cloud Code API
     #[serde(default)]
     pub project_id: Option<String>,
+    /// Project pinned with `agcp accounts set-project`. Takes precedence over
+    /// `project_id` and is never touched by project discovery.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_project_id: Option<String>,
     /// Whether this account is enabled
     #[serde(default = "default_true")]
     pub enabled: bool,
@@ -68,7 +73,7 @@ pub struct Account {
     /// Last time this account was used (unix timestamp)
     #[serde(default)]
     pub last_used: u64,
-    /// Token bucket for rate limiting (0 - 50)
+    /// Token bucket for rate limiting (0 - [`TOKEN_BUCKET_CAPACITY`])
     #[serde(default = "default_tokens")]
     pub tokens_available: u32,
     /// Whether account has auth issues
@@ -83,6 +88,15 @@ pub struct Account {
     /// Per-model quota threshold overrides (takes priority over account-level)
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub model_quota_thresholds: HashMap<String, f64>,
+    /// Requests this account may serve per budget day (None means unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_request_budget: Option<u64>,
+    /// Input + output tokens this account may use per budget day (None means unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_token_budget: Option<u64>,
+    /// Usage counted against the daily budgets
+    #[serde(default, skip_serializing_if = "DailyUsage::is_empty")]
+    pub daily_usage: DailyUsage,
 
     // Runtime state (not persisted)
     #[serde(skip)]
@@ -91,6 +105,27 @@ pub struct Account {
     pub access_token_expires: Option<u64>,
 }
 
+/// Requests and tokens an account has served during one budget day
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyUsage {
+    /// Budget day the counters belong to (see [`budget_day`])
+    pub day: u64,
+    pub requests: u64,
+    pub tokens: u64,
+}
+
+impl DailyUsage {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Number of the budget day containing `now` (unix seconds), where each day
+/// starts at `reset_hour_utc`:00 UTC.
+pub fn budget_day(now: u64, reset_hour_utc: u8) -> u64 {
+    now.saturating_sub(u64::from(reset_hour_utc) * 3600) / 86400
+}
+
 fn default_true() -> bool {
     true
 }
@@ -99,11 +134,22 @@ fn default_health() -> f64 {
     1.0
 }
 
+/// Size of each account's request token bucket
+pub const TOKEN_BUCKET_CAPACITY: u32 = 50;
+
 fn default_tokens() -> u32 {
-    50
+    TOKEN_BUCKET_CAPACITY
 }
 
 impl Account {
+    /// Project to send requests with: the pinned project if set, otherwise
+    /// the discovered one.
+    pub fn effective_project_id(&self) -> Option<&str> {
+        self.pinned_project_id
+            .as_deref()
+            .or(self.project_id.as_deref())
+    }
+
     /// Create a new account from OAuth credentials
     pub fn new(email: String, refresh_token: String) -> Self {
         Self {
@@ -111,17 +157,21 @@ impl Account {
             email,
             refresh_token,
             project_id: None,
+            pinned_project_id: None,
             enabled: true,
             subscription_tier: None,
             quota: HashMap::new(),
             rate_limits: HashMap::new(),
             health_score: 1.0,
             last_used: 0,
-            tokens_available: 50,
+            tokens_available: TOKEN_BUCKET_CAPACITY,
             is_invalid: false,
             invalid_reason: None,
             quota_threshold: None,
             model_quota_thresholds: HashMap::new(),
+            daily_request_budget: None,
+            daily_token_budget: None,
+            daily_usage: DailyUsage::default(),
             access_token: None,
             access_token_expires: None,
         }
@@ -147,6 +197,12 @@ impl Account {
         }
     }
 
+    /// Check if account is rate-limited for any model
+    pub fn is_rate_limited_any(&self) -> bool {
+        let now = now_secs();
+        self.rate_limits.values().any(|limit| now < limit.until)
+    }
+
     /// Get remaining rate limit time in seconds
     pub fn rate_limit_remaining(&self, model: &str) -> u64 {
         if let Some(limit) = self.rate_limits.get(model) {
@@ -169,6 +225,12 @@ impl Account {
         self.rate_limits.remove(model);
     }
 
+    /// Whether `target` (an account ID, ID prefix, or email) names this account
+    pub fn matches_target(&self, target: &str) -> bool {
+        self.email.eq_ignore_ascii_case(target)
+            || (!target.is_empty() && self.id.starts_with(target))
+    }
+
     /// Get quota fraction for a model (defaults to 1.0 if unknown)
     pub fn get_quota_fraction(&self, model: &str) -> f64 {
         self.quota
@@ -211,9 +273,47 @@ impl Account {
         quota < threshold
     }
 
-    /// Check if account is usable (enabled, valid, not rate-limited)
+    /// Check if account is usable (enabled, valid, not rate-limited, within
+    /// its daily budgets)
     pub fn is_usable(&self, model: &str) -> bool {
-        self.enabled && !self.is_invalid && !self.is_rate_limited(model)
+        self.enabled
+            && !self.is_invalid
+            && !self.is_rate_limited(model)
+            && !self.is_over_daily_budget()
+    }
+
+    /// Whether today's usage has reached either daily budget. Expects the
+    /// counters to have been rolled over with [`Account::roll_daily_usage`].
+    pub fn is_over_daily_budget(&self) -> bool {
+        self.daily_request_budget
+            .is_some_and(|budget| self.daily_usage.requests >= budget)
+            || self
+                .daily_token_budget
+                .is_some_and(|budget| self.daily_usage.tokens >= budget)
+    }
+
+    /// Reset the daily counters if they belong to an earlier budget day
+    pub fn roll_daily_usage(&mut self, day: u64) {
+        if self.daily_usage.day != day {
+            self.daily_usage = DailyUsage {
+                day,
+                ..DailyUsage::default()
+            };
+        }
+    }
+
+    /// Remaining (requests, tokens) for budget day `day`; None where no budget is set
+    pub fn remaining_daily_budget(&self, day: u64) -> (Option<u64>, Option<u64>) {
+        let (requests, tokens) = if self.daily_usage.day == day {
+            (self.daily_usage.requests, self.daily_usage.tokens)
+        } else {
+            (0, 0)
+        };
+        (
+            self.daily_request_budget
+                .map(|b| b.saturating_sub(requests)),
+            self.daily_token_budget.map(|b| b.saturating_sub(tokens)),
+        )
     }
 
     /// Record successful request
@@ -242,7 +342,7 @@ impl Account {
 
     /// Refill tokens (called periodically)
     pub fn refill_tokens(&mut self, amount: u32) {
-        self.tokens_available = (self.tokens_available + amount).min(50);
+        self.tokens_available = (self.tokens_available + amount).min(TOKEN_BUCKET_CAPACITY);
     }
 
     /// Get access token, refreshing if needed
@@ -261,6 +361,28 @@ impl Account {
         Ok(access_token)
     }
 
+    /// Replace the refresh token in place, keeping id, stats and settings.
+    ///
+    /// The new token is validated by performing a refresh before anything is
+    /// changed; on success the fresh access token is kept and the account is
+    /// marked valid again. Returns the email the token belongs to.
+    pub async fn swap_refresh_token(
+        &mut self,
+        http_client: &super::HttpClient,
+        refresh_token: &str,
+    ) -> Result<String> {
+        let (access_token, expires_in) = refresh_access_token(http_client, refresh_token).await?;
+        let email = super::token::get_user_email(http_client, &access_token).await?;
+
+        self.refresh_token = refresh_token.to_string();
+        self.access_token = Some(access_token);
+        self.access_token_expires = Some(now_secs() + expires_in);
+        self.is_invalid = false;
+        self.invalid_reason = None;
+
+        Ok(email)
+    }
+
     /// Load a single account (for backward compatibility)
     /// Returns the first enabled, valid account from the store
     pub fn load() -> Result<Option<Account>> {
@@ -294,12 +416,27 @@ pub struct AccountStore {
     /// Global quota threshold (accounts below this are deprioritized)
     #[serde(default = "default_quota_threshold")]
     pub quota_threshold: f64,
+    /// How long a rate limit the sticky strategy waits out on the active
+    /// account before switching, in seconds. Comes from config, not the file.
+    #[serde(skip, default = "default_sticky_rate_limit_grace")]
+    pub sticky_rate_limit_grace_secs: u64,
+    /// UTC hour at which daily budgets reset. Comes from config, not the file.
+    #[serde(skip)]
+    pub budget_reset_hour_utc: u8,
+    /// (model pattern, account id prefix or email) pairs whose account serves
+    /// matching models ahead of the strategy. Comes from config, not the file.
+    #[serde(skip)]
+    pub model_affinity: Vec<(String, String)>,
 }
 
 fn default_quota_threshold() -> f64 {
     0.1
 }
 
+fn default_sticky_rate_limit_grace() -> u64 {
+    120
+}
+
 impl Default for AccountStore {
     fn default() -> Self {
         Self {
@@ -307,6 +444,9 @@ impl Default for AccountStore {
             active_account_id: None,
             strategy: SelectionStrategy::Hybrid,
             quota_threshold: 0.1,
+            sticky_rate_limit_grace_secs: default_sticky_rate_limit_grace(),
+            budget_reset_hour_utc: 0,
+            model_affinity: Vec::new(),
         }
     }
 }
@@ -348,12 +488,31 @@ impl AccountStore {
         Ok(AccountStore::default())
     }
 
+    /// Check that the accounts file and its directory can be written,
+    /// without modifying the stored accounts.
+    pub fn check_writable() -> std::io::Result<()> {
+        let path = Self::path();
+        if path.exists() {
+            std::fs::OpenOptions::new().append(true).open(&path)?;
+        }
+        let dir = Config::dir();
+        std::fs::create_dir_all(&dir)?;
+        let probe = dir.join(".accounts.json.probe");
+        std::fs::write(&probe, b"")?;
+        let _ = std::fs::remove_file(&probe);
+        Ok(())
+    }
+
     /// Save accounts to disk
     pub fn save(&self) -> Result<()> {
         let dir = Config::dir();
         std::fs::create_dir_all(&dir)?;
+        self.save_to(&Self::path())
+    }
+
+    fn save_to(&self, path: &Path) -> Result<()> {
         let content = serde_json::to_string_pretty(self)?;
-        std::fs::write(Self::path(), content)?;
+        write_atomic(path, content.as_bytes())?;
         Ok(())
     }
 
@@ -484,7 +643,7 @@ impl AccountStore {
                     continue;
                 }
             };
-            let existing_project = account.project_id.as_deref();
+            let existing_project = account.effective_project_id();
             match crate::cloudcode::discover_project_and_tier(
                 http_client,
                 &access_token,
@@ -515,6 +674,17 @@ impl AccountStore {
         }
     }
 
+    /// IDs of invalid accounts, optionally only those not used since
+    /// `last_used_before` (Unix seconds). Never-used accounts count as unused.
+    pub fn prunable_account_ids(&self, last_used_before: Option<u64>) -> Vec<String> {
+        self.accounts
+            .iter()
+            .filter(|a| a.is_invalid)
+            .filter(|a| last_used_before.is_none_or(|cutoff| a.last_used < cutoff))
+            .map(|a| a.id.clone())
+            .collect()
+    }
+
     /// Remove an account by ID
     pub fn remove_account(&mut self, id: &str) -> bool {
         let len_before = self.accounts.len();
@@ -540,8 +710,107 @@ impl AccountStore {
         }
     }
 
+    /// Move the given accounts to the front in the given order; the rest keep
+    /// their relative order after them. Returns false if an ID is unknown.
+    pub fn reorder(&mut self, ids: &[String]) -> bool {
+        if !ids
+            .iter()
+            .all(|id| self.accounts.iter().any(|a| &a.id == id))
+        {
+            return false;
+        }
+
+        let mut ordered = Vec::with_capacity(self.accounts.len());
+        for id in ids {
+            if let Some(pos) = self.accounts.iter().position(|a| &a.id == id) {
+                ordered.push(self.accounts.remove(pos));
+            }
+        }
+        ordered.append(&mut self.accounts);
+        self.accounts = ordered;
+        true
+    }
+
+    /// Move an account one position up (towards the front) or down.
+    /// Returns its new index, or `None` if it was not found or already at the edge.
+    pub fn move_account(&mut self, id: &str, up: bool) -> Option<usize> {
+        let pos = self.accounts.iter().position(|a| a.id == id)?;
+        let new_pos = if up { pos.checked_sub(1)? } else { pos + 1 };
+        if new_pos >= self.accounts.len() {
+            return None;
+        }
+        self.accounts.swap(pos, new_pos);
+        Some(new_pos)
+    }
+
+    /// Seconds until the soonest enabled account can serve `model` again,
+    /// or `None` if one can serve it now (or there are no accounts).
+    pub fn retry_after_secs(&self, model: &str) -> Option<u64> {
+        let candidates: Vec<&Account> = self
+            .accounts
+            .iter()
+            .filter(|a| a.enabled && !a.is_invalid)
+            .collect();
+        if candidates.iter().any(|a| !a.is_rate_limited(model)) {
+            return None;
+        }
+        candidates
+            .iter()
+            .map(|a| a.rate_limit_remaining(model))
+            .min()
+    }
+
+    /// Clear `active_account_id` if it no longer names an enabled, valid
+    /// account (e.g. one disabled or invalidated since it was persisted).
+    /// Returns whether it was cleared.
+    pub fn clear_stale_active_account(&mut self) -> bool {
+        let stale = self.active_account_id.as_ref().is_some_and(|id| {
+            !self
+                .accounts
+                .iter()
+                .any(|a| &a.id == id && a.enabled && !a.is_invalid)
+        });
+        if stale {
+            self.active_account_id = None;
+        }
+        stale
+    }
+
+    /// Current budget day for this store's reset hour
+    pub fn current_budget_day(&self) -> u64 {
+        budget_day(now_secs(), self.budget_reset_hour_utc)
+    }
+
+    /// Count a request against an account's daily budget
+    pub fn record_daily_request(&mut self, id: &str) {
+        let day = self.current_budget_day();
+        if let Some(account) = self.get_account_mut(id) {
+            account.roll_daily_usage(day);
+            account.daily_usage.requests += 1;
+        }
+    }
+
+    /// Count tokens against an account's daily budget
+    pub fn record_daily_tokens(&mut self, id: &str, tokens: u64) {
+        let day = self.current_budget_day();
+        if let Some(account) = self.get_account_mut(id) {
+            account.roll_daily_usage(day);
+            account.daily_usage.tokens += tokens;
+        }
+    }
+
     /// Select best account for a request using configured strategy
     pub fn select_account(&mut self, model: &str) -> Option<String> {
+        // A stale active account would otherwise be kept by the sticky
+        // strategy's short-rate-limit check
+        self.clear_stale_active_account();
+        let day = self.current_budget_day();
+        for account in &mut self.accounts {
+            account.roll_daily_usage(day);
+        }
+        if let Some(id) = self.affinity_account(model) {
+            return Some(id);
+        }
         match self.strategy {
             SelectionStrategy::Sticky => self.select_sticky(model),
             SelectionStrategy::RoundRobin => self.select_round_robin(model),
@@ -549,6 +818,33 @@ impl AccountStore {
         }
     }
 
+    /// The account pinned to `model` by the first matching `model_affinity`
+    /// rule, if it is usable and above its quota threshold. Otherwise the
+    /// strategy picks as usual.
+    fn affinity_account(&self, model: &str) -> Option<String> {
+        let (_, target) = self
+            .model_affinity
+            .iter()
+            .find(|(pattern, _)| crate::models::model_matches(model, pattern))?;
+        self.accounts
+            .iter()
+            .find(|a| {
+                a.matches_target(target)
+                    && a.is_usable(model)
+                    && !a.is_quota_below_threshold(model, self.quota_threshold)
+            })
+            .map(|a| a.id.clone())
+    }
+
+    /// Model patterns that `model_affinity` pins to `account`.
+    pub fn affinities_for(&self, account: &Account) -> Vec<&str> {
+        self.model_affinity
+            .iter()
+            .filter(|(_, target)| account.matches_target(target))
+            .map(|(pattern, _)| pattern.as_str())
+            .collect()
+    }
+
     /// Sticky strategy: stay on current account until rate-limited
     fn select_sticky(&mut self, model: &str) -> Option<String> {
         // Check if active account is usable
@@ -558,8 +854,10 @@ impl AccountStore {
             if account.is_usable(model) {
                 return Some(id.clone());
             }
-            // Check if rate limit is short (< 2 minutes) - wait instead of switch
-            if account.rate_limit_remaining(model) < 120 {
+            // Short rate limit - wait instead of switch
+            if !account.is_over_daily_budget()
+                && account.rate_limit_remaining(model) < self.sticky_rate_limit_grace_secs
+            {
                 return Some(id.clone());
             }
         }
@@ -572,10 +870,10 @@ impl AccountStore {
             }
         }
 
-        // Emergency: return any enabled account
+        // Emergency: return any enabled, valid account with budget left
         self.accounts
             .iter()
-            .find(|a| a.enabled)
+            .find(|a| a.enabled && !a.is_invalid && !a.is_over_daily_budget())
             .map(|a| a.id.clone())
     }
 
@@ -591,7 +889,7 @@ impl AccountStore {
             return self
                 .accounts
                 .iter()
-                .find(|a| a.enabled)
+                .find(|a| a.enabled && !a.is_invalid && !a.is_over_daily_budget())
                 .map(|a| a.id.clone());
         }
 
@@ -617,11 +915,13 @@ impl AccountStore {
             .accounts
             .iter()
             .filter(|a| a.enabled && !a.is_invalid && !a.is_rate_limited(model))
+            .filter(|a| !a.is_over_daily_budget())
             .filter(|a| !a.is_quota_below_threshold(model, global_threshold))
             .map(|a| {
                 // Score formula: health*2 + tokens*5 + quota*3 + freshness*0.1
                 let health_score = a.health_score * 2.0;
-                let token_score = (a.tokens_available as f64 / 50.0) * 100.0 * 5.0;
+                let token_score =
+                    (a.tokens_available as f64 / TOKEN_BUCKET_CAPACITY as f64) * 100.0 * 5.0;
                 let quota_score = a.get_quota_fraction(model) * 100.0 * 3.0;
                 let freshness = if a.last_used == 0 {
                     100.0
@@ -643,14 +943,100 @@ impl AccountStore {
             return Some(id.clone());
         }
 
-        // Emergency fallback: any enabled account
-        self.accounts.iter().find(|a| a.enabled).map(|a| {
-            self.active_account_id = Some(a.id.clone());
-            a.id.clone()
-        })
+        // Emergency fallback: any enabled, valid account with budget left
+        self.accounts
+            .iter()
+            .find(|a| a.enabled && !a.is_invalid && !a.is_over_daily_budget())
+            .map(|a| {
+                self.active_account_id = Some(a.id.clone());
+                a.id.clone()
+            })
     }
 }
 
+/// Best-effort recovery of accounts from a damaged accounts file.
+///
+/// Scans the text for anything that parses as a complete account object and
+/// skips everything else, so a truncated or partially overwritten file still
+/// yields every account that was written out in full.
+pub fn salvage_accounts(content: &str) -> Vec<Account> {
+    let mut recovered: Vec<Account> = Vec::new();
+    let mut pos = 0;
+
+    while let Some(offset) = content[pos..].find('{') {
+        let start = pos + offset;
+        let mut stream =
+            serde_json::Deserializer::from_str(&content[start..]).into_iter::<Account>();
+        match stream.next() {
+            Some(Ok(account)) if !account.email.is_empty() && !account.refresh_token.is_empty() => {
+                pos = start + stream.byte_offset();
+                if !recovered.iter().any(|a| a.email == account.email) {
+                    recovered.push(account);
+                }
+            }
+            _ => pos = start + 1,
+        }
+    }
+
+    recovered
+}
+
+/// Write `contents` to `path` without ever leaving a truncated file behind.
+///
+/// The data is written to a temp file in the same directory, flushed to disk,
+/// and then renamed over the target, so readers see either the old file or the
+/// complete new one. Each write uses its own temp file, so concurrent saves
+/// can't truncate each other's.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, Uuid::new_v4()));
+
+    let result = (|| {
+        let mut file = std::fs::File::create(&tmp_path)?;
+        // Keep a restrictive mode the user set on the file being replaced
+        if let Ok(metadata) = std::fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        sync_parent_dir(path)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Flush a rename in `path`'s directory to disk so it survives a crash.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => std::fs::File::open(dir)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+/// Directories can't be opened as files on Windows; renames there are
+/// flushed with the file.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Returns true if a write error means the accounts file can't be written at all
+/// (read-only mount, missing permissions) rather than failing transiently.
+pub fn is_read_only_error(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::ReadOnlyFilesystem
+    )
+}
+
 fn now_secs() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -672,6 +1058,49 @@ mod tests {
         assert_eq!(account.tokens_available, 50);
     }
 
+    #[test]
+    fn test_pinned_project_takes_precedence() {
+        let mut account = Account::new("test@example.com".to_string(), "token".to_string());
+        assert_eq!(account.effective_project_id(), None);
+
+        account.project_id = Some("discovered".to_string());
+        assert_eq!(account.effective_project_id(), Some("discovered"));
+
+        account.pinned_project_id = Some("pinned".to_string());
+        assert_eq!(account.effective_project_id(), Some("pinned"));
+
+        // Survives a save/load round trip; absent when unset
+        let json = serde_json::to_string(&account).unwrap();
+        let loaded: Account = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.pinned_project_id.as_deref(), Some("pinned"));
+        account.pinned_project_id = None;
+        assert!(
+            !serde_json::to_string(&account)
+                .unwrap()
+                .contains("pinned_project_id")
+        );
+    }
+
+    #[test]
+    fn test_prunable_account_ids() {
+        let mut store = AccountStore::default();
+        let valid = Account::new("valid@example.com".to_string(), "rt".to_string());
+        let mut stale = Account::new("stale@example.com".to_string(), "rt".to_string());
+        stale.is_invalid = true;
+        stale.last_used = 1_000;
+        let mut recent = Account::new("recent@example.com".to_string(), "rt".to_string());
+        recent.is_invalid = true;
+        recent.last_used = 5_000;
+        let (stale_id, recent_id) = (stale.id.clone(), recent.id.clone());
+        store.accounts = vec![valid, stale, recent];
+
+        assert_eq!(
+            store.prunable_account_ids(None),
+            vec![stale_id.clone(), recent_id]
+        );
+        assert_eq!(store.prunable_account_ids(Some(2_000)), vec![stale_id]);
+    }
+
     #[test]
     fn test_account_rate_limit() {
         let mut account = Account::new("test@example.com".to_string(), "token".to_string());
@@ -715,6 +1144,240 @@ mod tests {
         assert_eq!(store.accounts.len(), 0);
     }
 
+    #[test]
+    fn test_account_store_reorder() {
+        let mut store = AccountStore::default();
+        let ids: Vec<String> = ["a", "b", "c"]
+            .iter()
+            .map(|name| {
+                let account = Account::new(format!("{name}@example.com"), name.to_string());
+                let id = account.id.clone();
+                store.add_account(account);
+                id
+            })
+            .collect();
+        let order = |store: &AccountStore| -> Vec<String> {
+            store.accounts.iter().map(|a| a.id.clone()).collect()
+        };
+
+        assert!(store.reorder(&[ids[2].clone(), ids[0].clone()]));
+        assert_eq!(
+            order(&store),
+            vec![ids[2].clone(), ids[0].clone(), ids[1].clone()]
+        );
+
+        assert!(!store.reorder(&["missing".to_string()]));
+        assert_eq!(
+            order(&store),
+            vec![ids[2].clone(), ids[0].clone(), ids[1].clone()]
+        );
+
+        assert_eq!(store.move_account(&ids[1], true), Some(1));
+        assert_eq!(store.move_account(&ids[2], true), None);
+        assert_eq!(store.move_account(&ids[0], false), None);
+        assert_eq!(
+            order(&store),
+            vec![ids[2].clone(), ids[1].clone(), ids[0].clone()]
+        );
+    }
+
+    #[test]
+    fn test_retry_after_secs() {
+        let mut store = AccountStore::default();
+        assert_eq!(store.retry_after_secs("model-a"), None);
+
+        let mut a1 = Account::new("a1@example.com".to_string(), "token1".to_string());
+        a1.set_rate_limit("model-a", now_secs() + 300);
+        let mut a2 = Account::new("a2@example.com".to_string(), "token2".to_string());
+        store.add_account(a1);
+        store.add_account(a2.clone());
+        assert_eq!(store.retry_after_secs("model-a"), None);
+
+        store.remove_account(&a2.id);
+        a2.set_rate_limit("model-a", now_secs() + 60);
+        store.add_account(a2);
+        let secs = store.retry_after_secs("model-a").unwrap();
+        assert!((59..=60).contains(&secs), "got {secs}");
+        assert_eq!(store.retry_after_secs("model-b"), None);
+    }
+
+    #[test]
+    fn test_sticky_rate_limit_grace() {
+        let mut store = AccountStore {
+            strategy: SelectionStrategy::Sticky,
+            ..Default::default()
+        };
+        let mut a1 = Account::new("a1@example.com".to_string(), "token1".to_string());
+        a1.set_rate_limit("model", now_secs() + 300);
+        let a2 = Account::new("a2@example.com".to_string(), "token2".to_string());
+        store.active_account_id = Some(a1.id.clone());
+        let (a1_id, a2_id) = (a1.id.clone(), a2.id.clone());
+        store.add_account(a1);
+        store.add_account(a2);
+
+        // A 5 minute limit is past the default 2 minute grace
+        assert_eq!(store.select_account("model"), Some(a2_id));
+
+        store.active_account_id = Some(a1_id.clone());
+        store.sticky_rate_limit_grace_secs = 600;
+        assert_eq!(store.select_account("model"), Some(a1_id));
+    }
+
+    #[test]
+    fn test_model_affinity_pins_account() {
+        let mut store = AccountStore {
+            strategy: SelectionStrategy::RoundRobin,
+            ..Default::default()
+        };
+        let a1 = Account::new("a1@example.com".to_string(), "token1".to_string());
+        let mut a2 = Account::new("ultra@example.com".to_string(), "token2".to_string());
+        a2.id = "f6c3b4aa-0000-0000-0000-000000000000".to_string();
+        let (a1_id, a2_id) = (a1.id.clone(), a2.id.clone());
+        store.add_account(a1);
+        store.add_account(a2);
+        store.model_affinity = vec![
+            ("opus".to_string(), "ULTRA@example.com".to_string()),
+            ("gemini-3-pro-*".to_string(), "f6c3b4".to_string()),
+        ];
+
+        for _ in 0..3 {
+            assert_eq!(
+                store.select_account("claude-opus-4-6-thinking"),
+                Some(a2_id.clone())
+            );
+            assert_eq!(
+                store.select_account("gemini-3-pro-high"),
+                Some(a2_id.clone())
+            );
+        }
+        assert_eq!(
+            store.affinities_for(&store.accounts[1]),
+            vec!["opus", "gemini-3-pro-*"]
+        );
+
+        // The first matching rule wins, in config order
+        store
+            .model_affinity
+            .insert(0, ("gemini-*".to_string(), a1_id.clone()));
+        assert_eq!(
+            store.select_account("gemini-3-pro-high"),
+            Some(a1_id.clone())
+        );
+        store.model_affinity.remove(0);
+
+        // A pinned account below the quota threshold isn't honored
+        store.accounts[1].quota.insert(
+            "claude-opus-4-6-thinking".to_string(),
+            ModelQuota {
+                remaining_fraction: 0.05,
+                reset_time: 0,
+            },
+        );
+        assert_eq!(store.affinity_account("claude-opus-4-6-thinking"), None);
+        store.accounts[1].quota.clear();
+
+        // An unusable pinned account falls back to the strategy
+        store.accounts[1].set_rate_limit("claude-opus-4-6-thinking", now_secs() + 300);
+        assert_eq!(
+            store.select_account("claude-opus-4-6-thinking"),
+            Some(a1_id)
+        );
+    }
+
+    #[test]
+    fn test_emergency_fallback_skips_invalid_accounts() {
+        for strategy in [
+            SelectionStrategy::Sticky,
+            SelectionStrategy::RoundRobin,
+            SelectionStrategy::Hybrid,
+        ] {
+            let mut store = AccountStore {
+                strategy,
+                ..Default::default()
+            };
+            let mut a1 = Account::new("a1@example.com".to_string(), "token1".to_string());
+            a1.is_invalid = true;
+            let a2 = Account::new("a2@example.com".to_string(), "token2".to_string());
+            let a2_id = a2.id.clone();
+            store.add_account(a1);
+            store.add_account(a2);
+            for account in &mut store.accounts {
+                account.set_rate_limit("model", now_secs() + 3600);
+            }
+
+            assert_eq!(store.select_account("model"), Some(a2_id), "{strategy:?}");
+        }
+    }
+
+    #[test]
+    fn test_daily_budget_skips_account_until_reset() {
+        let mut store = AccountStore {
+            strategy: SelectionStrategy::Sticky,
+            ..Default::default()
+        };
+        let mut a1 = Account::new("a1@example.com".to_string(), "token1".to_string());
+        a1.daily_request_budget = Some(2);
+        a1.daily_token_budget = Some(1000);
+        let a2 = Account::new("a2@example.com".to_string(), "token2".to_string());
+        let (a1_id, a2_id) = (a1.id.clone(), a2.id.clone());
+        store.add_account(a1);
+        store.add_account(a2);
+
+        assert_eq!(store.select_account("model"), Some(a1_id.clone()));
+        store.record_daily_request(&a1_id);
+        store.record_daily_tokens(&a1_id, 1000);
+        // Token budget used up, even though requests remain
+        assert_eq!(store.select_account("model"), Some(a2_id.clone()));
+
+        let day = store.current_budget_day();
+        let a1 = store.accounts.iter().find(|a| a.id == a1_id).unwrap();
+        assert_eq!(a1.remaining_daily_budget(day), (Some(1), Some(0)));
+        // Yesterday's usage doesn't count against today
+        assert_eq!(a1.remaining_daily_budget(day + 1), (Some(2), Some(1000)));
+
+        // Rolled over on the next selection once the day changes
+        store.get_account_mut(&a1_id).unwrap().daily_usage.day = day - 1;
+        store.active_account_id = Some(a1_id.clone());
+        assert_eq!(store.select_account("model"), Some(a1_id));
+    }
+
+    #[test]
+    fn test_budget_day_boundary() {
+        let midnight = 20_000 * 86400;
+        assert_eq!(budget_day(midnight, 0), 20_000);
+        assert_eq!(budget_day(midnight - 1, 0), 19_999);
+        // With a 06:00 reset, 05:59 still belongs to the previous day
+        assert_eq!(budget_day(midnight + 6 * 3600 - 1, 6), 19_999);
+        assert_eq!(budget_day(midnight + 6 * 3600, 6), 20_000);
+    }
+
+    #[test]
+    fn test_stale_active_account_is_replaced() {
+        let mut store = AccountStore {
+            strategy: SelectionStrategy::Sticky,
+            ..Default::default()
+        };
+        let mut disabled = Account::new("a1@example.com".to_string(), "token1".to_string());
+        disabled.enabled = false;
+        let healthy = Account::new("a2@example.com".to_string(), "token2".to_string());
+        store.active_account_id = Some(disabled.id.clone());
+        let healthy_id = healthy.id.clone();
+        store.add_account(disabled);
+        store.add_account(healthy);
+
+        // Round-trips through the file like a restart would
+        let json = serde_json::to_string(&store).unwrap();
+        let mut store: AccountStore = serde_json::from_str(&json).unwrap();
+
+        assert!(store.clear_stale_active_account());
+        assert_eq!(store.active_account_id, None);
+        assert!(!store.clear_stale_active_account());
+
+        store.active_account_id = Some("no-such-account".to_string());
+        assert_eq!(store.select_account("model"), Some(healthy_id.clone()));
+        assert_eq!(store.active_account_id, Some(healthy_id));
+    }
+
     #[test]
     fn test_hybrid_selection() {
         let mut store = AccountStore::default();
@@ -787,4 +1450,128 @@ mod tests {
             .insert("model".to_string(), 0.1);
         assert!(!account.is_quota_below_threshold("model", 0.2));
     }
+
+    #[test]
+    fn test_write_atomic_survives_interrupted_write() {
+        let dir = std::env::temp_dir().join(format!("agcp-atomic-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("accounts.json");
+
+        let mut store = AccountStore::default();
+        store.add_account(Account::new(
+            "a@example.com".to_string(),
+            "token_a".to_string(),
+        ));
+        write_atomic(&path, serde_json::to_string(&store).unwrap().as_bytes()).unwrap();
+
+        // Simulate a crash mid-write: a partial temp file is left behind
+        // and the rename never happens.
+        std::fs::write(dir.join(".accounts.json.tmp"), b"{\"accounts\": [{\"id\"").unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let loaded: AccountStore = serde_json::from_str(&content).unwrap();
+        assert_eq!(loaded.accounts.len(), 1);
+        assert_eq!(loaded.accounts[0].email, "a@example.com");
+
+        // A later write uses its own temp file and still replaces the target
+        store.add_account(Account::new(
+            "b@example.com".to_string(),
+            "token_b".to_string(),
+        ));
+        write_atomic(&path, serde_json::to_string(&store).unwrap().as_bytes()).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let loaded: AccountStore = serde_json::from_str(&content).unwrap();
+        assert_eq!(loaded.accounts.len(), 2);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_keeps_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("agcp-atomic-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("accounts.json");
+        std::fs::write(&path, b"{}").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+        let mut store = AccountStore::default();
+        store.add_account(Account::new(
+            "a@example.com".to_string(),
+            "token_a".to_string(),
+        ));
+        store.save_to(&path).unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .contains("a@example.com")
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_write_atomic_concurrent_writes() {
+        let dir = std::env::temp_dir().join(format!("agcp-atomic-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("accounts.json");
+
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let contents = format!(
+                        "{{\"writer\": {}, \"padding\": \"{}\"}}",
+                        i,
+                        "x".repeat(64 * 1024)
+                    );
+                    write_atomic(&path, contents.as_bytes())
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+
+        // The target is one complete write and no temp files are left behind
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(serde_json::from_str::<serde_json::Value>(&content).is_ok());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_salvage_accounts_from_truncated_file() {
+        let mut store = AccountStore::default();
+        store.add_account(Account::new(
+            "a@example.com".to_string(),
+            "token_a".to_string(),
+        ));
+        store.add_account(Account::new(
+            "b@example.com".to_string(),
+            "token_b".to_string(),
+        ));
+        store.add_account(Account::new(
+            "c@example.com".to_string(),
+            "token_c".to_string(),
+        ));
+        let json = serde_json::to_string_pretty(&store).unwrap();
+
+        // Cut the file off in the middle of the third account
+        let cut = json.find("c@example.com").unwrap();
+        let damaged = format!("garbage\u{0}{}", &json[..cut]);
+
+        let recovered = salvage_accounts(&damaged);
+        let emails: Vec<_> = recovered.iter().map(|a| a.email.as_str()).collect();
+        assert_eq!(emails, vec!["a@example.com", "b@example.com"]);
+        assert_eq!(recovered[1].refresh_token, "token_b");
+
+        assert!(salvage_accounts("not json at all").is_empty());
+    }
 }
@@ -9,8 +9,14 @@ pub use token::get_user_email;
 use http_body_util::{BodyExt, Empty, Full};
 use hyper::Request;
 use hyper::body::Bytes;
+use hyper::header::{HeaderName, HeaderValue};
 use hyper_util::client::legacy::Client;
-use hyper_util::rt::TokioExecutor;
+
+use crate::cloudcode::client::{
+    apply_extra_headers, http_status_error, upstream_client_builder, upstream_extra_headers,
+    upstream_http_connector,
+};
+use crate::config::CloudCodeConfig;
 
 pub struct HttpClient {
     full_client: Client<
@@ -21,22 +27,30 @@ pub struct HttpClient {
         hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>,
         Empty<Bytes>,
     >,
+    /// `[cloudcode] extra_headers`, sent on Cloud Code API calls
+    extra_headers: Vec<(HeaderName, HeaderValue)>,
 }
 
 impl HttpClient {
     pub fn new() -> Self {
+        Self::with_config(&CloudCodeConfig::default())
+    }
+
+    /// Build with the `[cloudcode]` connection pool and keep-alive settings
+    pub fn with_config(config: &CloudCodeConfig) -> Self {
         let connector = hyper_rustls::HttpsConnectorBuilder::new()
             .with_webpki_roots()
             .https_only()
             .enable_http1()
-            .build();
+            .wrap_connector(upstream_http_connector(config));
 
-        let full_client = Client::builder(TokioExecutor::new()).build(connector.clone());
-        let empty_client = Client::builder(TokioExecutor::new()).build(connector);
+        let full_client = upstream_client_builder(config).build(connector.clone());
+        let empty_client = upstream_client_builder(config).build(connector);
 
         Self {
             full_client,
             empty_client,
+            extra_headers: upstream_extra_headers(config),
         }
     }
 
@@ -60,7 +74,7 @@ impl HttpClient {
             .map_err(|e| e.to_string())?;
 
         if !response.status().is_success() {
-            return Err(format!("HTTP {}", response.status()));
+            return Err(error_from_response(response).await);
         }
 
         let body = response
@@ -86,7 +100,7 @@ impl HttpClient {
             .map_err(|e| e.to_string())?;
 
         if !response.status().is_success() {
-            return Err(format!("HTTP {}", response.status()));
+            return Err(error_from_response(response).await);
         }
 
         let body = response
@@ -115,7 +129,7 @@ impl HttpClient {
 
         let client_metadata = r#"{"ideType":"IDE_UNSPECIFIED","platform":"PLATFORM_UNSPECIFIED","pluginType":"GEMINI"}"#;
 
-        let req = Request::builder()
+        let mut req = Request::builder()
             .method("POST")
             .uri(url)
             .header("Authorization", format!("Bearer {}", token))
@@ -128,6 +142,7 @@ impl HttpClient {
             .header("Client-Metadata", client_metadata)
             .body(Full::new(Bytes::from(body.to_vec())))
             .map_err(|e| e.to_string())?;
+        apply_extra_headers(req.headers_mut(), &self.extra_headers);
 
         let response = self
             .full_client
@@ -136,7 +151,7 @@ impl HttpClient {
             .map_err(|e| e.to_string())?;
 
         if !response.status().is_success() {
-            return Err(format!("HTTP {}", response.status()));
+            return Err(error_from_response(response).await);
         }
 
         let body = response
@@ -180,9 +195,10 @@ impl HttpClient {
             req = req.header(*name, *value);
         }
 
-        let req = req
+        let mut req = req
             .body(Full::new(Bytes::from(body.to_vec())))
             .map_err(|e| e.to_string())?;
+        apply_extra_headers(req.headers_mut(), &self.extra_headers);
 
         let response = self
             .full_client
@@ -191,7 +207,7 @@ impl HttpClient {
             .map_err(|e| e.to_string())?;
 
         if !response.status().is_success() {
-            return Err(format!("HTTP {}", response.status()));
+            return Err(error_from_response(response).await);
         }
 
         let body = response
@@ -219,7 +235,7 @@ impl HttpClient {
             .map_err(|e| e.to_string())?;
 
         if !response.status().is_success() {
-            return Err(format!("HTTP {}", response.status()));
+            return Err(error_from_response(response).await);
         }
 
         let body = response
@@ -229,6 +245,62 @@ impl HttpClient {
             .map_err(|e| e.to_string())?;
         Ok(body.to_bytes().to_vec())
     }
+
+    /// GET that follows redirects, for release assets served from a CDN
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>, String> {
+        let mut url = url.to_string();
+
+        for _ in 0..5 {
+            let req = Request::builder()
+                .method("GET")
+                .uri(&url)
+                .header("User-Agent", "agcp")
+                .body(Empty::new())
+                .map_err(|e| e.to_string())?;
+
+            let response = self
+                .empty_client
+                .request(req)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            if response.status().is_redirection() {
+                url = response
+                    .headers()
+                    .get(hyper::header::LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .ok_or_else(|| format!("HTTP {} without Location", response.status()))?
+                    .to_string();
+                continue;
+            }
+
+            if !response.status().is_success() {
+                return Err(error_from_response(response).await);
+            }
+
+            let body = response
+                .into_body()
+                .collect()
+                .await
+                .map_err(|e| e.to_string())?;
+            return Ok(body.to_bytes().to_vec());
+        }
+
+        Err("Too many redirects".to_string())
+    }
+}
+
+/// Read a non-2xx response into an error string that keeps the upstream's
+/// (summarized) error body.
+async fn error_from_response(response: hyper::Response<hyper::body::Incoming>) -> String {
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map(|b| b.to_bytes())
+        .unwrap_or_default();
+    http_status_error(status, &body)
 }
 
 impl Default for HttpClient {
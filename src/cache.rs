@@ -11,6 +11,8 @@ struct CacheEntry {
     response: Bytes,
     created_at: Instant,
     ttl: Duration,
+    /// Upstream tokens (input + output) the cached response cost to produce.
+    tokens: u64,
 }
 
 impl CacheEntry {
@@ -29,6 +31,16 @@ pub struct CacheStats {
     pub hits: u64,
     pub misses: u64,
     pub hit_rate: f64,
+    /// Total size of the response bodies currently cached.
+    pub bytes_stored: usize,
+    /// Cumulative bytes returned from cache hits.
+    pub bytes_served: u64,
+    /// Upstream requests avoided (one per hit).
+    pub upstream_calls_saved: u64,
+    /// Estimated upstream tokens avoided, from the usage of each cached response.
+    pub tokens_saved: u64,
+    /// Entries dropped to make room for new ones (expiry is not counted).
+    pub evictions: u64,
 }
 
 /// Response cache with LRU eviction and TTL expiration.
@@ -37,9 +49,15 @@ pub struct ResponseCache {
     order: VecDeque<String>,
     max_entries: usize,
     default_ttl: Duration,
+    /// Per-model TTL overrides as (glob pattern, TTL), first match wins.
+    model_ttls: Vec<(String, Duration)>,
     enabled: bool,
     hits: u64,
     misses: u64,
+    bytes_stored: usize,
+    bytes_served: u64,
+    tokens_saved: u64,
+    evictions: u64,
 }
 
 impl ResponseCache {
@@ -55,16 +73,46 @@ impl ResponseCache {
             order: VecDeque::with_capacity(max_entries),
             max_entries,
             default_ttl: Duration::from_secs(ttl_seconds),
+            model_ttls: Vec::new(),
             enabled,
             hits: 0,
             misses: 0,
+            bytes_stored: 0,
+            bytes_served: 0,
+            tokens_saved: 0,
+            evictions: 0,
         }
     }
 
+    /// Set per-model TTL overrides as (glob pattern, seconds) pairs.
+    ///
+    /// Models matching none of the patterns use the default TTL.
+    pub fn with_model_ttls(mut self, ttls: impl IntoIterator<Item = (String, u64)>) -> Self {
+        self.model_ttls = ttls
+            .into_iter()
+            .map(|(pattern, secs)| (pattern, Duration::from_secs(secs)))
+            .collect();
+        self
+    }
+
+    /// TTL for responses from `model`: the first matching override, else the default.
+    pub fn ttl_for_model(&self, model: &str) -> Duration {
+        self.model_ttls
+            .iter()
+            .find(|(pattern, _)| crate::models::glob_match(pattern, model))
+            .map(|(_, ttl)| *ttl)
+            .unwrap_or(self.default_ttl)
+    }
+
+    /// Whether [`put_with_ttl`](Self::put_with_ttl) with `ttl` would store anything.
+    pub fn stores(&self, ttl: Duration) -> bool {
+        self.enabled && !ttl.is_zero()
+    }
+
     /// Generate a cache key from request parameters using SHA-256.
     ///
     /// The key is a deterministic hash of the model, messages, system prompt,
-    /// tools, temperature, max_tokens, top_p, top_k, and stop_sequences.
+    /// tools, temperature, max_tokens, top_p, top_k, stop_sequences, and seed.
     /// Returns a hex-encoded string (64 chars).
     #[allow(clippy::too_many_arguments)]
     pub fn make_key(
@@ -77,6 +125,7 @@ impl ResponseCache {
         top_p: Option<f32>,
         top_k: Option<u32>,
         stop_sequences: Option<&str>,
+        seed: Option<i64>,
     ) -> String {
         let mut hasher = Sha256::new();
         hasher.update(model.as_bytes());
@@ -108,6 +157,10 @@ impl ResponseCache {
         if let Some(stop) = stop_sequences {
             hasher.update(stop.as_bytes());
         }
+        hasher.update(b"|");
+        if let Some(seed) = seed {
+            hasher.update(seed.to_le_bytes());
+        }
         let result = hasher.finalize();
         // Use a pre-allocated string and write hex directly (avoids per-byte format!)
         let mut hex = String::with_capacity(64);
@@ -133,44 +186,58 @@ impl ResponseCache {
         if let Some(entry) = self.entries.get(key) {
             if entry.is_expired() {
                 // Remove expired entry
-                self.entries.remove(key);
+                self.remove_entry(key);
                 self.order.retain(|k| k != key);
                 self.misses += 1;
                 return None;
             }
 
+            let response = entry.response.clone();
+            self.bytes_served += response.len() as u64;
+            self.tokens_saved += entry.tokens;
+
             // Hit - update LRU order (move to back = most recently used)
             self.order.retain(|k| k != key);
             self.order.push_back(key.to_string());
             self.hits += 1;
 
-            return Some(entry.response.clone());
+            return Some(response);
         }
 
         self.misses += 1;
         None
     }
 
-    /// Store a response in the cache.
+    /// Store a response in the cache with the default TTL.
     ///
     /// If the cache is at capacity, evicts the least recently used entry.
+    #[cfg(test)]
     pub fn put(&mut self, key: String, response: Vec<u8>) {
-        if !self.enabled {
+        self.put_with_ttl(key, response, self.default_ttl);
+    }
+
+    /// Store a response in the cache with its own TTL.
+    ///
+    /// A zero TTL stores nothing. If the cache is at capacity, evicts the
+    /// least recently used entry.
+    pub fn put_with_ttl(&mut self, key: String, response: Vec<u8>, ttl: Duration) {
+        if !self.stores(ttl) {
             return;
         }
 
+        let tokens = response_tokens(&response);
         let response = Bytes::from(response);
+        self.bytes_stored += response.len();
+        let entry = CacheEntry {
+            response,
+            created_at: Instant::now(),
+            ttl,
+            tokens,
+        };
 
         // If key already exists, update it and move to back of LRU
-        if self.entries.contains_key(&key) {
-            self.entries.insert(
-                key.clone(),
-                CacheEntry {
-                    response,
-                    created_at: Instant::now(),
-                    ttl: self.default_ttl,
-                },
-            );
+        if self.remove_entry(&key) {
+            self.entries.insert(key.clone(), entry);
             self.order.retain(|k| k != &key);
             self.order.push_back(key);
             return;
@@ -179,24 +246,31 @@ impl ResponseCache {
         // Evict LRU entries if at capacity
         while self.entries.len() >= self.max_entries {
             if let Some(oldest_key) = self.order.pop_front() {
-                self.entries.remove(&oldest_key);
+                if self.remove_entry(&oldest_key) {
+                    self.evictions += 1;
+                }
             } else {
                 break;
             }
         }
 
         // Insert new entry
-        self.entries.insert(
-            key.clone(),
-            CacheEntry {
-                response,
-                created_at: Instant::now(),
-                ttl: self.default_ttl,
-            },
-        );
+        self.entries.insert(key.clone(), entry);
         self.order.push_back(key);
     }
 
+    /// Remove an entry from the map, keeping `bytes_stored` in sync.
+    /// The caller is responsible for the LRU order.
+    fn remove_entry(&mut self, key: &str) -> bool {
+        match self.entries.remove(key) {
+            Some(entry) => {
+                self.bytes_stored -= entry.response.len();
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Get cache statistics.
     pub fn stats(&self) -> CacheStats {
         let total = self.hits + self.misses;
@@ -213,6 +287,11 @@ impl ResponseCache {
             hits: self.hits,
             misses: self.misses,
             hit_rate,
+            bytes_stored: self.bytes_stored,
+            bytes_served: self.bytes_served,
+            upstream_calls_saved: self.hits,
+            tokens_saved: self.tokens_saved,
+            evictions: self.evictions,
         }
     }
 
@@ -220,7 +299,28 @@ impl ResponseCache {
     pub fn clear(&mut self) {
         self.entries.clear();
         self.order.clear();
+        self.bytes_stored = 0;
+    }
+}
+
+/// Read `usage.input_tokens + usage.output_tokens` from a cached response body.
+/// Returns 0 for bodies that aren't a Messages API response.
+fn response_tokens(body: &[u8]) -> u64 {
+    #[derive(serde::Deserialize)]
+    struct Usage {
+        #[serde(default)]
+        input_tokens: u64,
+        #[serde(default)]
+        output_tokens: u64,
+    }
+    #[derive(serde::Deserialize)]
+    struct Body {
+        usage: Usage,
     }
+
+    serde_json::from_slice::<Body>(body)
+        .map(|b| b.usage.input_tokens + b.usage.output_tokens)
+        .unwrap_or(0)
 }
 
 #[cfg(test)]
@@ -241,6 +341,7 @@ mod tests {
             None,
             None,
             None,
+            None,
         );
         let response = b"test response".to_vec();
 
@@ -271,6 +372,7 @@ mod tests {
         // Verify nothing was stored
         assert_eq!(cache.entries.len(), 0);
         assert_eq!(cache.order.len(), 0);
+        assert!(!cache.stores(Duration::from_secs(3600)));
     }
 
     #[test]
@@ -310,6 +412,7 @@ mod tests {
             None,
             None,
             None,
+            None,
         );
         let key2 = ResponseCache::make_key(
             "claude-3",
@@ -321,6 +424,7 @@ mod tests {
             None,
             None,
             None,
+            None,
         );
         assert_eq!(key1, key2);
 
@@ -335,6 +439,7 @@ mod tests {
             None,
             None,
             None,
+            None,
         );
         assert_ne!(key1, key3);
 
@@ -349,6 +454,7 @@ mod tests {
             None,
             None,
             None,
+            None,
         );
         assert_ne!(key1, key4);
 
@@ -363,6 +469,7 @@ mod tests {
             None,
             None,
             None,
+            None,
         );
         assert_ne!(key1, key5);
 
@@ -377,6 +484,7 @@ mod tests {
             None,
             None,
             None,
+            None,
         );
         assert_ne!(key1, key6);
 
@@ -391,6 +499,7 @@ mod tests {
             None,
             None,
             None,
+            None,
         );
         assert_ne!(key1, key7);
 
@@ -438,4 +547,69 @@ mod tests {
         assert_eq!(stats.misses, 1);
         assert!((stats.hit_rate - 0.666666).abs() < 0.001);
     }
+
+    #[test]
+    fn test_cache_efficiency_metrics() {
+        let mut cache = ResponseCache::new(true, 3600, 2);
+        let body = br#"{"id":"msg_1","usage":{"input_tokens":100,"output_tokens":20}}"#.to_vec();
+
+        cache.put("key1".to_string(), body.clone());
+        cache.put("key2".to_string(), b"plain".to_vec());
+        assert_eq!(cache.stats().bytes_stored, body.len() + 5);
+
+        cache.get("key1");
+        cache.get("key1");
+        let stats = cache.stats();
+        assert_eq!(stats.bytes_served, 2 * body.len() as u64);
+        assert_eq!(stats.upstream_calls_saved, 2);
+        assert_eq!(stats.tokens_saved, 240);
+        assert_eq!(stats.evictions, 0);
+
+        // Overwriting doesn't double-count stored bytes
+        cache.put("key2".to_string(), b"plain!".to_vec());
+        assert_eq!(cache.stats().bytes_stored, body.len() + 6);
+
+        // key2 was rewritten last, so key1 is the one evicted
+        cache.put("key3".to_string(), b"abc".to_vec());
+        let stats = cache.stats();
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.bytes_stored, 6 + 3);
+
+        cache.clear();
+        assert_eq!(cache.stats().bytes_stored, 0);
+    }
+
+    #[test]
+    fn test_cache_model_ttl_overrides() {
+        let mut cache = ResponseCache::new(true, 300, 10).with_model_ttls(vec![
+            ("gemini-3-flash".to_string(), 3600),
+            ("claude-opus-*".to_string(), 0),
+            ("claude-*".to_string(), 60),
+        ]);
+
+        assert_eq!(
+            cache.ttl_for_model("gemini-3-flash"),
+            Duration::from_secs(3600)
+        );
+        assert_eq!(cache.ttl_for_model("claude-opus-4-6"), Duration::ZERO);
+        assert!(!cache.stores(Duration::ZERO));
+        assert!(cache.stores(Duration::from_secs(60)));
+        assert_eq!(
+            cache.ttl_for_model("claude-sonnet-4-5"),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            cache.ttl_for_model("gemini-3-pro-high"),
+            Duration::from_secs(300)
+        );
+
+        // Entries carry their own expiry
+        let ttl = cache.ttl_for_model("claude-opus-4-6");
+        cache.put_with_ttl("opus".to_string(), b"short".to_vec(), ttl);
+        let ttl = cache.ttl_for_model("gemini-3-flash");
+        cache.put_with_ttl("flash".to_string(), b"long".to_vec(), ttl);
+        assert!(cache.get("opus").is_none());
+        assert!(cache.get("flash").is_some());
+        assert_eq!(cache.stats().entries, 1);
+    }
 }
@@ -1,13 +1,16 @@
 use http_body_util::{BodyExt, Full};
 use hyper::Request;
 use hyper::body::Bytes;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
 use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::rt::TokioExecutor;
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
-use tracing::{debug, info, warn};
+use tracing::{Instrument, debug, debug_span, info, warn};
 
 use crate::config::CloudCodeConfig;
 use crate::error::{ApiError, Error, Result};
@@ -28,6 +31,206 @@ pub const ENDPOINTS: &[&str] = &[
 const GEMINI_DISABLED_ERROR_MARKER: &str = "gemini has been disabled in this account";
 const GEMINI_DISABLED_WARNING: &str = "Gemini has been disabled in this Google account for a Terms of Service violation. Requests cannot continue until access is restored. Contact Google Cloud Support or email gemini-code-assist-user-feedback@google.com.";
 
+/// Error reasons in a 403 body that mean the account itself can't be used
+/// (its project is disabled, deleted or denied), paired with the reason
+/// recorded when the account is marked invalid. A bare `PERMISSION_DENIED`
+/// status is deliberately absent: it is also returned for model- and
+/// request-level denials.
+const ACCOUNT_FATAL_MARKERS: &[(&str, &str)] = &[
+    (
+        "SERVICE_DISABLED",
+        "Cloud Code API is disabled for the account's project",
+    ),
+    (
+        "CONSUMER_INVALID",
+        "the account's project is invalid or deleted",
+    ),
+    (
+        "USER_PROJECT_DENIED",
+        "access to the account's project was denied",
+    ),
+];
+
+/// Reason to mark the account invalid if `error` says the account itself is
+/// unusable rather than that this request failed. The Gemini-disabled 403 is
+/// excluded since other model families keep working on that account.
+pub fn account_fatal_reason(error: &Error) -> Option<&'static str> {
+    let body = match error {
+        Error::Http(message) if message.starts_with("HTTP 403") => message,
+        Error::Api(ApiError::ServerError {
+            status: 403,
+            message,
+        }) if message != GEMINI_DISABLED_WARNING => message,
+        _ => return None,
+    };
+    ACCOUNT_FATAL_MARKERS
+        .iter()
+        .find(|(marker, _)| body.contains(marker))
+        .map(|(_, reason)| *reason)
+}
+
+/// Upstream error bodies longer than this are truncated before being
+/// surfaced to clients and logs.
+const MAX_ERROR_BODY_CHARS: usize = 1000;
+
+/// Condense an upstream error body for error messages: HTML error pages are
+/// reduced to their `<title>`, everything else is truncated.
+pub fn summarize_error_body(body: &str) -> String {
+    let body = body.trim();
+    let lower = body.to_ascii_lowercase();
+
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        let title = lower
+            .find("<title>")
+            .map(|start| start + "<title>".len())
+            .and_then(|start| {
+                let end = start + lower[start..].find("</title>")?;
+                Some(
+                    body[start..end]
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                )
+            })
+            .filter(|t| !t.is_empty());
+        return match title {
+            Some(title) => format!("HTML error page: {}", title),
+            None => "HTML error page".to_string(),
+        };
+    }
+
+    if body.chars().count() > MAX_ERROR_BODY_CHARS {
+        let truncated: String = body.chars().take(MAX_ERROR_BODY_CHARS).collect();
+        format!("{}... (truncated)", truncated)
+    } else {
+        body.to_string()
+    }
+}
+
+/// Error text for a non-2xx response: the status plus the summarized body.
+pub fn http_status_error(status: hyper::StatusCode, body: &[u8]) -> String {
+    let summary = summarize_error_body(&String::from_utf8_lossy(body));
+    if summary.is_empty() {
+        format!("HTTP {}", status)
+    } else {
+        format!("HTTP {}: {}", status, summary)
+    }
+}
+
+/// TCP connector for upstream HTTPS clients, with the `[cloudcode]` connect
+/// timeout, happy eyeballs and keep-alive settings applied.
+pub fn upstream_http_connector(config: &CloudCodeConfig) -> HttpConnector {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    http.set_connect_timeout(
+        (config.connect_timeout_secs > 0).then(|| Duration::from_secs(config.connect_timeout_secs)),
+    );
+    http.set_happy_eyeballs_timeout(
+        (config.happy_eyeballs_timeout_ms > 0)
+            .then(|| Duration::from_millis(config.happy_eyeballs_timeout_ms)),
+    );
+    http.set_keepalive(config.tcp_keepalive_secs.map(Duration::from_secs));
+    http
+}
+
+/// Parse `[cloudcode] extra_headers`, dropping `Authorization` and anything
+/// that isn't a valid header.
+pub fn upstream_extra_headers(config: &CloudCodeConfig) -> Vec<(HeaderName, HeaderValue)> {
+    config
+        .extra_headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let parsed = HeaderName::from_bytes(name.as_bytes())
+                .ok()
+                .zip(HeaderValue::from_str(value).ok());
+            match parsed {
+                Some((name, _)) if name == hyper::header::AUTHORIZATION => {
+                    warn!("Ignoring extra_headers entry: Authorization cannot be overridden");
+                    None
+                }
+                None => {
+                    warn!(header = %name, "Ignoring invalid extra_headers entry");
+                    None
+                }
+                parsed => parsed,
+            }
+        })
+        .collect()
+}
+
+/// Merge extra headers into an upstream request, replacing same-named ones.
+pub fn apply_extra_headers(headers: &mut HeaderMap, extra: &[(HeaderName, HeaderValue)]) {
+    for (name, value) in extra {
+        headers.insert(name.clone(), value.clone());
+    }
+}
+
+/// Client builder with the `[cloudcode]` connection pool settings applied.
+/// Unset options keep hyper's defaults.
+pub fn upstream_client_builder(config: &CloudCodeConfig) -> hyper_util::client::legacy::Builder {
+    let mut builder = Client::builder(TokioExecutor::new());
+    builder.pool_timer(hyper_util::rt::TokioTimer::new());
+    builder.pool_idle_timeout(
+        (config.pool_idle_timeout_secs > 0)
+            .then(|| Duration::from_secs(config.pool_idle_timeout_secs)),
+    );
+    if let Some(max_idle) = config.pool_max_idle_per_host {
+        builder.pool_max_idle_per_host(max_idle);
+    }
+    if let Some(interval) = config.http2_keep_alive_interval_secs {
+        builder
+            .timer(hyper_util::rt::TokioTimer::new())
+            .http2_keep_alive_interval(Duration::from_secs(interval))
+            .http2_keep_alive_while_idle(true);
+    }
+    builder
+}
+
+/// `Content-Encoding` applied to large request bodies (`[cloudcode]
+/// request_compression`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestCompression {
+    Gzip,
+    Brotli,
+}
+
+impl RequestCompression {
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "gzip" => Some(Self::Gzip),
+            "br" => Some(Self::Brotli),
+            _ => None,
+        }
+    }
+
+    pub fn encoding(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Brotli => "br",
+        }
+    }
+
+    /// Compress with settings that favor speed, since this runs on every
+    /// large request.
+    pub fn compress(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        use std::io::Write;
+
+        match self {
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            Self::Brotli => {
+                let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                writer.write_all(body)?;
+                Ok(writer.into_inner())
+            }
+        }
+    }
+}
+
 /// HTTP client for Google Cloud Code API with retry logic and rate limiting.
 ///
 /// Features:
@@ -43,29 +246,69 @@ pub struct CloudCodeClient {
     request_semaphore: Arc<Semaphore>,
     last_request_time: Mutex<std::time::Instant>,
     api_timeout: Duration,
+    upstream_timeout: Option<Duration>,
     max_retries: u32,
     min_request_interval: Duration,
+    extra_headers: Vec<(HeaderName, HeaderValue)>,
+    request_compression: Option<RequestCompression>,
+    request_compression_min_bytes: usize,
+    /// Endpoints (scheme and host) that have refused a compressed body
+    compression_rejected: parking_lot::Mutex<HashSet<String>>,
+    /// Base URLs tried in order; [`ENDPOINTS`] outside tests
+    endpoints: Vec<String>,
 }
 
 impl CloudCodeClient {
     /// Create a new Cloud Code client with the given configuration.
     pub fn new(config: &CloudCodeConfig) -> Self {
-        let connector = hyper_rustls::HttpsConnectorBuilder::new()
-            .with_webpki_roots()
-            .https_only()
+        let endpoints = ENDPOINTS.iter().map(|e| e.to_string()).collect();
+        Self::build(config, endpoints, true)
+    }
+
+    /// Client that sends to `endpoints` instead of the Cloud Code API, over
+    /// plain HTTP if given, and doesn't retry 429s.
+    #[cfg(test)]
+    pub(crate) fn with_endpoints(endpoints: Vec<String>) -> Self {
+        let config = CloudCodeConfig {
+            max_retries: 0,
+            ..Default::default()
+        };
+        Self::build(&config, endpoints, false)
+    }
+
+    fn build(config: &CloudCodeConfig, endpoints: Vec<String>, https_only: bool) -> Self {
+        let builder = hyper_rustls::HttpsConnectorBuilder::new().with_webpki_roots();
+        let builder = if https_only {
+            builder.https_only()
+        } else {
+            builder.https_or_http()
+        };
+        let connector = builder
             .enable_http1()
             .enable_http2()
-            .build();
+            .wrap_connector(upstream_http_connector(config));
 
-        let client = Client::builder(TokioExecutor::new()).build(connector);
+        let client = upstream_client_builder(config).build(connector);
 
         Self {
             client,
             request_semaphore: Arc::new(Semaphore::new(config.max_concurrent_requests)),
             last_request_time: Mutex::new(std::time::Instant::now()),
             api_timeout: Duration::from_secs(config.timeout_secs),
+            upstream_timeout: config
+                .upstream_timeout_secs
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
             max_retries: config.max_retries,
             min_request_interval: Duration::from_millis(config.min_request_interval_ms),
+            extra_headers: upstream_extra_headers(config),
+            request_compression: config
+                .request_compression
+                .as_deref()
+                .and_then(RequestCompression::from_config),
+            request_compression_min_bytes: config.request_compression_min_bytes,
+            compression_rejected: parking_lot::Mutex::new(HashSet::new()),
+            endpoints,
         }
     }
 
@@ -91,13 +334,45 @@ impl CloudCodeClient {
         Ok(permit)
     }
 
+    /// Bound a whole upstream call, endpoint failover and retries included,
+    /// by `upstream_timeout_secs` when set.
+    async fn with_upstream_timeout<T>(
+        &self,
+        call: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let Some(limit) = self.upstream_timeout else {
+            return call.await;
+        };
+        match tokio::time::timeout(limit, call).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!(timeout_secs = limit.as_secs(), "Upstream call timed out");
+                Err(Error::Timeout(limit))
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "upstream_request", level = "debug", skip_all, fields(model = %model))]
     pub async fn send_request(
         &self,
         body: Bytes,
         access_token: &str,
         model: &str,
     ) -> Result<GenerateContentResponse> {
-        let _permit = self.acquire_request_permit().await?;
+        self.with_upstream_timeout(self.try_send_request(body, access_token, model))
+            .await
+    }
+
+    async fn try_send_request(
+        &self,
+        body: Bytes,
+        access_token: &str,
+        model: &str,
+    ) -> Result<GenerateContentResponse> {
+        let _permit = self
+            .acquire_request_permit()
+            .instrument(debug_span!("upstream_queue"))
+            .await?;
 
         let headers = super::request::build_headers(access_token, model, false);
         let start_time = std::time::Instant::now();
@@ -105,7 +380,7 @@ impl CloudCodeClient {
         let mut last_error = None;
         let mut capacity_retry_count = 0u32;
 
-        for (i, endpoint) in ENDPOINTS.iter().enumerate() {
+        for (i, endpoint) in self.endpoints.iter().enumerate() {
             let url = format!("{endpoint}/v1internal:generateContent");
 
             debug!(endpoint = %endpoint, attempt = i + 1, "Sending request to Cloud Code API");
@@ -311,13 +586,29 @@ impl CloudCodeClient {
         Err(last_error.unwrap_or_else(|| Error::Http("All endpoints failed".to_string())))
     }
 
+    /// Send a streaming request, returning once the upstream responds with
+    /// headers (`upstream_timeout_secs` bounds the wait for them).
+    #[tracing::instrument(name = "upstream_request", level = "debug", skip_all, fields(model = %model, streaming = true))]
     pub async fn send_streaming_request(
         &self,
         body: Bytes,
         access_token: &str,
         model: &str,
     ) -> Result<hyper::Response<hyper::body::Incoming>> {
-        let _permit = self.acquire_request_permit().await?;
+        self.with_upstream_timeout(self.try_send_streaming_request(body, access_token, model))
+            .await
+    }
+
+    async fn try_send_streaming_request(
+        &self,
+        body: Bytes,
+        access_token: &str,
+        model: &str,
+    ) -> Result<hyper::Response<hyper::body::Incoming>> {
+        let _permit = self
+            .acquire_request_permit()
+            .instrument(debug_span!("upstream_queue"))
+            .await?;
 
         let headers = super::request::build_headers(access_token, model, true);
         let start_time = std::time::Instant::now();
@@ -325,7 +616,7 @@ impl CloudCodeClient {
         let mut last_error = None;
         let mut capacity_retry_count = 0u32;
 
-        for (i, endpoint) in ENDPOINTS.iter().enumerate() {
+        for (i, endpoint) in self.endpoints.iter().enumerate() {
             let url = format!("{endpoint}/v1internal:streamGenerateContent?alt=sse");
 
             debug!(endpoint = %endpoint, attempt = i + 1, "Sending streaming request");
@@ -371,7 +662,7 @@ impl CloudCodeClient {
                             .ok()
                             .and_then(|b| String::from_utf8(b.to_bytes().to_vec()).ok())
                             .unwrap_or_default();
-                        let error_preview: String = error_body.chars().take(500).collect();
+                        let error_preview = summarize_error_body(&error_body);
 
                         if status == 429 && retry_count < self.max_retries {
                             retry_count += 1;
@@ -504,7 +795,7 @@ impl CloudCodeClient {
                             error_body = %error_preview,
                             "Non-success status, trying next endpoint"
                         );
-                        last_error = Some(map_http_error(status, &error_preview, Some(model)));
+                        last_error = Some(map_http_error(status, &error_body, Some(model)));
                         break;
                     }
                     Err(e) => {
@@ -519,6 +810,30 @@ impl CloudCodeClient {
         Err(last_error.unwrap_or_else(|| Error::Http("All endpoints failed".to_string())))
     }
 
+    /// Make a trivial upstream call (a model list fetch) over the pooled
+    /// connections, so they and the token stay warm while idle. Tries each
+    /// endpoint in order until one answers.
+    pub async fn ping(&self, access_token: &str, project_id: Option<&str>) -> Result<()> {
+        let headers = super::request::build_headers(access_token, "", false);
+        let body = Bytes::from(match project_id {
+            Some(pid) => serde_json::json!({ "project": pid }).to_string(),
+            None => "{}".to_string(),
+        });
+
+        let mut last_error = None;
+        for endpoint in &self.endpoints {
+            let url = format!("{endpoint}/v1internal:fetchAvailableModels");
+            match tokio::time::timeout(self.api_timeout, self.post(&url, &headers, body.clone()))
+                .await
+            {
+                Ok(Ok(_)) => return Ok(()),
+                Ok(Err(e)) => last_error = Some(e),
+                Err(_) => last_error = Some(Error::Timeout(self.api_timeout)),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| Error::Http("All endpoints failed".to_string())))
+    }
+
     async fn post(
         &self,
         url: &str,
@@ -535,9 +850,11 @@ impl CloudCodeClient {
                 .await
                 .map(|b| b.to_bytes())
                 .unwrap_or_default();
-            let message = String::from_utf8_lossy(&body_bytes).to_string();
-
-            return Err(map_http_error(status, &message, None));
+            return Err(map_http_error(
+                status,
+                &String::from_utf8_lossy(&body_bytes),
+                None,
+            ));
         }
 
         let body = response
@@ -553,16 +870,93 @@ impl CloudCodeClient {
         url: &str,
         headers: &[(Cow<'static, str>, Cow<'static, str>)],
         body: Bytes,
+    ) -> Result<hyper::Response<hyper::body::Incoming>> {
+        let Some((compressed, compression)) = self.compress_body(url, &body).await else {
+            return self.send_post(url, headers, body, None).await;
+        };
+
+        let response = self
+            .send_post(url, headers, compressed, Some(compression))
+            .await?;
+        let status = response.status();
+        if status != hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE
+            && status != hyper::StatusCode::BAD_REQUEST
+        {
+            return Ok(response);
+        }
+
+        // The endpoint may not take compressed bodies; resend this one plain
+        let retry = self.send_post(url, headers, body, None).await?;
+        // A 400 on the plain body too means the request itself is bad, not
+        // its encoding. Otherwise remember the endpoint, so every later
+        // request to it isn't sent twice.
+        if status == hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE
+            || retry.status() != hyper::StatusCode::BAD_REQUEST
+        {
+            let origin = url_origin(url);
+            warn!(
+                encoding = compression.encoding(),
+                status = status.as_u16(),
+                endpoint = %origin,
+                "Upstream rejected a compressed request body; sending uncompressed to it from now on"
+            );
+            self.compression_rejected.lock().insert(origin.to_string());
+        }
+        Ok(retry)
+    }
+
+    /// The compressed body, when request compression is on and worthwhile
+    /// for a body this size. Compression runs on a blocking thread, since
+    /// these bodies are large.
+    async fn compress_body(&self, url: &str, body: &Bytes) -> Option<(Bytes, RequestCompression)> {
+        let compression = self.request_compression?;
+        if body.len() < self.request_compression_min_bytes
+            || self.compression_rejected.lock().contains(url_origin(url))
+        {
+            return None;
+        }
+        let input = body.clone();
+        let compressed = tokio::task::spawn_blocking(move || compression.compress(&input))
+            .await
+            .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+        match compressed {
+            Ok(compressed) if compressed.len() < body.len() => {
+                debug!(
+                    encoding = compression.encoding(),
+                    original = body.len(),
+                    compressed = compressed.len(),
+                    "Compressed upstream request body"
+                );
+                Some((Bytes::from(compressed), compression))
+            }
+            Ok(_) => None,
+            Err(e) => {
+                debug!(error = %e, "Request compression failed; sending uncompressed");
+                None
+            }
+        }
+    }
+
+    async fn send_post(
+        &self,
+        url: &str,
+        headers: &[(Cow<'static, str>, Cow<'static, str>)],
+        body: Bytes,
+        compression: Option<RequestCompression>,
     ) -> Result<hyper::Response<hyper::body::Incoming>> {
         let mut req = Request::builder().method("POST").uri(url);
 
         for (name, value) in headers {
             req = req.header(name.as_ref(), value.as_ref());
         }
+        if let Some(compression) = compression {
+            req = req.header("Content-Encoding", compression.encoding());
+        }
 
-        let req = req
+        let mut req = req
             .body(Full::new(body))
             .map_err(|e| Error::Http(e.to_string()))?;
+        apply_extra_headers(req.headers_mut(), &self.extra_headers);
 
         self.client
             .request(req)
@@ -571,18 +965,41 @@ impl CloudCodeClient {
     }
 }
 
+/// Scheme and host of `url`, identifying the endpoint it is sent to.
+fn url_origin(url: &str) -> &str {
+    let host_start = url.find("://").map_or(0, |i| i + 3);
+    match url[host_start..].find('/') {
+        Some(i) => &url[..host_start + i],
+        None => url,
+    }
+}
+
 impl Default for CloudCodeClient {
     fn default() -> Self {
         Self::new(&crate::config::CloudCodeConfig::default())
     }
 }
 
-fn map_http_error(status: u16, message: &str, model: Option<&str>) -> Error {
+/// Map a non-2xx upstream response to an error. The status is classified on
+/// the full `body`; only its summary is kept as the error message.
+fn map_http_error(status: u16, body: &str, model: Option<&str>) -> Error {
+    let mut message = summarize_error_body(body);
+    // Keep the reason an account-fatal 403 is recognized by, even if the
+    // summary cut it off
+    if status == 403
+        && let Some((marker, _)) = ACCOUNT_FATAL_MARKERS
+            .iter()
+            .find(|(marker, _)| body.contains(marker) && !message.contains(marker))
+    {
+        message.push_str(&format!(" [{}]", marker));
+    }
+    let message = message.as_str();
+
     match status {
         401 => Error::Auth(crate::error::AuthError::TokenExpired),
         429 => {
             if let Some(model) = model {
-                let (_, reset_time) = parse_reset_time(message, 60000);
+                let (_, reset_time) = parse_reset_time(body, 60000);
                 Error::Api(ApiError::QuotaExhausted {
                     model: model.to_string(),
                     reset_time,
@@ -600,7 +1017,7 @@ fn map_http_error(status: u16, message: &str, model: Option<&str>) -> Error {
             size: 0,
             max: 10 * 1024 * 1024,
         }),
-        403 if message
+        403 if body
             .to_ascii_lowercase()
             .contains(GEMINI_DISABLED_ERROR_MARKER) =>
         {
@@ -672,4 +1089,279 @@ mod tests {
             other => panic!("expected 403 server error warning, got {other:?}"),
         }
     }
+
+    #[test]
+    fn test_map_http_error_reads_reset_time_past_truncation() {
+        let body = format!(
+            r#"{{"error":{{"code":429,"message":"{}","details":[{{"metadata":{{"quotaResetDelay":"42s"}}}}]}}}}"#,
+            "x".repeat(2000)
+        );
+        let Error::Api(ApiError::QuotaExhausted { reset_time, .. }) =
+            map_http_error(429, &body, Some("m"))
+        else {
+            panic!("expected quota exhausted");
+        };
+        assert_eq!(reset_time, parse_reset_time(&body, 60000).1);
+        assert_ne!(reset_time, parse_reset_time("", 60000).1);
+    }
+
+    #[test]
+    fn test_account_fatal_reason() {
+        let service_disabled = r#"{"error":{"code":403,"message":"Cloud Code API has not been used in project 123 before or it is disabled.","status":"PERMISSION_DENIED","details":[{"reason":"SERVICE_DISABLED"}]}}"#;
+        let error = map_http_error(403, service_disabled, Some("claude-sonnet-4-5"));
+        assert_eq!(
+            account_fatal_reason(&error),
+            Some("Cloud Code API is disabled for the account's project")
+        );
+
+        // Gemini being disabled doesn't make the account unusable for Claude
+        let gemini = r#"{"error":{"code":403,"message":"Gemini has been disabled in this account","status":"PERMISSION_DENIED"}}"#;
+        assert_eq!(
+            account_fatal_reason(&map_http_error(403, gemini, None)),
+            None
+        );
+
+        // A plain permission denial may be specific to the model or request
+        let denied = r#"{"error":{"code":403,"message":"The caller does not have permission","status":"PERMISSION_DENIED"}}"#;
+        assert_eq!(
+            account_fatal_reason(&map_http_error(403, denied, Some("claude-opus-4-6"))),
+            None
+        );
+
+        // Classified on the whole body, even where the message is truncated
+        let long = format!(
+            r#"{{"error":{{"code":403,"message":"{}","details":[{{"reason":"SERVICE_DISABLED"}}]}}}}"#,
+            "x".repeat(2000)
+        );
+        let error = map_http_error(403, &long, None);
+        assert!(error.to_string().len() < long.len());
+        assert_eq!(
+            account_fatal_reason(&error),
+            Some("Cloud Code API is disabled for the account's project")
+        );
+
+        assert_eq!(
+            account_fatal_reason(&map_http_error(500, "PERMISSION_DENIED", None)),
+            None
+        );
+        assert_eq!(
+            account_fatal_reason(&map_http_error(429, "quota", Some("m"))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extra_headers_cannot_override_authorization() {
+        let config = CloudCodeConfig {
+            extra_headers: [
+                ("authorization", "Bearer evil"),
+                ("X-Goog-User-Project", "my-project"),
+                ("User-Agent", "custom"),
+                ("bad header", "x"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+            ..Default::default()
+        };
+        let extra = upstream_extra_headers(&config);
+        assert_eq!(extra.len(), 2);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            hyper::header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer real"),
+        );
+        headers.insert(
+            hyper::header::USER_AGENT,
+            HeaderValue::from_static("antigravity"),
+        );
+        apply_extra_headers(&mut headers, &extra);
+
+        assert_eq!(headers["authorization"], "Bearer real");
+        assert_eq!(headers["user-agent"], "custom");
+        assert_eq!(headers["x-goog-user-project"], "my-project");
+    }
+
+    #[test]
+    fn test_request_compression_round_trip() {
+        use std::io::Read;
+
+        let body = br#"{"contents":[{"role":"user","parts":[{"text":"hello"}]}]}"#.repeat(200);
+
+        let gzip = RequestCompression::from_config("GZIP").unwrap();
+        let compressed = gzip.compress(&body).unwrap();
+        assert!(compressed.len() < body.len());
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+
+        let br = RequestCompression::from_config("br").unwrap();
+        let compressed = br.compress(&body).unwrap();
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(compressed.as_slice(), 4096)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+
+        assert_eq!(RequestCompression::from_config("deflate"), None);
+    }
+
+    #[tokio::test]
+    async fn test_compress_body_respects_min_size_and_rejection() {
+        let config = CloudCodeConfig {
+            request_compression: Some("gzip".to_string()),
+            request_compression_min_bytes: 1024,
+            ..Default::default()
+        };
+        let client = CloudCodeClient::new(&config);
+        let small = Bytes::from_static(&[b'a'; 512]);
+        let large = Bytes::from_static(&[b'a'; 4096]);
+
+        let url = "https://a.example.com/v1internal:generateContent";
+        let other = "https://b.example.com/v1internal:generateContent";
+
+        assert!(client.compress_body(url, &small).await.is_none());
+        assert!(client.compress_body(url, &large).await.is_some());
+
+        // Rejection is remembered per endpoint
+        client
+            .compression_rejected
+            .lock()
+            .insert("https://a.example.com".to_string());
+        assert!(client.compress_body(url, &large).await.is_none());
+        assert!(client.compress_body(other, &large).await.is_some());
+
+        let off = CloudCodeClient::new(&CloudCodeConfig::default());
+        assert!(off.compress_body(url, &large).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_post_raw_retries_uncompressed_after_rejection() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = format!("http://{}", listener.local_addr().unwrap());
+        let (encoding_tx, mut encoding_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let encoding_tx = encoding_tx.clone();
+                let service = hyper::service::service_fn(move |req: Request<_>| {
+                    let encoding = req
+                        .headers()
+                        .get("content-encoding")
+                        .map(|v| v.to_str().unwrap().to_string());
+                    // This upstream answers compressed bodies with a plain 400
+                    let status = if encoding.is_some() {
+                        hyper::StatusCode::BAD_REQUEST
+                    } else {
+                        hyper::StatusCode::OK
+                    };
+                    let _ = encoding_tx.send(encoding);
+                    async move {
+                        let mut response =
+                            hyper::Response::new(Full::new(Bytes::from_static(b"{}")));
+                        *response.status_mut() = status;
+                        Ok::<_, std::convert::Infallible>(response)
+                    }
+                });
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(hyper_util::rt::TokioIo::new(stream), service),
+                );
+            }
+        });
+
+        let config = CloudCodeConfig {
+            max_retries: 0,
+            request_compression: Some("gzip".to_string()),
+            request_compression_min_bytes: 1024,
+            ..Default::default()
+        };
+        let client = CloudCodeClient::build(&config, vec![upstream.clone()], false);
+        let url = format!("{}/v1internal:generateContent", upstream);
+        let body = Bytes::from(vec![b'a'; 4096]);
+
+        let response = client.post_raw(&url, &[], body.clone()).await.unwrap();
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(encoding_rx.recv().await.unwrap().as_deref(), Some("gzip"));
+        assert_eq!(encoding_rx.recv().await.unwrap(), None);
+
+        // The endpoint is remembered, so later requests go out plain once
+        let response = client.post_raw(&url, &[], body).await.unwrap();
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(encoding_rx.recv().await.unwrap(), None);
+        assert!(encoding_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_url_origin() {
+        assert_eq!(
+            url_origin("https://cloudcode-pa.googleapis.com/v1internal:generateContent"),
+            "https://cloudcode-pa.googleapis.com"
+        );
+        assert_eq!(url_origin("http://127.0.0.1:8080"), "http://127.0.0.1:8080");
+    }
+
+    #[tokio::test]
+    async fn test_ping_fetches_models_through_the_client() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = format!("http://{}", listener.local_addr().unwrap());
+        let (path_tx, mut path_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let path_tx = path_tx.clone();
+                let service = hyper::service::service_fn(move |req: Request<_>| {
+                    let _ = path_tx.send(req.uri().path().to_string());
+                    async {
+                        Ok::<_, std::convert::Infallible>(hyper::Response::new(Full::new(
+                            Bytes::from_static(b"{}"),
+                        )))
+                    }
+                });
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(hyper_util::rt::TokioIo::new(stream), service),
+                );
+            }
+        });
+
+        let client = CloudCodeClient::with_endpoints(vec![upstream]);
+        client.ping("token", Some("project")).await.unwrap();
+        assert_eq!(
+            path_rx.recv().await.as_deref(),
+            Some("/v1internal:fetchAvailableModels")
+        );
+    }
+
+    #[test]
+    fn test_summarize_error_body() {
+        let html = "<!DOCTYPE html>\n<html><head><title>Error 404 (Not\n Found)!!1</title></head><body>...</body></html>";
+        assert_eq!(
+            summarize_error_body(html),
+            "HTML error page: Error 404 (Not Found)!!1"
+        );
+        assert_eq!(
+            summarize_error_body("<html><body>oops</body></html>"),
+            "HTML error page"
+        );
+
+        let json = r#"{"error":{"code":400,"message":"Invalid argument"}}"#;
+        assert_eq!(summarize_error_body(json), json);
+
+        let long = "x".repeat(MAX_ERROR_BODY_CHARS + 10);
+        assert_eq!(
+            summarize_error_body(&long),
+            format!("{}... (truncated)", "x".repeat(MAX_ERROR_BODY_CHARS))
+        );
+
+        assert_eq!(
+            http_status_error(hyper::StatusCode::BAD_REQUEST, b"  "),
+            "HTTP 400 Bad Request"
+        );
+        assert_eq!(
+            http_status_error(hyper::StatusCode::FORBIDDEN, b"denied"),
+            "HTTP 403 Forbidden: denied"
+        );
+    }
 }
@@ -9,6 +9,6 @@ pub mod sse;
 pub use client::CloudCodeClient;
 pub use discover::discover_project_and_tier;
 pub use quota::{fetch_model_quotas, render_quota_display};
-pub use request::build_request;
+pub use request::{INTERLEAVED_THINKING_BETA, build_request, sends_interleaved_thinking};
 pub use response::parse_response;
 pub use sse::{SseParser, create_message_stop, format_sse_event};
@@ -8,6 +8,14 @@ use crate::models::{get_model_family, is_thinking_model};
 
 const SYSTEM_INSTRUCTION: &str = "You are Antigravity, a powerful agentic AI coding assistant designed by the Google Deepmind team working on Advanced Agentic Coding.You are pair programming with a USER to solve their coding task. The task may require creating a new codebase, modifying or debugging an existing codebase, or simply answering a question.**Absolute paths only****Proactiveness**";
 
+/// `anthropic-beta` flag sent upstream with requests for Claude thinking models.
+pub const INTERLEAVED_THINKING_BETA: &str = "interleaved-thinking-2025-05-14";
+
+/// Whether requests for `model` are sent with [`INTERLEAVED_THINKING_BETA`].
+pub fn sends_interleaved_thinking(model: &str) -> bool {
+    get_model_family(model) == "claude" && is_thinking_model(model)
+}
+
 /// Pre-allocated system instruction strings to avoid per-request allocation.
 static SYSTEM_INSTRUCTION_STRING: LazyLock<String> =
     LazyLock::new(|| SYSTEM_INSTRUCTION.to_string());
@@ -53,10 +61,10 @@ pub fn build_headers(
         Cow::Borrowed(r#"{"ideType":"IDE_UNSPECIFIED","platform":"PLATFORM_UNSPECIFIED","pluginType":"GEMINI"}"#),
     ));
 
-    if get_model_family(model) == "claude" && is_thinking_model(model) {
+    if sends_interleaved_thinking(model) {
         headers.push((
             Cow::Borrowed("anthropic-beta"),
-            Cow::Borrowed("interleaved-thinking-2025-05-14"),
+            Cow::Borrowed(INTERLEAVED_THINKING_BETA),
         ));
     }
 
@@ -8,6 +8,8 @@ use crate::models::get_model_family;
 
 pub struct SseParser {
     buffer: String,
+    /// Trailing bytes of a UTF-8 character split across frames
+    pending_bytes: Vec<u8>,
     model: String,
     message_id: String,
     has_emitted_start: bool,
@@ -32,6 +34,7 @@ impl SseParser {
     pub fn new(model: &str) -> Self {
         Self {
             buffer: String::with_capacity(4096),
+            pending_bytes: Vec::new(),
             model: model.to_string(),
             message_id: format!("msg_{:032x}", generate_random()),
             has_emitted_start: false,
@@ -46,49 +49,63 @@ impl SseParser {
         }
     }
 
-    /// Feed data to the parser and get any complete events
+    /// Feed raw frame bytes to the parser and get any complete events.
+    ///
+    /// Upstream frames can end in the middle of a multi-byte character, so an
+    /// incomplete trailing sequence is held back until the next frame instead
+    /// of being decoded (and replaced with U+FFFD) on its own.
+    pub fn feed_bytes(&mut self, data: &[u8]) -> Vec<StreamEvent> {
+        self.pending_bytes.extend_from_slice(data);
+
+        let split = self.pending_bytes.len() - incomplete_utf8_suffix_len(&self.pending_bytes);
+        let tail = self.pending_bytes.split_off(split);
+        let complete = std::mem::replace(&mut self.pending_bytes, tail);
+
+        self.feed(&String::from_utf8_lossy(&complete))
+    }
+
+    /// Feed data to the parser and get any complete events.
+    ///
+    /// Partial events are buffered until their terminating blank line arrives,
+    /// so an event split across calls is emitted once, intact.
     pub fn feed(&mut self, data: &str) -> Vec<StreamEvent> {
         self.buffer.push_str(data);
 
         let mut events = Vec::new();
 
         // Process complete events (handle both \n\n and \r\n\r\n as delimiters)
-        loop {
-            // Try to find the next event boundary
-            let boundary = self
-                .buffer
-                .find("\r\n\r\n")
-                .map(|p| (p, 4)) // CRLF version: skip 4 chars
-                .or_else(|| self.buffer.find("\n\n").map(|p| (p, 2))); // LF version: skip 2 chars
-
-            match boundary {
-                Some((pos, skip)) => {
-                    let line = self.buffer[..pos].to_string();
-                    self.buffer.drain(..pos + skip);
-
-                    if let Some(event) = self.parse_line(&line) {
-                        events.extend(event);
-                    }
-                }
-                None => break,
+        while let Some((pos, skip)) = find_event_boundary(&self.buffer) {
+            let block = self.buffer[..pos].to_string();
+            self.buffer.drain(..pos + skip);
+
+            if let Some(event) = self.parse_event(&block) {
+                events.extend(event);
             }
         }
 
         events
     }
 
-    /// Parse a single SSE line
-    fn parse_line(&mut self, line: &str) -> Option<Vec<StreamEvent>> {
-        // Handle data: prefix using strip_prefix
-        let data = if let Some(stripped) = line.strip_prefix("data: ") {
-            stripped
-        } else if let Some(stripped) = line.strip_prefix("data:") {
-            stripped
-        } else {
+    /// Parse a single SSE event block, joining its `data:` lines
+    fn parse_event(&mut self, block: &str) -> Option<Vec<StreamEvent>> {
+        // Other fields (`event:`, `id:`, `:` comments) carry nothing we use
+        let mut payload = String::new();
+        let mut has_data = false;
+        for line in block.lines() {
+            let Some(value) = line.strip_prefix("data:") else {
+                continue;
+            };
+            if has_data {
+                payload.push('\n');
+            }
+            payload.push_str(value.strip_prefix(' ').unwrap_or(value));
+            has_data = true;
+        }
+        if !has_data {
             return None;
-        };
+        }
 
-        let data = data.trim();
+        let data = payload.trim();
 
         // Handle [DONE]
         if data == "[DONE]" {
@@ -487,10 +504,34 @@ impl SseParser {
         events
     }
 
+    /// Finish a response the proxy stopped reading early, dropping any
+    /// partial event and reporting `max_tokens` so clients see it as cut off.
+    pub fn finish_truncated(mut self) -> Vec<StreamEvent> {
+        self.buffer.clear();
+        self.pending_bytes.clear();
+        self.stop_reason = Some("max_tokens".to_string());
+        self.finish()
+    }
+
     /// Finish parsing and get final events
-    pub fn finish(self) -> Vec<StreamEvent> {
+    pub fn finish(mut self) -> Vec<StreamEvent> {
         let mut events = Vec::new();
 
+        // Flush an event the stream ended without terminating with a blank line
+        let mut rest = std::mem::take(&mut self.buffer);
+        rest.push_str(&String::from_utf8_lossy(&self.pending_bytes));
+        if !rest.trim().is_empty()
+            && let Some(trailing) = self.parse_event(&rest)
+        {
+            // A trailing [DONE] would put message_stop ahead of message_delta;
+            // callers send their own message_stop after finishing
+            events.extend(
+                trailing
+                    .into_iter()
+                    .filter(|e| !matches!(e, StreamEvent::MessageStop)),
+            );
+        }
+
         // Close any open block
         if let Some(block_type) = self.current_block_type {
             // If in thinking block, emit signature first
@@ -527,6 +568,35 @@ impl SseParser {
     }
 }
 
+/// Find the earliest event terminator, returning its position and length
+fn find_event_boundary(buffer: &str) -> Option<(usize, usize)> {
+    let crlf = buffer.find("\r\n\r\n").map(|p| (p, 4));
+    let lf = buffer.find("\n\n").map(|p| (p, 2));
+    match (crlf, lf) {
+        (Some(a), Some(b)) => Some(if a.0 <= b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Number of trailing bytes that start a UTF-8 character but don't finish it
+fn incomplete_utf8_suffix_len(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - back];
+        // Skip continuation bytes until we reach the sequence's lead byte
+        if byte & 0xC0 == 0x80 {
+            continue;
+        }
+        let needed = match byte {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        return if needed > back { back } else { 0 };
+    }
+    0
+}
+
 fn generate_random() -> u128 {
     use std::time::{SystemTime, UNIX_EPOCH};
     SystemTime::now()
@@ -617,6 +687,98 @@ mod tests {
         assert!(has_message_delta);
     }
 
+    fn text_deltas(events: &[StreamEvent]) -> String {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                StreamEvent::ContentBlockDelta {
+                    delta: ContentDelta::Text { text },
+                    ..
+                } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sse_parser_event_split_across_feeds() {
+        let mut parser = SseParser::new("claude-sonnet-4-5");
+        let event = "data: {\"response\":{\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hello\"}]}}]}}\n\n";
+        let (first, second) = event.split_at(event.len() / 2);
+
+        // Nothing is emitted until the event is complete
+        assert!(parser.feed(first).is_empty());
+        let events = parser.feed(second);
+
+        assert!(matches!(events[0], StreamEvent::MessageStart { .. }));
+        assert_eq!(text_deltas(&events), "Hello");
+    }
+
+    #[test]
+    fn test_sse_parser_utf8_split_across_frames() {
+        let mut parser = SseParser::new("claude-sonnet-4-5");
+        let event = "data: {\"response\":{\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"héllo 👋\"}]}}]}}\n\n";
+        let bytes = event.as_bytes();
+        // Split inside the four-byte emoji
+        let split = event.find('👋').unwrap() + 2;
+
+        let mut events = parser.feed_bytes(&bytes[..split]);
+        events.extend(parser.feed_bytes(&bytes[split..]));
+
+        assert_eq!(text_deltas(&events), "héllo 👋");
+    }
+
+    #[test]
+    fn test_sse_parser_mixed_line_endings() {
+        let mut parser = SseParser::new("claude-sonnet-4-5");
+        // An LF-terminated event followed by a CRLF one must not be merged
+        let data = "data: {\"response\":{\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"a\"}]}}]}}\n\n\
+                    data: {\"response\":{\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"b\"}]}}]}}\r\n\r\n";
+
+        let events = parser.feed(data);
+
+        assert_eq!(text_deltas(&events), "ab");
+    }
+
+    #[test]
+    fn test_sse_parser_finish_flushes_unterminated_event() {
+        let mut parser = SseParser::new("claude-sonnet-4-5");
+        let data = "data: {\"response\":{\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"tail\"}]}}]}}\n";
+
+        assert!(parser.feed(data).is_empty());
+        let events = parser.finish();
+
+        assert_eq!(text_deltas(&events), "tail");
+        assert!(matches!(
+            events.last(),
+            Some(StreamEvent::MessageDelta { .. })
+        ));
+    }
+
+    #[test]
+    fn test_sse_parser_finish_truncated() {
+        let mut parser = SseParser::new("claude-sonnet-4-5");
+        let data = "data: {\"response\":{\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hi\"}]},\"finishReason\":\"STOP\"}]}}\n\ndata: {\"resp";
+        let _ = parser.feed(data);
+
+        let events = parser.finish_truncated();
+
+        // The open text block is closed and the partial event is dropped
+        assert!(matches!(
+            events[0],
+            StreamEvent::ContentBlockStop { index: 0 }
+        ));
+        match events.last() {
+            Some(StreamEvent::MessageDelta { delta, .. }) => {
+                assert_eq!(
+                    delta.stop_reason,
+                    Some(crate::format::StopReason::MaxTokens)
+                );
+            }
+            other => panic!("Expected MessageDelta, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_format_sse_event() {
         let event = StreamEvent::MessageStop;
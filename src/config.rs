@@ -1,7 +1,9 @@
+use indexmap::IndexMap;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::sync::{Arc, LazyLock};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, OnceLock};
 
 /// Error type for configuration loading
 #[derive(Debug)]
@@ -99,6 +101,12 @@ pub struct Config {
     pub cloudcode: CloudCodeConfig,
     #[serde(default)]
     pub mappings: MappingsConfig,
+    #[serde(default)]
+    pub defaults: DefaultsConfig,
+    #[serde(default)]
+    pub models: ModelsConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -107,21 +115,188 @@ pub struct ServerConfig {
     pub port: u16,
     #[serde(default = "default_host")]
     pub host: String,
+    /// Network interface (e.g. "tailscale0") whose IPv4 address to bind to
+    /// instead of `host`. `--host`/`--network` take precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    /// Address `interface` resolved to when the config was loaded
+    #[serde(skip)]
+    pub interface_ip: Option<String>,
     /// Optional API key for authenticating requests to /v1/* endpoints
     #[serde(default)]
     pub api_key: Option<String>,
     /// Request timeout in seconds (default: 300 = 5 minutes)
     #[serde(default = "default_request_timeout")]
     pub request_timeout_secs: u64,
+    /// Largest number of tool definitions a request may carry (default: 512)
+    #[serde(default = "default_max_tools")]
+    pub max_tools: usize,
+    /// Largest number of messages a request may carry (default: 10000)
+    #[serde(default = "default_max_messages")]
+    pub max_messages: usize,
+    /// Emulate OpenAI `n > 1` by issuing the request N times (off by default
+    /// since every choice costs a full upstream request)
+    #[serde(default)]
+    pub emulate_n: bool,
+    /// Exit at startup unless at least one account can obtain a valid token
+    #[serde(default)]
+    pub startup_health_check: bool,
+    /// Serve all non-streaming requests via the streaming endpoint, not just
+    /// thinking models
+    #[serde(default)]
+    pub prefer_streaming_endpoint: bool,
+    /// Text prepended to every request's system prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prefix: Option<String>,
+    /// Replaces every request's system prompt (takes precedence over `system_prefix`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_override: Option<String>,
+    /// Clamp each request's `max_tokens` to at most this value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_cap: Option<u32>,
+    /// Per-model `max_tokens` caps (glob pattern -> tokens). First match wins
+    /// over `max_tokens_cap`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub model_max_tokens_cap: Vec<ModelMaxTokensRule>,
+    /// Periodically make a cheap upstream call so the first request after a
+    /// lull doesn't pay for a cold connection or token refresh
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keepalive_ping_secs: Option<u64>,
+    /// API endpoint groups to serve (see [`ENDPOINT_GROUPS`]); the rest return
+    /// 404. Unset serves all of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled_endpoints: Option<Vec<String>>,
+    /// Emit a running-usage `message_delta` on Anthropic streams each time
+    /// roughly this many more output tokens have streamed (default: off)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_usage_interval: Option<u32>,
+    /// Exit once no `/v1/*` request has arrived for this many seconds
+    /// (default: off)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_shutdown_secs: Option<u64>,
+    /// Serve the `/debug/*` diagnostics endpoints. Only takes effect when
+    /// `api_key` is set, since they expose account state.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub debug_api: bool,
+    /// What to do with empty text blocks and empty `tool_result` content,
+    /// which the upstream rejects: `"normalize"` (default) drops or fills
+    /// them, `"reject"` fails the request with an `invalid_request_error`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub empty_content: Option<String>,
+    /// Requests per minute allowed for each client on the generation
+    /// endpoints. Clients are told apart by `metadata.user_id` (OpenAI
+    /// `user`), falling back to their IP (default: off)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_rate_limit_per_minute: Option<u32>,
+    /// Remove thinking blocks from `/v1/messages` responses, for clients that
+    /// only understand text and tool content. `X-AGCP-Strip-Thinking`
+    /// overrides it per request.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strip_thinking: bool,
+    /// Advertise the serving account's remaining capacity on successful
+    /// responses via `X-RateLimit-*` headers (default: off)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rate_limit_headers: bool,
+    /// How long `/account-limits` serves cached quota data before fetching
+    /// it again in the background; 0 fetches on every call (default: 60)
+    #[serde(default = "default_account_limits_ttl")]
+    pub account_limits_ttl_secs: u64,
+}
+
+/// Values accepted by `[server] empty_content`.
+pub const EMPTY_CONTENT_POLICIES: &[&str] = &["normalize", "reject"];
+
+/// Endpoint groups accepted by `[server] enabled_endpoints`.
+pub const ENDPOINT_GROUPS: &[&str] = &[
+    "messages",
+    "chat_completions",
+    "completions",
+    "responses",
+    "models",
+];
+
+/// A per-model `max_tokens` cap.
+///
+/// Example in `config.toml`:
+/// ```toml
+/// [[server.model_max_tokens_cap]]
+/// model = "claude-opus-*"
+/// max_tokens = 4096
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModelMaxTokensRule {
+    /// Glob pattern matched against the resolved model name
+    pub model: String,
+    /// Largest `max_tokens` allowed for matching models
+    pub max_tokens: u32,
+}
+
+impl ServerConfig {
+    /// The `max_tokens` cap for `model`: the first matching per-model cap,
+    /// else the global cap.
+    pub fn max_tokens_cap_for(&self, model: &str) -> Option<u32> {
+        self.model_max_tokens_cap
+            .iter()
+            .find(|rule| crate::models::glob_match(&rule.model, model))
+            .map(|rule| rule.max_tokens)
+            .or(self.max_tokens_cap)
+    }
+
+    /// Whether the endpoint group (one of [`ENDPOINT_GROUPS`]) is served.
+    pub fn endpoint_enabled(&self, group: &str) -> bool {
+        self.enabled_endpoints
+            .as_ref()
+            .is_none_or(|enabled| enabled.iter().any(|e| e.eq_ignore_ascii_case(group)))
+    }
+
+    /// Whether requests with empty content blocks are rejected rather than
+    /// normalized.
+    pub fn reject_empty_content(&self) -> bool {
+        self.empty_content
+            .as_deref()
+            .is_some_and(|policy| policy.eq_ignore_ascii_case("reject"))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct LoggingConfig {
     #[serde(default)]
     pub debug: bool,
+    /// Full `EnvFilter` directive string (e.g. `agcp=info,agcp::server=debug`).
+    /// Overrides `debug` when set; `RUST_LOG` still takes precedence.
+    #[serde(default)]
+    pub filter: Option<String>,
     /// Log full request/response bodies for debugging
     #[serde(default)]
     pub log_requests: bool,
+    /// Replace message text in logged bodies with length placeholders
+    #[serde(default)]
+    pub redact: bool,
+    /// Also redact tool definitions, tool call arguments, and tool results
+    #[serde(default)]
+    pub redact_tools: bool,
+    /// Also redact system prompts
+    #[serde(default)]
+    pub redact_system: bool,
+    /// Write logged request/response bodies to this file instead of the main
+    /// log. Setting it turns body logging on; relative paths are resolved
+    /// against the config directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_file: Option<PathBuf>,
+    /// Size in bytes at which `trace_file` is rotated to `<trace_file>.old`
+    /// (default: 50 MiB)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_file_max_bytes: Option<u64>,
+}
+
+impl LoggingConfig {
+    /// Resolved location of `trace_file`, if set.
+    pub fn trace_path(&self) -> Option<PathBuf> {
+        self.trace_file
+            .as_ref()
+            .filter(|path| !path.as_os_str().is_empty())
+            .map(|path| Config::dir().join(path))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -135,6 +310,22 @@ pub struct AccountsConfig {
     /// Enable model fallback on quota exhaustion
     #[serde(default)]
     pub fallback: bool,
+    /// Base delay in milliseconds before a fallback attempt (jittered, doubled
+    /// per chained attempt, capped at 5000)
+    #[serde(default = "default_fallback_backoff_ms")]
+    pub fallback_backoff_ms: u64,
+    /// Sticky strategy: rate limits shorter than this many seconds are waited
+    /// out on the active account instead of switching (default: 120)
+    #[serde(default = "default_sticky_rate_limit_grace_secs")]
+    pub sticky_rate_limit_grace_secs: u64,
+    /// UTC hour (0-23) at which per-account daily budgets reset (default: 0)
+    #[serde(default)]
+    pub budget_reset_hour_utc: u8,
+    /// Model (alias or glob) to account (ID prefix or email) pins, consulted
+    /// before the strategy in file order, first match winning; an unavailable
+    /// pinned account falls back to it
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub model_affinity: IndexMap<String, String>,
 }
 
 fn default_strategy() -> String {
@@ -145,16 +336,39 @@ fn default_quota_threshold() -> f64 {
     0.1
 }
 
+fn default_fallback_backoff_ms() -> u64 {
+    250
+}
+
+fn default_sticky_rate_limit_grace_secs() -> u64 {
+    120
+}
+
 impl Default for AccountsConfig {
     fn default() -> Self {
         Self {
             strategy: default_strategy(),
             quota_threshold: default_quota_threshold(),
             fallback: false,
+            fallback_backoff_ms: default_fallback_backoff_ms(),
+            sticky_rate_limit_grace_secs: default_sticky_rate_limit_grace_secs(),
+            budget_reset_hour_utc: 0,
+            model_affinity: IndexMap::new(),
         }
     }
 }
 
+impl AccountsConfig {
+    /// `model_affinity` as (model pattern, account) pairs in file order, the
+    /// form the account store matches against.
+    pub fn model_affinity_rules(&self) -> Vec<(String, String)> {
+        self.model_affinity
+            .iter()
+            .map(|(model, account)| (model.clone(), account.clone()))
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheConfig {
     /// Enable response caching for non-streaming requests
@@ -166,6 +380,28 @@ pub struct CacheConfig {
     /// Maximum number of cached responses (default: 100)
     #[serde(default = "default_cache_max_entries")]
     pub max_entries: usize,
+    /// Per-model TTL overrides (glob pattern -> seconds). First match wins.
+    #[serde(default)]
+    pub model_ttl: Vec<ModelTtlRule>,
+    /// Models whose responses are never cached (IDs, aliases, or globs)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_cache_models: Vec<String>,
+}
+
+/// A per-model cache TTL override.
+///
+/// Example in `config.toml`:
+/// ```toml
+/// [[cache.model_ttl]]
+/// model = "gemini-*-flash"
+/// ttl_seconds = 3600
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModelTtlRule {
+    /// Glob pattern matched against the resolved model name
+    pub model: String,
+    /// TTL in seconds for responses from matching models
+    pub ttl_seconds: u64,
 }
 
 fn default_cache_enabled() -> bool {
@@ -186,6 +422,8 @@ impl Default for CacheConfig {
             enabled: default_cache_enabled(),
             ttl_seconds: default_cache_ttl(),
             max_entries: default_cache_max_entries(),
+            model_ttl: Vec::new(),
+            no_cache_models: Vec::new(),
         }
     }
 }
@@ -203,6 +441,10 @@ pub struct CloudCodeConfig {
     /// API timeout in seconds (default: 120)
     #[serde(default = "default_api_timeout")]
     pub timeout_secs: u64,
+    /// Overall limit in seconds for one upstream call, including retries and
+    /// endpoint failover; a timeout is eligible for model fallback (default: off)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_timeout_secs: Option<u64>,
     /// Maximum retry attempts for failed requests (default: 5)
     #[serde(default = "default_max_retries")]
     pub max_retries: u32,
@@ -212,6 +454,55 @@ pub struct CloudCodeConfig {
     /// Minimum interval between requests in milliseconds (default: 50)
     #[serde(default = "default_min_request_interval")]
     pub min_request_interval_ms: u64,
+    /// Seconds to wait for a TCP connection to an upstream host before giving
+    /// up and trying the next endpoint; 0 disables the limit (default: 10)
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout_secs: u64,
+    /// Milliseconds to wait on the preferred address family (IPv6/IPv4)
+    /// before racing the other one; 0 disables happy eyeballs (default: 300)
+    #[serde(default = "default_happy_eyeballs_timeout")]
+    pub happy_eyeballs_timeout_ms: u64,
+    /// Maximum idle pooled connections kept per upstream host (default: unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Seconds an idle pooled connection is kept before closing; 0 keeps it
+    /// indefinitely (default: 90)
+    #[serde(default = "default_pool_idle_timeout")]
+    pub pool_idle_timeout_secs: u64,
+    /// TCP keep-alive interval in seconds for upstream connections (default: off)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp_keepalive_secs: Option<u64>,
+    /// HTTP/2 PING interval in seconds to keep upstream connections alive
+    /// (default: off)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http2_keep_alive_interval_secs: Option<u64>,
+    /// Diagnostic: also POST each non-streaming `generateContent` request body
+    /// to this URL in the background and log how the responses compare
+    /// (default: off). No credentials are sent to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror_url: Option<String>,
+    /// Compress large request bodies sent to Cloud Code with this
+    /// `Content-Encoding` (`"gzip"` or `"br"`). Off by default. A body an
+    /// endpoint rejects with 400 or 415 is resent uncompressed, and that
+    /// endpoint gets plain bodies for the rest of the process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_compression: Option<String>,
+    /// Smallest request body, in bytes, that `request_compression` applies
+    /// to (default: 65536)
+    #[serde(default = "default_request_compression_min_bytes")]
+    pub request_compression_min_bytes: usize,
+    /// Most SSE events buffered when a non-streaming response is assembled
+    /// from the streaming endpoint; past it the response is cut off with
+    /// `stop_reason: "max_tokens"` (default: 100000)
+    #[serde(default = "default_max_collected_events")]
+    pub max_collected_events: usize,
+    /// Most upstream bytes buffered for the same responses (default: 64 MiB)
+    #[serde(default = "default_max_collected_bytes")]
+    pub max_collected_bytes: usize,
+    /// Extra headers added to every Cloud Code request (replacing same-named
+    /// defaults). `Authorization` cannot be overridden.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra_headers: BTreeMap<String, String>,
 }
 
 fn default_api_timeout() -> u64 {
@@ -230,13 +521,53 @@ fn default_min_request_interval() -> u64 {
     50
 }
 
+fn default_connect_timeout() -> u64 {
+    10
+}
+
+fn default_happy_eyeballs_timeout() -> u64 {
+    300
+}
+
+fn default_pool_idle_timeout() -> u64 {
+    90
+}
+
+fn default_request_compression_min_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_max_collected_events() -> usize {
+    100_000
+}
+
+fn default_max_collected_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+/// Values accepted by `[cloudcode] request_compression`.
+pub const REQUEST_COMPRESSIONS: &[&str] = &["gzip", "br"];
+
 impl Default for CloudCodeConfig {
     fn default() -> Self {
         Self {
             timeout_secs: default_api_timeout(),
+            upstream_timeout_secs: None,
             max_retries: default_max_retries(),
             max_concurrent_requests: default_max_concurrent(),
             min_request_interval_ms: default_min_request_interval(),
+            connect_timeout_secs: default_connect_timeout(),
+            happy_eyeballs_timeout_ms: default_happy_eyeballs_timeout(),
+            pool_max_idle_per_host: None,
+            pool_idle_timeout_secs: default_pool_idle_timeout(),
+            tcp_keepalive_secs: None,
+            http2_keep_alive_interval_secs: None,
+            mirror_url: None,
+            request_compression: None,
+            request_compression_min_bytes: default_request_compression_min_bytes(),
+            max_collected_events: default_max_collected_events(),
+            max_collected_bytes: default_max_collected_bytes(),
+            extra_headers: BTreeMap::new(),
         }
     }
 }
@@ -265,6 +596,9 @@ pub struct MappingRule {
 /// preset = "balanced"
 /// background_task_model = "gemini-3-flash"
 ///
+/// [mappings.aliases]
+/// fast = "gemini-3-flash"
+///
 /// [[mappings.rules]]
 /// from = "gpt-4*"
 /// to = "gemini-3-pro-high"
@@ -277,6 +611,10 @@ pub struct MappingsConfig {
     /// Model used for background tasks (title generation, summaries, etc.)
     #[serde(default = "default_background_model")]
     pub background_task_model: String,
+    /// User-defined model aliases (alias -> model or built-in alias).
+    /// Checked before mapping rules and the built-in aliases.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
     /// Custom mapping rules (glob pattern -> target model). First match wins.
     #[serde(default)]
     pub rules: Vec<MappingRule>,
@@ -295,11 +633,141 @@ impl Default for MappingsConfig {
         Self {
             preset: default_preset(),
             background_task_model: default_background_model(),
+            aliases: BTreeMap::new(),
             rules: Vec::new(),
         }
     }
 }
 
+impl MappingsConfig {
+    /// Whether `model` is a target configured here (an alias or rule target,
+    /// or the background task model). Such targets may name models agcp has
+    /// no entry for, so they are sent upstream as is.
+    pub fn is_target(&self, model: &str) -> bool {
+        self.background_task_model == model
+            || self.rules.iter().any(|rule| rule.to == model)
+            || self
+                .aliases
+                .values()
+                .any(|target| crate::models::resolve_model_alias(target) == model)
+    }
+}
+
+/// Default sampling parameters applied to requests that omit them.
+///
+/// Precedence: an explicit value in the client request always wins, then
+/// the value configured here, then the upstream model's own default.
+///
+/// Example in `config.toml`:
+/// ```toml
+/// [defaults]
+/// temperature = 0.0
+/// max_tokens = 8192
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DefaultsConfig {
+    /// Temperature used when the client doesn't send one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling value used when the client doesn't send one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Top-k value used when the client doesn't send one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    /// Output token limit used when the client doesn't send one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+/// Server-side model availability controls.
+///
+/// Example in `config.toml`:
+/// ```toml
+/// [models]
+/// disabled = ["opus", "gemini-3-pro-*"]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ModelsConfig {
+    /// Models to reject after alias/mapping resolution (aliases and globs allowed)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled: Vec<String>,
+    /// What to do when a model doesn't resolve to a known model: `"reject"`
+    /// (default) fails with an `invalid_request_error` listing the available
+    /// models, `"fallback"` serves the request with `default_model`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unknown_model: Option<String>,
+    /// Model (or alias) used for unknown model names when
+    /// `unknown_model = "fallback"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_model: Option<String>,
+}
+
+/// Values accepted by `[models] unknown_model`.
+pub const UNKNOWN_MODEL_POLICIES: &[&str] = &["reject", "fallback"];
+
+impl ModelsConfig {
+    /// The model to serve unknown model names with, or `None` to reject them.
+    pub fn unknown_model_fallback(&self) -> Option<&str> {
+        let fallback = self
+            .unknown_model
+            .as_deref()
+            .is_some_and(|policy| policy.eq_ignore_ascii_case("fallback"));
+        if fallback {
+            self.default_model
+                .as_deref()
+                .map(crate::models::resolve_model_alias)
+        } else {
+            None
+        }
+    }
+}
+
+/// Webhooks for account pool events. All are off unless a URL is set.
+///
+/// Example in `config.toml`:
+/// ```toml
+/// [notifications]
+/// account_invalid_url = "https://hooks.example.com/agcp"
+/// low_quota_url = "https://hooks.example.com/agcp"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    /// POSTed a JSON payload when an account becomes invalid or recovers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_invalid_url: Option<String>,
+    /// POSTed a JSON payload when a model's quota on an account drops below
+    /// `low_quota_threshold` or is exhausted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low_quota_url: Option<String>,
+    /// Remaining quota fraction that counts as low (default: 0.1)
+    #[serde(default = "default_low_quota_threshold")]
+    pub low_quota_threshold: f64,
+    /// Minimum seconds between repeated alerts for the same account and model
+    /// (default: 3600)
+    #[serde(default = "default_low_quota_debounce")]
+    pub low_quota_debounce_secs: u64,
+}
+
+fn default_low_quota_threshold() -> f64 {
+    0.1
+}
+
+fn default_low_quota_debounce() -> u64 {
+    3600
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            account_invalid_url: None,
+            low_quota_url: None,
+            low_quota_threshold: default_low_quota_threshold(),
+            low_quota_debounce_secs: default_low_quota_debounce(),
+        }
+    }
+}
+
 fn default_port() -> u16 {
     8080
 }
@@ -312,17 +780,63 @@ fn default_request_timeout() -> u64 {
     300
 }
 
+fn default_max_tools() -> usize {
+    512
+}
+
+fn default_max_messages() -> usize {
+    10_000
+}
+
+fn default_account_limits_ttl() -> u64 {
+    60
+}
+
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             port: default_port(),
             host: default_host(),
+            interface: None,
+            interface_ip: None,
             api_key: None,
             request_timeout_secs: default_request_timeout(),
+            max_tools: default_max_tools(),
+            max_messages: default_max_messages(),
+            emulate_n: false,
+            startup_health_check: false,
+            prefer_streaming_endpoint: false,
+            system_prefix: None,
+            system_override: None,
+            max_tokens_cap: None,
+            model_max_tokens_cap: Vec::new(),
+            keepalive_ping_secs: None,
+            enabled_endpoints: None,
+            stream_usage_interval: None,
+            idle_shutdown_secs: None,
+            debug_api: false,
+            empty_content: None,
+            client_rate_limit_per_minute: None,
+            strip_thinking: false,
+            rate_limit_headers: false,
+            account_limits_ttl_secs: default_account_limits_ttl(),
         }
     }
 }
 
+/// Config file chosen with the global `--config` flag.
+static CONFIG_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `path` as the config file for this process (the global `--config` flag).
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH_OVERRIDE.set(path);
+}
+
+/// The config file set with `--config`, if any.
+pub fn config_path_override() -> Option<&'static Path> {
+    CONFIG_PATH_OVERRIDE.get().map(PathBuf::as_path)
+}
+
 impl Config {
     pub fn dir() -> PathBuf {
         dirs::config_dir()
@@ -330,21 +844,48 @@ impl Config {
             .join("agcp")
     }
 
+    /// Config file location: `--config`, then `AGCP_CONFIG`, then
+    /// `config.toml` in [`Config::dir`].
     pub fn path() -> PathBuf {
-        Self::dir().join("config.toml")
+        Self::explicit_path().unwrap_or_else(|| Self::dir().join("config.toml"))
+    }
+
+    /// Config file named with `--config` or `AGCP_CONFIG`, if any
+    fn explicit_path() -> Option<PathBuf> {
+        if let Some(path) = config_path_override() {
+            return Some(path.to_path_buf());
+        }
+        std::env::var_os("AGCP_CONFIG")
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
     }
 
     pub fn load() -> Result<Self, ConfigError> {
-        let path = Self::path();
+        match Self::explicit_path() {
+            Some(path) => Self::load_from(path, true),
+            None => Self::load_from(Self::dir().join("config.toml"), false),
+        }
+    }
+
+    /// Load and validate the config at `path`. A missing file means defaults,
+    /// unless the user named it explicitly.
+    fn load_from(path: PathBuf, explicit: bool) -> Result<Self, ConfigError> {
+        if explicit && !path.exists() {
+            return Err(ConfigError::ReadError {
+                path,
+                source: std::io::Error::from(std::io::ErrorKind::NotFound),
+            });
+        }
         if path.exists() {
             let content = std::fs::read_to_string(&path).map_err(|e| ConfigError::ReadError {
                 path: path.clone(),
                 source: e,
             })?;
-            let config: Config = toml::from_str(&content).map_err(|e| ConfigError::ParseError {
-                path: path.clone(),
-                source: e,
-            })?;
+            let mut config: Config =
+                toml::from_str(&content).map_err(|e| ConfigError::ParseError {
+                    path: path.clone(),
+                    source: e,
+                })?;
 
             // Validate strategy
             let valid_strategies = vec![
@@ -374,6 +915,168 @@ impl Config {
                 });
             }
 
+            if config.accounts.fallback_backoff_ms > 5000 {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "accounts.fallback_backoff_ms".to_string(),
+                    value: config.accounts.fallback_backoff_ms.to_string(),
+                    valid_values: vec!["0 to 5000".to_string()],
+                });
+            }
+
+            if config.accounts.budget_reset_hour_utc > 23 {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "accounts.budget_reset_hour_utc".to_string(),
+                    value: config.accounts.budget_reset_hour_utc.to_string(),
+                    valid_values: vec!["0 to 23".to_string()],
+                });
+            }
+
+            if let Some(filter) = &config.logging.filter
+                && tracing_subscriber::EnvFilter::try_new(filter).is_err()
+            {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "logging.filter".to_string(),
+                    value: filter.clone(),
+                    valid_values: vec![
+                        "an EnvFilter directive like \"agcp=info,agcp::server=debug\"".to_string(),
+                    ],
+                });
+            }
+
+            if config.server.client_rate_limit_per_minute == Some(0) {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "server.client_rate_limit_per_minute".to_string(),
+                    value: "0".to_string(),
+                    valid_values: vec!["1 or more (omit to disable)".to_string()],
+                });
+            }
+
+            if let Some(endpoint) = config.server.enabled_endpoints.iter().flatten().find(|e| {
+                !ENDPOINT_GROUPS
+                    .iter()
+                    .any(|group| group.eq_ignore_ascii_case(e))
+            }) {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "server.enabled_endpoints".to_string(),
+                    value: endpoint.clone(),
+                    valid_values: ENDPOINT_GROUPS.iter().map(|g| g.to_string()).collect(),
+                });
+            }
+
+            if let Some(encoding) = &config.cloudcode.request_compression
+                && !REQUEST_COMPRESSIONS
+                    .iter()
+                    .any(|e| e.eq_ignore_ascii_case(encoding))
+            {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "cloudcode.request_compression".to_string(),
+                    value: encoding.clone(),
+                    valid_values: REQUEST_COMPRESSIONS.iter().map(|e| e.to_string()).collect(),
+                });
+            }
+
+            if let Some(policy) = &config.server.empty_content
+                && !EMPTY_CONTENT_POLICIES
+                    .iter()
+                    .any(|p| p.eq_ignore_ascii_case(policy))
+            {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "server.empty_content".to_string(),
+                    value: policy.clone(),
+                    valid_values: EMPTY_CONTENT_POLICIES
+                        .iter()
+                        .map(|p| p.to_string())
+                        .collect(),
+                });
+            }
+
+            if let Some(policy) = &config.models.unknown_model
+                && !UNKNOWN_MODEL_POLICIES
+                    .iter()
+                    .any(|p| p.eq_ignore_ascii_case(policy))
+            {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "models.unknown_model".to_string(),
+                    value: policy.clone(),
+                    valid_values: UNKNOWN_MODEL_POLICIES
+                        .iter()
+                        .map(|p| p.to_string())
+                        .collect(),
+                });
+            }
+
+            // "fallback" needs a default_model that names a known model
+            if config
+                .models
+                .unknown_model
+                .as_deref()
+                .is_some_and(|policy| policy.eq_ignore_ascii_case("fallback"))
+                && config
+                    .models
+                    .unknown_model_fallback()
+                    .and_then(crate::models::Model::from_id)
+                    .is_none()
+            {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "models.default_model".to_string(),
+                    value: config.models.default_model.unwrap_or_default(),
+                    valid_values: crate::models::all_target_models()
+                        .iter()
+                        .map(|m| m.to_string())
+                        .collect(),
+                });
+            }
+
+            // Validate request defaults
+            if let Some(temp) = config.defaults.temperature
+                && !(0.0..=2.0).contains(&temp)
+            {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "defaults.temperature".to_string(),
+                    value: temp.to_string(),
+                    valid_values: vec!["0.0 to 2.0".to_string()],
+                });
+            }
+            if let Some(top_p) = config.defaults.top_p
+                && !(0.0..=1.0).contains(&top_p)
+            {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "defaults.top_p".to_string(),
+                    value: top_p.to_string(),
+                    valid_values: vec!["0.0 to 1.0".to_string()],
+                });
+            }
+            if let Some(max_tokens) = config.defaults.max_tokens
+                && !(1..=200_000).contains(&max_tokens)
+            {
+                return Err(ConfigError::InvalidValue {
+                    path,
+                    field: "defaults.max_tokens".to_string(),
+                    value: max_tokens.to_string(),
+                    valid_values: vec!["1 to 200000".to_string()],
+                });
+            }
+
+            // A missing interface is reported when the server starts, so other
+            // commands keep working with `host`
+            config.server.interface_ip = config
+                .server
+                .interface
+                .as_deref()
+                .and_then(|name| resolve_interface_ip(name).ok())
+                .map(|ip| ip.to_string());
+
             Ok(config)
         } else {
             Ok(Self::default())
@@ -412,8 +1115,13 @@ impl Config {
         self.server.port
     }
 
+    /// Address to bind to: the configured interface's address if it
+    /// resolved, otherwise `server.host`
     pub fn host(&self) -> &str {
-        &self.server.host
+        self.server
+            .interface_ip
+            .as_deref()
+            .unwrap_or(&self.server.host)
     }
 
     pub fn with_overrides(mut self, port: Option<u16>, host: Option<String>, debug: bool) -> Self {
@@ -422,6 +1130,7 @@ impl Config {
         }
         if let Some(h) = host {
             self.server.host = h;
+            self.server.interface_ip = None;
         }
         if debug {
             self.logging.debug = true;
@@ -434,6 +1143,31 @@ impl Config {
     }
 }
 
+/// IPv4 address of the network interface called `name`, or an error naming
+/// the interfaces that do exist
+pub fn resolve_interface_ip(name: &str) -> Result<std::net::Ipv4Addr, String> {
+    let interfaces = if_addrs::get_if_addrs()
+        .map_err(|e| format!("Failed to list network interfaces: {}", e))?;
+    let mut found = false;
+    for iface in interfaces.iter().filter(|i| i.name == name) {
+        found = true;
+        if let std::net::IpAddr::V4(ip) = iface.ip() {
+            return Ok(ip);
+        }
+    }
+    if found {
+        return Err(format!("Network interface '{}' has no IPv4 address", name));
+    }
+    let mut names: Vec<&str> = interfaces.iter().map(|i| i.name.as_str()).collect();
+    names.sort_unstable();
+    names.dedup();
+    Err(format!(
+        "Network interface '{}' not found (available: {})",
+        name,
+        names.join(", ")
+    ))
+}
+
 /// Get the path to the daemon address file (`~/.config/agcp/agcp.addr`).
 /// This file contains the `host:port` the daemon is actually listening on,
 /// which may differ from the config file if `--port` was used at startup.
@@ -539,6 +1273,25 @@ mod tests {
         assert!(!config.logging.debug);
     }
 
+    #[test]
+    fn test_model_affinity_keeps_file_order() {
+        let config: Config = toml::from_str(
+            r#"
+            [accounts.model_affinity]
+            opus = "b@example.com"
+            "claude-*" = "a@example.com"
+            "#,
+        )
+        .unwrap();
+        let patterns: Vec<&str> = config
+            .accounts
+            .model_affinity
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(patterns, ["opus", "claude-*"]);
+    }
+
     #[test]
     fn test_config_accessors() {
         let config = Config::default();
@@ -553,6 +1306,29 @@ mod tests {
         assert!(path.to_string_lossy().ends_with("config.toml"));
     }
 
+    #[test]
+    fn test_load_missing_explicit_path_is_an_error() {
+        let path = std::env::temp_dir().join("agcp-missing-config-test.toml");
+        let _ = std::fs::remove_file(&path);
+
+        assert!(matches!(
+            Config::load_from(path.clone(), true),
+            Err(ConfigError::ReadError { .. })
+        ));
+        assert!(Config::load_from(path, false).is_ok());
+    }
+
+    #[test]
+    fn test_endpoint_enabled() {
+        let mut server = ServerConfig::default();
+        assert!(ENDPOINT_GROUPS.iter().all(|g| server.endpoint_enabled(g)));
+
+        server.enabled_endpoints = Some(vec!["Messages".to_string()]);
+        assert!(server.endpoint_enabled("messages"));
+        assert!(!server.endpoint_enabled("chat_completions"));
+        assert!(!server.endpoint_enabled("responses"));
+    }
+
     #[test]
     fn test_config_dir() {
         let dir = Config::dir();
@@ -15,6 +15,10 @@ pub enum Error {
     #[error("json error: {0}")]
     Json(#[from] serde_json::Error),
 
+    /// A request body that failed to deserialize, described by JSON path
+    #[error("invalid request body: {0}")]
+    InvalidBody(String),
+
     #[error("http error: {0}")]
     Http(String),
 
@@ -37,11 +41,35 @@ impl Error {
             Error::Api(ApiError::CapacityExhausted) => {
                 Some("Model is overloaded, try again in a few minutes")
             }
+            Error::Auth(AuthError::NoAccounts) => Some("Run 'agcp login' to add an account"),
             Error::Api(ApiError::RateLimited { .. }) => Some("Too many requests, slow down"),
             Error::Timeout(_) => Some("Check your internet connection or try again"),
             _ => None,
         }
     }
+
+    /// Stable machine-readable code for this error, emitted as `error.code`.
+    ///
+    /// Unlike messages, these never change wording, so clients can branch on them.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Auth(AuthError::TokenExpired) => "token_expired",
+            Error::Auth(AuthError::RefreshFailed(_)) => "token_refresh_failed",
+            Error::Auth(AuthError::OAuthFailed(_)) => "auth_failed",
+            Error::Auth(AuthError::NoAccounts) => "no_accounts",
+            Error::Api(ApiError::RateLimited { .. }) => "rate_limited",
+            Error::Api(ApiError::QuotaExhausted { .. }) => "quota_exhausted",
+            Error::Api(ApiError::InvalidRequest { .. }) => "invalid_request",
+            Error::Api(ApiError::ServerError { status, .. }) if *status >= 500 => "upstream_5xx",
+            Error::Api(ApiError::ServerError { .. }) => "upstream_4xx",
+            Error::Api(ApiError::CapacityExhausted) => "capacity_exhausted",
+            Error::Api(ApiError::RequestTooLarge { .. }) => "request_too_large",
+            Error::Io(_) => "io_error",
+            Error::Json(_) | Error::InvalidBody(_) => "invalid_json",
+            Error::Http(_) => "upstream_unreachable",
+            Error::Timeout(_) => "timeout",
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -54,6 +82,9 @@ pub enum AuthError {
 
     #[error("OAuth flow failed: {0}")]
     OAuthFailed(String),
+
+    #[error("no enabled accounts available")]
+    NoAccounts,
 }
 
 #[derive(Debug, Error)]
@@ -144,4 +175,33 @@ mod tests {
         let display = format!("{}", err);
         assert!(display.contains("network error"));
     }
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(Error::Auth(AuthError::NoAccounts).code(), "no_accounts");
+        assert_eq!(
+            Error::Api(ApiError::QuotaExhausted {
+                model: "gemini-3-flash".to_string(),
+                reset_time: "1h".to_string(),
+            })
+            .code(),
+            "quota_exhausted"
+        );
+        assert_eq!(
+            Error::Api(ApiError::ServerError {
+                status: 503,
+                message: "unavailable".to_string(),
+            })
+            .code(),
+            "upstream_5xx"
+        );
+        assert_eq!(
+            Error::Api(ApiError::ServerError {
+                status: 404,
+                message: "not found".to_string(),
+            })
+            .code(),
+            "upstream_4xx"
+        );
+    }
 }
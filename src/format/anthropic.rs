@@ -4,7 +4,9 @@ use serde::{Deserialize, Serialize};
 pub struct MessagesRequest {
     pub model: String,
     pub messages: Vec<Message>,
-    pub max_tokens: u32,
+    /// `None` when the client omitted it; filled from `[defaults]` before validation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
     #[serde(default)]
     pub stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -23,6 +25,12 @@ pub struct MessagesRequest {
     pub tool_choice: Option<ToolChoice>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub thinking: Option<ThinkingConfig>,
+    /// Sampling seed for reproducible output; forwarded upstream, which may ignore it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    /// Caller-supplied metadata; `user_id` keys per-client rate limits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<RequestMetadata>,
     /// Internal: structured output schema to pass through to Google.
     /// Not part of Anthropic's public API, used for OpenAI json_schema forwarding.
     #[serde(skip)]
@@ -30,6 +38,28 @@ pub struct MessagesRequest {
     /// Internal: number of candidates to generate (for OpenAI n parameter).
     #[serde(skip)]
     pub candidate_count: Option<u32>,
+    /// Internal: per-request `X-AGCP-Strip-Thinking` override of
+    /// `[server] strip_thinking`.
+    #[serde(skip)]
+    pub strip_thinking: Option<bool>,
+}
+
+/// The request `metadata` object.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RequestMetadata {
+    /// Opaque id for the end user behind the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+}
+
+impl MessagesRequest {
+    /// The non-empty `metadata.user_id`, if the client sent one.
+    pub fn user_id(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|m| m.user_id.as_deref())
+            .filter(|id| !id.is_empty())
+    }
 }
 
 /// Internal response format for passing structured output config to Google.
@@ -0,0 +1,239 @@
+//! OpenAI legacy Completions API (`POST /v1/completions`) types and conversion.
+//!
+//! A prompt is sent upstream as a single user message, so the existing
+//! Messages pipeline serves it unchanged.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::anthropic::{ContentBlock, MessagesRequest, MessagesResponse};
+use super::openai::{ChatCompletionRequest, ChatContent, ChatMessage, ChatUsage, StopSequence};
+use super::openai_convert::{chat_usage, openai_to_anthropic};
+
+/// OpenAI legacy Completions request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionRequest {
+    pub model: String,
+    pub prompt: Prompt,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<StopSequence>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    /// Prepend the prompt to the completion text
+    #[serde(default)]
+    pub echo: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Prompt {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Prompt {
+    /// The prompt text, or `None` for a batch of several prompts.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Prompt::Single(text) => Some(text),
+            Prompt::Multiple(prompts) => match prompts.as_slice() {
+                [text] => Some(text),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// OpenAI legacy Completions response (also used for streamed chunks)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<CompletionChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionChoice {
+    pub text: String,
+    pub index: u32,
+    pub logprobs: Option<serde_json::Value>,
+    pub finish_reason: Option<String>,
+}
+
+/// Convert a Completions request into a single-user-message Messages request.
+///
+/// Returns `None` when the prompt is a batch, which is not supported.
+pub fn completion_to_anthropic(request: &CompletionRequest) -> Option<MessagesRequest> {
+    let prompt = request.prompt.text()?;
+
+    let chat_request = ChatCompletionRequest {
+        model: request.model.clone(),
+        messages: vec![ChatMessage {
+            role: "user".to_string(),
+            content: Some(ChatContent::Text(prompt.to_string())),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }],
+        max_tokens: request.max_tokens,
+        max_completion_tokens: None,
+        temperature: request.temperature,
+        top_p: request.top_p,
+        stop: request.stop.clone(),
+        stream: request.stream,
+        tools: None,
+        tool_choice: None,
+        n: None,
+        user: request.user.clone(),
+        response_format: None,
+        seed: request.seed,
+    };
+
+    Some(openai_to_anthropic(&chat_request))
+}
+
+/// Convert a Messages response into a Completions response.
+pub fn anthropic_to_completion(
+    response: &MessagesResponse,
+    model: &str,
+    request_id: &str,
+    echo_prompt: Option<&str>,
+) -> CompletionResponse {
+    let mut text: String = echo_prompt.unwrap_or_default().to_string();
+    for block in &response.content {
+        if let ContentBlock::Text { text: part, .. } = block {
+            text.push_str(part);
+        }
+    }
+
+    CompletionResponse {
+        id: format!("cmpl-{}", request_id),
+        object: "text_completion".to_string(),
+        created: now_secs(),
+        model: model.to_string(),
+        choices: vec![CompletionChoice {
+            text,
+            index: 0,
+            logprobs: None,
+            finish_reason: response.stop_reason.map(|r| r.to_openai_str().to_string()),
+        }],
+        usage: Some(chat_usage(&response.usage)),
+    }
+}
+
+/// Build one streamed `text_completion.chunk`.
+pub fn completion_chunk(
+    request_id: &str,
+    model: &str,
+    created: i64,
+    text: String,
+    finish_reason: Option<String>,
+) -> CompletionResponse {
+    CompletionResponse {
+        id: format!("cmpl-{}", request_id),
+        object: "text_completion.chunk".to_string(),
+        created,
+        model: model.to_string(),
+        choices: vec![CompletionChoice {
+            text,
+            index: 0,
+            logprobs: None,
+            finish_reason,
+        }],
+        usage: None,
+    }
+}
+
+pub fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::anthropic::{MessageContent, Role, StopReason, Usage};
+
+    fn request(prompt: serde_json::Value) -> CompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "gemini-3-flash",
+            "prompt": prompt,
+            "max_tokens": 16,
+            "stop": "\n",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_prompt_becomes_single_user_message() {
+        for prompt in [serde_json::json!("Say hi"), serde_json::json!(["Say hi"])] {
+            let messages_request = completion_to_anthropic(&request(prompt)).unwrap();
+            assert_eq!(messages_request.messages.len(), 1);
+            assert_eq!(messages_request.messages[0].role, Role::User);
+            assert!(matches!(
+                &messages_request.messages[0].content,
+                MessageContent::Text(text) if text == "Say hi"
+            ));
+            assert_eq!(messages_request.max_tokens, Some(16));
+            assert_eq!(
+                messages_request.stop_sequences,
+                Some(vec!["\n".to_string()])
+            );
+        }
+    }
+
+    #[test]
+    fn test_batched_prompts_rejected() {
+        assert!(completion_to_anthropic(&request(serde_json::json!(["a", "b"]))).is_none());
+    }
+
+    #[test]
+    fn test_response_shape() {
+        let response = MessagesResponse {
+            id: "msg_1".to_string(),
+            response_type: "message".to_string(),
+            role: Role::Assistant,
+            content: vec![ContentBlock::Text {
+                text: " world".to_string(),
+                cache_control: None,
+            }],
+            model: "gemini-3-flash".to_string(),
+            stop_reason: Some(StopReason::MaxTokens),
+            stop_sequence: None,
+            usage: Usage {
+                input_tokens: 3,
+                output_tokens: 2,
+                ..Default::default()
+            },
+        };
+
+        let completion = anthropic_to_completion(&response, "gemini-3-flash", "req", Some("Hello"));
+        assert_eq!(completion.object, "text_completion");
+        assert_eq!(completion.id, "cmpl-req");
+        assert_eq!(completion.choices[0].text, "Hello world");
+        assert_eq!(
+            completion.choices[0].finish_reason.as_deref(),
+            Some("length")
+        );
+        assert_eq!(completion.usage.unwrap().total_tokens, 5);
+    }
+}
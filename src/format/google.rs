@@ -110,6 +110,8 @@ pub struct GenerationConfig {
     pub response_schema: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub candidate_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
 }
 
 // Claude uses snake_case, Gemini uses camelCase with thinkingBudget
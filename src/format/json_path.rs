@@ -0,0 +1,96 @@
+//! Request body parsing that reports where in the JSON a value failed to
+//! deserialize (e.g. `messages[2].content`), instead of a line and column.
+
+use serde::de::DeserializeOwned;
+
+/// Deserialize a request body, describing failures as `path: reason`.
+pub fn parse_json_body<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    let value = serde_path_to_error::deserialize(&mut deserializer).map_err(describe_error)?;
+    deserializer.end().map_err(|e| strip_location(&e))?;
+    Ok(value)
+}
+
+/// Like [`parse_json_body`], reading the body incrementally from `reader`.
+pub fn parse_json_reader<T: DeserializeOwned, R: std::io::Read>(reader: R) -> Result<T, String> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let value = serde_path_to_error::deserialize(&mut deserializer).map_err(describe_error)?;
+    deserializer.end().map_err(|e| strip_location(&e))?;
+    Ok(value)
+}
+
+fn describe_error(error: serde_path_to_error::Error<serde_json::Error>) -> String {
+    let path = error.path().to_string();
+    let message = strip_location(error.inner());
+    if path == "." {
+        message
+    } else {
+        format!("{}: {}", path, message)
+    }
+}
+
+/// serde_json's message without its trailing " at line N column M".
+fn strip_location(error: &serde_json::Error) -> String {
+    let message = error.to_string();
+    let location = format!(" at line {} column {}", error.line(), error.column());
+    message
+        .strip_suffix(&location)
+        .map(str::to_string)
+        .unwrap_or(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::MessagesRequest;
+
+    #[test]
+    fn test_error_names_nested_field() {
+        let body = br#"{
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 10,
+            "messages": [
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": "hello"},
+                {"role": "user", "content": 5}
+            ]
+        }"#;
+        let err = parse_json_body::<MessagesRequest>(body).unwrap_err();
+        assert!(err.starts_with("messages[2].content: "), "got: {}", err);
+        assert!(!err.contains("at line"));
+    }
+
+    #[test]
+    fn test_error_names_object_missing_field() {
+        let body = br#"{"model":"m","max_tokens":1,"messages":[{"content":"hi"}]}"#;
+        let err = parse_json_body::<MessagesRequest>(body).unwrap_err();
+        assert!(
+            err.starts_with("messages[0]: missing field `role`"),
+            "got: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_reader_matches_slice_errors() {
+        let body = br#"{"model":"m","max_tokens":1,"messages":[{"role":"user","content":5}]}"#;
+        let from_reader = parse_json_reader::<MessagesRequest, _>(&body[..]).unwrap_err();
+        assert_eq!(
+            from_reader,
+            parse_json_body::<MessagesRequest>(body).unwrap_err()
+        );
+        assert!(from_reader.starts_with("messages[0].content: "));
+
+        let trailing = parse_json_reader::<MessagesRequest, _>(
+            &br#"{"model":"m","max_tokens":1,"messages":[]} x"#[..],
+        )
+        .unwrap_err();
+        assert!(!trailing.contains("at line"), "got: {}", trailing);
+    }
+
+    #[test]
+    fn test_syntax_error_drops_location() {
+        let err = parse_json_body::<MessagesRequest>(b"{\"model\": ").unwrap_err();
+        assert!(!err.contains("at line"), "got: {}", err);
+    }
+}
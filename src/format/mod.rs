@@ -1,7 +1,10 @@
 pub mod anthropic;
+pub mod completions;
 pub mod google;
+pub mod json_path;
 pub mod openai;
 pub mod openai_convert;
+pub mod redact;
 pub mod responses;
 pub mod responses_convert;
 pub mod signature_cache;
@@ -13,9 +16,12 @@ pub use anthropic::{
     MessagesRequest, MessagesResponse, ModelInfo, ModelsResponse, Role, StopReason, StreamEvent,
     Usage,
 };
+pub use completions::{CompletionRequest, anthropic_to_completion, completion_to_anthropic};
 pub use google::GenerateContentResponse;
-pub use openai::ChatCompletionRequest;
+pub use json_path::{parse_json_body, parse_json_reader};
+pub use openai::{ChatCompletionRequest, ChatCompletionResponse};
 pub use openai_convert::{anthropic_to_openai, openai_to_anthropic};
+pub use redact::Redaction;
 pub use responses::ResponsesRequest;
 pub use responses_convert::{anthropic_to_responses, responses_to_anthropic};
 pub use signature_cache::{
@@ -1,13 +1,17 @@
 use crate::format::anthropic::{
-    ContentBlock, Message, MessageContent, MessagesRequest, MessagesResponse, Role, SystemPrompt,
-    Tool, ToolResultContent,
+    ContentBlock, Message, MessageContent, MessagesRequest, MessagesResponse, RequestMetadata,
+    ResponseFormatInternal, Role, SystemPrompt, Tool, ToolResultContent, Usage,
 };
 use crate::format::openai::{
     ChatCompletionRequest, ChatCompletionResponse, ChatContent, ChatUsage, Choice, FunctionCall,
-    ResponseMessage, StopSequence, ToolCall,
+    PromptTokensDetails, ResponseMessage, StopSequence, ToolCall,
 };
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Output token limit for chat and legacy completions requests that set none
+/// and aren't covered by `[defaults] max_tokens`.
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+
 /// Convert OpenAI ChatCompletionRequest to Anthropic MessagesRequest
 pub fn openai_to_anthropic(request: &ChatCompletionRequest) -> MessagesRequest {
     let mut system: Option<SystemPrompt> = None;
@@ -106,11 +110,8 @@ pub fn openai_to_anthropic(request: &ChatCompletionRequest) -> MessagesRequest {
         }
     }
 
-    // Determine max_tokens
-    let max_tokens = request
-        .max_completion_tokens
-        .or(request.max_tokens)
-        .unwrap_or(4096);
+    // Determine max_tokens; left unset so `[defaults]` can fill it
+    let max_tokens = request.max_completion_tokens.or(request.max_tokens);
 
     // Convert stop sequences
     let stop_sequences = request.stop.as_ref().map(|s| match s {
@@ -158,61 +159,16 @@ pub fn openai_to_anthropic(request: &ChatCompletionRequest) -> MessagesRequest {
         }
     });
 
-    // Handle response_format: inject JSON instruction for json_object,
-    // or pass through schema for json_schema (native Google API support)
-    let (system, response_format) = if let Some(ref fmt) = request.response_format {
-        match fmt.format_type.as_str() {
-            "json_object" => {
-                let json_instruction =
-                    "You must respond with valid JSON. Output only JSON, no other text.";
-                let sys = match system {
-                    Some(SystemPrompt::Text(existing)) => Some(SystemPrompt::Text(format!(
-                        "{}\n\n{}",
-                        existing, json_instruction
-                    ))),
-                    None => Some(SystemPrompt::Text(json_instruction.to_string())),
-                    other => other,
-                };
-                (
-                    sys,
-                    Some(crate::format::anthropic::ResponseFormatInternal::JsonObject),
-                )
-            }
-            "json_schema" => {
-                // Extract schema from json_schema field
-                let schema = fmt.json_schema.as_ref().and_then(|js| {
-                    // OpenAI format: {"name": "...", "schema": {...}}
-                    js.get("schema").cloned().or_else(|| Some(js.clone()))
-                });
-                if let Some(schema) = schema {
-                    (
-                        system,
-                        Some(
-                            crate::format::anthropic::ResponseFormatInternal::JsonSchema { schema },
-                        ),
-                    )
-                } else {
-                    // No schema provided, fall back to json_object behavior
-                    let json_instruction =
-                        "You must respond with valid JSON. Output only JSON, no other text.";
-                    let sys = match system {
-                        Some(SystemPrompt::Text(existing)) => Some(SystemPrompt::Text(format!(
-                            "{}\n\n{}",
-                            existing, json_instruction
-                        ))),
-                        None => Some(SystemPrompt::Text(json_instruction.to_string())),
-                        other => other,
-                    };
-                    (
-                        sys,
-                        Some(crate::format::anthropic::ResponseFormatInternal::JsonObject),
-                    )
-                }
-            }
-            _ => (system, None),
+    let (system, response_format) = match &request.response_format {
+        Some(fmt) => {
+            // OpenAI format: {"name": "...", "schema": {...}}
+            let schema = fmt
+                .json_schema
+                .as_ref()
+                .map(|js| js.get("schema").cloned().unwrap_or_else(|| js.clone()));
+            convert_response_format(&fmt.format_type, schema, system)
         }
-    } else {
-        (system, None)
+        None => (system, None),
     };
 
     MessagesRequest {
@@ -228,8 +184,43 @@ pub fn openai_to_anthropic(request: &ChatCompletionRequest) -> MessagesRequest {
         tools,
         tool_choice,
         thinking: None,
+        seed: request.seed,
+        // OpenAI's `user` plays the same role as Anthropic's metadata.user_id
+        metadata: request.user.clone().map(|user_id| RequestMetadata {
+            user_id: Some(user_id),
+        }),
         response_format,
         candidate_count: request.n.filter(|&n| n > 1),
+        strip_thinking: None,
+    }
+}
+
+/// Map an OpenAI-style response format to the internal one: inject a JSON
+/// instruction for `json_object` (or `json_schema` without a schema), and pass
+/// the schema through for `json_schema` (native Google API support).
+pub(crate) fn convert_response_format(
+    format_type: &str,
+    schema: Option<serde_json::Value>,
+    system: Option<SystemPrompt>,
+) -> (Option<SystemPrompt>, Option<ResponseFormatInternal>) {
+    match (format_type, schema) {
+        ("json_schema", Some(schema)) => {
+            (system, Some(ResponseFormatInternal::JsonSchema { schema }))
+        }
+        ("json_object" | "json_schema", _) => {
+            let json_instruction =
+                "You must respond with valid JSON. Output only JSON, no other text.";
+            let sys = match system {
+                Some(SystemPrompt::Text(existing)) => Some(SystemPrompt::Text(format!(
+                    "{}\n\n{}",
+                    existing, json_instruction
+                ))),
+                None => Some(SystemPrompt::Text(json_instruction.to_string())),
+                other => other,
+            };
+            (sys, Some(ResponseFormatInternal::JsonObject))
+        }
+        _ => (system, None),
     }
 }
 
@@ -301,15 +292,30 @@ pub fn anthropic_to_openai(
             finish_reason,
             logprobs: None,
         }],
-        usage: Some(ChatUsage {
-            prompt_tokens: response.usage.input_tokens,
-            completion_tokens: response.usage.output_tokens,
-            total_tokens: response.usage.input_tokens + response.usage.output_tokens,
-        }),
+        usage: Some(chat_usage(&response.usage)),
         system_fingerprint: None,
     }
 }
 
+/// Convert Anthropic usage to OpenAI usage, carrying any prompt cache tokens
+/// in `prompt_tokens_details`.
+pub(crate) fn chat_usage(usage: &Usage) -> ChatUsage {
+    let cache_read = usage.cache_read_input_tokens.filter(|&n| n > 0);
+    let cache_creation = usage.cache_creation_input_tokens.filter(|&n| n > 0);
+    let prompt_tokens_details =
+        (cache_read.is_some() || cache_creation.is_some()).then(|| PromptTokensDetails {
+            cached_tokens: cache_read.unwrap_or(0),
+            cache_creation_tokens: cache_creation,
+        });
+
+    ChatUsage {
+        prompt_tokens: usage.input_tokens,
+        completion_tokens: usage.output_tokens,
+        total_tokens: usage.input_tokens + usage.output_tokens,
+        prompt_tokens_details,
+    }
+}
+
 fn content_to_string(content: &ChatContent) -> String {
     match content {
         ChatContent::Text(s) => s.clone(),
@@ -412,12 +418,13 @@ mod tests {
             n: None,
             user: None,
             response_format: None,
+            seed: None,
         };
 
         let anthropic = openai_to_anthropic(&request);
 
         assert_eq!(anthropic.model, "gpt-4");
-        assert_eq!(anthropic.max_tokens, 100);
+        assert_eq!(anthropic.max_tokens, Some(100));
         assert!(anthropic.system.is_some());
         assert_eq!(anthropic.messages.len(), 1);
         assert!(matches!(anthropic.messages[0].role, Role::User));
@@ -484,4 +491,50 @@ mod tests {
         assert_eq!(tool_calls.len(), 1);
         assert_eq!(tool_calls[0].function.name, "get_weather");
     }
+
+    #[test]
+    fn test_cache_tokens_in_all_usage_shapes() {
+        let response = MessagesResponse {
+            id: "msg_123".to_string(),
+            response_type: "message".to_string(),
+            role: Role::Assistant,
+            content: vec![ContentBlock::Text {
+                text: "Hello!".to_string(),
+                cache_control: None,
+            }],
+            model: "claude-sonnet-4-5".to_string(),
+            stop_reason: Some(StopReason::EndTurn),
+            stop_sequence: None,
+            usage: Usage {
+                input_tokens: 10,
+                output_tokens: 5,
+                cache_read_input_tokens: Some(300),
+                cache_creation_input_tokens: Some(40),
+            },
+        };
+
+        let anthropic = serde_json::to_value(&response).unwrap();
+        assert_eq!(anthropic["usage"]["cache_read_input_tokens"], 300);
+        assert_eq!(anthropic["usage"]["cache_creation_input_tokens"], 40);
+
+        let openai = serde_json::to_value(anthropic_to_openai(&response, "test", "req_1")).unwrap();
+        let details = &openai["usage"]["prompt_tokens_details"];
+        assert_eq!(details["cached_tokens"], 300);
+        assert_eq!(details["cache_creation_tokens"], 40);
+
+        let responses = serde_json::to_value(crate::format::anthropic_to_responses(
+            &response, "test", "req_1",
+        ))
+        .unwrap();
+        let details = &responses["usage"]["input_tokens_details"];
+        assert_eq!(details["cached_tokens"], 300);
+        assert_eq!(details["cache_creation_tokens"], 40);
+
+        // No cache tokens, no details
+        let plain = chat_usage(&Usage {
+            cache_creation_input_tokens: Some(0),
+            ..Default::default()
+        });
+        assert!(plain.prompt_tokens_details.is_none());
+    }
 }
@@ -0,0 +1,166 @@
+//! Redaction of request/response JSON for `log_requests`, replacing user
+//! content with length placeholders while keeping the structure intact.
+
+use serde_json::Value;
+
+/// Which parts of a logged body to redact.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Redaction {
+    /// Message text, thinking, and prompts
+    pub messages: bool,
+    /// System prompts and instructions
+    pub system: bool,
+    /// Tool definitions, tool call arguments, and tool results
+    pub tools: bool,
+}
+
+/// Keys holding message text.
+const TEXT_KEYS: &[&str] = &[
+    "text",
+    "thinking",
+    "content",
+    "prompt",
+    "refusal",
+    "reasoning_content",
+];
+
+/// Keys whose values describe structure rather than user data.
+const STRUCTURAL_KEYS: &[&str] = &[
+    "type",
+    "role",
+    "name",
+    "id",
+    "model",
+    "object",
+    "tool_use_id",
+    "tool_call_id",
+    "call_id",
+    "stop_reason",
+    "finish_reason",
+    "status",
+];
+
+impl Redaction {
+    pub fn is_empty(&self) -> bool {
+        !(self.messages || self.system || self.tools)
+    }
+
+    /// Redact `value` in place.
+    pub fn apply(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                let is_tool_result = matches!(
+                    map.get("type").and_then(Value::as_str),
+                    Some("tool_result" | "function_call_output")
+                ) || map.get("role").and_then(Value::as_str) == Some("tool");
+
+                for (key, child) in map.iter_mut() {
+                    match key.as_str() {
+                        "system" | "instructions" if self.system => redact_strings(child),
+                        "tools" | "functions" | "input" | "arguments" if self.tools => {
+                            redact_strings(child)
+                        }
+                        "content" | "output" if is_tool_result && self.tools => {
+                            redact_strings(child)
+                        }
+                        "system" | "instructions" | "tools" | "functions" | "input"
+                        | "arguments" => {}
+                        "content" | "output" if is_tool_result => {}
+                        k if TEXT_KEYS.contains(&k) && self.messages && child.is_string() => {
+                            redact_strings(child)
+                        }
+                        _ => self.apply(child),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.apply(item)),
+            _ => {}
+        }
+    }
+}
+
+/// Replace every string under `value` except structural fields.
+fn redact_strings(value: &mut Value) {
+    match value {
+        Value::String(s) => *s = format!("[redacted: {} chars]", s.chars().count()),
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if !(STRUCTURAL_KEYS.contains(&key.as_str()) && child.is_string()) {
+                    redact_strings(child);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_strings),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request() -> Value {
+        json!({
+            "model": "claude-sonnet-4-5",
+            "system": "You are secret",
+            "tools": [{"name": "get_weather", "description": "Weather", "input_schema": {"type": "object"}}],
+            "messages": [
+                {"role": "user", "content": "hello"},
+                {"role": "assistant", "content": [
+                    {"type": "text", "text": "calling"},
+                    {"type": "tool_use", "id": "t1", "name": "get_weather", "input": {"city": "Paris"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "t1", "content": "sunny"}
+                ]}
+            ],
+            "usage": {"input_tokens": 12}
+        })
+    }
+
+    #[test]
+    fn test_redact_messages_only() {
+        let mut value = request();
+        Redaction {
+            messages: true,
+            ..Default::default()
+        }
+        .apply(&mut value);
+
+        assert_eq!(value["model"], "claude-sonnet-4-5");
+        assert_eq!(value["system"], "You are secret");
+        assert_eq!(value["messages"][0]["role"], "user");
+        assert_eq!(value["messages"][0]["content"], "[redacted: 5 chars]");
+        assert_eq!(
+            value["messages"][1]["content"][0]["text"],
+            "[redacted: 7 chars]"
+        );
+        assert_eq!(value["messages"][1]["content"][1]["input"]["city"], "Paris");
+        assert_eq!(value["messages"][2]["content"][0]["content"], "sunny");
+        assert_eq!(value["usage"]["input_tokens"], 12);
+    }
+
+    #[test]
+    fn test_redact_system_and_tools() {
+        let mut value = request();
+        Redaction {
+            messages: false,
+            system: true,
+            tools: true,
+        }
+        .apply(&mut value);
+
+        assert_eq!(value["system"], "[redacted: 14 chars]");
+        assert_eq!(value["tools"][0]["name"], "get_weather");
+        assert_eq!(value["tools"][0]["description"], "[redacted: 7 chars]");
+        assert_eq!(value["tools"][0]["input_schema"]["type"], "object");
+        let tool_use = &value["messages"][1]["content"][1];
+        assert_eq!(tool_use["name"], "get_weather");
+        assert_eq!(tool_use["input"]["city"], "[redacted: 5 chars]");
+        let tool_result = &value["messages"][2]["content"][0];
+        assert_eq!(tool_result["tool_use_id"], "t1");
+        assert_eq!(tool_result["content"], "[redacted: 5 chars]");
+        assert_eq!(value["messages"][0]["content"], "hello");
+    }
+}
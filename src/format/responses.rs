@@ -38,6 +38,31 @@ pub struct ResponsesRequest {
     /// Tools available to the model
     #[serde(default)]
     pub tools: Option<Vec<ResponseTool>>,
+
+    /// Text output options, including structured output (`text.format`)
+    #[serde(default)]
+    pub text: Option<ResponseTextConfig>,
+
+    /// Chat Completions-style structured output, accepted for compatibility
+    #[serde(default)]
+    pub response_format: Option<crate::format::openai::ResponseFormat>,
+}
+
+/// Text output configuration (`text` in the request)
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResponseTextConfig {
+    #[serde(default)]
+    pub format: Option<ResponseTextFormat>,
+}
+
+/// Output format: `{"type": "text" | "json_object"}` or
+/// `{"type": "json_schema", "name": ..., "schema": {...}, "strict": ...}`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResponseTextFormat {
+    #[serde(rename = "type")]
+    pub format_type: String,
+    #[serde(default)]
+    pub schema: Option<serde_json::Value>,
 }
 
 /// Input can be a string or array of input items
@@ -211,6 +236,9 @@ pub struct ResponseUsage {
 #[derive(Debug, Clone, Serialize)]
 pub struct InputTokensDetails {
     pub cached_tokens: u32,
+    /// Tokens written to the prompt cache (an extension to OpenAI's schema)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_creation_tokens: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize)]
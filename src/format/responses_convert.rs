@@ -4,11 +4,16 @@ use crate::format::anthropic::{
     ContentBlock, Message, MessageContent, MessagesRequest, MessagesResponse, Role, SystemPrompt,
     Tool, ToolResultContent,
 };
+use crate::format::openai_convert::convert_response_format;
 use crate::format::responses::{
     InputTokensDetails, ResponseInput, ResponseInputContent, ResponseInputItem, ResponseInputPart,
     ResponseOutputContent, ResponseOutputItem, ResponseUsage, ResponsesRequest, ResponsesResponse,
 };
 
+/// Output token limit for Responses requests without `max_output_tokens`
+/// that aren't covered by `[defaults] max_tokens`.
+pub const DEFAULT_MAX_OUTPUT_TOKENS: u32 = 16384;
+
 /// Convert a Responses API request to an Anthropic Messages API request
 pub fn responses_to_anthropic(request: &ResponsesRequest) -> MessagesRequest {
     let mut messages = Vec::new();
@@ -156,14 +161,30 @@ pub fn responses_to_anthropic(request: &ResponsesRequest) -> MessagesRequest {
         .clone()
         .unwrap_or_else(|| "claude-sonnet-4-5".to_string());
 
+    let system = request
+        .instructions
+        .as_ref()
+        .map(|i| SystemPrompt::Text(i.clone()));
+    let text_format = request.text.as_ref().and_then(|t| t.format.as_ref());
+    let (system, response_format) = match (text_format, &request.response_format) {
+        (Some(format), _) => {
+            convert_response_format(&format.format_type, format.schema.clone(), system)
+        }
+        (None, Some(format)) => {
+            let schema = format
+                .json_schema
+                .as_ref()
+                .map(|js| js.get("schema").cloned().unwrap_or_else(|| js.clone()));
+            convert_response_format(&format.format_type, schema, system)
+        }
+        (None, None) => (system, None),
+    };
+
     MessagesRequest {
         model,
         messages,
-        max_tokens: request.max_output_tokens.unwrap_or(16384),
-        system: request
-            .instructions
-            .as_ref()
-            .map(|i| SystemPrompt::Text(i.clone())),
+        max_tokens: request.max_output_tokens,
+        system,
         temperature: request.temperature,
         top_p: request.top_p,
         top_k: None,
@@ -172,8 +193,11 @@ pub fn responses_to_anthropic(request: &ResponsesRequest) -> MessagesRequest {
         tools,
         tool_choice: None,
         thinking: None,
-        response_format: None,
+        seed: None,
+        metadata: None,
+        response_format,
         candidate_count: None,
+        strip_thinking: None,
     }
 }
 
@@ -242,9 +266,10 @@ pub fn anthropic_to_responses(
         input_tokens: u.input_tokens,
         output_tokens: u.output_tokens,
         total_tokens: u.input_tokens + u.output_tokens,
-        input_tokens_details: u.cache_read_input_tokens.map(|cached| InputTokensDetails {
-            cached_tokens: cached,
-        }),
+        input_tokens_details: input_tokens_details(
+            u.cache_read_input_tokens.unwrap_or(0),
+            u.cache_creation_input_tokens.unwrap_or(0),
+        ),
         output_tokens_details: None,
     });
 
@@ -267,3 +292,11 @@ pub fn anthropic_to_responses(
         status: "completed",
     }
 }
+
+/// Prompt cache breakdown for Responses `usage`, or `None` without cache tokens.
+pub fn input_tokens_details(cache_read: u32, cache_creation: u32) -> Option<InputTokensDetails> {
+    (cache_read > 0 || cache_creation > 0).then_some(InputTokensDetails {
+        cached_tokens: cache_read,
+        cache_creation_tokens: (cache_creation > 0).then_some(cache_creation),
+    })
+}
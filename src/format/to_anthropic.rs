@@ -148,6 +148,176 @@ fn generate_id() -> String {
     format!("{:x}", nanos)
 }
 
+// Thinking models must use streaming endpoint but client may want non-streaming response
+pub fn build_response_from_events(
+    events: &[StreamEvent],
+    model: &str,
+    request_id: &str,
+) -> Result<MessagesResponse, String> {
+    let mut content: Vec<ContentBlock> = Vec::new();
+    let mut stop_reason: Option<StopReason> = None;
+    let mut usage = Usage::default();
+
+    let mut current_text = String::new();
+    let mut current_thinking = String::new();
+    let mut current_signature = String::new();
+    let mut current_tool_json = String::new();
+    let mut in_text_block = false;
+    let mut in_thinking_block = false;
+    let mut in_tool_block = false;
+
+    for event in events {
+        match event {
+            StreamEvent::MessageStart { message } => {
+                usage = message.usage.clone();
+            }
+            StreamEvent::ContentBlockStart { content_block, .. } => {
+                // Start tracking this block type
+                match content_block {
+                    ContentBlock::Text { .. } => {
+                        in_text_block = true;
+                        current_text.clear();
+                    }
+                    ContentBlock::Thinking { signature, .. } => {
+                        in_thinking_block = true;
+                        current_thinking.clear();
+                        current_signature = signature.clone().unwrap_or_default();
+                    }
+                    ContentBlock::ToolUse { id, name, input } => {
+                        // Input arrives as input_json_delta fragments
+                        in_tool_block = true;
+                        current_tool_json.clear();
+                        content.push(ContentBlock::ToolUse {
+                            id: id.clone(),
+                            name: name.clone(),
+                            input: input.clone(),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            StreamEvent::ContentBlockDelta { delta, .. } => {
+                match delta {
+                    ContentDelta::Text { text } => {
+                        if in_text_block {
+                            current_text.push_str(text);
+                        }
+                    }
+                    ContentDelta::Thinking { thinking } => {
+                        if in_thinking_block {
+                            current_thinking.push_str(thinking);
+                        }
+                    }
+                    ContentDelta::InputJson { partial_json } => {
+                        if in_tool_block {
+                            current_tool_json.push_str(partial_json);
+                        }
+                    }
+                    ContentDelta::Signature { signature } => {
+                        // Signature for thinking block
+                        if in_thinking_block {
+                            current_signature = signature.clone();
+                        }
+                    }
+                }
+            }
+            StreamEvent::ContentBlockStop { .. } => {
+                // Finalize the current block
+                if in_text_block && !current_text.is_empty() {
+                    content.push(ContentBlock::Text {
+                        text: std::mem::take(&mut current_text),
+                        cache_control: None,
+                    });
+                }
+                if in_thinking_block && !current_thinking.is_empty() {
+                    let signature = if current_signature.is_empty() {
+                        None
+                    } else {
+                        Some(std::mem::take(&mut current_signature))
+                    };
+                    content.push(ContentBlock::Thinking {
+                        thinking: std::mem::take(&mut current_thinking),
+                        signature,
+                    });
+                }
+                if in_tool_block {
+                    finish_tool_input(&mut content, &std::mem::take(&mut current_tool_json))?;
+                }
+                in_text_block = false;
+                in_thinking_block = false;
+                in_tool_block = false;
+            }
+            StreamEvent::MessageDelta {
+                delta,
+                usage: delta_usage,
+            } => {
+                if delta.stop_reason.is_some() {
+                    stop_reason = delta.stop_reason;
+                }
+                usage.output_tokens = delta_usage.output_tokens;
+            }
+            _ => {}
+        }
+    }
+
+    // Finalize any remaining blocks
+    if in_text_block && !current_text.is_empty() {
+        content.push(ContentBlock::Text {
+            text: current_text,
+            cache_control: None,
+        });
+    }
+    if in_thinking_block && !current_thinking.is_empty() {
+        content.push(ContentBlock::Thinking {
+            thinking: current_thinking,
+            signature: if current_signature.is_empty() {
+                None
+            } else {
+                Some(current_signature)
+            },
+        });
+    }
+    if in_tool_block {
+        finish_tool_input(&mut content, &current_tool_json)?;
+    }
+
+    Ok(MessagesResponse {
+        id: request_id.to_string(),
+        response_type: "message".to_string(),
+        role: Role::Assistant,
+        content,
+        model: model.to_string(),
+        stop_reason,
+        stop_sequence: None,
+        usage,
+    })
+}
+
+/// Parse the accumulated `input_json_delta` fragments into the input of the
+/// last `tool_use` block.
+fn finish_tool_input(content: &mut [ContentBlock], json: &str) -> Result<(), String> {
+    let Some(ContentBlock::ToolUse { name, input, .. }) = content.last_mut() else {
+        return Ok(());
+    };
+    if json.is_empty() {
+        return Ok(());
+    }
+    match serde_json::from_str::<serde_json::Value>(json) {
+        Ok(parsed) if parsed.is_object() => {
+            *input = parsed;
+            Ok(())
+        }
+        Ok(_) => Err(format!(
+            "tool_use block '{}' input is not a JSON object",
+            name
+        )),
+        Err(e) => Err(format!(
+            "tool_use block '{}' has incomplete or malformed input JSON: {}",
+            name, e
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -302,7 +472,8 @@ mod tests {
             },
         ];
 
-        let result = build_response_from_events(&events, "claude-sonnet-4-5", "req_stream");
+        let result =
+            build_response_from_events(&events, "claude-sonnet-4-5", "req_stream").unwrap();
 
         assert_eq!(result.id, "req_stream");
         assert_eq!(result.content.len(), 1);
@@ -313,141 +484,84 @@ mod tests {
         assert_eq!(result.stop_reason, Some(StopReason::EndTurn));
         assert_eq!(result.usage.output_tokens, 10);
     }
-}
 
-// Thinking models must use streaming endpoint but client may want non-streaming response
-pub fn build_response_from_events(
-    events: &[StreamEvent],
-    model: &str,
-    request_id: &str,
-) -> MessagesResponse {
-    let mut content: Vec<ContentBlock> = Vec::new();
-    let mut stop_reason: Option<StopReason> = None;
-    let mut usage = Usage::default();
-
-    let mut current_text = String::new();
-    let mut current_thinking = String::new();
-    let mut current_signature = String::new();
-    let mut in_text_block = false;
-    let mut in_thinking_block = false;
-
-    for event in events {
-        match event {
-            StreamEvent::MessageStart { message } => {
-                usage = message.usage.clone();
-            }
-            StreamEvent::ContentBlockStart { content_block, .. } => {
-                // Start tracking this block type
-                match content_block {
-                    ContentBlock::Text { .. } => {
-                        in_text_block = true;
-                        current_text.clear();
-                    }
-                    ContentBlock::Thinking { signature, .. } => {
-                        in_thinking_block = true;
-                        current_thinking.clear();
-                        current_signature = signature.clone().unwrap_or_default();
-                    }
-                    ContentBlock::ToolUse { id, name, input } => {
-                        // Tool use blocks come complete
-                        content.push(ContentBlock::ToolUse {
-                            id: id.clone(),
-                            name: name.clone(),
-                            input: input.clone(),
-                        });
-                    }
-                    _ => {}
-                }
-            }
-            StreamEvent::ContentBlockDelta { delta, .. } => {
-                match delta {
-                    ContentDelta::Text { text } => {
-                        if in_text_block {
-                            current_text.push_str(text);
-                        }
-                    }
-                    ContentDelta::Thinking { thinking } => {
-                        if in_thinking_block {
-                            current_thinking.push_str(thinking);
-                        }
-                    }
-                    ContentDelta::InputJson { partial_json } => {
-                        // Tool input - update last tool_use block
-                        if let Some(ContentBlock::ToolUse { input, .. }) = content.last_mut()
-                            && let Ok(parsed) = serde_json::from_str(partial_json)
-                        {
-                            *input = parsed;
-                        }
-                    }
-                    ContentDelta::Signature { signature } => {
-                        // Signature for thinking block
-                        if in_thinking_block {
-                            current_signature = signature.clone();
-                        }
-                    }
-                }
-            }
-            StreamEvent::ContentBlockStop { .. } => {
-                // Finalize the current block
-                if in_text_block && !current_text.is_empty() {
-                    content.push(ContentBlock::Text {
-                        text: std::mem::take(&mut current_text),
-                        cache_control: None,
-                    });
-                    in_text_block = false;
-                }
-                if in_thinking_block && !current_thinking.is_empty() {
-                    let signature = if current_signature.is_empty() {
-                        None
-                    } else {
-                        Some(std::mem::take(&mut current_signature))
-                    };
-                    content.push(ContentBlock::Thinking {
-                        thinking: std::mem::take(&mut current_thinking),
-                        signature,
-                    });
-                    in_thinking_block = false;
-                }
-            }
-            StreamEvent::MessageDelta {
-                delta,
-                usage: delta_usage,
-            } => {
-                if delta.stop_reason.is_some() {
-                    stop_reason = delta.stop_reason;
-                }
-                usage.output_tokens = delta_usage.output_tokens;
-            }
-            _ => {}
-        }
-    }
-
-    // Finalize any remaining blocks
-    if in_text_block && !current_text.is_empty() {
-        content.push(ContentBlock::Text {
-            text: current_text,
-            cache_control: None,
-        });
-    }
-    if in_thinking_block && !current_thinking.is_empty() {
-        content.push(ContentBlock::Thinking {
-            thinking: current_thinking,
-            signature: if current_signature.is_empty() {
-                None
-            } else {
-                Some(current_signature)
+    fn thinking_tool_events(json_fragments: &[&str]) -> Vec<StreamEvent> {
+        let mut events = vec![
+            StreamEvent::ContentBlockStart {
+                index: 0,
+                content_block: ContentBlock::Thinking {
+                    thinking: String::new(),
+                    signature: None,
+                },
+            },
+            StreamEvent::ContentBlockDelta {
+                index: 0,
+                delta: ContentDelta::Thinking {
+                    thinking: "Need the weather".to_string(),
+                },
             },
+            StreamEvent::ContentBlockDelta {
+                index: 0,
+                delta: ContentDelta::Signature {
+                    signature: "sig".to_string(),
+                },
+            },
+            StreamEvent::ContentBlockStop { index: 0 },
+            StreamEvent::ContentBlockStart {
+                index: 1,
+                content_block: ContentBlock::ToolUse {
+                    id: "toolu_1".to_string(),
+                    name: "get_weather".to_string(),
+                    input: serde_json::json!({}),
+                },
+            },
+        ];
+        events.extend(
+            json_fragments
+                .iter()
+                .map(|fragment| StreamEvent::ContentBlockDelta {
+                    index: 1,
+                    delta: ContentDelta::InputJson {
+                        partial_json: fragment.to_string(),
+                    },
+                }),
+        );
+        events.push(StreamEvent::ContentBlockStop { index: 1 });
+        events.push(StreamEvent::MessageDelta {
+            delta: crate::format::MessageDeltaData {
+                stop_reason: Some(StopReason::ToolUse),
+                stop_sequence: None,
+            },
+            usage: crate::format::MessageDeltaUsage { output_tokens: 12 },
         });
+        events
     }
 
-    MessagesResponse {
-        id: request_id.to_string(),
-        response_type: "message".to_string(),
-        role: Role::Assistant,
-        content,
-        model: model.to_string(),
-        stop_reason,
-        stop_sequence: None,
-        usage,
+    #[test]
+    fn test_build_response_from_events_thinking_tool_use() {
+        let events = thinking_tool_events(&[r#"{"city": "Par"#, r#"is", "days": 2}"#]);
+        let result =
+            build_response_from_events(&events, "claude-opus-4-6-thinking", "req").unwrap();
+
+        assert_eq!(result.content.len(), 2);
+        assert!(matches!(
+            &result.content[0],
+            ContentBlock::Thinking { thinking, signature: Some(sig) }
+                if thinking == "Need the weather" && sig == "sig"
+        ));
+        match &result.content[1] {
+            ContentBlock::ToolUse { id, name, input } => {
+                assert_eq!(id, "toolu_1");
+                assert_eq!(name, "get_weather");
+                assert_eq!(input, &serde_json::json!({"city": "Paris", "days": 2}));
+            }
+            other => panic!("Expected ToolUse block, got {:?}", other),
+        }
+        assert_eq!(result.stop_reason, Some(StopReason::ToolUse));
+
+        let events = thinking_tool_events(&[r#"{"city": "Par"#]);
+        let err =
+            build_response_from_events(&events, "claude-opus-4-6-thinking", "req").unwrap_err();
+        assert!(err.contains("get_weather"), "{err}");
     }
 }
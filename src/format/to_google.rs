@@ -80,14 +80,14 @@ pub fn convert_request(request: &MessagesRequest) -> GenerateContentRequest {
     };
 
     // Cap max_output_tokens to Cloud Code API limits
-    let max_tokens = match model_family {
-        "claude" => request.max_tokens.min(CLAUDE_MAX_OUTPUT_TOKENS),
-        "gemini" => request.max_tokens.min(GEMINI_MAX_OUTPUT_TOKENS),
-        _ => request.max_tokens,
-    };
+    let max_tokens = request.max_tokens.map(|max_tokens| match model_family {
+        "claude" => max_tokens.min(CLAUDE_MAX_OUTPUT_TOKENS),
+        "gemini" => max_tokens.min(GEMINI_MAX_OUTPUT_TOKENS),
+        _ => max_tokens,
+    });
 
     let generation_config = Some(GenerationConfig {
-        max_output_tokens: Some(max_tokens),
+        max_output_tokens: max_tokens,
         temperature,
         top_p,
         top_k,
@@ -109,6 +109,7 @@ pub fn convert_request(request: &MessagesRequest) -> GenerateContentRequest {
             _ => None,
         },
         candidate_count: request.candidate_count,
+        seed: request.seed,
     });
 
     let tools = request.tools.as_ref().and_then(|t| {
@@ -480,7 +481,7 @@ mod tests {
                 role: Role::User,
                 content: MessageContent::Text(content.to_string()),
             }],
-            max_tokens: 1024,
+            max_tokens: Some(1024),
             system: None,
             temperature: None,
             top_p: None,
@@ -490,8 +491,11 @@ mod tests {
             tools: None,
             tool_choice: None,
             thinking: None,
+            seed: None,
+            metadata: None,
             response_format: None,
             candidate_count: None,
+            strip_thinking: None,
         }
     }
 
@@ -583,6 +587,75 @@ mod tests {
         assert_eq!(tools[0].function_declarations[0].name, "get_weather");
     }
 
+    #[test]
+    fn test_seed_reaches_generation_config() {
+        let chat_request: crate::format::ChatCompletionRequest =
+            serde_json::from_value(serde_json::json!({
+                "model": "gemini-3-flash",
+                "messages": [{"role": "user", "content": "Hello"}],
+                "seed": 42
+            }))
+            .unwrap();
+        let request = crate::format::openai_to_anthropic(&chat_request);
+        assert_eq!(request.seed, Some(42));
+
+        let google_req = convert_request(&request);
+        let json = serde_json::to_value(&google_req).unwrap();
+        assert_eq!(json["generationConfig"]["seed"], 42);
+
+        let request = create_test_request("gemini-3-flash", "Hello");
+        let json = serde_json::to_value(convert_request(&request)).unwrap();
+        assert!(json["generationConfig"].get("seed").is_none());
+    }
+
+    #[test]
+    fn test_json_object_sets_response_mime_type() {
+        let chat_request: crate::format::ChatCompletionRequest =
+            serde_json::from_value(serde_json::json!({
+                "model": "gemini-3-flash",
+                "messages": [{"role": "user", "content": "List three colors"}],
+                "response_format": {"type": "json_object"}
+            }))
+            .unwrap();
+        let request = crate::format::openai_to_anthropic(&chat_request);
+
+        let json = serde_json::to_value(convert_request(&request)).unwrap();
+        assert_eq!(
+            json["generationConfig"]["responseMimeType"],
+            "application/json"
+        );
+        assert!(json["generationConfig"].get("responseSchema").is_none());
+    }
+
+    #[test]
+    fn test_responses_json_schema_passes_schema() {
+        let responses_request: crate::format::responses::ResponsesRequest =
+            serde_json::from_value(serde_json::json!({
+                "model": "gemini-3-flash",
+                "input": "List three colors",
+                "text": {"format": {
+                    "type": "json_schema",
+                    "name": "colors",
+                    "strict": true,
+                    "schema": {
+                        "type": "object",
+                        "properties": {"colors": {"type": "array", "items": {"type": "string"}}}
+                    }
+                }}
+            }))
+            .unwrap();
+        let request = crate::format::responses_convert::responses_to_anthropic(&responses_request);
+
+        let json = serde_json::to_value(convert_request(&request)).unwrap();
+        let config = &json["generationConfig"];
+        assert_eq!(config["responseMimeType"], "application/json");
+        assert_eq!(config["responseSchema"]["type"], "object");
+        assert_eq!(
+            config["responseSchema"]["properties"]["colors"]["items"]["type"],
+            "string"
+        );
+    }
+
     #[test]
     fn test_tool_use_in_history_gets_skip_signature_for_gemini() {
         // Create a request with tool use in the conversation history
@@ -1,11 +1,32 @@
+/// Set by the global `--quiet`/`-q` flag.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Whether decorative CLI output (banners, spinners, tips) is suppressed.
+fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// `println!` for informational/decorative output that `--quiet` suppresses.
+/// Errors and essential results keep using `eprintln!`/`println!` directly.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !$crate::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
 mod cache;
 mod colors;
 mod config;
 mod error;
 mod models;
+mod notify;
 mod server;
 mod setup;
 mod stats;
+mod token_history;
+mod websocket;
 
 mod tui;
 
@@ -42,6 +63,12 @@ struct Spinner {
 impl Spinner {
     fn new(message: &str) -> Self {
         let running = Arc::new(AtomicBool::new(true));
+        if is_quiet() {
+            return Self {
+                running,
+                handle: None,
+            };
+        }
         let running_clone = running.clone();
         let message = message.to_string();
 
@@ -89,6 +116,13 @@ fn get_pid_path() -> std::path::PathBuf {
     Config::dir().join("agcp.pid")
 }
 
+/// Set on the daemon's `serve --foreground` child, which takes over the
+/// instance lock from the parent that spawned it.
+const DAEMON_CHILD_ENV: &str = "AGCP_DAEMON_CHILD";
+
+/// How long the daemon's child waits for its parent to release the lock.
+const LOCK_HANDOFF_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 fn get_lock_path() -> std::path::PathBuf {
     Config::dir().join("agcp.lock")
 }
@@ -104,7 +138,6 @@ fn read_addr() -> Option<String> {
 }
 
 /// Write the daemon's actual listening address to the addr file.
-#[cfg(unix)]
 fn write_addr(host: &str, port: u16) {
     let addr_path = get_addr_path();
     if let Some(parent) = addr_path.parent() {
@@ -123,13 +156,47 @@ fn parse_strategy(s: &str) -> Option<auth::accounts::SelectionStrategy> {
     }
 }
 
+/// Remove every `--quiet` / `-q` from `args`, returning whether one was present.
+fn take_quiet_flag(args: &mut Vec<String>) -> bool {
+    let before = args.len();
+    args.retain(|a| a != "--quiet" && a != "-q");
+    args.len() != before
+}
+
+/// Remove `--config <path>` / `--config=<path>` from `args`, returning the path.
+fn take_config_flag(args: &mut Vec<String>) -> Option<std::path::PathBuf> {
+    let pos = args
+        .iter()
+        .position(|a| a == "--config" || a.starts_with("--config="))?;
+    let flag = args.remove(pos);
+    let value = match flag.strip_prefix("--config=") {
+        Some(value) => value.to_string(),
+        None if pos < args.len() => args.remove(pos),
+        None => {
+            eprintln!("\x1b[31mMissing value:\x1b[0m --config requires a file path");
+            std::process::exit(1);
+        }
+    };
+    Some(std::path::PathBuf::from(value))
+}
+
 #[tokio::main]
 async fn main() {
     rustls::crypto::ring::default_provider()
         .install_default()
         .expect("Failed to install rustls crypto provider");
 
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    // Global `--config <path>` applies to every subcommand
+    if let Some(path) = take_config_flag(&mut args) {
+        config::set_config_path(path);
+    }
+
+    // Global `--quiet` keeps only errors and essential results
+    if take_quiet_flag(&mut args) {
+        QUIET.store(true, Ordering::Relaxed);
+    }
 
     // Check for subcommands first
     if args.len() > 1 {
@@ -147,11 +214,15 @@ async fn main() {
                 return;
             }
             "status" => {
-                run_status_command();
+                if args[2..].iter().any(|a| a == "--short" || a == "--prompt") {
+                    run_status_short();
+                } else {
+                    run_status_command();
+                }
                 return;
             }
             "login" => {
-                init_logging_foreground(false);
+                init_logging_foreground(false, None);
                 let no_browser = args.iter().any(|a| a == "--no-browser");
                 if let Err(e) = run_login(no_browser).await {
                     eprintln!("\x1b[31mLogin failed:\x1b[0m {}", e);
@@ -189,7 +260,7 @@ async fn main() {
                 return;
             }
             "test" => {
-                run_test_command().await;
+                run_test_command(&args[2..]).await;
                 return;
             }
             "config" => {
@@ -197,7 +268,7 @@ async fn main() {
                 return;
             }
             "stats" => {
-                run_stats_command().await;
+                run_stats_command(&args[2..]).await;
                 return;
             }
             "setup" => {
@@ -226,23 +297,45 @@ async fn main() {
                 return;
             }
             "upgrade" => {
-                run_upgrade_command().await;
+                let yes = args[2..].iter().any(|a| a == "--yes" || a == "-y");
+                run_upgrade_command(yes).await;
                 return;
             }
             "tui" => {
-                if let Err(e) = tui::run() {
+                // Accept both `agcp tui usage` and `agcp tui --tab usage`
+                let tab_arg = match args.get(2).map(String::as_str) {
+                    Some("--tab") => args.get(3),
+                    _ => args.get(2),
+                };
+                let start_tab = match tab_arg {
+                    Some(name) => match tui::Tab::from_name(name) {
+                        Some(tab) => Some(tab),
+                        None => {
+                            let valid: Vec<String> = tui::Tab::all()
+                                .iter()
+                                .map(|t| t.name().to_lowercase())
+                                .collect();
+                            eprintln!("{}Unknown tab:{} {}", RED, RESET, name);
+                            eprintln!("Valid tabs: {}", valid.join(", "));
+                            std::process::exit(1);
+                        }
+                    },
+                    None => None,
+                };
+                if let Err(e) = tui::run(start_tab) {
                     eprintln!("\x1b[31mTUI error:\x1b[0m {}", e);
                     std::process::exit(1);
                 }
                 return;
             }
+            "serve" => {} // Server options are parsed below
             arg if !arg.starts_with('-') => {
                 eprintln!("\x1b[31mUnknown command:\x1b[0m {}", arg);
                 eprintln!();
                 eprintln!("Run '\x1b[33magcp --help\x1b[0m' for usage information.");
                 std::process::exit(1);
             }
-            _ => {} // Options like --port, --debug are handled below
+            _ => {} // Bare `agcp [OPTIONS]` is an alias for `agcp serve`
         }
     }
 
@@ -253,7 +346,11 @@ async fn main() {
     let mut fallback = false;
     let mut network = false;
 
-    let mut i = 1;
+    let mut i = if args.get(1).is_some_and(|a| a == "serve") {
+        2
+    } else {
+        1
+    };
     while i < args.len() {
         match args[i].as_str() {
             "--port" | "-p" => {
@@ -303,7 +400,13 @@ async fn main() {
                 eprintln!("Run '\x1b[33magcp --help\x1b[0m' for usage information.");
                 std::process::exit(1);
             }
-            _ => {} // Values for --port/--host are consumed above
+            // Values for --port/--host are consumed above, so anything left is stray
+            arg => {
+                eprintln!("\x1b[31mUnexpected argument:\x1b[0m {}", arg);
+                eprintln!();
+                eprintln!("Run '\x1b[33magcp --help\x1b[0m' for usage information.");
+                std::process::exit(1);
+            }
         }
         i += 1;
     }
@@ -330,6 +433,7 @@ async fn main() {
             std::process::exit(1);
         }
     };
+    let host_overridden = host.is_some() || network;
     let mut config = config.with_overrides(port, host, debug);
 
     // Apply fallback flag if specified on command line
@@ -340,13 +444,27 @@ async fn main() {
     // Apply network mode - bind to all interfaces
     if network {
         config.server.host = "0.0.0.0".to_string();
+        config.server.interface_ip = None;
+    }
+
+    // Refuse to fall back to `host` when the configured interface is missing
+    if !host_overridden
+        && let Some(name) = &config.server.interface
+        && let Err(e) = config::resolve_interface_ip(name)
+    {
+        eprintln!("\x1b[31mError:\x1b[0m {}", e);
+        eprintln!("  \x1b[2mCheck server.interface in your config, or pass --host.\x1b[0m");
+        std::process::exit(1);
     }
 
     // Initialize global config for access from other modules
     config::init_config(config.clone());
 
     if foreground {
-        init_logging_foreground(debug);
+        init_logging_foreground(debug, config.logging.filter.as_deref());
+        // Held for the whole run, by the daemon's child and by any other
+        // foreground server (e.g. one started by a process supervisor)
+        let _lock_file = lock_foreground(std::env::var_os(DAEMON_CHILD_ENV).is_some());
         run_server(config).await;
     } else {
         run_daemon(config, debug).await;
@@ -402,7 +520,7 @@ async fn run_daemon(config: Config, debug: bool) {
     }
 
     // Try to acquire exclusive lock - if we can't, another instance is running
-    let _lock_file = match try_acquire_lock() {
+    let lock_file = match try_acquire_lock() {
         Some(lock) => lock,
         None => {
             // Another instance has the lock - check if it's responsive
@@ -422,16 +540,16 @@ async fn run_daemon(config: Config, debug: bool) {
                     .is_some();
 
                 if is_responsive {
-                    println!("\x1b[32m●\x1b[0m AGCP is already running (PID: {})", pid);
+                    say!("\x1b[32m●\x1b[0m AGCP is already running (PID: {})", pid);
                     if let Some((host, port_str)) = addr.rsplit_once(':') {
                         let port = port_str.parse::<u16>().unwrap_or(config.port());
                         print_listening_address(host, port);
                     } else {
                         print_listening_address(config.host(), config.port());
                     }
-                    println!();
-                    println!("  \x1b[2mUse 'agcp logs' to view logs\x1b[0m");
-                    println!("  \x1b[2mUse 'agcp stop' to stop the server\x1b[0m");
+                    say!();
+                    say!("  \x1b[2mUse 'agcp logs' to view logs\x1b[0m");
+                    say!("  \x1b[2mUse 'agcp stop' to stop the server\x1b[0m");
                     return;
                 }
             }
@@ -460,16 +578,16 @@ async fn run_daemon(config: Config, debug: bool) {
             .is_some();
 
         if is_responsive {
-            println!("\x1b[32m●\x1b[0m AGCP is already running (PID: {})", pid);
+            say!("\x1b[32m●\x1b[0m AGCP is already running (PID: {})", pid);
             if let Some((host, port_str)) = addr.rsplit_once(':') {
                 let port = port_str.parse::<u16>().unwrap_or(config.port());
                 print_listening_address(host, port);
             } else {
                 print_listening_address(config.host(), config.port());
             }
-            println!();
-            println!("  \x1b[2mUse 'agcp logs' to view logs\x1b[0m");
-            println!("  \x1b[2mUse 'agcp stop' to stop the server\x1b[0m");
+            say!();
+            say!("  \x1b[2mUse 'agcp logs' to view logs\x1b[0m");
+            say!("  \x1b[2mUse 'agcp stop' to stop the server\x1b[0m");
             return;
         } else {
             // PID exists but server not responding - clean up stale PID
@@ -525,11 +643,15 @@ async fn run_daemon(config: Config, debug: bool) {
             .expect("Failed to open log file");
 
         let mut cmd = std::process::Command::new(exe);
-        cmd.arg("--foreground");
+        cmd.args(["serve", "--foreground"]);
+        cmd.env(DAEMON_CHILD_ENV, "1");
         if let Some(p) = config.port().checked_sub(0) {
             cmd.args(["--port", &p.to_string()]);
         }
         cmd.args(["--host", config.host()]);
+        if let Some(path) = config::config_path_override() {
+            cmd.arg("--config").arg(path);
+        }
         if debug {
             cmd.arg("--debug");
         }
@@ -551,8 +673,9 @@ async fn run_daemon(config: Config, debug: bool) {
         match cmd.spawn() {
             Ok(child) => {
                 let pid = child.id();
-                write_pid(pid);
-                write_addr(config.host(), config.port());
+                // The child takes the lock over and writes the PID and
+                // address files once it is listening
+                drop(lock_file);
 
                 // Show spinner while waiting for startup
                 let spinner = Spinner::new("Starting AGCP...");
@@ -560,11 +683,11 @@ async fn run_daemon(config: Config, debug: bool) {
                 spinner.stop();
 
                 if is_process_running(pid) {
-                    println!("\x1b[32m●\x1b[0m AGCP started (PID: {})", pid);
+                    say!("\x1b[32m●\x1b[0m AGCP started (PID: {})", pid);
                     print_listening_address(config.host(), config.port());
-                    println!();
-                    println!("  \x1b[2mUse 'agcp logs' to view logs\x1b[0m");
-                    println!("  \x1b[2mUse 'agcp stop' to stop the server\x1b[0m");
+                    say!();
+                    say!("  \x1b[2mUse 'agcp logs' to view logs\x1b[0m");
+                    say!("  \x1b[2mUse 'agcp stop' to stop the server\x1b[0m");
                 } else {
                     eprintln!("\x1b[31m●\x1b[0m AGCP failed to start. Check logs:");
                     eprintln!("  agcp logs");
@@ -581,7 +704,8 @@ async fn run_daemon(config: Config, debug: bool) {
     #[cfg(not(unix))]
     {
         // On non-Unix, just run in foreground
-        init_logging_foreground(debug);
+        let _lock_file = lock_file;
+        init_logging_foreground(debug, config.logging.filter.as_deref());
         run_server(config).await;
     }
 }
@@ -620,8 +744,29 @@ async fn run_server(config: Config) {
         accounts.strategy = strategy;
     }
     accounts.quota_threshold = config.accounts.quota_threshold;
+    accounts.sticky_rate_limit_grace_secs = config.accounts.sticky_rate_limit_grace_secs;
+    accounts.budget_reset_hour_utc = config.accounts.budget_reset_hour_utc;
+    accounts.model_affinity = config.accounts.model_affinity_rules();
+    for (model, target) in &accounts.model_affinity {
+        if !accounts.accounts.iter().any(|a| a.matches_target(target)) {
+            warn!(model = %model, account = %target, "model_affinity names no known account");
+        }
+    }
 
-    let http_client = HttpClient::new();
+    // Don't let a persisted active account that has since been disabled or
+    // invalidated keep attracting requests
+    if accounts.clear_stale_active_account() {
+        info!("Persisted active account is no longer usable, selecting a new one");
+        if let Err(e) = accounts.save() {
+            warn!(error = %e, "Failed to save accounts");
+        }
+    }
+
+    for conflict in models::mapping_conflicts(&config.mappings.rules, &config.mappings.aliases) {
+        warn!(%conflict, "Model mapping conflict");
+    }
+
+    let http_client = HttpClient::with_config(&config.cloudcode);
 
     // Verify at least one account has valid credentials by getting a token
     let first_enabled = accounts
@@ -629,11 +774,11 @@ async fn run_server(config: Config) {
         .iter_mut()
         .find(|a| a.enabled && !a.is_invalid);
 
-    if let Some(account) = first_enabled {
+    let first_account_ok = if let Some(account) = first_enabled {
         match account.get_access_token(&http_client).await {
             Ok(access_token) => {
                 // Try to discover/update project ID and subscription tier
-                let existing_project = account.project_id.as_deref();
+                let existing_project = account.effective_project_id();
                 match cloudcode::discover_project_and_tier(
                     &http_client,
                     &access_token,
@@ -642,7 +787,9 @@ async fn run_server(config: Config) {
                 .await
                 {
                     Ok(result) => {
+                        // A pinned project is never replaced by discovery
                         if let Some(ref project_id) = result.project_id
+                            && account.pinned_project_id.is_none()
                             && account.project_id.as_deref() != Some(project_id)
                         {
                             info!(
@@ -665,6 +812,7 @@ async fn run_server(config: Config) {
                         warn!(error = %e, "loadCodeAssist failed, continuing with existing project");
                     }
                 }
+                true
             }
             Err(e) => {
                 warn!(
@@ -672,25 +820,85 @@ async fn run_server(config: Config) {
                     error = %e,
                     "Failed to get access token for first account, will retry on request"
                 );
+                false
             }
         }
-    }
+    } else {
+        false
+    };
 
-    if let Err(e) = accounts.save() {
-        warn!(error = %e, "Failed to save updated accounts");
+    // Strict mode: refuse to start unless some account can obtain a token
+    if config.server.startup_health_check && !first_account_ok {
+        let mut healthy = false;
+        for account in accounts
+            .accounts
+            .iter_mut()
+            .filter(|a| a.enabled && !a.is_invalid)
+            .skip(1)
+        {
+            match account.get_access_token(&http_client).await {
+                Ok(_) => {
+                    info!(email = %account.email, "Startup health check passed");
+                    healthy = true;
+                    break;
+                }
+                Err(e) => {
+                    warn!(
+                        email = %account.email,
+                        error = %e,
+                        "Startup health check: failed to get access token"
+                    );
+                }
+            }
+        }
+        if !healthy {
+            error!("Startup health check failed: no account could obtain a valid access token");
+            std::process::exit(1);
+        }
     }
 
+    let accounts_read_only = match accounts.save() {
+        Ok(()) => false,
+        Err(error::Error::Io(e)) if auth::accounts::is_read_only_error(&e) => {
+            warn!(
+                error = %e,
+                "Accounts file is not writable, keeping account state in memory for this session"
+            );
+            true
+        }
+        Err(e) => {
+            warn!(error = %e, "Failed to save updated accounts");
+            false
+        }
+    };
+
     let cache_config = config::get_config().cache.clone();
     let cloudcode_config = config::get_config().cloudcode.clone();
     let state = Arc::new(ServerState {
         accounts: RwLock::new(accounts),
         http_client,
         cloudcode_client: CloudCodeClient::new(&cloudcode_config),
-        cache: tokio::sync::Mutex::new(ResponseCache::new(
-            cache_config.enabled,
-            cache_config.ttl_seconds,
-            cache_config.max_entries,
-        )),
+        cache: tokio::sync::Mutex::new(
+            ResponseCache::new(
+                cache_config.enabled,
+                cache_config.ttl_seconds,
+                cache_config.max_entries,
+            )
+            .with_model_ttls(
+                cache_config
+                    .model_ttl
+                    .iter()
+                    .map(|rule| (rule.model.clone(), rule.ttl_seconds)),
+            ),
+        ),
+        accounts_read_only: AtomicBool::new(accounts_read_only),
+        in_flight: server::InFlightRequests::default(),
+        account_load: server::AccountLoad::default(),
+        active_streams: server::ActiveStreams::default(),
+        api_activity: server::ApiActivity::default(),
+        idempotency: server::IdempotencyCache::default(),
+        client_limits: server::ClientRateLimiter::default(),
+        account_limits: server::AccountLimitsCache::default(),
     });
 
     let refresh_state = state.clone();
@@ -698,17 +906,126 @@ async fn run_server(config: Config) {
         background_token_refresh(refresh_state).await;
     });
 
+    let usage_state = state.clone();
+    tokio::spawn(async move {
+        background_usage_persistence(usage_state).await;
+    });
+
+    if let Some(secs) = config::get_config().server.keepalive_ping_secs
+        && secs > 0
+    {
+        let ping_state = state.clone();
+        tokio::spawn(async move {
+            background_keepalive_ping(ping_state, std::time::Duration::from_secs(secs)).await;
+        });
+    }
+
     let addr: SocketAddr = format!("{}:{}", config.host(), config.port())
         .parse()
         .expect("Invalid address");
 
-    info!(address = %addr, "Starting AGCP proxy server");
-    if let Err(e) = run_server_with_shutdown(addr, state).await {
+    info!(address = %addr, config = %Config::path().display(), "Starting AGCP proxy server");
+    if let Err(e) = run_server_with_shutdown(addr, state.clone()).await {
         error!(error = %e, "Server error");
+        // This process holds the lock, so the files can only be its own or stale
+        let _ = std::fs::remove_file(get_pid_path());
+        let _ = std::fs::remove_file(get_addr_path());
         std::process::exit(1);
     }
+    persist_usage(&state).await;
 
     let _ = std::fs::remove_file(get_pid_path());
+    let _ = std::fs::remove_file(get_addr_path());
+}
+
+/// Take the instance lock for a server run with `--foreground`. The
+/// daemon's child waits for its parent to hand the lock over; anything else
+/// exits if another instance holds it. The returned lock must be held while
+/// running.
+fn lock_foreground(daemon_child: bool) -> std::fs::File {
+    let deadline = std::time::Instant::now() + LOCK_HANDOFF_TIMEOUT;
+    loop {
+        if let Some(lock) = try_acquire_lock() {
+            return lock;
+        }
+        if !daemon_child || std::time::Instant::now() >= deadline {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    match read_pid() {
+        Some(pid) => eprintln!("\x1b[31m●\x1b[0m AGCP is already running (PID: {})", pid),
+        None => eprintln!("\x1b[31m●\x1b[0m Another AGCP instance is starting"),
+    }
+    eprintln!("  \x1b[2mUse 'agcp stop' to stop it first\x1b[0m");
+    std::process::exit(1);
+}
+
+/// Background task that records token usage history and saves cumulative
+/// stats on an interval, so both are kept for headless runs too
+async fn background_usage_persistence(state: Arc<ServerState>) {
+    let interval = std::time::Duration::from_secs(30);
+    loop {
+        tokio::time::sleep(interval).await;
+        persist_usage(&state).await;
+    }
+}
+
+/// Add a token history snapshot and save it along with the stats and the
+/// accounts' daily budget usage.
+///
+/// The history file is re-read each time so a reset from the TUI's Usage tab
+/// is not overwritten. Quota periods follow the reset times in the account
+/// store, which are only known once quotas have been fetched.
+async fn persist_usage(state: &Arc<ServerState>) {
+    let totals: Vec<(String, u64)> = stats::get_stats()
+        .summary()
+        .models
+        .into_iter()
+        .map(|m| (m.model, m.input_tokens + m.output_tokens))
+        .collect();
+    let reset_ts = state
+        .accounts
+        .read()
+        .await
+        .accounts
+        .iter()
+        .flat_map(|a| a.quota.values())
+        .map(|q| q.reset_time)
+        .filter(|&t| t > 0)
+        .min();
+
+    let mut history = token_history::TokenHistory::load();
+    if history.record(totals, reset_ts) {
+        history.save();
+    }
+    stats::get_stats().save_persistent();
+
+    // Daily budget counters must survive restarts and show up in
+    // `agcp accounts list`, so save them while any budget is in use. They
+    // are serialized under the lock and written on a blocking thread after.
+    if !state.accounts_read_only.load(Ordering::Relaxed) {
+        let json = {
+            let accounts = state.accounts.read().await;
+            let has_budgets = accounts
+                .accounts
+                .iter()
+                .any(|a| a.daily_request_budget.is_some() || a.daily_token_budget.is_some());
+            if has_budgets {
+                serde_json::to_string_pretty(&*accounts).ok()
+            } else {
+                None
+            }
+        };
+        if let Some(json) = json {
+            server::save_accounts(
+                Arc::clone(state),
+                auth::accounts::AccountStore::path(),
+                json,
+            )
+            .await;
+        }
+    }
 }
 
 /// Background task that proactively refreshes tokens before they expire
@@ -760,9 +1077,140 @@ async fn background_token_refresh(state: Arc<ServerState>) {
     }
 }
 
+/// Background task that keeps the Cloud Code client's pooled connections warm
+/// and the active account's token fresh with a cheap model list fetch.
+async fn background_keepalive_ping(state: Arc<ServerState>, interval: std::time::Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+
+        // Refresh the token under the lock (no-op while it's still valid), then ping without it
+        let credentials = {
+            let mut accounts = state.accounts.write().await;
+            let active_id = accounts.active_account_id.clone();
+            let account = accounts
+                .accounts
+                .iter_mut()
+                .filter(|a| a.enabled && !a.is_invalid)
+                .min_by_key(|a| Some(&a.id) != active_id.as_ref());
+            match account {
+                Some(account) => match account.get_access_token(&state.http_client).await {
+                    Ok(token) => Some((token, account.effective_project_id().map(str::to_string))),
+                    Err(e) => {
+                        tracing::warn!(email = %account.email, error = %e, "Keepalive ping token refresh failed");
+                        None
+                    }
+                },
+                None => None,
+            }
+        };
+
+        let Some((access_token, project_id)) = credentials else {
+            continue;
+        };
+        match state
+            .cloudcode_client
+            .ping(&access_token, project_id.as_deref())
+            .await
+        {
+            Ok(()) => tracing::debug!("Keepalive ping successful"),
+            Err(e) => tracing::warn!(error = %e, "Keepalive ping failed"),
+        }
+    }
+}
+
+/// Parse a `--since`/`--until` value: either a relative duration back from now
+/// (`30s`, `15m`, `1h`, `2d`; never negative) or an absolute local time
+/// (`2024-06-01T10:00`, `2024-06-01 10:00:00`, `2024-06-01`, or full RFC 3339).
+fn parse_log_time_arg(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+    let value = value.trim();
+
+    // Plain digits only, so a sign (`-1h`) isn't taken as a duration
+    if let Some(unit) = value.chars().last()
+        && let digits = &value[..value.len() - unit.len_utf8()]
+        && !digits.is_empty()
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && let Ok(amount) = digits.parse::<u64>()
+    {
+        let multiplier = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => return None,
+        };
+        let secs = i64::try_from(amount.checked_mul(multiplier)?).ok()?;
+        return Utc::now().checked_sub_signed(chrono::Duration::try_seconds(secs)?);
+    }
+
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&Utc));
+    }
+
+    let naive = [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|fmt| NaiveDateTime::parse_from_str(value, fmt).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+    })?;
+
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Print log lines whose timestamp falls within `[since, until]`, reading the
+/// file forward from the start. With no bounds this dumps the whole file.
+///
+/// Lines without a timestamp (multi-line messages, panics) are attributed to
+/// the closest timestamped line before them.
+fn print_log_range(
+    log_path: &std::path::Path,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    until: Option<chrono::DateTime<chrono::Utc>>,
+) {
+    let file = File::open(log_path).expect("Failed to open log file");
+    let mut current_ts = None;
+
+    for line in BufReader::new(file).lines() {
+        let Ok(line) = line else { break };
+        if let Some(ts) = tui::parse_log_line_timestamp(&line) {
+            current_ts = Some(ts);
+        }
+
+        let Some(ts) = current_ts else {
+            // Nothing timestamped seen yet; only shown without a lower bound
+            if since.is_none() {
+                println!("{}", line);
+            }
+            continue;
+        };
+
+        if until.is_some_and(|until| ts > until) {
+            // Log lines are chronological, nothing later can match
+            break;
+        }
+        if since.is_none_or(|since| ts >= since) {
+            println!("{}", line);
+        }
+    }
+}
+
 fn run_logs_command(args: &[String]) {
     let mut follow = true;
     let mut lines = 50usize;
+    let mut all = false;
+    let mut since = None;
+    let mut until = None;
 
     let mut i = 0;
     while i < args.len() {
@@ -771,12 +1219,8 @@ fn run_logs_command(args: &[String]) {
                 i += 1;
                 if i < args.len() {
                     match args[i].parse::<usize>() {
-                        Ok(n) if n > 0 => lines = n,
-                        Ok(_) => {
-                            eprintln!(
-                                "\x1b[33mWarning:\x1b[0m --lines must be positive, using default (50)"
-                            );
-                        }
+                        Ok(0) => all = true,
+                        Ok(n) => lines = n,
                         Err(_) => {
                             eprintln!(
                                 "\x1b[33mWarning:\x1b[0m '{}' is not a valid number for --lines, using default (50)",
@@ -787,11 +1231,36 @@ fn run_logs_command(args: &[String]) {
                 }
             }
             "--no-follow" => follow = false,
+            "--all" => all = true,
+            flag @ ("--since" | "--until") => {
+                i += 1;
+                let Some(value) = args.get(i) else {
+                    eprintln!("\x1b[31mError:\x1b[0m {} requires a time value", flag);
+                    std::process::exit(1);
+                };
+                let Some(time) = parse_log_time_arg(value) else {
+                    eprintln!(
+                        "\x1b[31mError:\x1b[0m invalid time '{}' (use e.g. 1h, 30m, 2024-06-01T10:00)",
+                        value
+                    );
+                    std::process::exit(1);
+                };
+                if flag == "--since" {
+                    since = Some(time);
+                } else {
+                    until = Some(time);
+                }
+            }
             _ => {}
         }
         i += 1;
     }
 
+    // A bounded time range ends the output, so there is nothing to follow
+    if until.is_some() {
+        follow = false;
+    }
+
     let log_path = get_log_path();
 
     if !log_path.exists() {
@@ -799,6 +1268,16 @@ fn run_logs_command(args: &[String]) {
         return;
     }
 
+    // Full dumps and time windows read forward from the start of the file
+    // instead of using the reverse-chunk tail reader below
+    if all || since.is_some() || until.is_some() {
+        print_log_range(&log_path, since, until);
+        if follow {
+            follow_log(&log_path);
+        }
+        return;
+    }
+
     // Print last N lines (read from end of file to avoid loading entire file)
     let mut file = File::open(&log_path).expect("Failed to open log file");
     let file_len = file.metadata().map(|m| m.len()).unwrap_or(0);
@@ -843,23 +1322,22 @@ fn run_logs_command(args: &[String]) {
         return;
     }
 
-    // Follow mode
-    println!("\x1b[2m--- Following logs (Ctrl+C to stop) ---\x1b[0m");
+    follow_log(&log_path);
+}
 
-    let mut file = File::open(&log_path).expect("Failed to open log file");
-    file.seek(SeekFrom::End(0)).expect("Failed to seek");
+/// Print new lines appended to the log file until interrupted.
+fn follow_log(log_path: &std::path::Path) {
+    println!("\x1b[2m--- Following logs (Ctrl+C to stop) ---\x1b[0m");
 
-    let mut reader = BufReader::new(file);
+    // Reopens the path when the log is rotated or truncated, like `tail -F`
+    let mut file = tui::FollowedFile::new(log_path);
     loop {
-        let mut line = String::new();
-        match reader.read_line(&mut line) {
-            Ok(0) => {
-                std::thread::sleep(std::time::Duration::from_millis(100));
-            }
-            Ok(_) => {
-                print!("{}", line);
-            }
-            Err(_) => break,
+        let lines = file.read_new_lines();
+        if lines.is_empty() {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        for line in lines {
+            println!("{}", line);
         }
     }
 }
@@ -892,6 +1370,13 @@ fn run_config_command() {
     println!("  {}[server]{}", DIM, RESET);
     println!("    port = {}{}{}", CYAN, config.server.port, RESET);
     println!("    host = {}\"{}\"{}", CYAN, config.server.host, RESET);
+    if let Some(interface) = &config.server.interface {
+        let resolved = config.server.interface_ip.as_deref().unwrap_or("not found");
+        println!(
+            "    interface = {}\"{}\"{} {}({}){}",
+            CYAN, interface, RESET, DIM, resolved, RESET
+        );
+    }
     if config.server.api_key.is_some() {
         println!(
             "    api_key = {}\"****\"{} {}(set){}",
@@ -902,10 +1387,26 @@ fn run_config_command() {
 
     println!("  {}[logging]{}", DIM, RESET);
     println!("    debug = {}{}{}", CYAN, config.logging.debug, RESET);
+    if let Some(filter) = &config.logging.filter {
+        println!("    filter = {}\"{}\"{}", CYAN, filter, RESET);
+    }
     println!(
         "    log_requests = {}{}{}",
         CYAN, config.logging.log_requests, RESET
     );
+    if let Some(path) = config.logging.trace_path() {
+        println!("    trace_file = {}{}{}", CYAN, path.display(), RESET);
+    }
+    if config.logging.redact || config.logging.redact_system || config.logging.redact_tools {
+        println!(
+            "    redact = {}{}{} (system: {}, tools: {})",
+            CYAN,
+            config.logging.redact,
+            RESET,
+            config.logging.redact_system,
+            config.logging.redact_tools
+        );
+    }
     println!();
 
     println!("  {}[accounts]{}", DIM, RESET);
@@ -921,8 +1422,25 @@ fn run_config_command() {
         "    fallback = {}{}{}",
         CYAN, config.accounts.fallback, RESET
     );
+    println!(
+        "    sticky_rate_limit_grace_secs = {}{}{}",
+        CYAN, config.accounts.sticky_rate_limit_grace_secs, RESET
+    );
+    println!(
+        "    budget_reset_hour_utc = {}{}{}",
+        CYAN, config.accounts.budget_reset_hour_utc, RESET
+    );
     println!();
 
+    let conflicts = models::mapping_conflicts(&config.mappings.rules, &config.mappings.aliases);
+    if !conflicts.is_empty() {
+        println!("{}Mapping conflicts:{}", BOLD, RESET);
+        for conflict in &conflicts {
+            println!("  {}!{} {}", YELLOW, RESET, conflict);
+        }
+        println!();
+    }
+
     println!("{}Environment variables:{}", BOLD, RESET);
     let api_key_set = std::env::var("API_KEY").is_ok();
     if api_key_set {
@@ -994,7 +1512,7 @@ async fn run_restart_command() {
     if let Some(pid) = read_pid()
         && is_process_running(pid)
     {
-        println!("\x1b[33m●\x1b[0m Stopping AGCP (PID: {})...", pid);
+        say!("\x1b[33m●\x1b[0m Stopping AGCP (PID: {})...", pid);
 
         #[cfg(unix)]
         {
@@ -1038,6 +1556,29 @@ async fn run_restart_command() {
     run_daemon(config, false).await;
 }
 
+/// One undecorated line for shell prompts, e.g. `agcp:up:8080:3acct` or
+/// `agcp:down`. Only reads local files, so it never waits on the server.
+fn run_status_short() {
+    if !read_pid().is_some_and(is_process_running) {
+        println!("agcp:down");
+        return;
+    }
+
+    let port = read_addr()
+        .and_then(|addr| addr.rsplit_once(':')?.1.parse::<u16>().ok())
+        .unwrap_or_else(|| Config::load().unwrap_or_default().port());
+    let accounts = AccountStore::load()
+        .map(|store| {
+            store
+                .accounts
+                .iter()
+                .filter(|a| a.enabled && !a.is_invalid)
+                .count()
+        })
+        .unwrap_or(0);
+    println!("agcp:up:{}:{}acct", port, accounts);
+}
+
 fn run_status_command() {
     if let Some(pid) = read_pid() {
         if is_process_running(pid) {
@@ -1056,8 +1597,30 @@ fn run_status_command() {
                 print_listening_address(config.host(), config.port());
             }
 
+            // Flag a daemon that was started from a different build than this binary
+            if let Ok(version) = fetch_json_sync(&addr, "/version")
+                && let Some(running) = version["version"].as_str()
+            {
+                let sha = version["git_sha"].as_str().unwrap_or("unknown");
+                if running == env!("CARGO_PKG_VERSION") && sha == env!("AGCP_GIT_SHA") {
+                    println!("  Version: {}{}{} ({})", CYAN, running, RESET, sha);
+                } else {
+                    println!(
+                        "  Version: {}{}{} ({}) {}- this binary is {}, run 'agcp restart'{}",
+                        YELLOW,
+                        running,
+                        RESET,
+                        sha,
+                        DIM,
+                        env!("CARGO_PKG_VERSION"),
+                        RESET
+                    );
+                }
+            }
+
             // Try to fetch stats from running server
-            if let Ok(stats) = fetch_stats_sync(&addr) {
+            let stats = fetch_json_sync(&addr, "/stats").ok();
+            if let Some(ref stats) = stats {
                 // Uptime
                 if let Some(uptime_secs) = stats["uptime_seconds"].as_u64() {
                     println!("  Uptime: {}{}{}", CYAN, format_uptime(uptime_secs), RESET);
@@ -1071,7 +1634,28 @@ fn run_status_command() {
                 }
             }
 
-            if let Ok(store) = auth::accounts::AccountStore::load() {
+            // Prefer the server's live account state; fall back to accounts.json
+            let live_accounts = stats
+                .as_ref()
+                .map(|s| &s["accounts"])
+                .filter(|a| a.is_object());
+            if let Some(accounts) = live_accounts {
+                println!(
+                    "  Accounts: {}{}{} active ({} strategy)",
+                    CYAN,
+                    accounts["enabled"].as_u64().unwrap_or(0),
+                    RESET,
+                    accounts["strategy"].as_str().unwrap_or("unknown")
+                );
+                if let Some(limited) = accounts["rate_limited"].as_u64()
+                    && limited > 0
+                {
+                    println!("  Rate-limited: {}{}{}", YELLOW, limited, RESET);
+                }
+                if let Some(email) = accounts["active"]["email"].as_str() {
+                    println!("  Current:  {}{}{}", DIM, email, RESET);
+                }
+            } else if let Ok(store) = auth::accounts::AccountStore::load() {
                 let enabled = store
                     .accounts
                     .iter()
@@ -1160,8 +1744,8 @@ fn handle_corrupted_accounts_file(error: &dyn std::error::Error) -> bool {
     true
 }
 
-/// Synchronous version of fetch_stats_http for use in non-async context
-fn fetch_stats_sync(addr: &str) -> Result<serde_json::Value, String> {
+/// Synchronous JSON GET against the running server for use in non-async context
+fn fetch_json_sync(addr: &str, path: &str) -> Result<serde_json::Value, String> {
     use std::io::{Read, Write};
     use std::net::TcpStream;
     use std::time::Duration;
@@ -1177,8 +1761,8 @@ fn fetch_stats_sync(addr: &str) -> Result<serde_json::Value, String> {
         .map_err(|e| e.to_string())?;
 
     let request = format!(
-        "GET /stats HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
-        addr
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, addr
     );
 
     stream
@@ -1232,7 +1816,6 @@ fn try_acquire_lock() -> Option<std::fs::File> {
     }
 }
 
-#[cfg(unix)]
 fn write_pid(pid: u32) {
     let pid_path = get_pid_path();
     if let Some(parent) = pid_path.parent() {
@@ -1284,8 +1867,24 @@ fn get_local_ip() -> Option<String> {
     Some(addr.ip().to_string())
 }
 
+/// Name of the configured `server.interface`, if `host` is its address
+fn bound_interface(host: &str) -> Option<String> {
+    let config = config::get_config();
+    let interface = config.server.interface.as_ref()?;
+    (config.server.interface_ip.as_deref() == Some(host)).then(|| interface.clone())
+}
+
 /// Print the listening address, showing LAN IP when bound to all interfaces
+/// and the interface name when bound through `server.interface`
 fn print_listening_address(host: &str, port: u16) {
+    // Quiet mode prints just the URL for scripts
+    if is_quiet() {
+        match get_local_ip().filter(|_| host == "0.0.0.0") {
+            Some(lan_ip) => println!("http://{lan_ip}:{port}"),
+            None => println!("http://{host}:{port}"),
+        }
+        return;
+    }
     if host == "0.0.0.0" {
         // Network mode - show the actual LAN IP
         if let Some(lan_ip) = get_local_ip() {
@@ -1296,6 +1895,8 @@ fn print_listening_address(host: &str, port: u16) {
                 "  Listening on {CYAN}http://0.0.0.0:{port}{RESET} {DIM}(all interfaces){RESET}"
             );
         }
+    } else if let Some(interface) = bound_interface(host) {
+        println!("  Listening on {CYAN}http://{host}:{port}{RESET} {DIM}({interface}){RESET}");
     } else {
         println!("  Listening on {CYAN}http://{host}:{port}{RESET}");
     }
@@ -1331,15 +1932,20 @@ fn find_process_using_port(_port: u16) -> Option<String> {
     None
 }
 
-fn init_logging_foreground(debug: bool) {
-    let filter = if debug {
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("agcp=debug,warn"))
-    } else {
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("agcp=info,warn"))
+/// `RUST_LOG` wins, then `[logging] filter`, then the debug flag
+fn init_logging_foreground(debug: bool, directives: Option<&str>) {
+    let default = match directives {
+        Some(directives) => directives,
+        None if debug => "agcp=debug,warn",
+        None => "agcp=info,warn",
     };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default));
 
+    // Spans are debug-level, so their close events (with time.busy/time.idle)
+    // only show up with --debug, breaking a request's latency down by phase
     tracing_subscriber::fmt()
         .with_env_filter(filter)
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
         .with_target(false)
         .with_thread_ids(false)
         .compact()
@@ -1352,9 +1958,15 @@ async fn run_server_with_shutdown(
 ) -> std::io::Result<()> {
     let listener = tokio::net::TcpListener::bind(addr).await?;
     info!(address = %addr, "Server listening");
+    // Only now, so `agcp status`, `stop` and `restart` never find a server
+    // that failed to start
+    write_pid(std::process::id());
+    write_addr(&addr.ip().to_string(), addr.port());
 
     let shutdown = shutdown_signal();
     tokio::pin!(shutdown);
+    let idle = idle_shutdown(state.clone());
+    tokio::pin!(idle);
 
     loop {
         tokio::select! {
@@ -1362,6 +1974,10 @@ async fn run_server_with_shutdown(
                 info!("Received shutdown signal, stopping server");
                 break;
             }
+            _ = &mut idle => {
+                info!("No API requests within idle_shutdown_secs, stopping server");
+                break;
+            }
             result = listener.accept() => {
                 let (stream, remote_addr) = result?;
                 let state = state.clone();
@@ -1379,6 +1995,28 @@ async fn run_server_with_shutdown(
     Ok(())
 }
 
+/// Resolves once no `/v1/*` request has arrived for `[server] idle_shutdown_secs`
+/// and none is still in flight; never resolves when the option is unset.
+async fn idle_shutdown(state: Arc<ServerState>) {
+    let Some(secs) = config::get_config()
+        .server
+        .idle_shutdown_secs
+        .filter(|&secs| secs > 0)
+    else {
+        return std::future::pending().await;
+    };
+    let limit = std::time::Duration::from_secs(secs);
+
+    loop {
+        let idle_for = state.api_activity.idle_for();
+        if idle_for >= limit && state.account_load.snapshot().is_empty() {
+            return;
+        }
+        let wait = limit.saturating_sub(idle_for);
+        tokio::time::sleep(wait.max(std::time::Duration::from_secs(1))).await;
+    }
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         tokio::signal::ctrl_c()
@@ -1448,9 +2086,11 @@ fn print_help() {
 {DIM}Anthropic → Google Cloud Code Proxy{RESET}
 
 {BOLD}USAGE:{RESET}  {GREEN}agcp{RESET} [COMMAND] [OPTIONS]
+        {GREEN}agcp{RESET} [OPTIONS]  {DIM}(same as agcp serve){RESET}
 
 {BOLD}COMMANDS{RESET}
 ┌─────────────┬────────────────────────────────────────┐
+│ {YELLOW}serve{RESET}       │ Start the server (daemon by default)   │
 │ {YELLOW}login{RESET}       │ Authenticate with Google OAuth         │
 │ {YELLOW}setup{RESET}       │ Configure AI tools to use AGCP         │
 │ {YELLOW}accounts{RESET}    │ Manage multiple accounts               │
@@ -1475,14 +2115,25 @@ fn print_help() {
 │ {YELLOW}-p{RESET}, {YELLOW}--port{RESET} <PORT>    │ Server port {DIM}(default: 8080){RESET}           │
 │ {YELLOW}--host{RESET} <HOST>        │ Bind address {DIM}(default: 127.0.0.1){RESET}     │
 │ {YELLOW}--network{RESET}            │ Listen on all interfaces (LAN access) │
+│ {YELLOW}--config{RESET} <PATH>      │ Config file {DIM}(or AGCP_CONFIG){RESET}          │
 │ {YELLOW}-f{RESET}, {YELLOW}--foreground{RESET}     │ Run in foreground (don't daemonize)   │
 │ {YELLOW}-d{RESET}, {YELLOW}--debug{RESET}          │ Enable debug logging                  │
 │ {YELLOW}--fallback{RESET}           │ Enable model fallback on exhaustion   │
+│ {YELLOW}-q{RESET}, {YELLOW}--quiet{RESET}          │ Only print errors and results         │
 │ {YELLOW}-h{RESET}, {YELLOW}--help{RESET}           │ Show this help message                │
 │ {YELLOW}-V{RESET}, {YELLOW}--version{RESET}        │ Show version information              │
 ├──────────────────────┼───────────────────────────────────────┤
 │ {YELLOW}-n{RESET}, {YELLOW}--lines{RESET} <N>      │ {DIM}logs:{RESET} Show last N lines {DIM}(default: 50){RESET} │
 │ {YELLOW}--no-follow{RESET}          │ {DIM}logs:{RESET} Don't follow log output         │
+│ {YELLOW}--all{RESET}                │ {DIM}logs:{RESET} Print the whole file {DIM}(or -n 0){RESET}  │
+│ {YELLOW}--since{RESET} <TIME>       │ {DIM}logs:{RESET} Lines from TIME (1h, ISO date)  │
+│ {YELLOW}--until{RESET} <TIME>       │ {DIM}logs:{RESET} Up to TIME {DIM}(no follow){RESET}          │
+│ {YELLOW}-y{RESET}, {YELLOW}--yes{RESET}            │ {DIM}upgrade:{RESET} Install the update now       │
+│ {YELLOW}--short{RESET}              │ {DIM}status:{RESET} One line for a prompt         │
+│ {YELLOW}-w{RESET}, {YELLOW}--watch{RESET}          │ {DIM}stats:{RESET} Live refresh until Ctrl+C      │
+│ {YELLOW}--interval{RESET} <SECS>    │ {DIM}stats:{RESET} Refresh interval {DIM}(default: 2){RESET}  │
+│ {YELLOW}-r{RESET}, {YELLOW}--request{RESET} <FILE> │ {DIM}test:{RESET} Send a JSON request body        │
+│ {YELLOW}--endpoint{RESET} <PATH>    │ {DIM}test:{RESET} Override the detected endpoint  │
 └──────────────────────┴───────────────────────────────────────┘
 
 {BOLD}MODEL ALIASES{RESET}
@@ -1496,17 +2147,31 @@ fn print_help() {
 │ {YELLOW}3-flash{RESET}         │ gemini-3-flash             │
 │ {YELLOW}3-pro{RESET}           │ gemini-3-pro-high          │
 │ {YELLOW}oss{RESET}             │ gpt-oss-120b-medium        │
-└─────────────────┴────────────────────────────┘
+└─────────────────┴────────────────────────────┘"#
+    );
+
+    // User-defined aliases from [mappings.aliases]
+    let config = config::get_config();
+    if !config.mappings.aliases.is_empty() {
+        println!("{DIM}Configured:{RESET}");
+        for (alias, target) in &config.mappings.aliases {
+            println!("  {YELLOW}{alias}{RESET} → {target}");
+        }
+    }
 
+    println!(
+        r#"
 {BOLD}EXAMPLES{RESET}
   {GREEN}agcp login{RESET}                    {DIM}# First-time setup{RESET}
   {GREEN}agcp login --no-browser{RESET}       {DIM}# Headless server (manual code){RESET}
   {GREEN}agcp setup{RESET}                    {DIM}# Configure AI tools to use AGCP{RESET}
+  {GREEN}agcp setup --tool codex -y{RESET}    {DIM}# Configure one tool without prompts{RESET}
   {GREEN}agcp{RESET}                          {DIM}# Start proxy as daemon{RESET}
   {GREEN}agcp --port 3000{RESET}              {DIM}# Start on custom port{RESET}
   {GREEN}agcp --fallback{RESET}               {DIM}# Enable model fallback{RESET}
   {GREEN}agcp logs{RESET}                     {DIM}# View logs{RESET}
   {GREEN}agcp logs -n 100 --no-follow{RESET}  {DIM}# Last 100 lines, no follow{RESET}
+  {GREEN}agcp logs --since 1h{RESET}          {DIM}# Logs from the last hour{RESET}
   {GREEN}agcp -f -d{RESET}                    {DIM}# Foreground with debug{RESET}
 
 {DIM}Config: ~/.config/agcp/config.toml
@@ -1640,11 +2305,11 @@ async fn run_login(no_browser: bool) -> error::Result<()> {
         extract_code_from_input(input, &state)?
     } else {
         // Normal mode - open browser and wait for callback
-        println!("Opening browser for authentication...");
-        println!();
-        println!("If the browser doesn't open, visit this URL:");
+        say!("Opening browser for authentication...");
+        say!();
+        say!("If the browser doesn't open, visit this URL:");
         println!("{}", auth_url);
-        println!();
+        say!();
 
         #[cfg(target_os = "macos")]
         {
@@ -1703,7 +2368,7 @@ async fn run_login(no_browser: bool) -> error::Result<()> {
             Ok(result) => {
                 spinner.stop();
                 if let Some(ref id) = result.project_id {
-                    println!("\x1b[32m✓\x1b[0m Project ID: {}", id);
+                    say!("\x1b[32m✓\x1b[0m Project ID: {}", id);
                 }
                 if let Some(ref tier) = result.subscription_tier {
                     let tier_badge = match tier.as_str() {
@@ -1711,7 +2376,7 @@ async fn run_login(no_browser: bool) -> error::Result<()> {
                         "pro" => "\x1b[36mPro\x1b[0m",
                         _ => "\x1b[33mFree\x1b[0m",
                     };
-                    println!("\x1b[32m✓\x1b[0m Subscription: {}", tier_badge);
+                    say!("\x1b[32m✓\x1b[0m Subscription: {}", tier_badge);
                 }
                 (result.project_id, result.subscription_tier)
             }
@@ -1736,9 +2401,9 @@ async fn run_login(no_browser: bool) -> error::Result<()> {
 
     account.save()?;
 
-    println!("Account saved to ~/.config/agcp/account.json");
-    println!();
-    println!("You can now start the proxy with: agcp");
+    say!("Account saved to ~/.config/agcp/account.json");
+    say!();
+    say!("You can now start the proxy with: agcp");
 
     Ok(())
 }
@@ -1769,7 +2434,7 @@ async fn run_quota_command() -> error::Result<()> {
             .await
             .map_err(|e| error::Error::Auth(error::AuthError::RefreshFailed(e.to_string())))?;
 
-        cloudcode::fetch_model_quotas(&http_client, &access_token, account.project_id.as_deref())
+        cloudcode::fetch_model_quotas(&http_client, &access_token, account.effective_project_id())
             .await
             .map_err(|e| error::Error::Api(error::ApiError::InvalidRequest { message: e }))
     }
@@ -1783,7 +2448,7 @@ async fn run_quota_command() -> error::Result<()> {
     Ok(())
 }
 
-async fn run_upgrade_command() {
+async fn run_upgrade_command(yes: bool) {
     let current_version = env!("CARGO_PKG_VERSION");
     let repo = env!("CARGO_PKG_REPOSITORY");
 
@@ -1866,8 +2531,6 @@ async fn run_upgrade_command() {
         YELLOW, RESET, current_clean, latest_clean
     );
     println!();
-    println!("{}To upgrade:{}", BOLD, RESET);
-    println!();
 
     // Check if cargo is available
     let has_cargo = std::process::Command::new("cargo")
@@ -1876,9 +2539,38 @@ async fn run_upgrade_command() {
         .map(|o| o.status.success())
         .unwrap_or(false);
 
-    if has_cargo {
-        println!("  {}# Via cargo (recommended){}", DIM, RESET);
-        println!("  {}cargo install agcp --force{}", CYAN, RESET);
+    if yes {
+        let result = if let Some(asset) = release_asset_name(latest_clean) {
+            install_release_binary(repo_path, latest_clean, &asset).await
+        } else if has_cargo {
+            install_with_cargo()
+        } else {
+            Err(format!(
+                "No prebuilt binary for {} and cargo is not installed",
+                env!("AGCP_TARGET")
+            ))
+        };
+
+        match result {
+            Ok(()) => {
+                println!("{}✓ Upgraded to v{}{}", GREEN, latest_clean, RESET);
+                println!();
+                offer_daemon_restart().await;
+                return;
+            }
+            Err(e) => {
+                eprintln!("{}Upgrade failed:{} {}", RED, RESET, e);
+                eprintln!();
+            }
+        }
+    }
+
+    println!("{}To upgrade:{}", BOLD, RESET);
+    println!();
+
+    if has_cargo {
+        println!("  {}# Via cargo (recommended){}", DIM, RESET);
+        println!("  {}cargo install agcp --force{}", CYAN, RESET);
         println!();
     }
 
@@ -1888,6 +2580,172 @@ async fn run_upgrade_command() {
     println!("  {}# Or download from:{}", DIM, RESET);
     println!("  {}{}/releases/latest{}", CYAN, repo, RESET);
     println!();
+
+    if yes {
+        std::process::exit(1);
+    }
+}
+
+/// Release tarball for this build's target, if the release workflow publishes one
+fn release_asset_name(version: &str) -> Option<String> {
+    const PREBUILT_TARGETS: &[&str] = &[
+        "x86_64-unknown-linux-gnu",
+        "aarch64-unknown-linux-gnu",
+        "x86_64-apple-darwin",
+        "aarch64-apple-darwin",
+    ];
+
+    let target = env!("AGCP_TARGET");
+    PREBUILT_TARGETS
+        .contains(&target)
+        .then(|| format!("agcp-v{}-{}.tar.gz", version, target))
+}
+
+/// Download a release tarball, verify it against the release's SHA256SUMS,
+/// and swap it in for the running executable.
+async fn install_release_binary(repo_path: &str, version: &str, asset: &str) -> Result<(), String> {
+    use sha2::{Digest, Sha256};
+
+    let base_url = format!(
+        "https://github.com/{}/releases/download/v{}",
+        repo_path, version
+    );
+    let client = auth::HttpClient::new();
+
+    println!("  {}Downloading {}...{}", DIM, asset, RESET);
+    let sums = client
+        .download(&format!("{}/SHA256SUMS", base_url))
+        .await
+        .map_err(|e| format!("Could not fetch SHA256SUMS: {}", e))?;
+    let sums = String::from_utf8_lossy(&sums);
+    let expected = sums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, name)| name.trim().trim_start_matches('*') == asset)
+        .map(|(hash, _)| hash.to_lowercase())
+        .ok_or_else(|| format!("{} is not listed in SHA256SUMS", asset))?;
+
+    let tarball = client
+        .download(&format!("{}/{}", base_url, asset))
+        .await
+        .map_err(|e| format!("Could not download {}: {}", asset, e))?;
+
+    let actual: String = Sha256::digest(&tarball)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if actual != expected {
+        return Err(format!(
+            "Checksum mismatch for {} (expected {}, got {})",
+            asset, expected, actual
+        ));
+    }
+    println!("  {}Checksum verified{}", DIM, RESET);
+
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let exe = exe.canonicalize().unwrap_or(exe);
+    let exe_dir = exe
+        .parent()
+        .ok_or_else(|| "Cannot determine executable directory".to_string())?;
+
+    // Unpack next to the executable so the final rename stays on one filesystem
+    let staging = exe_dir.join(format!(".agcp-upgrade-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::create_dir_all(&staging).map_err(|e| {
+        format!(
+            "Cannot write to {} ({}); upgrade with the tool that installed agcp",
+            exe_dir.display(),
+            e
+        )
+    })?;
+
+    let result = unpack_and_replace(&tarball, &staging, &exe);
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+fn unpack_and_replace(
+    tarball: &[u8],
+    staging: &std::path::Path,
+    exe: &std::path::Path,
+) -> Result<(), String> {
+    let archive = staging.join("agcp.tar.gz");
+    std::fs::write(&archive, tarball).map_err(|e| e.to_string())?;
+
+    let status = std::process::Command::new("tar")
+        .arg("xzf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(staging)
+        .status()
+        .map_err(|e| format!("Failed to run tar: {}", e))?;
+    if !status.success() {
+        return Err("Failed to unpack release archive".to_string());
+    }
+
+    let new_exe = staging.join("agcp");
+    if !new_exe.is_file() {
+        return Err("Release archive does not contain an agcp binary".to_string());
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&new_exe, std::fs::Permissions::from_mode(0o755))
+            .map_err(|e| e.to_string())?;
+    }
+
+    // rename() atomically replaces the path; the running process keeps the old inode
+    std::fs::rename(&new_exe, exe).map_err(|e| {
+        format!(
+            "Cannot replace {} ({}); upgrade with the tool that installed agcp",
+            exe.display(),
+            e
+        )
+    })
+}
+
+fn install_with_cargo() -> Result<(), String> {
+    println!("  {}Running cargo install agcp --force...{}", DIM, RESET);
+    let status = std::process::Command::new("cargo")
+        .args(["install", "agcp", "--force"])
+        .status()
+        .map_err(|e| format!("Failed to run cargo: {}", e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("cargo install exited with {}", status))
+    }
+}
+
+/// After an upgrade, restart a running daemon so it picks up the new binary
+async fn offer_daemon_restart() {
+    use std::io::IsTerminal;
+
+    if !read_pid().is_some_and(is_process_running) {
+        return;
+    }
+
+    if std::io::stdin().is_terminal() {
+        print!("  Restart the running daemon now? [Y/n] ");
+        std::io::Write::flush(&mut std::io::stdout()).ok();
+
+        let mut input = String::new();
+        if std::io::stdin().read_line(&mut input).is_ok()
+            && matches!(input.trim().to_lowercase().as_str(), "" | "y" | "yes")
+        {
+            println!();
+            run_restart_command().await;
+            return;
+        }
+    }
+
+    println!(
+        "  {}The daemon is still running the old version. Run 'agcp restart' to load the update.{}",
+        DIM, RESET
+    );
+    println!();
 }
 
 async fn fetch_latest_version(api_url: &str) -> Result<String, String> {
@@ -1933,12 +2791,63 @@ fn compare_versions(a: &str, b: &str) -> bool {
     false
 }
 
-async fn run_test_command() {
+async fn run_test_command(args: &[String]) {
+    let mut request_file: Option<String> = None;
+    let mut endpoint: Option<String> = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--request" | "-r" => {
+                i += 1;
+                match args.get(i) {
+                    Some(path) => request_file = Some(path.clone()),
+                    None => {
+                        eprintln!("\x1b[31mMissing value:\x1b[0m --request requires a file path");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--endpoint" => {
+                i += 1;
+                match args.get(i) {
+                    Some(path) if path.starts_with('/') => endpoint = Some(path.clone()),
+                    _ => {
+                        eprintln!(
+                            "\x1b[31mMissing value:\x1b[0m --endpoint requires a path like /v1/messages"
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    // Read the request body up front so a bad file fails before any network work
+    let request = request_file.map(|path| {
+        let body = std::fs::read(&path).unwrap_or_else(|e| {
+            eprintln!("\x1b[31mError:\x1b[0m Cannot read {}: {}", path, e);
+            std::process::exit(1);
+        });
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap_or_else(|e| {
+            eprintln!("\x1b[31mError:\x1b[0m {} is not valid JSON: {}", path, e);
+            std::process::exit(1);
+        });
+        let path = endpoint
+            .clone()
+            .unwrap_or_else(|| test_request_endpoint(&json).to_string());
+        let stream = json["stream"].as_bool().unwrap_or(false);
+        (path, body, stream)
+    });
+
     println!();
     println!("{}{}Testing AGCP...{}", BOLD, CYAN, RESET);
     println!();
 
     let config = Config::load().unwrap_or_default();
+    let api_key = config.server.api_key.as_deref();
     let addr = format!("{}:{}", config.host(), config.port());
     let base_url = format!("http://{}", addr);
 
@@ -1976,7 +2885,7 @@ async fn run_test_command() {
     print!("  Models:  ");
     std::io::Write::flush(&mut std::io::stdout()).ok();
 
-    match test_models_endpoint(&base_url).await {
+    match test_models_endpoint(&base_url, api_key).await {
         Ok(count) => {
             println!("{} models available {}✓{}", count, GREEN, RESET);
         }
@@ -1987,6 +2896,24 @@ async fn run_test_command() {
         }
     }
 
+    // Step 4: Send the request from --request, if any
+    if let Some((path, body, stream)) = request {
+        println!(
+            "  Request: POST {}{}",
+            path,
+            if stream { " (streaming)" } else { "" }
+        );
+        println!();
+        if let Err(e) = send_test_request(&base_url, &path, body, api_key, stream).await {
+            eprintln!("  {}Error: {}{}", RED, e, RESET);
+            std::process::exit(1);
+        }
+        println!();
+        println!("{}● Request succeeded!{}", GREEN, RESET);
+        println!();
+        return;
+    }
+
     println!();
     println!("{}● Setup verified!{}", GREEN, RESET);
     println!();
@@ -1997,25 +2924,135 @@ async fn run_test_command() {
     println!();
 }
 
-async fn test_models_endpoint(base_url: &str) -> Result<usize, String> {
-    use http_body_util::{BodyExt, Empty};
+/// Pick the endpoint for a request body read by `agcp test --request`.
+/// `input` means the Responses API; OpenAI-only fields or roles mean Chat
+/// Completions; anything else is sent as an Anthropic Messages request.
+fn test_request_endpoint(body: &serde_json::Value) -> &'static str {
+    if body.get("messages").is_none() && body.get("input").is_some() {
+        return "/v1/responses";
+    }
+    const OPENAI_FIELDS: &[&str] = &[
+        "max_completion_tokens",
+        "response_format",
+        "stream_options",
+        "frequency_penalty",
+        "presence_penalty",
+        "n",
+    ];
+    let openai_message = body["messages"].as_array().is_some_and(|messages| {
+        messages.iter().any(|m| {
+            matches!(m["role"].as_str(), Some("system" | "developer" | "tool"))
+                || m.get("tool_calls").is_some()
+        })
+    });
+    if openai_message || OPENAI_FIELDS.iter().any(|f| body.get(*f).is_some()) {
+        "/v1/chat/completions"
+    } else {
+        "/v1/messages"
+    }
+}
+
+/// Send a request to the local server without TLS, authenticating with the
+/// configured API key if one is set.
+async fn local_request(
+    base_url: &str,
+    method: &str,
+    path: &str,
+    body: Vec<u8>,
+    api_key: Option<&str>,
+) -> Result<hyper::Response<hyper::body::Incoming>, String> {
+    use http_body_util::Full;
     use hyper::Request;
     use hyper::body::Bytes;
     use hyper_util::client::legacy::Client;
     use hyper_util::rt::TokioExecutor;
 
-    let url = format!("{}/v1/models", base_url);
+    let url = format!("{}{}", base_url, path);
 
     // Use plain HTTP client for localhost
-    let client: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new()).build_http();
+    let client: Client<_, Full<Bytes>> = Client::builder(TokioExecutor::new()).build_http();
 
-    let req = Request::builder()
-        .method("GET")
-        .uri(&url)
-        .body(Empty::new())
+    let mut builder = Request::builder().method(method).uri(&url);
+    if !body.is_empty() {
+        builder = builder.header("Content-Type", "application/json");
+    }
+    if let Some(key) = api_key {
+        builder = builder.header("x-api-key", key);
+    }
+    // Ask for the serving account, which `agcp test` shows
+    builder = builder.header("x-agcp-report-account", "true");
+    let req = builder
+        .body(Full::new(Bytes::from(body)))
         .map_err(|e| e.to_string())?;
 
-    let response = client.request(req).await.map_err(|e| e.to_string())?;
+    client.request(req).await.map_err(|e| e.to_string())
+}
+
+/// Send the body from `agcp test --request` and print the response,
+/// streaming frames to stdout as they arrive when `stream` is set.
+async fn send_test_request(
+    base_url: &str,
+    path: &str,
+    body: Vec<u8>,
+    api_key: Option<&str>,
+    stream: bool,
+) -> Result<(), String> {
+    use http_body_util::BodyExt;
+
+    let response = local_request(base_url, "POST", path, body, api_key).await?;
+    let status = response.status();
+    // The server names the account it used; show its email when it's known here
+    if let Some(id) = response
+        .headers()
+        .get("x-agcp-account")
+        .and_then(|v| v.to_str().ok())
+    {
+        let email = auth::accounts::AccountStore::load().ok().and_then(|store| {
+            store
+                .accounts
+                .into_iter()
+                .find(|a| a.id == id)
+                .map(|a| a.email)
+        });
+        println!(
+            "  {}Served by:{} {}{}{}",
+            DIM,
+            RESET,
+            CYAN,
+            email.as_deref().unwrap_or(id),
+            RESET
+        );
+        println!();
+    }
+    let mut body = response.into_body();
+
+    if status.is_success() && stream {
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(|e| e.to_string())?;
+            if let Some(data) = frame.data_ref() {
+                print!("{}", String::from_utf8_lossy(data));
+                std::io::stdout().flush().ok();
+            }
+        }
+        return Ok(());
+    }
+
+    let bytes = body.collect().await.map_err(|e| e.to_string())?.to_bytes();
+    let text = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(json) => serde_json::to_string_pretty(&json).unwrap_or_default(),
+        Err(_) => String::from_utf8_lossy(&bytes).into_owned(),
+    };
+    if !status.is_success() {
+        return Err(format!("HTTP {}\n{}", status, text));
+    }
+    println!("{}", text);
+    Ok(())
+}
+
+async fn test_models_endpoint(base_url: &str, api_key: Option<&str>) -> Result<usize, String> {
+    use http_body_util::BodyExt;
+
+    let response = local_request(base_url, "GET", "/v1/models", Vec::new(), api_key).await?;
 
     if !response.status().is_success() {
         return Err(format!("HTTP {}", response.status()));
@@ -2081,8 +3118,13 @@ async fn run_doctor_command() {
                     println!("{}✓{} Access token valid", GREEN, RESET);
 
                     // Check 4: Project ID
-                    if let Some(ref project_id) = account.project_id {
-                        println!("{}✓{} Project ID: {}", GREEN, RESET, project_id);
+                    if let Some(project_id) = account.effective_project_id() {
+                        let pinned = if account.pinned_project_id.is_some() {
+                            " (pinned)"
+                        } else {
+                            ""
+                        };
+                        println!("{}✓{} Project ID: {}{}", GREEN, RESET, project_id, pinned);
                     } else {
                         println!("{}!{} No project ID configured", YELLOW, RESET);
                         all_ok = false;
@@ -2093,7 +3135,7 @@ async fn run_doctor_command() {
                     match cloudcode::fetch_model_quotas(
                         &http_client,
                         &token,
-                        account.project_id.as_deref(),
+                        account.effective_project_id(),
                     )
                     .await
                     {
@@ -2152,7 +3194,22 @@ async fn run_doctor_command() {
         }
     }
 
-    // Check 6: Server status
+    // Check 6: Accounts file writability
+    match AccountStore::check_writable() {
+        Ok(()) => {
+            println!("{}✓{} Accounts file is writable", GREEN, RESET);
+        }
+        Err(e) => {
+            println!("{}!{} Accounts file is not writable: {}", YELLOW, RESET, e);
+            println!(
+                "  {}The server will keep account health in memory only{}",
+                DIM, RESET
+            );
+            all_ok = false;
+        }
+    }
+
+    // Check 7: Server status
     if let Some(pid) = read_pid() {
         if is_process_running(pid) {
             let config = Config::load().unwrap_or_default();
@@ -2182,11 +3239,38 @@ async fn run_doctor_command() {
     println!();
 }
 
-async fn run_stats_command() {
+async fn run_stats_command(args: &[String]) {
+    let mut watch = false;
+    let mut interval = 2u64;
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-w" | "--watch" => watch = true,
+            "--interval" => {
+                i += 1;
+                match args.get(i).and_then(|v| v.parse::<u64>().ok()) {
+                    Some(secs) if secs > 0 => interval = secs,
+                    _ => {
+                        eprintln!("\x1b[31mError:\x1b[0m --interval requires a number of seconds");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
     // Check if server is running
     let config = Config::load().unwrap_or_default();
     let addr = format!("{}:{}", config.host(), config.port());
 
+    if watch {
+        run_stats_watch(&addr, std::time::Duration::from_secs(interval)).await;
+        return;
+    }
+
     println!();
     println!("{}{}AGCP Stats{}", BOLD, GREEN, RESET);
     println!();
@@ -2281,6 +3365,93 @@ async fn run_stats_command() {
     println!();
 }
 
+/// Redraw a compact stats summary in place every `interval` until Ctrl+C.
+async fn run_stats_watch(addr: &str, interval: std::time::Duration) {
+    println!();
+    println!(
+        "{}{}AGCP Stats{} {}(every {}s, Ctrl+C to exit){}",
+        BOLD,
+        GREEN,
+        RESET,
+        DIM,
+        interval.as_secs(),
+        RESET
+    );
+    println!();
+
+    let mut drawn = 0;
+    loop {
+        let lines = match fetch_stats_http(addr).await {
+            Ok(stats) => stats_watch_lines(&stats["requests"]),
+            Err(_) => vec![format!("{}○{} Server not running, retrying...", DIM, RESET)],
+        };
+
+        // Move back over the previous frame and clear it
+        if drawn > 0 {
+            print!("\x1b[{}A", drawn);
+        }
+        print!("\r\x1b[J");
+        for line in &lines {
+            println!("{}", line);
+        }
+        let _ = std::io::stdout().flush();
+        drawn = lines.len();
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = tokio::signal::ctrl_c() => {
+                println!();
+                return;
+            }
+        }
+    }
+}
+
+/// Compact summary lines for `agcp stats --watch`.
+fn stats_watch_lines(requests: &serde_json::Value) -> Vec<String> {
+    let uptime_secs = requests["uptime_seconds"].as_u64().unwrap_or(0);
+    let total = requests["total_requests"].as_u64().unwrap_or(0);
+    let token_usage = &requests["token_usage"];
+    let input = token_usage["total_input_tokens"].as_u64().unwrap_or(0);
+    let output = token_usage["total_output_tokens"].as_u64().unwrap_or(0);
+    let cache = token_usage["total_cache_read_tokens"].as_u64().unwrap_or(0);
+
+    let mut lines = vec![
+        format!(
+            "{}Uptime:{} {}   {}Requests:{} {}",
+            BOLD,
+            RESET,
+            format_uptime(uptime_secs),
+            BOLD,
+            RESET,
+            total
+        ),
+        format!(
+            "{}Tokens:{} {} in / {} out{}",
+            BOLD,
+            RESET,
+            format_token_count(input),
+            format_token_count(output),
+            if cache > 0 {
+                format!(" ({} cached)", format_token_count(cache))
+            } else {
+                String::new()
+            }
+        ),
+    ];
+
+    if let Some(models) = requests["models"].as_array() {
+        for model in models {
+            lines.push(format!(
+                "  {}: {} reqs",
+                model["model"].as_str().unwrap_or("unknown"),
+                model["requests"].as_u64().unwrap_or(0)
+            ));
+        }
+    }
+    lines
+}
+
 async fn fetch_stats_http(addr: &str) -> Result<serde_json::Value, String> {
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio::net::TcpStream;
@@ -2337,6 +3508,33 @@ fn format_token_count(tokens: u64) -> String {
     }
 }
 
+/// Format a unix timestamp as a local date with a relative offset, e.g.
+/// "2025-06-01 14:03:27 (2h 5m ago)"
+fn format_unix_time(ts: u64, now: u64) -> String {
+    let Some(dt) = chrono::DateTime::from_timestamp(ts as i64, 0) else {
+        return ts.to_string();
+    };
+    let local = dt.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S");
+    if ts <= now {
+        format!("{} ({} ago)", local, format_uptime(now - ts))
+    } else {
+        format!("{} (in {})", local, format_uptime(ts - now))
+    }
+}
+
+/// Remaining daily budget for `accounts list`, or None when no budget is set
+fn format_daily_budget(account: &Account, budget_day: u64) -> Option<String> {
+    let (requests, tokens) = account.remaining_daily_budget(budget_day);
+    let mut parts = Vec::new();
+    if let Some(requests) = requests {
+        parts.push(format!("{} requests", requests));
+    }
+    if let Some(tokens) = tokens {
+        parts.push(format!("{} tokens", format_token_count(tokens)));
+    }
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
 async fn run_accounts_command(args: &[String]) {
     use auth::HttpClient;
     use auth::accounts::{AccountStore, SelectionStrategy};
@@ -2351,6 +3549,35 @@ async fn run_accounts_command(args: &[String]) {
         }
     }
 
+    /// Resolve an account ID prefix to a full ID, exiting if it is unknown or ambiguous
+    fn resolve_account_id_or_exit(store: &AccountStore, prefix: &str) -> String {
+        let matching: Vec<_> = store
+            .accounts
+            .iter()
+            .filter(|a| a.id.starts_with(prefix))
+            .collect();
+        match matching.as_slice() {
+            [account] => account.id.clone(),
+            [] => {
+                eprintln!(
+                    "{}No account found with ID starting with '{}'{}",
+                    RED, prefix, RESET
+                );
+                std::process::exit(1);
+            }
+            _ => {
+                eprintln!(
+                    "{}Multiple accounts match '{}', please be more specific:{}",
+                    RED, prefix, RESET
+                );
+                for a in matching {
+                    eprintln!("  {} - {}", &a.id[..8], a.email);
+                }
+                std::process::exit(1);
+            }
+        }
+    }
+
     let subcommand = args.first().map(|s| s.as_str()).unwrap_or("list");
 
     match subcommand {
@@ -2389,6 +3616,12 @@ async fn run_accounts_command(args: &[String]) {
             let http_client = HttpClient::new();
             store.refresh_subscription_tiers(&http_client).await;
 
+            let budget_day = auth::accounts::budget_day(
+                chrono::Utc::now().timestamp() as u64,
+                config::get_config().accounts.budget_reset_hour_utc,
+            );
+            store.model_affinity = config::get_config().accounts.model_affinity_rules();
+
             println!();
             println!(
                 "{}{}Accounts{} (strategy: {:?})",
@@ -2429,6 +3662,16 @@ async fn run_accounts_command(args: &[String]) {
                     };
                     println!("      {}tier: {}", DIM, tier_badge);
                 }
+                if let Some(project) = &account.pinned_project_id {
+                    println!("      {}project: {} (pinned){}", DIM, project, RESET);
+                }
+                let affinities = store.affinities_for(account);
+                if !affinities.is_empty() {
+                    println!("      {}affinity: {}{}", DIM, affinities.join(", "), RESET);
+                }
+                if let Some(budget) = format_daily_budget(account, budget_day) {
+                    println!("      {}budget left today: {}{}", DIM, budget, RESET);
+                }
                 if account.health_score < 1.0 {
                     println!(
                         "      {}health: {:.0}%{}",
@@ -2441,7 +3684,7 @@ async fn run_accounts_command(args: &[String]) {
 
             // Show legend if there's an active account marker
             if store.active_account_id.is_some() {
-                println!("  {}* = active account (sticky mode){}", DIM, RESET);
+                println!("  {}* = active account{}", DIM, RESET);
             }
             println!();
             println!(
@@ -2451,38 +3694,180 @@ async fn run_accounts_command(args: &[String]) {
             println!();
         }
 
-        "add" => {
-            println!("{}Use 'agcp login' to add a new account.{}", DIM, RESET);
-        }
-
-        "remove" | "rm" => {
-            let id = match args.get(1) {
-                Some(id) => id,
-                None => {
-                    eprintln!("{}Usage: agcp accounts remove <id>{}", RED, RESET);
-                    eprintln!("{}Get account IDs with 'agcp accounts list'{}", DIM, RESET);
-                    std::process::exit(1);
-                }
+        "show" => {
+            let Some(id) = args.get(1) else {
+                eprintln!("{}Usage: agcp accounts show <id>{}", RED, RESET);
+                eprintln!("{}Get account IDs with 'agcp accounts list'{}", DIM, RESET);
+                std::process::exit(1);
             };
 
             let mut store = load_store_or_exit();
-
-            // Find account by ID prefix
-            let matching: Vec<_> = store
+            let full_id = resolve_account_id_or_exit(&store, id);
+            store.model_affinity = config::get_config().accounts.model_affinity_rules();
+            let account = store
                 .accounts
                 .iter()
-                .filter(|a| a.id.starts_with(id))
-                .collect();
+                .find(|a| a.id == full_id)
+                .expect("resolved account ID exists");
+            let now = chrono::Utc::now().timestamp() as u64;
+            let budget_day = auth::accounts::budget_day(
+                now,
+                config::get_config().accounts.budget_reset_hour_utc,
+            );
 
-            if matching.is_empty() {
-                eprintln!(
-                    "{}No account found with ID starting with '{}'{}",
-                    RED, id, RESET
-                );
-                std::process::exit(1);
-            } else if matching.len() > 1 {
-                eprintln!(
-                    "{}Multiple accounts match '{}', please be more specific:{}",
+            let status = if !account.enabled {
+                format!("{}disabled{}", DIM, RESET)
+            } else if account.is_invalid {
+                format!("{}invalid{}", RED, RESET)
+            } else {
+                format!("{}active{}", GREEN, RESET)
+            };
+
+            println!();
+            println!("{}{}{}{}", BOLD, GREEN, account.email, RESET);
+            println!();
+            println!("  {}ID:{}          {}", BOLD, RESET, account.id);
+            println!("  {}Status:{}      {}", BOLD, RESET, status);
+            if let Some(reason) = &account.invalid_reason {
+                println!("  {}Reason:{}      {}", BOLD, RESET, reason);
+            }
+            if store.active_account_id.as_ref() == Some(&account.id) {
+                println!(
+                    "  {}Active:{}      yes (strategy: {:?})",
+                    BOLD, RESET, store.strategy
+                );
+            }
+            println!(
+                "  {}Tier:{}        {}",
+                BOLD,
+                RESET,
+                account.subscription_tier.as_deref().unwrap_or("unknown")
+            );
+            match (&account.pinned_project_id, &account.project_id) {
+                (Some(pinned), _) => {
+                    println!("  {}Project:{}     {} (pinned)", BOLD, RESET, pinned)
+                }
+                (None, Some(project)) => println!("  {}Project:{}     {}", BOLD, RESET, project),
+                (None, None) => println!("  {}Project:{}     {}none{}", BOLD, RESET, DIM, RESET),
+            }
+            println!(
+                "  {}Health:{}      {:.0}%",
+                BOLD,
+                RESET,
+                account.health_score * 100.0
+            );
+            println!(
+                "  {}Tokens:{}      {}/{}",
+                BOLD,
+                RESET,
+                account.tokens_available,
+                auth::accounts::TOKEN_BUCKET_CAPACITY
+            );
+            if account.last_used > 0 {
+                println!(
+                    "  {}Last used:{}   {}",
+                    BOLD,
+                    RESET,
+                    format_unix_time(account.last_used, now)
+                );
+            } else {
+                println!("  {}Last used:{}   {}never{}", BOLD, RESET, DIM, RESET);
+            }
+            if let Some(budget) = format_daily_budget(account, budget_day) {
+                println!("  {}Budget left:{} {}", BOLD, RESET, budget);
+            }
+            let affinities = store.affinities_for(account);
+            if !affinities.is_empty() {
+                println!("  {}Affinity:{}    {}", BOLD, RESET, affinities.join(", "));
+            }
+
+            let mut rate_limits: Vec<_> = account
+                .rate_limits
+                .iter()
+                .filter(|(_, limit)| limit.until > now)
+                .collect();
+            rate_limits.sort_by(|a, b| a.0.cmp(b.0));
+            println!();
+            println!("  {}Rate limits:{}", BOLD, RESET);
+            if rate_limits.is_empty() {
+                println!("    {}none{}", DIM, RESET);
+            }
+            for (model, limit) in rate_limits {
+                println!(
+                    "    {:<32} {}until {}{}",
+                    model,
+                    YELLOW,
+                    format_unix_time(limit.until, now),
+                    RESET
+                );
+            }
+
+            let mut quota: Vec<_> = account.quota.iter().collect();
+            quota.sort_by(|a, b| a.0.cmp(b.0));
+            println!();
+            println!("  {}Quota (cached):{}", BOLD, RESET);
+            if quota.is_empty() {
+                println!("    {}none cached yet{}", DIM, RESET);
+            }
+            for (model, q) in quota {
+                let color = if q.remaining_fraction < 0.1 {
+                    RED
+                } else if q.remaining_fraction < 0.5 {
+                    YELLOW
+                } else {
+                    GREEN
+                };
+                let reset = if q.reset_time > 0 {
+                    format!("resets {}", format_unix_time(q.reset_time, now))
+                } else {
+                    String::new()
+                };
+                println!(
+                    "    {:<32} {}{:>4.0}%{}  {}{}{}",
+                    model,
+                    color,
+                    q.remaining_fraction * 100.0,
+                    RESET,
+                    DIM,
+                    reset,
+                    RESET
+                );
+            }
+            println!();
+        }
+
+        "add" => {
+            println!("{}Use 'agcp login' to add a new account.{}", DIM, RESET);
+        }
+
+        "remove" | "rm" => {
+            let id = match args.get(1) {
+                Some(id) => id,
+                None => {
+                    eprintln!("{}Usage: agcp accounts remove <id>{}", RED, RESET);
+                    eprintln!("{}Get account IDs with 'agcp accounts list'{}", DIM, RESET);
+                    std::process::exit(1);
+                }
+            };
+
+            let mut store = load_store_or_exit();
+
+            // Find account by ID prefix
+            let matching: Vec<_> = store
+                .accounts
+                .iter()
+                .filter(|a| a.id.starts_with(id))
+                .collect();
+
+            if matching.is_empty() {
+                eprintln!(
+                    "{}No account found with ID starting with '{}'{}",
+                    RED, id, RESET
+                );
+                std::process::exit(1);
+            } else if matching.len() > 1 {
+                eprintln!(
+                    "{}Multiple accounts match '{}', please be more specific:{}",
                     RED, id, RESET
                 );
                 for a in matching {
@@ -2524,6 +3909,79 @@ async fn run_accounts_command(args: &[String]) {
             }
         }
 
+        "prune" => {
+            let mut force = false;
+            let mut last_used_before = None;
+            let mut i = 1;
+            while i < args.len() {
+                match args[i].as_str() {
+                    "--force" | "-f" => force = true,
+                    "--older-than" => {
+                        i += 1;
+                        let Some(cutoff) = args.get(i).and_then(|v| parse_log_time_arg(v)) else {
+                            eprintln!(
+                                "{}Usage: agcp accounts prune [--older-than <age>] [--force]{}",
+                                RED, RESET
+                            );
+                            eprintln!("{}Ages look like 30d, 12h or 2024-06-01{}", DIM, RESET);
+                            std::process::exit(1);
+                        };
+                        last_used_before = Some(cutoff.timestamp().max(0) as u64);
+                    }
+                    other => {
+                        eprintln!("{}Unknown option: {}{}", RED, other, RESET);
+                        std::process::exit(1);
+                    }
+                }
+                i += 1;
+            }
+
+            let mut store = load_store_or_exit();
+            let ids = store.prunable_account_ids(last_used_before);
+            if ids.is_empty() {
+                println!("{}No invalid accounts to prune{}", DIM, RESET);
+                return;
+            }
+
+            eprintln!("Invalid accounts to remove:");
+            for account in store.accounts.iter().filter(|a| ids.contains(&a.id)) {
+                eprintln!(
+                    "  {} - \x1b[33m{}\x1b[0m {}({}){}",
+                    &account.id[..8.min(account.id.len())],
+                    account.email,
+                    DIM,
+                    account
+                        .invalid_reason
+                        .as_deref()
+                        .unwrap_or("no reason recorded"),
+                    RESET
+                );
+            }
+
+            if !force {
+                eprint!("Remove {} account(s)? [y/N] ", ids.len());
+                let _ = std::io::Write::flush(&mut std::io::stderr());
+
+                let mut input = String::new();
+                if std::io::stdin().read_line(&mut input).is_err() {
+                    std::process::exit(1);
+                }
+
+                let confirmed = matches!(input.trim().to_lowercase().as_str(), "y" | "yes");
+                if !confirmed {
+                    println!("{}Cancelled{}", DIM, RESET);
+                    return;
+                }
+            }
+
+            let removed = ids.iter().filter(|id| store.remove_account(id)).count();
+            if let Err(e) = store.save() {
+                eprintln!("{}Failed to save accounts: {}{}", RED, e, RESET);
+                std::process::exit(1);
+            }
+            println!("{}Removed {} invalid account(s){}", GREEN, removed, RESET);
+        }
+
         "enable" => {
             let id = match args.get(1) {
                 Some(id) => id,
@@ -2611,6 +4069,175 @@ async fn run_accounts_command(args: &[String]) {
             }
         }
 
+        "set-project" => {
+            let (Some(id), Some(project)) = (args.get(1), args.get(2)) else {
+                eprintln!(
+                    "{}Usage: agcp accounts set-project <id> <project|--clear>{}",
+                    RED, RESET
+                );
+                std::process::exit(1);
+            };
+
+            let mut store = load_store_or_exit();
+            let full_id = resolve_account_id_or_exit(&store, id);
+            let account = store
+                .get_account_mut(&full_id)
+                .expect("resolved account ID exists");
+            let email = account.email.clone();
+            let message = if project == "--clear" {
+                account.pinned_project_id = None;
+                format!("{}Unpinned project for {}{}", GREEN, email, RESET)
+            } else {
+                account.pinned_project_id = Some(project.clone());
+                format!("{}Pinned {} to project {}{}", GREEN, email, project, RESET)
+            };
+            if let Err(e) = store.save() {
+                eprintln!("{}Failed to save accounts: {}{}", RED, e, RESET);
+                std::process::exit(1);
+            }
+            println!("{}", message);
+            say!(
+                "  {}Restart the daemon to apply: agcp restart{}",
+                DIM,
+                RESET
+            );
+        }
+
+        "budget" => {
+            let Some(id) = args.get(1).filter(|a| !a.starts_with("--")) else {
+                eprintln!(
+                    "{}Usage: agcp accounts budget <id> [--requests <n|none>] [--tokens <n|none>]{}",
+                    RED, RESET
+                );
+                std::process::exit(1);
+            };
+
+            // None = flag not given, Some(None) = "none" (remove the budget)
+            let parse_budget = |flag: &str| -> Option<Option<u64>> {
+                let pos = args.iter().position(|a| a == flag)?;
+                let value = args.get(pos + 1).map(|s| s.as_str()).unwrap_or_default();
+                if value.eq_ignore_ascii_case("none") {
+                    return Some(None);
+                }
+                match value.parse::<u64>() {
+                    Ok(n) => Some(Some(n)),
+                    Err(_) => {
+                        eprintln!(
+                            "{}Invalid value for {}: '{}' (expected a number or 'none'){}",
+                            RED, flag, value, RESET
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            };
+            let requests = parse_budget("--requests");
+            let tokens = parse_budget("--tokens");
+
+            let mut store = load_store_or_exit();
+            let full_id = resolve_account_id_or_exit(&store, id);
+            let budget_day = auth::accounts::budget_day(
+                chrono::Utc::now().timestamp() as u64,
+                config::get_config().accounts.budget_reset_hour_utc,
+            );
+            let account = store
+                .get_account_mut(&full_id)
+                .expect("resolved account ID exists");
+
+            if requests.is_none() && tokens.is_none() {
+                println!("{}", account.email);
+                println!(
+                    "  requests/day: {}",
+                    account
+                        .daily_request_budget
+                        .map_or("unlimited".to_string(), |n| n.to_string())
+                );
+                println!(
+                    "  tokens/day:   {}",
+                    account
+                        .daily_token_budget
+                        .map_or("unlimited".to_string(), format_token_count)
+                );
+                if let Some(budget) = format_daily_budget(account, budget_day) {
+                    println!("  {}left today: {}{}", DIM, budget, RESET);
+                }
+                return;
+            }
+
+            if let Some(requests) = requests {
+                account.daily_request_budget = requests;
+            }
+            if let Some(tokens) = tokens {
+                account.daily_token_budget = tokens;
+            }
+            let email = account.email.clone();
+            if let Err(e) = store.save() {
+                eprintln!("{}Failed to save accounts: {}{}", RED, e, RESET);
+                std::process::exit(1);
+            }
+            println!("{}Updated daily budget for {}{}", GREEN, email, RESET);
+            say!(
+                "  {}Restart the daemon to apply: agcp restart{}",
+                DIM,
+                RESET
+            );
+        }
+
+        "reorder" => {
+            let up = args.iter().any(|a| a == "--up");
+            let down = args.iter().any(|a| a == "--down");
+            let prefixes: Vec<&String> =
+                args[1..].iter().filter(|a| !a.starts_with("--")).collect();
+
+            if prefixes.is_empty() || (up && down) || ((up || down) && prefixes.len() != 1) {
+                eprintln!(
+                    "{}Usage: agcp accounts reorder <id> [<id> ...]{}",
+                    RED, RESET
+                );
+                eprintln!(
+                    "{}       agcp accounts reorder <id> --up|--down{}",
+                    RED, RESET
+                );
+                std::process::exit(1);
+            }
+
+            let mut store = load_store_or_exit();
+            let ids: Vec<String> = prefixes
+                .iter()
+                .map(|prefix| resolve_account_id_or_exit(&store, prefix))
+                .collect();
+
+            if up || down {
+                if store.move_account(&ids[0], up).is_none() {
+                    println!(
+                        "{}Account is already {}{}",
+                        DIM,
+                        if up { "first" } else { "last" },
+                        RESET
+                    );
+                    return;
+                }
+            } else {
+                store.reorder(&ids);
+            }
+
+            if let Err(e) = store.save() {
+                eprintln!("{}Failed to save accounts: {}{}", RED, e, RESET);
+                std::process::exit(1);
+            }
+
+            println!("{}Account order updated:{}", GREEN, RESET);
+            for (i, account) in store.accounts.iter().enumerate() {
+                println!(
+                    "  {}. {}[{}]{} {}",
+                    i + 1,
+                    DIM,
+                    &account.id[..8],
+                    RESET,
+                    account.email
+                );
+            }
+        }
+
         "strategy" => {
             let strategy_str = match args.get(1) {
                 Some(s) => s,
@@ -2658,6 +4285,102 @@ async fn run_accounts_command(args: &[String]) {
             println!("{}Strategy set to: {:?}{}", GREEN, strategy, RESET);
         }
 
+        "swap-refresh" => {
+            use std::io::IsTerminal;
+
+            let Some(id) = args.get(1).filter(|a| !a.starts_with("--")) else {
+                eprintln!(
+                    "{}Usage: agcp accounts swap-refresh <id> [--stdin]{}",
+                    RED, RESET
+                );
+                std::process::exit(1);
+            };
+
+            let mut store = load_store_or_exit();
+            let full_id = resolve_account_id_or_exit(&store, id);
+
+            // Read from stdin when piped so tokens never end up in shell history
+            let from_stdin = args.iter().any(|a| a == "--stdin") || !std::io::stdin().is_terminal();
+            let token = if from_stdin {
+                let mut input = String::new();
+                if let Err(e) = std::io::stdin().read_line(&mut input) {
+                    eprintln!("{}Failed to read refresh token: {}{}", RED, e, RESET);
+                    std::process::exit(1);
+                }
+                input
+            } else {
+                match dialoguer::Password::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt("New refresh token")
+                    .interact()
+                {
+                    Ok(token) => token,
+                    Err(e) => {
+                        eprintln!("{}Failed to read refresh token: {}{}", RED, e, RESET);
+                        std::process::exit(1);
+                    }
+                }
+            };
+            let token = token.trim();
+            if token.is_empty() {
+                eprintln!("{}Refresh token cannot be empty{}", RED, RESET);
+                std::process::exit(1);
+            }
+
+            let account = store
+                .get_account_mut(&full_id)
+                .expect("resolved account ID exists");
+            let email = account.email.clone();
+            let was_invalid = account.is_invalid;
+
+            // Validate against a copy so a mismatched token leaves the account untouched
+            let mut candidate = account.clone();
+            let token_email = match candidate
+                .swap_refresh_token(&HttpClient::new(), token)
+                .await
+            {
+                Ok(token_email) => token_email,
+                Err(e) => {
+                    eprintln!("{}Refresh token rejected: {}{}", RED, e, RESET);
+                    std::process::exit(1);
+                }
+            };
+            if !token_email.eq_ignore_ascii_case(&email) {
+                eprintln!(
+                    "{}Refresh token belongs to {}, not {}{}",
+                    RED, token_email, email, RESET
+                );
+                eprintln!(
+                    "{}Use 'agcp login' to add it as a separate account.{}",
+                    DIM, RESET
+                );
+                std::process::exit(1);
+            }
+            *account = candidate;
+
+            if let Err(e) = store.save() {
+                eprintln!("{}Failed to save accounts: {}{}", RED, e, RESET);
+                std::process::exit(1);
+            }
+
+            if was_invalid {
+                let config = Config::load().unwrap_or_default();
+                notify::account_status_changed(
+                    &config.notifications,
+                    &email,
+                    notify::AccountStatus::Recovered,
+                    None,
+                )
+                .await;
+            }
+
+            println!("{}Updated refresh token for {}{}", GREEN, email, RESET);
+            say!(
+                "  {}Restart the daemon to apply: agcp restart{}",
+                DIM,
+                RESET
+            );
+        }
+
         "verify" => {
             let http_client = HttpClient::new();
 
@@ -2676,6 +4399,7 @@ async fn run_accounts_command(args: &[String]) {
             println!();
 
             let mut all_ok = true;
+            let mut transitions = Vec::new();
             for account in &mut store.accounts {
                 match account.get_access_token(&http_client).await {
                     Ok(_) => {
@@ -2684,10 +4408,22 @@ async fn run_accounts_command(args: &[String]) {
                         if account.is_invalid {
                             account.is_invalid = false;
                             account.invalid_reason = None;
+                            transitions.push((
+                                account.email.clone(),
+                                notify::AccountStatus::Recovered,
+                                None,
+                            ));
                         }
                     }
                     Err(e) => {
                         println!("  {}✗{} {} - {}", RED, RESET, account.email, e);
+                        if !account.is_invalid {
+                            transitions.push((
+                                account.email.clone(),
+                                notify::AccountStatus::Invalid,
+                                Some(e.to_string()),
+                            ));
+                        }
                         account.is_invalid = true;
                         account.invalid_reason = Some(e.to_string());
                         all_ok = false;
@@ -2700,6 +4436,17 @@ async fn run_accounts_command(args: &[String]) {
                 eprintln!("{}Failed to save accounts: {}{}", RED, e, RESET);
             }
 
+            let config = Config::load().unwrap_or_default();
+            for (email, status, reason) in transitions {
+                notify::account_status_changed(
+                    &config.notifications,
+                    &email,
+                    status,
+                    reason.as_deref(),
+                )
+                .await;
+            }
+
             println!();
             if all_ok {
                 println!("{}All accounts verified successfully.{}", GREEN, RESET);
@@ -2713,12 +4460,88 @@ async fn run_accounts_command(args: &[String]) {
             println!();
         }
 
+        "repair" => {
+            let backup_path = match args.get(1) {
+                Some(path) => std::path::PathBuf::from(path),
+                None => AccountStore::path().with_extension("json.corrupted"),
+            };
+
+            let content = match std::fs::read(&backup_path) {
+                Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                Err(e) => {
+                    eprintln!(
+                        "{}Failed to read backup {}: {}{}",
+                        RED,
+                        backup_path.display(),
+                        e,
+                        RESET
+                    );
+                    eprintln!(
+                        "{}A backup is created when a corrupted accounts file is reset{}",
+                        DIM, RESET
+                    );
+                    std::process::exit(1);
+                }
+            };
+
+            let recovered = auth::accounts::salvage_accounts(&content);
+            if recovered.is_empty() {
+                println!(
+                    "{}No recoverable accounts found in {}{}",
+                    YELLOW,
+                    backup_path.display(),
+                    RESET
+                );
+                return;
+            }
+
+            let mut store = load_store_or_exit();
+            let mut restored = 0;
+            let mut skipped = 0;
+            for account in recovered {
+                if store.accounts.iter().any(|a| a.email == account.email) {
+                    println!(
+                        "  {}○{} {} {}(already present){}",
+                        DIM, RESET, account.email, DIM, RESET
+                    );
+                    skipped += 1;
+                } else {
+                    println!("  {}✓{} {}", GREEN, RESET, account.email);
+                    store.accounts.push(account);
+                    restored += 1;
+                }
+            }
+
+            if restored > 0
+                && let Err(e) = store.save()
+            {
+                eprintln!("{}Failed to save accounts: {}{}", RED, e, RESET);
+                std::process::exit(1);
+            }
+
+            println!();
+            println!(
+                "{}Recovered {} account(s){} ({} already present)",
+                GREEN, restored, RESET, skipped
+            );
+            if restored > 0 {
+                println!(
+                    "{}Run 'agcp accounts verify' to check the recovered tokens{}",
+                    DIM, RESET
+                );
+            }
+        }
+
         "help" | "-h" | "--help" => {
             println!();
             println!("{}Usage: agcp accounts <subcommand>{}", BOLD, RESET);
             println!();
             println!("{}Subcommands:{}", BOLD, RESET);
             println!("  {}list{}      Show all accounts", YELLOW, RESET);
+            println!(
+                "  {}show{}      Show everything about one account",
+                YELLOW, RESET
+            );
             println!(
                 "  {}remove{}    Remove an account by ID prefix",
                 YELLOW, RESET
@@ -2729,32 +4552,84 @@ async fn run_accounts_command(args: &[String]) {
                 "  {}switch{}    Set active account (for sticky strategy)",
                 YELLOW, RESET
             );
+            println!(
+                "  {}reorder{}   Change account priority order",
+                YELLOW, RESET
+            );
+            println!(
+                "  {}prune{}     Remove invalid accounts (--older-than <age>, --force)",
+                YELLOW, RESET
+            );
+            println!(
+                "  {}set-project{} Pin an account to a GCP project (--clear to unpin)",
+                YELLOW, RESET
+            );
+            println!(
+                "  {}budget{}    Show or set daily request/token budgets",
+                YELLOW, RESET
+            );
             println!(
                 "  {}strategy{}  Set selection strategy (sticky, roundrobin, hybrid)",
                 YELLOW, RESET
             );
+            println!(
+                "  {}swap-refresh{} Replace an account's refresh token in place",
+                YELLOW, RESET
+            );
             println!(
                 "  {}verify{}    Verify account tokens are valid",
                 YELLOW, RESET
             );
+            println!(
+                "  {}repair{}    Recover accounts from a corrupted backup",
+                YELLOW, RESET
+            );
             println!();
             println!("{}Examples:{}", BOLD, RESET);
             println!(
                 "  {}agcp accounts list{}                 # List all accounts",
                 DIM, RESET
             );
+            println!(
+                "  {}agcp accounts show f6c3b4{}          # Inspect one account",
+                DIM, RESET
+            );
             println!(
                 "  {}agcp accounts remove f6c3b4{}        # Remove account by ID prefix",
                 DIM, RESET
             );
+            println!(
+                "  {}agcp accounts reorder f6c3b4 --up{}  # Move account up one place",
+                DIM, RESET
+            );
             println!(
                 "  {}agcp accounts strategy roundrobin{}  # Set round-robin strategy",
                 DIM, RESET
             );
+            println!(
+                "  {}agcp accounts prune --older-than 30d{}  # Remove long-unused invalid accounts",
+                DIM, RESET
+            );
+            println!(
+                "  {}agcp accounts set-project f6c3b4 my-project{}  # Pin a GCP project",
+                DIM, RESET
+            );
+            println!(
+                "  {}agcp accounts budget f6c3b4 --requests 500{}  # Cap requests per day",
+                DIM, RESET
+            );
+            println!(
+                "  {}pbpaste | agcp accounts swap-refresh f6c3b4{}  # Swap in a new refresh token",
+                DIM, RESET
+            );
             println!(
                 "  {}agcp accounts verify{}               # Verify all account tokens",
                 DIM, RESET
             );
+            println!(
+                "  {}agcp accounts repair{}               # Salvage accounts.json.corrupted",
+                DIM, RESET
+            );
             println!();
         }
 
@@ -2765,6 +4640,10 @@ async fn run_accounts_command(args: &[String]) {
             println!();
             println!("{}Subcommands:{}", BOLD, RESET);
             println!("  {}list{}      Show all accounts", YELLOW, RESET);
+            println!(
+                "  {}show{}      Show everything about one account",
+                YELLOW, RESET
+            );
             println!("  {}remove{}    Remove an account", YELLOW, RESET);
             println!("  {}enable{}    Enable an account", YELLOW, RESET);
             println!("  {}disable{}   Disable an account", YELLOW, RESET);
@@ -2772,11 +4651,35 @@ async fn run_accounts_command(args: &[String]) {
                 "  {}switch{}    Set active account (for sticky strategy)",
                 YELLOW, RESET
             );
+            println!(
+                "  {}reorder{}   Change account priority order",
+                YELLOW, RESET
+            );
+            println!(
+                "  {}prune{}     Remove invalid accounts (--older-than <age>, --force)",
+                YELLOW, RESET
+            );
+            println!(
+                "  {}set-project{} Pin an account to a GCP project (--clear to unpin)",
+                YELLOW, RESET
+            );
+            println!(
+                "  {}budget{}    Show or set daily request/token budgets",
+                YELLOW, RESET
+            );
             println!("  {}strategy{}  Set selection strategy", YELLOW, RESET);
+            println!(
+                "  {}swap-refresh{} Replace an account's refresh token in place",
+                YELLOW, RESET
+            );
             println!(
                 "  {}verify{}    Verify account tokens are valid",
                 YELLOW, RESET
             );
+            println!(
+                "  {}repair{}    Recover accounts from a corrupted backup",
+                YELLOW, RESET
+            );
             println!();
             std::process::exit(1);
         }
@@ -2791,11 +4694,11 @@ fn print_completions(shell: &str) {
     COMPREPLY=()
     cur="${{COMP_WORDS[COMP_CWORD]}}"
     prev="${{COMP_WORDS[COMP_CWORD-1]}}"
-    commands="login setup accounts config doctor test quota stats logs stop restart status upgrade tui version help completions"
+    commands="serve login setup accounts config doctor test quota stats logs stop restart status upgrade tui version help completions"
 
     case "${{prev}}" in
         agcp)
-            COMPREPLY=( $(compgen -W \"${{commands}} --port --host --network --foreground --debug --fallback --help --version\" -- \"${{cur}}\") )
+            COMPREPLY=( $(compgen -W \"${{commands}} --port --host --network --foreground --debug --fallback --quiet --help --version\" -- \"${{cur}}\") )
             return 0
             ;;
         --port|-p)
@@ -2804,12 +4707,44 @@ fn print_completions(shell: &str) {
         --host)
             return 0
             ;;
+        serve)
+            COMPREPLY=( $(compgen -W "--port --host --network --foreground --debug --fallback" -- "${{cur}}") )
+            return 0
+            ;;
         accounts)
-            COMPREPLY=( $(compgen -W "list remove enable disable switch strategy verify" -- "${{cur}}") )
+            COMPREPLY=( $(compgen -W "list show remove enable disable switch reorder prune set-project budget swap-refresh strategy verify repair" -- "${{cur}}") )
             return 0
             ;;
         logs)
-            COMPREPLY=( $(compgen -W "--lines --no-follow" -- "${{cur}}") )
+            COMPREPLY=( $(compgen -W "--lines --no-follow --all --since --until" -- "${{cur}}") )
+            return 0
+            ;;
+        stats)
+            COMPREPLY=( $(compgen -W "--watch --interval" -- "${{cur}}") )
+            return 0
+            ;;
+        test)
+            COMPREPLY=( $(compgen -f -W "--request --endpoint" -- "${{cur}}") )
+            return 0
+            ;;
+        setup)
+            COMPREPLY=( $(compgen -W "--tool --port --non-interactive --list --undo" -- "${{cur}}") )
+            return 0
+            ;;
+        --tool)
+            COMPREPLY=( $(compgen -W "claude-code codex opencode crush zed" -- "${{cur}}") )
+            return 0
+            ;;
+        upgrade)
+            COMPREPLY=( $(compgen -W "--yes" -- "${{cur}}") )
+            return 0
+            ;;
+        status)
+            COMPREPLY=( $(compgen -W "--short" -- "${{cur}}") )
+            return 0
+            ;;
+        tui)
+            COMPREPLY=( $(compgen -W "overview logs accounts config mappings quota usage about" -- "${{cur}}") )
             return 0
             ;;
         completions)
@@ -2819,7 +4754,7 @@ fn print_completions(shell: &str) {
     esac
 
     if [[ ${{cur}} == -* ]]; then
-        COMPREPLY=( $(compgen -W \"--port --host --network --foreground --debug --fallback --help --version\" -- \"${{cur}}\") )
+        COMPREPLY=( $(compgen -W \"--port --host --network --foreground --debug --fallback --quiet --help --version\" -- \"${{cur}}\") )
     fi
 }}
 complete -F _agcp agcp
@@ -2831,6 +4766,7 @@ complete -F _agcp agcp
 _agcp() {{
     local -a commands
     commands=(
+        'serve:Start the server'
         'login:Authenticate with Google OAuth'
         'setup:Configure AI tools to use AGCP'
         'accounts:Manage multiple accounts'
@@ -2862,6 +4798,8 @@ _agcp() {{
         '-d[Enable debug logging]'
         '--debug[Enable debug logging]'
         '--fallback[Enable model fallback on quota exhaustion]'
+        '-q[Only print errors and results]'
+        '--quiet[Only print errors and results]'
         '-h[Show help]'
         '--help[Show help]'
         '-V[Show version]'
@@ -2879,17 +4817,64 @@ _agcp() {{
             ;;
         args)
             case $words[1] in
+                serve)
+                    _arguments \
+                        '-p[Server port]:port' \
+                        '--port[Server port]:port' \
+                        '--host[Bind address]:host' \
+                        '--network[Listen on all interfaces for LAN access]' \
+                        '-f[Run in foreground]' \
+                        '--foreground[Run in foreground]' \
+                        '-d[Enable debug logging]' \
+                        '--debug[Enable debug logging]' \
+                        '--fallback[Enable model fallback on quota exhaustion]'
+                    ;;
                 logs)
                     _arguments \
                         '-n[Show last N lines]:lines' \
                         '--lines[Show last N lines]:lines' \
-                        '--no-follow[Do not follow log output]'
+                        '--no-follow[Do not follow log output]' \
+                        '--all[Print the whole log file]' \
+                        '--since[Show lines from TIME (1h, 30m, ISO date)]:time' \
+                        '--until[Show lines up to TIME, without following]:time'
+                    ;;
+                stats)
+                    _arguments \
+                        '-w[Refresh in place until Ctrl+C]' \
+                        '--watch[Refresh in place until Ctrl+C]' \
+                        '--interval[Refresh interval in seconds]:seconds'
+                    ;;
+                test)
+                    _arguments \
+                        '-r[Send a JSON request body from a file]:file:_files' \
+                        '--request[Send a JSON request body from a file]:file:_files' \
+                        '--endpoint[Override the detected endpoint path]:path'
+                    ;;
+                setup)
+                    _arguments \
+                        '*--tool[Configure this tool without the picker]:tool:(claude-code codex opencode crush zed)' \
+                        '--port[Proxy port to write into tool configs]:port' \
+                        '--non-interactive[Never prompt]' \
+                        '--list[List supported tools and their config paths]' \
+                        '--undo[Restore configs from backup]'
+                    ;;
+                status)
+                    _arguments \
+                        '--short[Print one undecorated line for shell prompts]'
+                    ;;
+                upgrade)
+                    _arguments \
+                        '-y[Install the update without asking]' \
+                        '--yes[Install the update without asking]'
                     ;;
                 completions)
                     _values 'shell' bash zsh fish
                     ;;
                 accounts)
-                    _values 'subcommand' list remove enable disable switch strategy verify
+                    _values 'subcommand' list show remove enable disable switch reorder prune set-project budget swap-refresh strategy verify repair
+                    ;;
+                tui)
+                    _values 'tab' overview logs accounts config mappings quota usage about
                     ;;
             esac
             ;;
@@ -2903,6 +4888,7 @@ _agcp "$@"
             r#"complete -c agcp -f
 
 # Commands
+complete -c agcp -n "__fish_use_subcommand" -a serve -d "Start the server"
 complete -c agcp -n "__fish_use_subcommand" -a login -d "Authenticate with Google OAuth"
 complete -c agcp -n "__fish_use_subcommand" -a setup -d "Configure AI tools to use AGCP"
 complete -c agcp -n "__fish_use_subcommand" -a accounts -d "Manage multiple accounts"
@@ -2929,24 +4915,67 @@ complete -c agcp -n "__fish_use_subcommand" -l lan -d "Listen on all interfaces
 complete -c agcp -n "__fish_use_subcommand" -s f -l foreground -d "Run in foreground"
 complete -c agcp -n "__fish_use_subcommand" -s d -l debug -d "Enable debug logging"
 complete -c agcp -n "__fish_use_subcommand" -l fallback -d "Enable model fallback on quota exhaustion"
+complete -c agcp -s q -l quiet -d "Only print errors and results"
 complete -c agcp -n "__fish_use_subcommand" -s h -l help -d "Show help"
 complete -c agcp -n "__fish_use_subcommand" -s V -l version -d "Show version"
 
+# serve subcommand
+complete -c agcp -n "__fish_seen_subcommand_from serve" -s p -l port -d "Server port" -r
+complete -c agcp -n "__fish_seen_subcommand_from serve" -l host -d "Bind address" -r
+complete -c agcp -n "__fish_seen_subcommand_from serve" -l network -d "Listen on all interfaces (LAN access)"
+complete -c agcp -n "__fish_seen_subcommand_from serve" -s f -l foreground -d "Run in foreground"
+complete -c agcp -n "__fish_seen_subcommand_from serve" -s d -l debug -d "Enable debug logging"
+complete -c agcp -n "__fish_seen_subcommand_from serve" -l fallback -d "Enable model fallback on quota exhaustion"
+
 # logs subcommand
 complete -c agcp -n "__fish_seen_subcommand_from logs" -s n -l lines -d "Show last N lines" -r
 complete -c agcp -n "__fish_seen_subcommand_from logs" -l no-follow -d "Do not follow log output"
+complete -c agcp -n "__fish_seen_subcommand_from logs" -l all -d "Print the whole log file"
+complete -c agcp -n "__fish_seen_subcommand_from logs" -l since -d "Show lines from TIME (1h, 30m, ISO date)" -r
+complete -c agcp -n "__fish_seen_subcommand_from logs" -l until -d "Show lines up to TIME, without following" -r
+
+# stats subcommand
+complete -c agcp -n "__fish_seen_subcommand_from stats" -s w -l watch -d "Refresh in place until Ctrl+C"
+complete -c agcp -n "__fish_seen_subcommand_from stats" -l interval -d "Refresh interval in seconds" -r
+
+# test subcommand
+complete -c agcp -n "__fish_seen_subcommand_from test" -s r -l request -d "Send a JSON request body from a file" -r -F
+complete -c agcp -n "__fish_seen_subcommand_from test" -l endpoint -d "Override the detected endpoint path" -r
+
+# setup subcommand
+complete -c agcp -n "__fish_seen_subcommand_from setup" -s t -l tool -d "Configure this tool without the picker" -r -a "claude-code codex opencode crush zed"
+complete -c agcp -n "__fish_seen_subcommand_from setup" -l port -d "Proxy port to write into tool configs" -r
+complete -c agcp -n "__fish_seen_subcommand_from setup" -s y -l non-interactive -d "Never prompt"
+complete -c agcp -n "__fish_seen_subcommand_from setup" -s l -l list -d "List supported tools and their config paths"
+complete -c agcp -n "__fish_seen_subcommand_from setup" -l undo -d "Restore configs from backup"
+
+# upgrade subcommand
+complete -c agcp -n "__fish_seen_subcommand_from upgrade" -s y -l yes -d "Install the update without asking"
+
+# status subcommand
+complete -c agcp -n "__fish_seen_subcommand_from status" -l short -d "One undecorated line for shell prompts"
 
 # completions subcommand
 complete -c agcp -n "__fish_seen_subcommand_from completions" -a "bash zsh fish"
 
+# tui subcommand
+complete -c agcp -n "__fish_seen_subcommand_from tui" -a "overview logs accounts config mappings quota usage about"
+
 # accounts subcommand
 complete -c agcp -n "__fish_seen_subcommand_from accounts" -a list -d "Show all accounts"
+complete -c agcp -n "__fish_seen_subcommand_from accounts" -a show -d "Show one account in detail"
 complete -c agcp -n "__fish_seen_subcommand_from accounts" -a remove -d "Remove an account"
 complete -c agcp -n "__fish_seen_subcommand_from accounts" -a enable -d "Enable an account"
 complete -c agcp -n "__fish_seen_subcommand_from accounts" -a disable -d "Disable an account"
 complete -c agcp -n "__fish_seen_subcommand_from accounts" -a switch -d "Set active account"
+complete -c agcp -n "__fish_seen_subcommand_from accounts" -a reorder -d "Change account priority order"
+complete -c agcp -n "__fish_seen_subcommand_from accounts" -a prune -d "Remove invalid accounts"
+complete -c agcp -n "__fish_seen_subcommand_from accounts" -a set-project -d "Pin an account to a GCP project"
+complete -c agcp -n "__fish_seen_subcommand_from accounts" -a swap-refresh -d "Replace an account's refresh token"
+complete -c agcp -n "__fish_seen_subcommand_from accounts" -a budget -d "Show or set daily budgets"
 complete -c agcp -n "__fish_seen_subcommand_from accounts" -a strategy -d "Set selection strategy"
 complete -c agcp -n "__fish_seen_subcommand_from accounts" -a verify -d "Verify account tokens"
+complete -c agcp -n "__fish_seen_subcommand_from accounts" -a repair -d "Recover accounts from backup"
 "#
         ),
         _ => {
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::config::MappingRule;
@@ -44,6 +46,64 @@ impl Model {
             Model::GptOss120bMedium,
         ]
     }
+
+    /// Look up a model by its Anthropic-style id (case-insensitive).
+    pub fn from_id(id: &str) -> Option<Model> {
+        Model::all()
+            .iter()
+            .copied()
+            .find(|m| m.anthropic_id().eq_ignore_ascii_case(id))
+    }
+
+    /// Request features the model can serve.
+    ///
+    /// `supports_thinking` means an explicit `thinking` parameter is honored;
+    /// `thinks_by_default` means the model thinks without one, and is what
+    /// [`is_thinking_model`] reports for known models.
+    pub fn capabilities(&self) -> ModelCapabilities {
+        match self {
+            Model::ClaudeOpus4_6Thinking
+            | Model::ClaudeOpus4_5Thinking
+            | Model::ClaudeSonnet4_5Thinking
+            | Model::Gemini3Flash
+            | Model::Gemini3ProHigh
+            | Model::Gemini3ProLow => ModelCapabilities {
+                supports_tools: true,
+                supports_thinking: true,
+                supports_vision: true,
+                thinks_by_default: true,
+            },
+            Model::ClaudeSonnet4_5 => ModelCapabilities {
+                supports_tools: true,
+                supports_thinking: true,
+                supports_vision: true,
+                thinks_by_default: false,
+            },
+            // Text-only, with a fixed reasoning effort the request can't change
+            Model::GptOss120bMedium => ModelCapabilities {
+                supports_tools: true,
+                supports_thinking: false,
+                supports_vision: false,
+                thinks_by_default: false,
+            },
+        }
+    }
+}
+
+/// Request features a model supports, checked before a request goes upstream
+/// so mismatches fail with a clear message instead of an opaque upstream 400.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    pub supports_tools: bool,
+    pub supports_thinking: bool,
+    pub supports_vision: bool,
+    pub thinks_by_default: bool,
+}
+
+/// Capabilities of a known model. Unknown models (e.g. custom mapping
+/// targets) return `None` and aren't gated.
+pub fn model_capabilities(model_name: &str) -> Option<ModelCapabilities> {
+    Model::from_id(model_name).map(|m| m.capabilities())
 }
 
 /// Case-insensitive ASCII substring check without allocation.
@@ -156,9 +216,19 @@ pub fn get_fallback_model(model: &str) -> Option<&'static str> {
     }
 }
 
+/// Whether a model thinks by default. Known models answer from their
+/// [`ModelCapabilities`]; other names (e.g. custom mapping targets) go by
+/// [`is_thinking_model_name`].
+pub fn is_thinking_model(model_name: &str) -> bool {
+    match model_capabilities(model_name) {
+        Some(capabilities) => capabilities.thinks_by_default,
+        None => is_thinking_model_name(model_name),
+    }
+}
+
 /// Claude models need "thinking" in name.
 /// Gemini 3+ models are all thinking models (e.g., gemini-3-flash).
-pub fn is_thinking_model(model_name: &str) -> bool {
+fn is_thinking_model_name(model_name: &str) -> bool {
     if contains_ignore_case(model_name, "claude") && contains_ignore_case(model_name, "thinking") {
         return true;
     }
@@ -211,12 +281,25 @@ pub fn glob_match(pattern: &str, input: &str) -> bool {
     }
 }
 
-/// Resolve a model name using user-defined mapping rules first,
+/// Look up a user-defined alias (case-insensitive).
+/// The target may itself be a built-in alias, which is resolved too.
+pub fn resolve_user_alias<'a>(
+    model: &str,
+    aliases: &'a BTreeMap<String, String>,
+) -> Option<&'a str> {
+    aliases
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(model))
+        .map(|(_, target)| resolve_model_alias(target))
+}
+
+/// Resolve a model name using user-defined aliases and mapping rules first,
 /// then falling back to the hardcoded alias table.
 /// Also handles the background task model substitution.
 pub fn resolve_with_mappings(
     model: &str,
     rules: &[MappingRule],
+    aliases: &BTreeMap<String, String>,
     background_task_model: &str,
 ) -> String {
     // Check for background task model
@@ -224,6 +307,11 @@ pub fn resolve_with_mappings(
         return background_task_model.to_string();
     }
 
+    // User aliases are exact names, so they win over glob rules and built-ins
+    if let Some(target) = resolve_user_alias(model, aliases) {
+        return target.to_string();
+    }
+
     // Check user mappings (first match wins)
     for rule in rules {
         if glob_match(&rule.from, model) {
@@ -235,6 +323,87 @@ pub fn resolve_with_mappings(
     resolve_model_alias(model).to_string()
 }
 
+/// Whether every model matched by `later` is also matched by `earlier`,
+/// i.e. a rule with pattern `later` placed after `earlier` can never win.
+fn pattern_shadows(earlier: &str, later: &str) -> bool {
+    let earlier = earlier.to_ascii_lowercase();
+    let later = later.to_ascii_lowercase();
+    match (earlier.split_once('*'), later.split_once('*')) {
+        (Some((prefix, suffix)), Some((later_prefix, later_suffix))) => {
+            later_prefix.starts_with(prefix) && later_suffix.ends_with(suffix)
+        }
+        (None, Some(_)) => false,
+        (_, None) => glob_match(&earlier, &later),
+    }
+}
+
+/// Find aliases and mapping rules that shadow each other with different targets.
+///
+/// Returns one message per conflict naming both entries and which one wins.
+/// Only full shadowing is reported: a specific rule placed before a broader
+/// one is the normal way to carve out exceptions and is not a conflict.
+pub fn mapping_conflicts(rules: &[MappingRule], aliases: &BTreeMap<String, String>) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    let differ = |a: &str, b: &str| resolve_model_alias(a) != resolve_model_alias(b);
+
+    // Aliases that differ only in case (the first in sorted order wins)
+    let alias_list: Vec<_> = aliases.iter().collect();
+    for (i, (alias, target)) in alias_list.iter().enumerate() {
+        for (other, other_target) in &alias_list[i + 1..] {
+            if alias.eq_ignore_ascii_case(other) && differ(target, other_target) {
+                conflicts.push(format!(
+                    "aliases '{}' -> '{}' and '{}' -> '{}' differ only in case; '{}' wins",
+                    alias, target, other, other_target, alias
+                ));
+            }
+        }
+    }
+
+    // Aliases are checked before rules, so they take over matching names
+    for (alias, target) in aliases {
+        if let Some(rule) = rules.iter().find(|r| glob_match(&r.from, alias))
+            && differ(target, &rule.to)
+        {
+            conflicts.push(format!(
+                "alias '{}' -> '{}' overrides rule '{}' -> '{}' for that name; the alias wins",
+                alias, target, rule.from, rule.to
+            ));
+        }
+    }
+
+    // Later rules fully covered by an earlier rule never match (first match wins)
+    for (i, rule) in rules.iter().enumerate() {
+        if let Some(earlier) = rules[..i]
+            .iter()
+            .find(|e| pattern_shadows(&e.from, &rule.from))
+            && differ(&earlier.to, &rule.to)
+        {
+            conflicts.push(format!(
+                "rule '{}' -> '{}' is shadowed by earlier rule '{}' -> '{}'; the earlier rule wins",
+                rule.from, rule.to, earlier.from, earlier.to
+            ));
+        }
+    }
+
+    conflicts
+}
+
+/// Check whether a resolved model is matched by any entry in a model list.
+/// Entries may be full model IDs, aliases (e.g. "opus"), or glob patterns.
+pub fn model_in_list(model: &str, entries: &[String]) -> bool {
+    entries.iter().any(|entry| model_matches(model, entry))
+}
+
+/// Check whether a resolved model is matched by a single model ID, alias or glob.
+pub fn model_matches(model: &str, entry: &str) -> bool {
+    glob_match(entry, model) || resolve_model_alias(entry) == model
+}
+
+/// Check whether a resolved model is in the disabled list.
+pub fn is_model_disabled(model: &str, disabled: &[String]) -> bool {
+    model_in_list(model, disabled)
+}
+
 /// Available mapping presets
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MappingPreset {
@@ -476,6 +645,45 @@ mod tests {
         assert!(!is_thinking_model("gpt-oss-120b-medium")); // Not a thinking model
     }
 
+    #[test]
+    fn test_model_capabilities() {
+        let opus = model_capabilities("claude-opus-4-6-thinking").unwrap();
+        assert!(opus.supports_tools && opus.supports_thinking && opus.supports_vision);
+
+        // Not a thinking model by default, but honors an explicit thinking parameter
+        assert!(
+            model_capabilities("claude-sonnet-4-5")
+                .unwrap()
+                .supports_thinking
+        );
+
+        let oss = model_capabilities("GPT-OSS-120B-MEDIUM").unwrap();
+        assert!(oss.supports_tools);
+        assert!(!oss.supports_thinking);
+        assert!(!oss.supports_vision);
+
+        assert!(model_capabilities("my-custom-model").is_none());
+    }
+
+    #[test]
+    fn test_capabilities_match_thinking_name_pattern() {
+        // Names not in the table fall back to the pattern, so the two must agree
+        for model in Model::all() {
+            let capabilities = model.capabilities();
+            assert_eq!(
+                capabilities.thinks_by_default,
+                is_thinking_model_name(model.anthropic_id()),
+                "{}",
+                model.anthropic_id()
+            );
+            assert!(!capabilities.thinks_by_default || capabilities.supports_thinking);
+        }
+        // Custom names still go by the pattern
+        assert!(is_thinking_model("claude-opus-5-thinking"));
+        assert!(is_thinking_model("gemini-4-pro"));
+        assert!(!is_thinking_model("my-custom-model"));
+    }
+
     #[test]
     fn test_model_aliases() {
         // Claude aliases - opus now defaults to 4.6
@@ -615,33 +823,129 @@ mod tests {
 
         // User mapping takes priority
         assert_eq!(
-            resolve_with_mappings("gpt-4o", &rules, "gemini-3-flash"),
+            resolve_with_mappings("gpt-4o", &rules, &BTreeMap::new(), "gemini-3-flash"),
             "gemini-3-pro-high"
         );
         assert_eq!(
-            resolve_with_mappings("claude-3-haiku-20240307", &rules, "gemini-3-flash"),
+            resolve_with_mappings(
+                "claude-3-haiku-20240307",
+                &rules,
+                &BTreeMap::new(),
+                "gemini-3-flash"
+            ),
             "gemini-3-flash"
         );
 
         // No user mapping match -> falls through to hardcoded aliases
         assert_eq!(
-            resolve_with_mappings("opus", &rules, "gemini-3-flash"),
+            resolve_with_mappings("opus", &rules, &BTreeMap::new(), "gemini-3-flash"),
             "claude-opus-4-6-thinking"
         );
 
         // Background task model
         assert_eq!(
-            resolve_with_mappings("internal-background-task", &rules, "gemini-3-flash"),
+            resolve_with_mappings(
+                "internal-background-task",
+                &rules,
+                &BTreeMap::new(),
+                "gemini-3-flash"
+            ),
             "gemini-3-flash"
         );
 
         // Unknown model passes through
         assert_eq!(
-            resolve_with_mappings("totally-unknown", &rules, "gemini-3-flash"),
+            resolve_with_mappings(
+                "totally-unknown",
+                &rules,
+                &BTreeMap::new(),
+                "gemini-3-flash"
+            ),
             "totally-unknown"
         );
     }
 
+    #[test]
+    fn test_resolve_with_user_aliases() {
+        let rules = vec![MappingRule {
+            from: "fast*".into(),
+            to: "gemini-3-pro-high".into(),
+        }];
+        let aliases = BTreeMap::from([
+            ("fast".to_string(), "gemini-3-flash".to_string()),
+            ("opus".to_string(), "claude-opus-4-5-thinking".to_string()),
+            ("smart".to_string(), "pro".to_string()),
+        ]);
+
+        // User alias beats glob rules and is case-insensitive
+        assert_eq!(
+            resolve_with_mappings("FAST", &rules, &aliases, "gemini-3-flash"),
+            "gemini-3-flash"
+        );
+        // User alias overrides a built-in alias
+        assert_eq!(
+            resolve_with_mappings("opus", &rules, &aliases, "gemini-3-flash"),
+            "claude-opus-4-5-thinking"
+        );
+        // Targets may be built-in aliases
+        assert_eq!(
+            resolve_with_mappings("smart", &rules, &aliases, "gemini-3-flash"),
+            "gemini-3-pro-high"
+        );
+        // Built-ins remain the fallback
+        assert_eq!(
+            resolve_with_mappings("sonnet", &rules, &aliases, "gemini-3-flash"),
+            "claude-sonnet-4-5"
+        );
+    }
+
+    #[test]
+    fn test_mapping_conflicts() {
+        let rule = |from: &str, to: &str| MappingRule {
+            from: from.into(),
+            to: to.into(),
+        };
+
+        // Specific-before-broad is intentional, not a conflict
+        let rules = vec![
+            rule("gpt-4o*", "gemini-3-flash"),
+            rule("gpt-4*", "gemini-3-pro-high"),
+        ];
+        assert!(mapping_conflicts(&rules, &BTreeMap::new()).is_empty());
+
+        // Broad-before-specific and duplicate patterns shadow later rules
+        let rules = vec![
+            rule("gpt-4*", "gemini-3-pro-high"),
+            rule("gpt-4o-mini", "gemini-3-flash"),
+            rule("GPT-4*", "gemini-3-flash"),
+            rule("gpt-4o*", "pro"), // same target once aliases resolve
+        ];
+        let conflicts = mapping_conflicts(&rules, &BTreeMap::new());
+        assert_eq!(conflicts.len(), 2);
+        assert!(conflicts[0].contains("'gpt-4o-mini'"));
+        assert!(conflicts[1].contains("'GPT-4*'"));
+
+        // Aliases overriding rules, and case-only duplicates
+        let aliases = BTreeMap::from([
+            ("Fast".to_string(), "gemini-3-flash".to_string()),
+            ("fast".to_string(), "gemini-3-pro-high".to_string()),
+            ("gpt-4-turbo".to_string(), "gemini-3-flash".to_string()),
+        ]);
+        let conflicts = mapping_conflicts(&rules[..1], &aliases);
+        assert_eq!(conflicts.len(), 2);
+        assert!(conflicts[0].contains("'Fast' wins"));
+        assert!(conflicts[1].contains("alias 'gpt-4-turbo'"));
+
+        // Built-in presets are conflict-free
+        for preset in [
+            MappingPreset::Balanced,
+            MappingPreset::Performance,
+            MappingPreset::Cost,
+        ] {
+            assert!(mapping_conflicts(&preset.rules(), &BTreeMap::new()).is_empty());
+        }
+    }
+
     #[test]
     fn test_mapping_presets() {
         // Balanced preset has rules
@@ -680,4 +984,16 @@ mod tests {
         assert_eq!(MappingPreset::Balanced.next(), MappingPreset::Performance);
         assert_eq!(MappingPreset::Custom.next(), MappingPreset::None);
     }
+
+    #[test]
+    fn test_is_model_disabled() {
+        let disabled = vec!["opus".to_string(), "gemini-3-pro-*".to_string()];
+
+        assert!(is_model_disabled("claude-opus-4-6-thinking", &disabled));
+        assert!(is_model_disabled("gemini-3-pro-high", &disabled));
+        assert!(is_model_disabled("gemini-3-pro-low", &disabled));
+        assert!(!is_model_disabled("claude-opus-4-5-thinking", &disabled));
+        assert!(!is_model_disabled("gemini-3-flash", &disabled));
+        assert!(!is_model_disabled("gemini-3-flash", &[]));
+    }
 }
@@ -0,0 +1,216 @@
+//! Optional webhook notifications (`[notifications]`) so external tooling can
+//! react to changes in the account pool.
+//!
+//! Delivery is best-effort: failures are logged and never affect requests.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tracing::{debug, info, warn};
+
+use crate::auth::HttpClient;
+use crate::config::NotificationsConfig;
+
+/// Account validity transition reported to `account_invalid_url`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountStatus {
+    /// The account was marked invalid (e.g. its refresh token was revoked)
+    Invalid,
+    /// A previously invalid account works again
+    Recovered,
+}
+
+#[derive(Debug, Serialize)]
+struct AccountEvent<'a> {
+    event: &'static str,
+    status: AccountStatus,
+    email: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+    timestamp: String,
+}
+
+fn account_event_body(email: &str, status: AccountStatus, reason: Option<&str>) -> Vec<u8> {
+    let event = AccountEvent {
+        event: "account_status",
+        status,
+        email,
+        reason,
+        timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    };
+    serde_json::to_vec(&event).unwrap_or_default()
+}
+
+/// POST an account validity change to `account_invalid_url`, if configured.
+pub async fn account_status_changed(
+    config: &NotificationsConfig,
+    email: &str,
+    status: AccountStatus,
+    reason: Option<&str>,
+) {
+    let Some(url) = config.account_invalid_url.as_deref() else {
+        return;
+    };
+
+    let body = account_event_body(email, status, reason);
+    match HttpClient::new().post(url, "application/json", &body).await {
+        Ok(_) => debug!(email = %email, status = ?status, "Sent account status notification"),
+        Err(e) => warn!(email = %email, error = %e, "Failed to send account status notification"),
+    }
+}
+
+/// Fire-and-forget variant of [`account_status_changed`] for the request path.
+pub fn spawn_account_status_changed(email: &str, status: AccountStatus, reason: Option<&str>) {
+    let config = crate::config::get_config();
+    if config.notifications.account_invalid_url.is_none() {
+        return;
+    }
+
+    let email = email.to_string();
+    let reason = reason.map(str::to_string);
+    tokio::spawn(async move {
+        account_status_changed(&config.notifications, &email, status, reason.as_deref()).await;
+    });
+}
+
+/// Quota condition reported to `low_quota_url`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaStatus {
+    /// Remaining quota dropped below `low_quota_threshold`
+    Low,
+    /// Remaining quota reached zero
+    Exhausted,
+}
+
+#[derive(Debug, Serialize)]
+struct QuotaEvent<'a> {
+    event: &'static str,
+    status: QuotaStatus,
+    email: &'a str,
+    model: &'a str,
+    remaining_fraction: f64,
+    timestamp: String,
+}
+
+/// (account, model, status) of a quota alert.
+type QuotaAlertKey = (String, String, QuotaStatus);
+
+/// Last alert sent per (account, model, status), for debouncing.
+static LAST_QUOTA_ALERT: LazyLock<Mutex<HashMap<QuotaAlertKey, Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The alert to send when a model's remaining quota moves from `previous`
+/// (unknown if `None`) to `current`, if it crossed the threshold or hit zero.
+fn quota_alert(previous: Option<f64>, current: f64, threshold: f64) -> Option<QuotaStatus> {
+    if current <= 0.0 && previous.is_none_or(|p| p > 0.0) {
+        Some(QuotaStatus::Exhausted)
+    } else if current < threshold && previous.is_none_or(|p| p >= threshold) {
+        Some(QuotaStatus::Low)
+    } else {
+        None
+    }
+}
+
+/// Record an alert unless the same one was sent within `debounce`.
+fn should_send_quota_alert(key: (String, String, QuotaStatus), debounce: Duration) -> bool {
+    let mut sent = LAST_QUOTA_ALERT.lock();
+    let now = Instant::now();
+    if sent
+        .get(&key)
+        .is_some_and(|last| now.duration_since(*last) < debounce)
+    {
+        return false;
+    }
+    sent.insert(key, now);
+    true
+}
+
+/// Check a freshly fetched quota value and POST to `low_quota_url` when it
+/// drops below `low_quota_threshold` or is exhausted. Runs in the background.
+pub fn quota_updated(email: &str, model: &str, previous: Option<f64>, current: f64) {
+    let config = crate::config::get_config();
+    let notifications = &config.notifications;
+    let Some(url) = notifications.low_quota_url.clone() else {
+        return;
+    };
+    let Some(status) = quota_alert(previous, current, notifications.low_quota_threshold) else {
+        return;
+    };
+    let debounce = Duration::from_secs(notifications.low_quota_debounce_secs);
+    if !should_send_quota_alert((email.to_string(), model.to_string(), status), debounce) {
+        return;
+    }
+
+    info!(email = %email, model = %model, remaining = current, status = ?status, "Quota alert");
+    let body = serde_json::to_vec(&QuotaEvent {
+        event: "quota",
+        status,
+        email,
+        model,
+        remaining_fraction: current,
+        timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    })
+    .unwrap_or_default();
+    let email = email.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = HttpClient::new()
+            .post(&url, "application/json", &body)
+            .await
+        {
+            warn!(email = %email, error = %e, "Failed to send quota notification");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_event_body() {
+        let body = account_event_body("a@example.com", AccountStatus::Invalid, Some("revoked"));
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value["event"], "account_status");
+        assert_eq!(value["status"], "invalid");
+        assert_eq!(value["email"], "a@example.com");
+        assert_eq!(value["reason"], "revoked");
+        assert!(value["timestamp"].as_str().unwrap().ends_with('Z'));
+
+        let body = account_event_body("a@example.com", AccountStatus::Recovered, None);
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value["status"], "recovered");
+        assert!(value.get("reason").is_none());
+    }
+
+    #[test]
+    fn test_quota_alert_on_crossing_only() {
+        assert_eq!(quota_alert(Some(0.5), 0.05, 0.1), Some(QuotaStatus::Low));
+        assert_eq!(quota_alert(None, 0.05, 0.1), Some(QuotaStatus::Low));
+        assert_eq!(quota_alert(Some(0.08), 0.05, 0.1), None);
+        assert_eq!(
+            quota_alert(Some(0.05), 0.0, 0.1),
+            Some(QuotaStatus::Exhausted)
+        );
+        assert_eq!(quota_alert(Some(0.0), 0.0, 0.1), None);
+        assert_eq!(quota_alert(Some(0.05), 0.5, 0.1), None);
+    }
+
+    #[test]
+    fn test_quota_alert_debounce() {
+        let key = || {
+            (
+                "d@example.com".to_string(),
+                "m".to_string(),
+                QuotaStatus::Low,
+            )
+        };
+        assert!(should_send_quota_alert(key(), Duration::from_secs(3600)));
+        assert!(!should_send_quota_alert(key(), Duration::from_secs(3600)));
+        assert!(should_send_quota_alert(key(), Duration::ZERO));
+    }
+}
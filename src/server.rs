@@ -4,33 +4,50 @@ use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper::{Method, Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
-use std::net::SocketAddr;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::task::{Context, Poll};
 use std::time::Duration;
 use tokio::net::TcpStream;
-use tokio::sync::{Mutex, RwLock, mpsc};
-use tracing::{debug, info, trace, warn};
+use tokio::sync::{Mutex, RwLock, mpsc, watch};
+use tracing::{Instrument, debug, debug_span, info, trace, warn};
 
 use crate::auth::HttpClient;
-use crate::auth::accounts::AccountStore;
+use crate::auth::accounts::{AccountStore, TOKEN_BUCKET_CAPACITY};
 use crate::cache::ResponseCache;
 use crate::cloudcode::{
-    CloudCodeClient, SseParser, build_request, create_message_stop, fetch_model_quotas,
-    format_sse_event, parse_response,
+    CloudCodeClient, INTERLEAVED_THINKING_BETA, SseParser, build_request, create_message_stop,
+    fetch_model_quotas, format_sse_event, parse_response, sends_interleaved_thinking,
 };
 use crate::config::get_config;
 use crate::error::{ApiError, AuthError, Error};
 use crate::format::{
-    ChatCompletionRequest, MessagesRequest, ModelInfo, ModelsResponse, StreamEvent,
+    ChatCompletionRequest, ChatCompletionResponse, CompletionRequest, MessagesRequest, ModelInfo,
+    ModelsResponse, StreamEvent,
 };
-use crate::models::{Model, get_fallback_model, is_thinking_model, resolve_with_mappings};
+use crate::models::{
+    Model, all_target_models, get_fallback_model, is_model_disabled, is_thinking_model,
+    model_capabilities, model_in_list, resolve_with_mappings,
+};
+use crate::notify::AccountStatus;
 use crate::stats::get_stats;
 
 /// Maximum request body size (10 MB).
 const MAX_REQUEST_SIZE: usize = 10 * 1024 * 1024;
 
+/// Request bodies at least this large are deserialized while streaming in
+/// instead of being buffered first (1 MB).
+const STREAMING_PARSE_THRESHOLD: usize = 1024 * 1024;
+
+/// Body chunks queued ahead of the streaming JSON parser.
+const BODY_CHUNK_BUFFER: usize = 16;
+
 /// Maximum time to wait for a single upstream frame before considering the
 /// stream stalled (seconds).
 const STREAM_FRAME_TIMEOUT_SECS: u64 = 300;
@@ -41,17 +58,28 @@ const STREAM_FRAME_TIMEOUT_SECS: u64 = 300;
 /// unbounded memory growth.  Each item is a small SSE text frame.
 const STREAM_CHANNEL_BUFFER: usize = 64;
 
+/// Largest `n` accepted when `[server] emulate_n` is enabled.
+const MAX_EMULATED_N: u32 = 8;
+
+/// Maximum number of emulated `n` sub-requests in flight at once.
+const EMULATED_N_CONCURRENCY: usize = 4;
+
 /// A streaming response body backed by an `mpsc` channel.
 ///
 /// Each received `Bytes` value is emitted as a single DATA frame.
 /// When the sender is dropped the body signals end-of-stream.
 pub struct ChannelBody {
     rx: mpsc::Receiver<Bytes>,
+    /// Keeps the account counted as busy until the stream is dropped
+    _account_load: Option<AccountLoadGuard>,
 }
 
 impl ChannelBody {
     fn new(rx: mpsc::Receiver<Bytes>) -> Self {
-        Self { rx }
+        Self {
+            rx,
+            _account_load: None,
+        }
     }
 }
 
@@ -93,11 +121,614 @@ fn streaming_body() -> (mpsc::Sender<Bytes>, ResponseBody) {
 /// - `http_client`: Shared HTTP client for OAuth operations
 /// - `cloudcode_client`: Google Cloud Code API client
 /// - `cache`: LRU response cache for non-streaming requests
+/// - `accounts_read_only`: set once `accounts.json` proves unwritable; account
+///   state is then kept in memory only for the rest of the session
+/// - `in_flight`: cache keys currently being fetched upstream, so identical
+///   concurrent requests wait for the first instead of duplicating it
+/// - `account_load`: number of upstream requests currently in flight per account
+/// - `active_streams`: cancellation handles for streaming Messages API requests
 pub struct ServerState {
     pub accounts: RwLock<AccountStore>,
     pub http_client: HttpClient,
     pub cloudcode_client: CloudCodeClient,
     pub cache: Mutex<ResponseCache>,
+    pub accounts_read_only: AtomicBool,
+    pub in_flight: InFlightRequests,
+    pub account_load: AccountLoad,
+    pub active_streams: ActiveStreams,
+    pub api_activity: ApiActivity,
+    pub idempotency: IdempotencyCache,
+    pub client_limits: ClientRateLimiter,
+    pub account_limits: AccountLimitsCache,
+}
+
+/// Single-flight registry of cache keys with an upstream request in progress.
+#[derive(Default)]
+pub struct InFlightRequests {
+    keys: parking_lot::Mutex<HashMap<String, watch::Receiver<()>>>,
+}
+
+impl InFlightRequests {
+    /// Claim `key` for the caller, or return a receiver whose `changed()`
+    /// resolves once the request currently holding it finishes.
+    fn claim(&self, key: &str) -> Result<InFlightGuard<'_>, watch::Receiver<()>> {
+        let mut keys = self.keys.lock();
+        if let Some(rx) = keys.get(key) {
+            return Err(rx.clone());
+        }
+        let (tx, rx) = watch::channel(());
+        keys.insert(key.to_string(), rx);
+        Ok(InFlightGuard {
+            requests: self,
+            key: key.to_string(),
+            _done: tx,
+        })
+    }
+}
+
+/// Held while a request computes a cache key. Dropping it releases the key
+/// and, by closing the channel, wakes every request waiting on it.
+struct InFlightGuard<'a> {
+    requests: &'a InFlightRequests,
+    key: String,
+    _done: watch::Sender<()>,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        // The sender is dropped after this runs, so waiters cloned under the
+        // lock always observe the close.
+        self.requests.keys.lock().remove(&self.key);
+    }
+}
+
+/// Live count of upstream requests in flight, keyed by account ID.
+#[derive(Default)]
+pub struct AccountLoad {
+    counts: Arc<parking_lot::Mutex<HashMap<String, usize>>>,
+}
+
+impl AccountLoad {
+    /// Count a request against `account_id` until the guard is dropped.
+    fn start(&self, account_id: &str) -> AccountLoadGuard {
+        *self
+            .counts
+            .lock()
+            .entry(account_id.to_string())
+            .or_default() += 1;
+        AccountLoadGuard {
+            counts: self.counts.clone(),
+            account_id: account_id.to_string(),
+        }
+    }
+
+    /// Snapshot of accounts with at least one request in flight.
+    pub fn snapshot(&self) -> HashMap<String, usize> {
+        self.counts.lock().clone()
+    }
+}
+
+struct AccountLoadGuard {
+    counts: Arc<parking_lot::Mutex<HashMap<String, usize>>>,
+    account_id: String,
+}
+
+impl Drop for AccountLoadGuard {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock();
+        if let Some(count) = counts.get_mut(&self.account_id) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.account_id);
+            }
+        }
+    }
+}
+
+/// Request ID -> (registration token, cancel signal) of the running streams.
+type StreamRegistry = Arc<parking_lot::Mutex<HashMap<String, (u64, watch::Sender<bool>)>>>;
+
+/// Streaming requests that can be cancelled by ID while they run.
+#[derive(Default)]
+pub struct ActiveStreams {
+    streams: StreamRegistry,
+    next_token: AtomicU64,
+}
+
+impl ActiveStreams {
+    /// Register a stream under each of `request_ids` until the returned
+    /// handle is dropped.
+    fn register(&self, request_ids: &[&str]) -> StreamCancellation {
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = watch::channel(false);
+        let mut streams = self.streams.lock();
+        for id in request_ids {
+            streams.insert(id.to_string(), (token, tx.clone()));
+        }
+        StreamCancellation {
+            streams: self.streams.clone(),
+            request_ids: request_ids.iter().map(|id| id.to_string()).collect(),
+            token,
+            cancelled: rx,
+        }
+    }
+
+    /// Signal the stream registered under `request_id` to stop. Returns
+    /// false if no such stream is running.
+    pub fn cancel(&self, request_id: &str) -> bool {
+        match self.streams.lock().get(request_id) {
+            Some((_, tx)) => {
+                tx.send_replace(true);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// How long a response stays replayable under its `Idempotency-Key`.
+const IDEMPOTENCY_TTL: Duration = Duration::from_secs(600);
+
+/// Most responses kept for `Idempotency-Key` replays.
+const IDEMPOTENCY_MAX_ENTRIES: usize = 1000;
+
+/// Completed responses keyed by the client's `Idempotency-Key`, so a retried
+/// request is answered without calling the upstream (and spending quota)
+/// again. Unlike the response cache this is keyed on the client's id, not the
+/// request content, though a repeat whose body differs is rejected rather
+/// than replayed. Only buffered 2xx responses are kept; streams aren't.
+#[derive(Default)]
+pub struct IdempotencyCache {
+    entries: parking_lot::Mutex<HashMap<String, StoredResponse>>,
+    in_flight: InFlightRequests,
+}
+
+struct StoredResponse {
+    stored_at: std::time::Instant,
+    /// SHA-256 of the request body that produced this response
+    body_digest: [u8; 32],
+    status: StatusCode,
+    headers: hyper::HeaderMap,
+    body: Bytes,
+}
+
+impl IdempotencyCache {
+    /// Claim `key` for a new request, or return the stored response once the
+    /// request already running under it finishes. If that request didn't
+    /// store a response, the caller claims the key and runs its own.
+    async fn claim_or_replay(
+        &self,
+        key: &str,
+        path: &str,
+        body_digest: &[u8; 32],
+    ) -> Result<InFlightGuard<'_>, Response<ResponseBody>> {
+        loop {
+            match self.in_flight.claim(key) {
+                Ok(guard) => {
+                    // The previous holder may have stored its response just
+                    // before releasing the key
+                    return match self.replay(key, path, body_digest) {
+                        Some(response) => Err(response),
+                        None => Ok(guard),
+                    };
+                }
+                Err(mut done) => {
+                    debug!("Waiting for in-flight request with the same Idempotency-Key");
+                    let _ = done.changed().await;
+                }
+            }
+        }
+    }
+
+    /// The stored response for `key`, marked with `X-Idempotent-Replay`, or
+    /// a 422 in `path`'s error format if it was stored for a different
+    /// request body.
+    fn replay(
+        &self,
+        key: &str,
+        path: &str,
+        body_digest: &[u8; 32],
+    ) -> Option<Response<ResponseBody>> {
+        let mut entries = self.entries.lock();
+        evict_expired_idempotency(&mut entries, std::time::Instant::now());
+        let stored = entries.get(key)?;
+        if stored.body_digest != *body_digest {
+            return Some(idempotency_mismatch_response(path));
+        }
+
+        let mut response = Response::new(full_body(Full::new(stored.body.clone())));
+        *response.status_mut() = stored.status;
+        *response.headers_mut() = stored.headers.clone();
+        response.headers_mut().insert(
+            "x-idempotent-replay",
+            hyper::header::HeaderValue::from_static("true"),
+        );
+        Some(response)
+    }
+
+    /// Keep a buffered response under `key` and hand it back unchanged.
+    async fn remember(
+        &self,
+        key: String,
+        body_digest: [u8; 32],
+        response: Response<ResponseBody>,
+    ) -> Response<ResponseBody> {
+        let (parts, body) = response.into_parts();
+        let Either::Left(full) = body else {
+            return Response::from_parts(parts, body);
+        };
+        let bytes = full
+            .collect()
+            .await
+            .map(|c| c.to_bytes())
+            .unwrap_or_default();
+
+        self.insert(
+            key,
+            StoredResponse {
+                stored_at: std::time::Instant::now(),
+                body_digest,
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: bytes.clone(),
+            },
+        );
+        Response::from_parts(parts, full_body(Full::new(bytes)))
+    }
+
+    fn insert(&self, key: String, response: StoredResponse) {
+        let mut entries = self.entries.lock();
+        evict_expired_idempotency(&mut entries, response.stored_at);
+        if entries.len() >= IDEMPOTENCY_MAX_ENTRIES
+            && let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, stored)| stored.stored_at)
+                .map(|(key, _)| key.clone())
+        {
+            entries.remove(&oldest);
+        }
+        entries.insert(key, response);
+    }
+}
+
+fn evict_expired_idempotency(
+    entries: &mut HashMap<String, StoredResponse>,
+    now: std::time::Instant,
+) {
+    entries.retain(|_, stored| now.duration_since(stored.stored_at) < IDEMPOTENCY_TTL);
+}
+
+/// Window for `[server] client_rate_limit_per_minute`.
+const CLIENT_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Per-client request counts for `[server] client_rate_limit_per_minute`,
+/// in fixed one-minute windows keyed by [`client_key`].
+#[derive(Default)]
+pub struct ClientRateLimiter {
+    windows: parking_lot::Mutex<HashMap<String, (std::time::Instant, u32)>>,
+}
+
+impl ClientRateLimiter {
+    /// Count a request from `client` as `cost` requests, or return how long
+    /// until its window resets if it has already made `limit` requests in it.
+    /// A request is let through while any of the window's budget is left, so
+    /// one costing more than `limit` isn't rejected forever.
+    fn check(
+        &self,
+        client: &str,
+        cost: u32,
+        limit: u32,
+        now: std::time::Instant,
+    ) -> Result<(), Duration> {
+        let mut windows = self.windows.lock();
+        windows.retain(|_, (started, _)| now.duration_since(*started) < CLIENT_RATE_WINDOW);
+
+        let (started, count) = windows.entry(client.to_string()).or_insert((now, 0));
+        if *count >= limit {
+            return Err(CLIENT_RATE_WINDOW.saturating_sub(now.duration_since(*started)));
+        }
+        *count = count.saturating_add(cost);
+        Ok(())
+    }
+}
+
+/// Last successful `/account-limits` payload. It is served as-is while younger
+/// than `[server] account_limits_ttl_secs`; once stale it is still served
+/// while a single background task fetches fresh quotas. Cold-cache requests
+/// share one fetch the same way.
+#[derive(Default)]
+pub struct AccountLimitsCache {
+    entry: parking_lot::Mutex<Option<(std::time::Instant, serde_json::Value)>>,
+    fetching: InFlightRequests,
+}
+
+/// [`AccountLimitsCache::fetching`] key for the quota fetch
+const ACCOUNT_LIMITS_FETCH: &str = "account-limits";
+
+impl AccountLimitsCache {
+    /// The cached payload and whether it is stale, or `None` if nothing is
+    /// cached or caching is off (`ttl` of zero).
+    fn get(&self, ttl: Duration, now: std::time::Instant) -> Option<(serde_json::Value, bool)> {
+        if ttl.is_zero() {
+            return None;
+        }
+        let entry = self.entry.lock();
+        let (fetched_at, payload) = entry.as_ref()?;
+        Some((payload.clone(), now.duration_since(*fetched_at) >= ttl))
+    }
+
+    fn store(&self, payload: serde_json::Value, now: std::time::Instant) {
+        *self.entry.lock() = Some((now, payload));
+    }
+}
+
+/// Rate-limit bucket for a request: its user id when it has one, else the
+/// client's IP (so one backend serving many users can be split fairly).
+fn client_key(request: &MessagesRequest, client_ip: IpAddr) -> String {
+    match request.user_id() {
+        Some(user_id) => format!("user:{}", user_id),
+        None => format!("ip:{}", client_ip),
+    }
+}
+
+/// Enforce `[server] client_rate_limit_per_minute` for a parsed request that
+/// makes `upstream_requests` upstream calls.
+fn check_client_rate_limit(
+    state: &ServerState,
+    request: &MessagesRequest,
+    client_ip: IpAddr,
+    request_id: &str,
+    upstream_requests: u32,
+) -> Result<(), Error> {
+    let Some(limit) = get_config().server.client_rate_limit_per_minute else {
+        return Ok(());
+    };
+    let client = client_key(request, client_ip);
+    state
+        .client_limits
+        .check(&client, upstream_requests, limit, std::time::Instant::now())
+        .map_err(|retry_after| {
+            warn!(
+                client = %client,
+                limit = limit,
+                request_id = %request_id,
+                "Client rate limit exceeded"
+            );
+            Error::Api(ApiError::RateLimited { retry_after })
+        })
+}
+
+/// Response header naming the account that served a request. Account IDs
+/// are internal, so it is only sent to clients that ask for it.
+const ACCOUNT_HEADER: &str = "X-AGCP-Account";
+
+/// Whether a response may carry [`ACCOUNT_HEADER`]: the client sent
+/// `X-AGCP-Report-Account: true`, or `[server] debug_api` is on.
+fn reports_account(headers: &hyper::HeaderMap) -> bool {
+    get_config().server.debug_api
+        || headers
+            .get("x-agcp-report-account")
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))
+}
+
+/// The API key a client sent, as a Bearer token or in `x-api-key`.
+fn provided_api_key(headers: &hyper::HeaderMap) -> Option<&str> {
+    headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+}
+
+/// The `Idempotency-Key` of a `/v1/*` POST, scoped to its path and to the
+/// client (its API key, or its IP without one) so clients sharing the proxy
+/// can't replay each other's responses.
+fn idempotency_key(
+    method: &Method,
+    path: &str,
+    headers: &hyper::HeaderMap,
+    client_ip: IpAddr,
+) -> Option<String> {
+    if *method != Method::POST || !path.starts_with("/v1/") {
+        return None;
+    }
+    let key = headers
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|key| !key.is_empty())?;
+    let client = match provided_api_key(headers) {
+        Some(api_key) => format!("key:{}", hex_digest(api_key.as_bytes())),
+        None => format!("ip:{}", client_ip),
+    };
+    Some(format!("{} {} {}", client, path, key))
+}
+
+/// The 422 for an Idempotency-Key reused with a different body, in the
+/// error format of the endpoint at `path`.
+fn idempotency_mismatch_response(path: &str) -> Response<ResponseBody> {
+    const MESSAGE: &str = "Idempotency-Key was already used with a different request body";
+    let status = StatusCode::UNPROCESSABLE_ENTITY;
+    match path {
+        "/v1/chat/completions" | "/v1/completions" => {
+            openai_error_response(status, MESSAGE, "invalid_request_error")
+        }
+        "/v1/responses" => responses_error_response(status, MESSAGE, "invalid_request_error"),
+        _ => json_response(
+            status,
+            &serde_json::json!({
+                "type": "error",
+                "error": { "type": "invalid_request_error", "message": MESSAGE }
+            })
+            .to_string(),
+        ),
+    }
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// A request body already read by [`handle_request`] to check it against an
+/// `Idempotency-Key`; [`read_json_body`] parses it instead of the drained
+/// `Incoming` body.
+#[derive(Clone)]
+struct BufferedBody(Bytes);
+
+/// When the last `/v1/*` request arrived, for `[server] idle_shutdown_secs`.
+pub struct ApiActivity {
+    started: std::time::Instant,
+    /// Seconds after `started`
+    last_request: AtomicU64,
+}
+
+impl Default for ApiActivity {
+    fn default() -> Self {
+        Self {
+            started: std::time::Instant::now(),
+            last_request: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ApiActivity {
+    fn touch(&self) {
+        self.last_request
+            .store(self.started.elapsed().as_secs(), Ordering::Relaxed);
+    }
+
+    /// Time since the last `/v1/*` request, or since startup if none.
+    pub fn idle_for(&self) -> Duration {
+        self.started.elapsed().saturating_sub(Duration::from_secs(
+            self.last_request.load(Ordering::Relaxed),
+        ))
+    }
+}
+
+/// Held by a streaming task; resolves `cancelled()` once a client asks to
+/// stop it, and unregisters the stream when dropped.
+struct StreamCancellation {
+    streams: StreamRegistry,
+    request_ids: Vec<String>,
+    token: u64,
+    cancelled: watch::Receiver<bool>,
+}
+
+impl StreamCancellation {
+    async fn cancelled(&mut self) {
+        if self
+            .cancelled
+            .wait_for(|cancelled| *cancelled)
+            .await
+            .is_err()
+        {
+            // Replaced by a newer stream with the same ID; never fires
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+impl Drop for StreamCancellation {
+    fn drop(&mut self) {
+        let mut streams = self.streams.lock();
+        for id in &self.request_ids {
+            // A later request may have reused the ID; leave its entry alone
+            if streams
+                .get(id)
+                .is_some_and(|(token, _)| *token == self.token)
+            {
+                streams.remove(id);
+            }
+        }
+    }
+}
+
+/// Keep a streaming response counted against its account until the client
+/// has received the whole stream. Buffered responses are already complete.
+fn hold_account_load(
+    response: Response<ResponseBody>,
+    guard: AccountLoadGuard,
+) -> Response<ResponseBody> {
+    response.map(|body| match body {
+        Either::Right(mut stream) => {
+            stream._account_load = Some(guard);
+            Either::Right(stream)
+        }
+        full => full,
+    })
+}
+
+/// `X-RateLimit-*` values for a successful response served by `account`.
+///
+/// Uses the model's quota (percent remaining, seconds until reset) when it is
+/// known, otherwise the account's rate-limit token bucket.
+/// `X-RateLimit-Limit` when a model's quota is known: the quota is reported as
+/// a percentage of the model's allowance.
+const QUOTA_PERCENT_LIMIT: u32 = 100;
+
+fn rate_limit_headers(
+    account: &crate::auth::Account,
+    model: &str,
+    now: u64,
+) -> Vec<(&'static str, String)> {
+    match account.quota.get(model) {
+        Some(quota) => {
+            let mut headers = vec![
+                ("X-RateLimit-Limit", QUOTA_PERCENT_LIMIT.to_string()),
+                (
+                    "X-RateLimit-Remaining",
+                    ((quota.remaining_fraction.clamp(0.0, 1.0) * QUOTA_PERCENT_LIMIT as f64).round()
+                        as u32)
+                        .to_string(),
+                ),
+            ];
+            if quota.reset_time > 0 {
+                headers.push((
+                    "X-RateLimit-Reset",
+                    quota.reset_time.saturating_sub(now).to_string(),
+                ));
+            }
+            headers
+        }
+        None => vec![
+            ("X-RateLimit-Limit", TOKEN_BUCKET_CAPACITY.to_string()),
+            (
+                "X-RateLimit-Remaining",
+                account.tokens_available.to_string(),
+            ),
+        ],
+    }
+}
+
+/// Attach `X-RateLimit-*` headers when `server.rate_limit_headers` is enabled.
+async fn with_rate_limit_headers(
+    state: &ServerState,
+    account_id: &str,
+    model: &str,
+    mut response: Response<ResponseBody>,
+) -> Response<ResponseBody> {
+    if !get_config().server.rate_limit_headers || !response.status().is_success() {
+        return response;
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let accounts = state.accounts.read().await;
+    if let Some(account) = accounts.accounts.iter().find(|a| a.id == account_id) {
+        let headers = response.headers_mut();
+        for (name, value) in rate_limit_headers(account, model, now) {
+            if let Ok(value) = hyper::header::HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+    }
+    response
 }
 
 /// Handle an incoming TCP connection.
@@ -119,18 +750,20 @@ pub async fn handle_connection(
     http1::Builder::new()
         .keep_alive(true)
         .serve_connection(io, service)
+        .with_upgrades()
         .await?;
 
     Ok(())
 }
 
 async fn handle_request(
-    req: Request<hyper::body::Incoming>,
+    mut req: Request<hyper::body::Incoming>,
     state: Arc<ServerState>,
     remote_addr: SocketAddr,
 ) -> Result<Response<ResponseBody>, hyper::Error> {
     let method = req.method().clone();
     let path = req.uri().path().to_string();
+    let report_account = reports_account(req.headers());
 
     // Use client-provided X-Request-ID if present, otherwise generate one
     let request_id = req
@@ -150,27 +783,23 @@ async fn handle_request(
 
     let start = std::time::Instant::now();
 
+    // Internal and health endpoints don't keep the daemon from idling out;
+    // `/messages` is an alias of `/v1/messages`
+    if path.starts_with("/v1/") || path == "/messages" {
+        state.api_activity.touch();
+    }
+
     // Handle CORS preflight requests
     if method == Method::OPTIONS {
         return Ok(cors_preflight_response());
     }
 
-    // Check API key authentication for /v1/* endpoints
+    // Check API key authentication for /v1/* and /debug/* endpoints
     let config = get_config();
-    if path.starts_with("/v1/")
+    if (path.starts_with("/v1/") || path.starts_with("/debug/"))
         && let Some(ref expected_key) = config.server.api_key
     {
-        let auth_header = req
-            .headers()
-            .get("authorization")
-            .and_then(|v| v.to_str().ok());
-        let x_api_key = req.headers().get("x-api-key").and_then(|v| v.to_str().ok());
-
-        let provided_key = auth_header
-            .and_then(|h| h.strip_prefix("Bearer "))
-            .or(x_api_key);
-
-        if provided_key != Some(expected_key.as_str()) {
+        if provided_api_key(req.headers()) != Some(expected_key.as_str()) {
             warn!(
                 remote = %remote_addr,
                 request_id = %request_id,
@@ -183,25 +812,87 @@ async fn handle_request(
         }
     }
 
+    if let Some(group) = endpoint_group(&path)
+        && !config.server.endpoint_enabled(group)
+    {
+        debug!(path = %path, group = group, "Endpoint disabled by server.enabled_endpoints");
+        return Ok(json_response(
+            StatusCode::NOT_FOUND,
+            r#"{"type":"error","error":{"type":"not_found","message":"Not found"}}"#,
+        ));
+    }
+
+    // Replay the stored response for a retried Idempotency-Key. The key is
+    // held until the response is stored, so concurrent duplicates wait for it
+    // instead of calling the upstream too. The body is read up front so a
+    // repeat with a different body can be told apart.
+    let response_state = Arc::clone(&state);
+    let idempotency_key = idempotency_key(&method, &path, req.headers(), remote_addr.ip());
+    let mut body_digest = [0u8; 32];
+    if idempotency_key.is_some() {
+        let body = match read_body_prefix(req.body_mut(), usize::MAX, MAX_REQUEST_SIZE).await {
+            Ok((body, _)) => body,
+            Err(e) => return Ok(error_to_response(&e, &request_id)),
+        };
+        body_digest = Sha256::digest(&body).into();
+        req.extensions_mut().insert(BufferedBody(body));
+    }
+    let _idempotency_guard = match &idempotency_key {
+        Some(key) => match response_state
+            .idempotency
+            .claim_or_replay(key, &path, &body_digest)
+            .await
+        {
+            Ok(guard) => Some(guard),
+            Err(response) => {
+                info!(
+                    method = %method,
+                    path = %path,
+                    request_id = %request_id,
+                    "Replaying response for Idempotency-Key"
+                );
+                return Ok(response);
+            }
+        },
+        None => None,
+    };
+
     let request_timeout = Duration::from_secs(config.server.request_timeout_secs);
     let response = match tokio::time::timeout(request_timeout, async {
         match (method.clone(), path.as_str()) {
             // Messages API (with and without /v1 prefix)
             (Method::POST, "/v1/messages") | (Method::POST, "/messages") => {
-                handle_messages(req, state, &request_id).await
+                handle_messages(req, state, &request_id, remote_addr.ip()).await
             }
 
             // OpenAI Chat Completions API
             (Method::POST, "/v1/chat/completions") => {
-                handle_chat_completions(req, state, &request_id).await
+                handle_chat_completions(req, state, &request_id, remote_addr.ip()).await
+            }
+
+            // OpenAI legacy Completions API
+            (Method::POST, "/v1/completions") => {
+                handle_completions(req, state, &request_id, remote_addr.ip()).await
             }
 
             // OpenAI Responses API (used by Codex CLI)
-            (Method::POST, "/v1/responses") => handle_responses(req, state, &request_id).await,
+            (Method::POST, "/v1/responses") => {
+                handle_responses(req, state, &request_id, remote_addr.ip()).await
+            }
+
+            // Streaming Messages API over WebSocket (for browser clients)
+            (Method::GET, "/v1/messages/ws") => {
+                handle_messages_ws(req, state, &request_id, remote_addr.ip()).await
+            }
 
             // Token counting API — estimates token count using chars/4 heuristic
             (Method::POST, "/v1/messages/count_tokens") => handle_count_tokens(req).await,
 
+            // Cancel an in-progress streaming request by its request ID
+            (Method::POST, p) if p.starts_with("/v1/messages/") && p.ends_with("/cancel") => {
+                handle_cancel_stream(&state, p)
+            }
+
             // Event logging batch (Claude Code sends these - acknowledge silently)
             (Method::POST, "/api/event_logging/batch") => {
                 Ok(json_response(StatusCode::OK, r#"{"status":"ok"}"#))
@@ -213,6 +904,11 @@ async fn handle_request(
             // Models API
             (Method::GET, "/v1/models") => handle_models().await,
 
+            // Build metadata
+            (Method::GET, "/version") => {
+                Ok(json_response(StatusCode::OK, &version_info().to_string()))
+            }
+
             // Stats API
             (Method::GET, "/stats") | (Method::GET, "/v1/stats") => handle_stats(&state).await,
 
@@ -235,6 +931,13 @@ async fn handle_request(
                 Ok(json_response(StatusCode::OK, r#"{"status":"cleared"}"#))
             }
 
+            // Live account state (opt-in, only with an API key configured)
+            (Method::GET, "/debug/accounts")
+                if config.server.debug_api && config.server.api_key.is_some() =>
+            {
+                handle_debug_accounts(&state).await
+            }
+
             // Account limits API (quota info for OpenCode)
             (Method::GET, "/account-limits") => handle_account_limits(&state).await,
 
@@ -253,6 +956,7 @@ async fn handle_request(
             )),
         }
     })
+    .instrument(debug_span!("request", request_id = %request_id))
     .await
     {
         Ok(result) => result,
@@ -265,8 +969,14 @@ async fn handle_request(
     let duration = start.elapsed();
 
     match response {
-        Ok(resp) => {
+        Ok(mut resp) => {
             let status = resp.status().as_u16();
+            // Streaming bodies have no exact size and are left out of the histogram
+            if !is_internal_endpoint(&path)
+                && let Some(size) = hyper::body::Body::size_hint(resp.body()).exact()
+            {
+                get_stats().record_response_size(size as usize);
+            }
             // Don't warn for expected 501 on count_tokens - it's not implemented by design
             let is_expected_501 = status == 501 && path == "/v1/messages/count_tokens";
             if status >= 400 && !is_expected_501 {
@@ -305,10 +1015,23 @@ async fn handle_request(
                     "Request completed"
                 );
             }
-            Ok(resp)
+            if !report_account {
+                resp.headers_mut().remove(ACCOUNT_HEADER);
+            }
+            match idempotency_key {
+                Some(key) if resp.status().is_success() => Ok(response_state
+                    .idempotency
+                    .remember(key, body_digest, resp)
+                    .await),
+                _ => Ok(resp),
+            }
         }
         Err(e) => {
-            let resp = error_to_response(&e, &request_id);
+            let mut resp = error_to_response(&e, &request_id);
+            if let Some(secs) = retry_after_secs(&response_state, &e).await {
+                resp.headers_mut()
+                    .insert(hyper::header::RETRY_AFTER, secs.into());
+            }
             warn!(
                 method = %method,
                 path = %path,
@@ -329,14 +1052,38 @@ fn is_internal_endpoint(path: &str) -> bool {
     matches!(
         path,
         "/" | "/health"
+            | "/version"
             | "/stats"
             | "/v1/stats"
             | "/cache/stats"
             | "/account-limits"
+            | "/debug/accounts"
             | "/api/event_logging/batch"
     )
 }
 
+/// Upper bound on the delay inserted before a fallback attempt.
+const MAX_FALLBACK_BACKOFF_MS: u64 = 5_000;
+
+/// Delay before the fallback attempt numbered `attempt` (0-based).
+///
+/// Doubles from `base_ms` per attempt up to `MAX_FALLBACK_BACKOFF_MS`, then
+/// picks a point in the upper half of that window so concurrent retries
+/// during pool-wide exhaustion don't all fire at once.
+fn fallback_backoff(attempt: u32, base_ms: u64) -> Duration {
+    let ceiling = base_ms
+        .saturating_mul(1u64 << attempt.min(16))
+        .min(MAX_FALLBACK_BACKOFF_MS);
+    if ceiling == 0 {
+        return Duration::ZERO;
+    }
+    let mut bytes = [0u8; 8];
+    getrandom::fill(&mut bytes).expect("Failed to generate random bytes");
+    let half = ceiling / 2;
+    let jitter = u64::from_le_bytes(bytes) % (half + 1);
+    Duration::from_millis(ceiling - half + jitter)
+}
+
 fn generate_request_id() -> String {
     let mut bytes = [0u8; 8];
     getrandom::fill(&mut bytes).expect("Failed to generate random bytes");
@@ -358,14 +1105,12 @@ async fn get_account_credentials(
 ) -> Result<(String, String, String, String), Error> {
     // Phase 1: Select account and extract data under a brief write lock.
     // If the cached token is still valid we return immediately.
-    let (account_id, project_id, email, token_or_refresh) = {
+    let (account_id, project_id, email, token_or_refresh) = async {
         let mut accounts = state.accounts.write().await;
 
-        let account_id = accounts.select_account(model).ok_or_else(|| {
-            Error::Auth(AuthError::OAuthFailed(
-                "No enabled accounts available. Run 'agcp login' to add an account.".to_string(),
-            ))
-        })?;
+        let account_id = accounts
+            .select_account(model)
+            .ok_or(Error::Auth(AuthError::NoAccounts))?;
 
         let account = accounts.get_account_mut(&account_id).ok_or_else(|| {
             Error::Auth(AuthError::OAuthFailed(
@@ -373,7 +1118,10 @@ async fn get_account_credentials(
             ))
         })?;
 
-        let project_id = account.project_id.clone().unwrap_or_default();
+        let project_id = account
+            .effective_project_id()
+            .unwrap_or_default()
+            .to_string();
         let id = account.id.clone();
         let email_val = account.email.clone();
 
@@ -387,22 +1135,36 @@ async fn get_account_credentials(
         if account.is_access_token_valid() {
             // Fast path: token is still valid, no network I/O needed.
             let token = account.access_token.clone().unwrap();
-            (id, project_id, email_val, Ok(token))
+            Ok::<_, Error>((id, project_id, email_val, Ok(token)))
         } else {
             // Slow path: need to refresh. Clone the refresh token and release the lock.
             let refresh_token = account.refresh_token.clone();
-            (id, project_id, email_val, Err(refresh_token))
+            Ok((id, project_id, email_val, Err(refresh_token)))
         }
         // Write lock is dropped here.
-    };
+    }
+    .instrument(debug_span!("account_selection", model = %model))
+    .await?;
 
     let access_token = match token_or_refresh {
         Ok(token) => token,
         Err(refresh_token) => {
             // Phase 2: Refresh token outside the lock (network I/O).
             let (new_token, expires_in) =
-                crate::auth::token::refresh_access_token(&state.http_client, &refresh_token)
-                    .await?;
+                match crate::auth::token::refresh_access_token(&state.http_client, &refresh_token)
+                    .instrument(debug_span!("token_refresh"))
+                    .await
+                {
+                    Ok(refreshed) => refreshed,
+                    Err(e) => {
+                        if let Error::Auth(AuthError::RefreshFailed(reason)) = &e
+                            && reason.contains("invalid_grant")
+                        {
+                            mark_account_invalid(state, &account_id, reason).await;
+                        }
+                        return Err(e);
+                    }
+                };
 
             // Phase 3: Store the refreshed token under a brief write lock.
             {
@@ -448,6 +1210,14 @@ async fn record_request_outcome(
 
         if let Some(account) = accounts.get_account_mut(account_id) {
             if success {
+                if account.is_invalid {
+                    info!(account = %account.email, "Account recovered");
+                    crate::notify::spawn_account_status_changed(
+                        &account.email,
+                        AccountStatus::Recovered,
+                        None,
+                    );
+                }
                 account.record_success();
                 account.clear_rate_limit(model);
             } else {
@@ -465,7 +1235,8 @@ async fn record_request_outcome(
         }
 
         // Only serialize if we need to persist (failure or rate limit)
-        if !success || rate_limit_until.is_some() {
+        let read_only = state.accounts_read_only.load(Ordering::Relaxed);
+        if !read_only && (!success || rate_limit_until.is_some()) {
             serde_json::to_string_pretty(&*accounts)
                 .ok()
                 .map(|json| (crate::auth::accounts::AccountStore::path(), json))
@@ -477,41 +1248,105 @@ async fn record_request_outcome(
 
     // Write to disk outside the lock using a blocking task.
     if let Some((path, json)) = save_data {
-        let dir = path.parent().map(|p| p.to_path_buf());
-        tokio::task::spawn_blocking(move || {
-            if let Some(dir) = dir {
-                let _ = std::fs::create_dir_all(dir);
-            }
-            if let Err(e) = std::fs::write(&path, json) {
-                tracing::warn!(error = %e, "Failed to save account state");
-            }
-        });
+        save_accounts_in_background(state, path, json);
     }
 }
 
-/// Extract outcome from a request result, log it, and record it for account health tracking.
-async fn track_request_outcome(
-    state: &Arc<ServerState>,
-    account_id: &str,
-    account_email: &str,
-    model: &str,
-    request_id: &str,
-    result: &Result<Response<ResponseBody>, Error>,
-) {
-    let (success, rate_limit_until) = match result {
-        Ok(_) => {
-            info!(
-                model = %model,
-                request_id = %request_id,
-                account = %account_email,
-                "Model used"
-            );
-            (true, None)
+/// Write serialized account state to disk on a blocking task.
+fn save_accounts_in_background(state: &Arc<ServerState>, path: PathBuf, json: String) {
+    tokio::spawn(save_accounts(Arc::clone(state), path, json));
+}
+
+/// Write serialized account state to disk on a blocking thread, so callers
+/// can serialize under the accounts lock and write after releasing it.
+pub async fn save_accounts(state: Arc<ServerState>, path: PathBuf, json: String) {
+    let _ = tokio::task::spawn_blocking(move || {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
         }
-        Err(Error::Api(ApiError::RateLimited { retry_after })) => {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
+        if let Err(e) = crate::auth::accounts::write_atomic(&path, json.as_bytes()) {
+            note_account_save_error(&state, &path, &e);
+        }
+    })
+    .await;
+}
+
+/// Mark an account invalid after its refresh token was rejected, so selection
+/// skips it until it recovers or is re-authenticated.
+async fn mark_account_invalid(state: &Arc<ServerState>, account_id: &str, reason: &str) {
+    let save_data = {
+        let mut accounts = state.accounts.write().await;
+        let Some(account) = accounts.get_account_mut(account_id) else {
+            return;
+        };
+        if account.is_invalid {
+            return;
+        }
+        account.is_invalid = true;
+        account.invalid_reason = Some(reason.to_string());
+        warn!(account = %account.email, reason = %reason, "Account marked invalid");
+        crate::notify::spawn_account_status_changed(
+            &account.email,
+            AccountStatus::Invalid,
+            Some(reason),
+        );
+
+        if state.accounts_read_only.load(Ordering::Relaxed) {
+            None
+        } else {
+            serde_json::to_string_pretty(&*accounts)
+                .ok()
+                .map(|json| (crate::auth::accounts::AccountStore::path(), json))
+        }
+    };
+
+    if let Some((path, json)) = save_data {
+        save_accounts_in_background(state, path, json);
+    }
+}
+
+/// Log a failed account state write. A permanent failure (read-only file or
+/// directory) is logged once and switches the server to in-memory account state.
+fn note_account_save_error(state: &ServerState, path: &Path, e: &std::io::Error) {
+    if crate::auth::accounts::is_read_only_error(e) {
+        if !state.accounts_read_only.swap(true, Ordering::Relaxed) {
+            warn!(
+                path = %path.display(),
+                error = %e,
+                "Accounts file is not writable, keeping account state in memory for this session"
+            );
+        }
+    } else {
+        warn!(error = %e, "Failed to save account state");
+    }
+}
+
+/// Extract outcome from a request result, log it, and record it for account health tracking.
+///
+/// Returns true when the upstream reported the account itself as unusable; the
+/// account is then marked invalid and the request can be retried on another.
+async fn track_request_outcome(
+    state: &Arc<ServerState>,
+    account_id: &str,
+    account_email: &str,
+    model: &str,
+    request_id: &str,
+    result: &Result<Response<ResponseBody>, Error>,
+) -> bool {
+    let (success, rate_limit_until) = match result {
+        Ok(_) => {
+            info!(
+                model = %model,
+                request_id = %request_id,
+                account = %account_email,
+                "Model used"
+            );
+            (true, None)
+        }
+        Err(Error::Api(ApiError::RateLimited { retry_after })) => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
                 .as_secs();
             let until = now + retry_after.as_secs();
             (false, Some(until))
@@ -526,25 +1361,161 @@ async fn track_request_outcome(
     };
 
     record_request_outcome(state, account_id, model, success, rate_limit_until).await;
+
+    let fatal_reason = result
+        .as_ref()
+        .err()
+        .and_then(crate::cloudcode::client::account_fatal_reason);
+    if let Some(reason) = fatal_reason {
+        warn!(
+            account = %account_email,
+            request_id = %request_id,
+            reason = %reason,
+            "Upstream rejected the account itself"
+        );
+        mark_account_invalid(state, account_id, reason).await;
+    }
+    fatal_reason.is_some()
 }
 
-/// Record token usage from a completed response
-fn record_usage(model: &str, usage: &crate::format::anthropic::Usage) {
-    get_stats().record_token_usage(
-        model,
-        usage.input_tokens,
-        usage.output_tokens,
-        usage.cache_read_input_tokens.unwrap_or(0),
-    );
+/// One upstream attempt prepared by [`execute_with_account_retry`] for the
+/// account it selected.
+struct UpstreamAttempt {
+    access_token: String,
+    /// Request ID of the converted Cloud Code request
+    request_id: String,
+    body: Bytes,
+    usage_sink: UsageSink,
+}
+
+/// Select an account, convert `messages_request` for it and send it with
+/// `send`. An account the upstream rejects outright is marked invalid and the
+/// request is retried once on another account. A response names the account
+/// that served it in [`ACCOUNT_HEADER`], which [`handle_request`] strips
+/// unless the client opted in.
+async fn execute_with_account_retry<F, Fut>(
+    state: &Arc<ServerState>,
+    model: &str,
+    messages_request: &MessagesRequest,
+    mut send: F,
+) -> Result<Response<ResponseBody>, Error>
+where
+    F: FnMut(UpstreamAttempt) -> Fut,
+    Fut: Future<Output = Result<Response<ResponseBody>, Error>>,
+{
+    let mut retried = false;
+    loop {
+        let (access_token, project_id, account_id, account_email) =
+            get_account_credentials(state, model).await?;
+        // Charged here rather than at selection so quota fetches through
+        // get_account_credentials don't eat into the daily budget
+        state
+            .accounts
+            .write()
+            .await
+            .record_daily_request(&account_id);
+        let account_load = state.account_load.start(&account_id);
+
+        let cc_request = debug_span!("convert_request")
+            .in_scope(|| build_request(messages_request, &project_id));
+        let attempt = UpstreamAttempt {
+            access_token,
+            request_id: cc_request.request_id.clone(),
+            body: Bytes::from(serde_json::to_vec(&cc_request)?),
+            usage_sink: UsageSink::new(state, &account_id),
+        };
+        let result = send(attempt).await;
+
+        if track_request_outcome(
+            state,
+            &account_id,
+            &account_email,
+            model,
+            &cc_request.request_id,
+            &result,
+        )
+        .await
+            && !retried
+        {
+            retried = true;
+            continue;
+        }
+
+        break match result {
+            Ok(mut response) => {
+                if let Ok(value) = hyper::header::HeaderValue::from_str(&account_id) {
+                    response.headers_mut().insert(ACCOUNT_HEADER, value);
+                }
+                let response = with_rate_limit_headers(state, &account_id, model, response).await;
+                Ok(hold_account_load(response, account_load))
+            }
+            Err(e) => Err(e),
+        };
+    }
+}
+
+/// Records a response's token usage in the global stats and against the
+/// daily token budget of the account that served it.
+struct UsageSink {
+    state: Arc<ServerState>,
+    account_id: String,
+}
+
+impl UsageSink {
+    fn new(state: &Arc<ServerState>, account_id: &str) -> Self {
+        Self {
+            state: Arc::clone(state),
+            account_id: account_id.to_string(),
+        }
+    }
+
+    /// Record token usage from a completed response
+    fn record(&self, model: &str, usage: &crate::format::anthropic::Usage) {
+        self.record_tokens(
+            model,
+            usage.input_tokens,
+            usage.output_tokens,
+            usage.cache_read_input_tokens.unwrap_or(0),
+            usage.cache_creation_input_tokens.unwrap_or(0),
+        );
+    }
+
+    fn record_tokens(
+        &self,
+        model: &str,
+        input_tokens: u32,
+        output_tokens: u32,
+        cache_read_tokens: u32,
+        cache_creation_tokens: u32,
+    ) {
+        get_stats().record_token_usage(
+            model,
+            input_tokens,
+            output_tokens,
+            cache_read_tokens,
+            cache_creation_tokens,
+        );
+
+        // Charged in the background so responses don't wait on the account lock
+        let state = Arc::clone(&self.state);
+        let account_id = self.account_id.clone();
+        tokio::spawn(async move {
+            state.accounts.write().await.record_daily_tokens(
+                &account_id,
+                u64::from(input_tokens) + u64::from(output_tokens),
+            );
+        });
+    }
 }
 
 async fn handle_messages(
     req: Request<hyper::body::Incoming>,
     state: Arc<ServerState>,
     request_id: &str,
+    client_ip: IpAddr,
 ) -> Result<Response<ResponseBody>, Error> {
     // Extract headers before consuming request
-    let bypass_cache = should_bypass_cache(req.headers());
+    let options = MessagesHeaderOptions::from_headers(req.headers());
 
     let content_type = req
         .headers()
@@ -569,9 +1540,42 @@ async fn handle_messages(
         }));
     }
 
-    let body_bytes = read_body_limited(req.into_body(), MAX_REQUEST_SIZE).await?;
+    let messages_request: MessagesRequest = read_json_body(req).await?;
+    run_messages_request(messages_request, options, &state, request_id, client_ip).await
+}
+
+/// Options a Messages request takes from its HTTP headers (for WebSocket
+/// requests, the upgrade request's headers).
+struct MessagesHeaderOptions {
+    cache_ttl: Option<Duration>,
+    bypass_cache: bool,
+    strip_thinking: Option<bool>,
+    betas: Vec<String>,
+}
+
+impl MessagesHeaderOptions {
+    fn from_headers(headers: &hyper::HeaderMap) -> Self {
+        let cache_ttl = cache_ttl_override(headers);
+        Self {
+            cache_ttl,
+            bypass_cache: should_bypass_cache(headers)
+                || cache_ttl.is_some_and(|ttl| ttl.is_zero()),
+            strip_thinking: strip_thinking_override(headers),
+            betas: requested_betas(headers),
+        }
+    }
+}
 
-    let mut messages_request: MessagesRequest = serde_json::from_slice(&body_bytes)?;
+/// Resolve, validate and rate-limit a parsed Messages request, then run it
+/// with fallback. Shared by the HTTP and WebSocket endpoints.
+async fn run_messages_request(
+    mut messages_request: MessagesRequest,
+    options: MessagesHeaderOptions,
+    state: &Arc<ServerState>,
+    request_id: &str,
+    client_ip: IpAddr,
+) -> Result<Response<ResponseBody>, Error> {
+    messages_request.strip_thinking = options.strip_thinking;
 
     // Resolve model aliases (e.g., "opus" -> "claude-opus-4-6-thinking")
     let original_model = messages_request.model.clone();
@@ -579,52 +1583,414 @@ async fn handle_messages(
     messages_request.model = resolve_with_mappings(
         &messages_request.model,
         &config.mappings.rules,
+        &config.mappings.aliases,
         &config.mappings.background_task_model,
     );
 
     debug!(
         original_model = %original_model,
         resolved_model = %messages_request.model,
+        user_id = messages_request.user_id(),
         request_id = %request_id,
         "Model resolution"
     );
+    handle_unknown_model(&mut messages_request.model, &config, request_id)?;
+
+    apply_request_defaults(&mut messages_request, &config.defaults, None);
+    apply_server_policies(&mut messages_request, &config.server, request_id);
+
+    validate_request(&messages_request, &config)?;
+    handle_empty_content(
+        &mut messages_request.messages,
+        config.server.reject_empty_content(),
+        request_id,
+    )?;
+    merge_consecutive_roles(&mut messages_request.messages);
+    validate_conversation(&messages_request.messages)?;
+    check_client_rate_limit(state, &messages_request, client_ip, request_id, 1)?;
+
+    let result = execute_with_fallback(
+        &messages_request,
+        state,
+        &config,
+        request_id,
+        options.bypass_cache,
+        options.cache_ttl,
+    )
+    .await;
+
+    let ignored = ignored_betas(&options.betas, &messages_request.model, request_id);
+    let result = with_ignored_betas_header(result, &ignored);
+
+    // Streams are filtered as they're sent; buffered bodies (including
+    // cache hits, which keep the thinking) are filtered here
+    match result {
+        Ok(response) if strips_thinking(&messages_request) => {
+            Ok(strip_thinking_from_response(response).await)
+        }
+        other => other,
+    }
+}
+
+/// `anthropic-beta` flags whose behavior every request already gets, so
+/// there is nothing to switch on upstream.
+const ACCEPTED_BETAS: &[&str] = &[
+    "prompt-caching-2024-07-31",
+    "token-efficient-tools-2025-02-19",
+];
+
+/// `anthropic-beta` flags Cloud Code can't provide, with the reason.
+const UNSUPPORTED_BETAS: &[(&str, &str)] = &[
+    (
+        "output-128k-2025-02-19",
+        "Cloud Code caps Claude output at 64000 tokens",
+    ),
+    (
+        "context-1m-2025-08-07",
+        "Cloud Code does not offer the 1M token context window",
+    ),
+    (
+        "fine-grained-tool-streaming-2025-05-14",
+        "tool input arrives upstream in one piece",
+    ),
+    ("files-api-2025-04-14", "the Files API is not proxied"),
+];
+
+/// The distinct flags from a request's `anthropic-beta` headers.
+fn requested_betas(headers: &hyper::HeaderMap) -> Vec<String> {
+    let mut betas: Vec<String> = Vec::new();
+    let flags = headers
+        .get_all("anthropic-beta")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|flag| !flag.is_empty());
+    for flag in flags {
+        if !betas.iter().any(|f| f == flag) {
+            betas.push(flag.to_string());
+        }
+    }
+    betas
+}
+
+/// Whether `flag` is in effect for a request to `model`: either every
+/// request already gets its behavior, or agcp sends it upstream itself.
+fn beta_in_effect(flag: &str, model: &str) -> bool {
+    ACCEPTED_BETAS.contains(&flag)
+        || (flag == INTERLEAVED_THINKING_BETA && sends_interleaved_thinking(model))
+}
+
+/// Flags from `anthropic-beta` headers that are not in effect for a request
+/// to `model`. They are echoed back in `X-AGCP-Beta-Ignored` so clients don't
+/// assume a beta is active when it isn't.
+fn ignored_betas(betas: &[String], model: &str, request_id: &str) -> Vec<String> {
+    let mut ignored = Vec::new();
+    for flag in betas {
+        if beta_in_effect(flag, model) {
+            continue;
+        }
+        match UNSUPPORTED_BETAS.iter().find(|(name, _)| name == flag) {
+            Some((_, reason)) => {
+                debug!(beta = %flag, reason = %reason, request_id = %request_id, "Unsupported anthropic-beta flag")
+            }
+            None => debug!(beta = %flag, request_id = %request_id, "Unknown anthropic-beta flag"),
+        }
+        ignored.push(flag.clone());
+    }
+    ignored
+}
+
+fn with_ignored_betas_header(
+    result: Result<Response<ResponseBody>, Error>,
+    ignored: &[String],
+) -> Result<Response<ResponseBody>, Error> {
+    if ignored.is_empty() {
+        return result;
+    }
+    result.map(|mut resp| {
+        if let Ok(value) = hyper::header::HeaderValue::from_str(&ignored.join(",")) {
+            resp.headers_mut().insert("X-AGCP-Beta-Ignored", value);
+        }
+        resp
+    })
+}
+
+/// `X-AGCP-Strip-Thinking: true|false` overriding `[server] strip_thinking`.
+fn strip_thinking_override(headers: &hyper::HeaderMap) -> Option<bool> {
+    let value = headers.get("x-agcp-strip-thinking")?.to_str().ok()?.trim();
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether thinking blocks should be removed from this request's response.
+fn strips_thinking(request: &MessagesRequest) -> bool {
+    request
+        .strip_thinking
+        .unwrap_or_else(|| get_config().server.strip_thinking)
+}
+
+/// Drop thinking blocks from a buffered Messages response. Streams and
+/// bodies that aren't a Messages response (errors) pass through unchanged.
+async fn strip_thinking_from_response(response: Response<ResponseBody>) -> Response<ResponseBody> {
+    let (parts, body) = response.into_parts();
+    let Either::Left(full) = body else {
+        return Response::from_parts(parts, body);
+    };
+    let bytes = full
+        .collect()
+        .await
+        .map(|c| c.to_bytes())
+        .unwrap_or_default();
+    let bytes = strip_thinking_blocks(&bytes).unwrap_or(bytes);
+    Response::from_parts(parts, full_body(Full::new(bytes)))
+}
+
+/// `body` without its thinking blocks, or `None` if there were none to remove.
+fn strip_thinking_blocks(body: &[u8]) -> Option<Bytes> {
+    let mut response: crate::format::MessagesResponse = serde_json::from_slice(body).ok()?;
+    let before = response.content.len();
+    response
+        .content
+        .retain(|block| !matches!(block, crate::format::ContentBlock::Thinking { .. }));
+    if response.content.len() == before {
+        return None;
+    }
+    serde_json::to_vec(&response).ok().map(Bytes::from)
+}
+
+/// Errors that make a request eligible for model fallback: exhausted quota,
+/// or an upstream call that timed out (e.g. via `upstream_timeout_secs`).
+fn triggers_fallback(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::Api(ApiError::QuotaExhausted { .. }) | Error::Timeout(_)
+    )
+}
+
+/// Tag the result of a fallback attempt with the model the client asked for,
+/// so clients that care about model fidelity can detect the substitution.
+/// Only a successful response was served by the fallback model; an error
+/// response is returned untagged.
+fn with_fallback_header(
+    result: Result<Response<ResponseBody>, Error>,
+    requested_model: &str,
+) -> Result<Response<ResponseBody>, Error> {
+    result.map(|mut resp| {
+        if resp.status().is_success()
+            && let Ok(value) = hyper::header::HeaderValue::from_str(requested_model)
+        {
+            resp.headers_mut().insert("X-AGCP-Fallback-From", value);
+        }
+        resp
+    })
+}
+
+/// Whether a non-streaming request for `model` should be served by collecting
+/// the streaming endpoint's SSE events instead of calling generateContent.
+///
+/// Always true for thinking models; `[server] prefer_streaming_endpoint`
+/// extends it to every model.
+fn use_streaming_endpoint(model: &str) -> bool {
+    is_thinking_model(model) || get_config().server.prefer_streaming_endpoint
+}
+
+/// Upgrade to a WebSocket that accepts one Messages API request as a JSON
+/// text message and streams the response events back as text frames, one
+/// event per frame, followed by a close frame.
+async fn handle_messages_ws(
+    req: Request<hyper::body::Incoming>,
+    state: Arc<ServerState>,
+    request_id: &str,
+    client_ip: IpAddr,
+) -> Result<Response<ResponseBody>, Error> {
+    let Some(key) = crate::websocket::handshake_key(req.headers()) else {
+        return Err(Error::Api(ApiError::InvalidRequest {
+            message: "Expected a WebSocket upgrade request".to_string(),
+        }));
+    };
+    let options = MessagesHeaderOptions::from_headers(req.headers());
+
+    let request_id_owned = request_id.to_string();
+    tokio::spawn(async move {
+        let request_id = request_id_owned;
+        match hyper::upgrade::on(req).await {
+            Ok(upgraded) => {
+                let mut ws =
+                    crate::websocket::server_stream(TokioIo::new(upgraded), MAX_REQUEST_SIZE).await;
+                let served = serve_messages_ws(&mut ws, &state, &request_id, client_ip, options);
+                if let Err(e) = served.await {
+                    debug!(request_id = %request_id, error = %e, "WebSocket connection ended");
+                }
+            }
+            Err(e) => {
+                warn!(request_id = %request_id, error = %e, "WebSocket upgrade failed");
+            }
+        }
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade")
+        .header("Sec-WebSocket-Accept", crate::websocket::accept_key(&key))
+        .header("X-Request-Id", request_id)
+        .body(full_body(Full::new(Bytes::new())))
+        .unwrap())
+}
+
+/// Run one request over an upgraded WebSocket connection.
+///
+/// The request goes through the same preparation and rate limiting as
+/// `POST /v1/messages`, and the stream comes from the regular SSE pipeline;
+/// each SSE event's `data` payload is forwarded as a text frame. Errors are
+/// sent as an error event with the same JSON body the HTTP endpoint would
+/// return.
+async fn serve_messages_ws<S>(
+    ws: &mut tokio_tungstenite::WebSocketStream<S>,
+    state: &Arc<ServerState>,
+    request_id: &str,
+    client_ip: IpAddr,
+    options: MessagesHeaderOptions,
+) -> Result<(), tokio_tungstenite::tungstenite::Error>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use crate::websocket::{CLOSE_INTERNAL_ERROR, CLOSE_NORMAL, close, send_text};
+
+    let Some(text) = crate::websocket::read_text_message(ws).await? else {
+        return Ok(());
+    };
+    get_stats().record_request_size(text.len());
+
+    let result = match crate::format::parse_json_body::<MessagesRequest>(text.as_bytes()) {
+        Ok(mut messages_request) => {
+            messages_request.stream = true;
+            run_messages_request(messages_request, options, state, request_id, client_ip).await
+        }
+        Err(e) => Err(Error::InvalidBody(e)),
+    };
+
+    let close_code = match result {
+        Ok(response) => {
+            let mut body = response.into_body();
+            while let Some(Ok(frame)) = body.frame().await {
+                let Ok(data) = frame.into_data() else {
+                    continue;
+                };
+                for line in String::from_utf8_lossy(&data).lines() {
+                    if let Some(payload) = line.strip_prefix("data: ") {
+                        send_text(ws, payload).await?;
+                    }
+                }
+            }
+            CLOSE_NORMAL
+        }
+        Err(e) => {
+            warn!(request_id = %request_id, error = %e, "WebSocket request failed");
+            let body = error_to_response(&e, request_id)
+                .into_body()
+                .collect()
+                .await
+                .map(|collected| collected.to_bytes())
+                .unwrap_or_default();
+            send_text(ws, &String::from_utf8_lossy(&body)).await?;
+            CLOSE_INTERNAL_ERROR
+        }
+    };
 
-    validate_request(&messages_request)?;
+    close(ws, close_code).await
+}
 
+/// Run `execute` on `messages_request`, then once more on the fallback model
+/// when `[accounts] fallback` is set and the primary model's quota is
+/// exhausted or its upstream call timed out. `execute` produces the
+/// endpoint's own response format and is told whether it runs the fallback
+/// attempt; `api` names the endpoint in the log.
+async fn run_with_fallback<'a, F, Fut>(
+    messages_request: &'a MessagesRequest,
+    config: &crate::config::Config,
+    request_id: &str,
+    api: &str,
+    execute: F,
+) -> Result<Response<ResponseBody>, Error>
+where
+    F: Fn(Cow<'a, MessagesRequest>, bool) -> Fut,
+    Fut: Future<Output = Result<Response<ResponseBody>, Error>>,
+{
     // Try the primary model first
-    let result =
-        execute_messages_request(&messages_request, &state, request_id, false, bypass_cache).await;
+    let result = execute(Cow::Borrowed(messages_request), false).await;
 
-    // Check if fallback is enabled and we got a quota exhaustion error
+    // Check if fallback is enabled and we got a quota exhaustion or upstream timeout
     if config.accounts.fallback
-        && let Err(Error::Api(ApiError::QuotaExhausted { .. })) = &result
+        && let Err(error) = &result
+        && triggers_fallback(error)
         && let Some(fallback_model) = get_fallback_model(&messages_request.model)
+        && !is_model_disabled(fallback_model, &config.models.disabled)
     {
         warn!(
             primary = %messages_request.model,
             fallback = %fallback_model,
+            api = api,
             request_id = %request_id,
-            "Quota exhausted, falling back to alternate model"
+            error = %error,
+            "Primary model failed, falling back to alternate model"
         );
 
         let mut fallback_request = messages_request.clone();
         fallback_request.model = fallback_model.to_string();
 
-        return execute_messages_request(&fallback_request, &state, request_id, true, bypass_cache)
-            .await;
+        tokio::time::sleep(fallback_backoff(0, config.accounts.fallback_backoff_ms)).await;
+
+        let result = execute(Cow::Owned(fallback_request), true).await;
+        return with_fallback_header(result, &messages_request.model);
     }
 
     result
 }
 
+/// Run a Messages request with model fallback.
+async fn execute_with_fallback(
+    messages_request: &MessagesRequest,
+    state: &Arc<ServerState>,
+    config: &crate::config::Config,
+    request_id: &str,
+    bypass_cache: bool,
+    cache_ttl: Option<Duration>,
+) -> Result<Response<ResponseBody>, Error> {
+    run_with_fallback(
+        messages_request,
+        config,
+        request_id,
+        "Messages",
+        |request, is_fallback| async move {
+            execute_messages_request(
+                &request,
+                state,
+                request_id,
+                is_fallback,
+                bypass_cache,
+                cache_ttl,
+            )
+            .await
+        },
+    )
+    .await
+}
+
 /// Execute a messages request with the given model.
 /// Set `is_fallback` to true to prevent recursive fallback attempts.
+/// `cache_ttl` overrides the configured TTL for a response cached by this request.
 async fn execute_messages_request(
     messages_request: &MessagesRequest,
     state: &Arc<ServerState>,
     request_id: &str,
     is_fallback: bool,
     bypass_cache: bool,
+    cache_ttl: Option<Duration>,
 ) -> Result<Response<ResponseBody>, Error> {
     let is_streaming = messages_request.stream;
     let model = &messages_request.model;
@@ -642,7 +2008,10 @@ async fn execute_messages_request(
 
     log_if_enabled(request_id, "Anthropic request", &messages_request);
 
-    let cache_key = if !is_streaming && !bypass_cache {
+    let cache_slot = if !is_streaming
+        && !bypass_cache
+        && !model_in_list(model, &get_config().cache.no_cache_models)
+    {
         let messages_json = serde_json::to_string(&messages_request.messages).unwrap_or_default();
         let system_json = messages_request
             .system
@@ -659,7 +2028,7 @@ async fn execute_messages_request(
             system_json.as_deref(),
             tools_json.as_deref(),
             messages_request.temperature,
-            messages_request.max_tokens,
+            messages_request.max_tokens.unwrap_or_default(),
             messages_request.top_p,
             messages_request.top_k,
             messages_request
@@ -667,6 +2036,7 @@ async fn execute_messages_request(
                 .as_ref()
                 .map(|s| serde_json::to_string(s).unwrap_or_default())
                 .as_deref(),
+            messages_request.seed,
         );
 
         {
@@ -679,78 +2049,85 @@ async fn execute_messages_request(
                 );
                 return Ok(json_ok_response(cached_response, request_id, Some("HIT")));
             }
+            debug!(model = %model, request_id = %request_id, "Cache MISS");
+            let ttl = cache_ttl.unwrap_or_else(|| cache.ttl_for_model(model));
+            // Nothing to fill (cache off or zero TTL), so nothing to coalesce on
+            cache.stores(ttl).then_some((key, ttl))
         }
-        debug!(model = %model, request_id = %request_id, "Cache MISS");
-        Some(key)
     } else {
         None
     };
 
-    let (access_token, project_id, account_id, account_email) =
-        get_account_credentials(state, model).await?;
+    // Coalesce identical concurrent requests: only one goes upstream, the
+    // rest wait for it and are served from the cache it fills. Skipped when
+    // the response won't be cached (no cache slot, or the streaming-endpoint
+    // path), since waiters would then just go upstream one after another.
+    let _in_flight_guard = match &cache_slot {
+        Some((key, _)) if !use_streaming_endpoint(model) => loop {
+            match state.in_flight.claim(key) {
+                Ok(guard) => break Some(guard),
+                Err(mut done) => {
+                    debug!(
+                        model = %model,
+                        request_id = %request_id,
+                        "Waiting for identical in-flight request"
+                    );
+                    let _ = done.changed().await;
+                    if let Some(cached_response) = state.cache.lock().await.get(key) {
+                        return Ok(json_ok_response(cached_response, request_id, Some("HIT")));
+                    }
+                    // The other request failed or wasn't cached; claim the key ourselves
+                }
+            }
+        },
+        _ => None,
+    };
 
-    let cc_request = build_request(messages_request, &project_id);
-    let request_body = Bytes::from(serde_json::to_vec(&cc_request)?);
+    execute_with_account_retry(state, model, messages_request, |attempt| {
+        let cache_slot = cache_slot.clone();
+        async move {
+            // Thinking models must use streaming endpoint even for non-streaming requests
+            // (the non-streaming generateContent endpoint returns 429 for thinking models)
+            if is_streaming {
+                // Cancellable by the client's request ID or the one in our response
+                let cancellation = state
+                    .active_streams
+                    .register(&[request_id, &attempt.request_id]);
+                let options = StreamOptions {
+                    cancellation,
+                    strip_thinking: strips_thinking(messages_request),
+                };
+                handle_streaming_messages(&state.cloudcode_client, attempt, model, options).await
+            } else if use_streaming_endpoint(model) {
+                // Use streaming endpoint but return non-streaming response
+                handle_thinking_non_streaming_messages(
+                    state,
+                    attempt.body,
+                    &attempt.access_token,
+                    model,
+                    &attempt.request_id,
+                    attempt.usage_sink,
+                )
+                .await
+            } else {
+                handle_non_streaming_messages(state, attempt, model, cache_slot).await
+            }
+        }
+    })
+    .await
+}
 
-    // Thinking models must use streaming endpoint even for non-streaming requests
-    // (the non-streaming generateContent endpoint returns 429 for thinking models)
-    let is_thinking = is_thinking_model(model);
-
-    let result = if is_streaming {
-        handle_streaming_messages(
-            &state.cloudcode_client,
-            request_body.clone(),
-            &access_token,
-            model,
-            &cc_request.request_id,
-        )
-        .await
-    } else if is_thinking {
-        // Use streaming endpoint but return non-streaming response
-        handle_thinking_non_streaming_messages(
-            &state.cloudcode_client,
-            request_body.clone(),
-            &access_token,
-            model,
-            &cc_request.request_id,
-        )
-        .await
-    } else {
-        handle_non_streaming_messages(
-            &state.cloudcode_client,
-            request_body.clone(),
-            &access_token,
-            model,
-            &cc_request.request_id,
-            cache_key.clone(),
-            state,
-        )
-        .await
-    };
-
-    track_request_outcome(
-        state,
-        &account_id,
-        &account_email,
-        model,
-        &cc_request.request_id,
-        &result,
-    )
-    .await;
-
-    result
-}
-
-async fn handle_chat_completions(
-    req: Request<hyper::body::Incoming>,
-    state: Arc<ServerState>,
-    request_id: &str,
-) -> Result<Response<ResponseBody>, Error> {
-    let content_type = req
-        .headers()
-        .get("content-type")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
+async fn handle_chat_completions(
+    req: Request<hyper::body::Incoming>,
+    state: Arc<ServerState>,
+    request_id: &str,
+    client_ip: IpAddr,
+) -> Result<Response<ResponseBody>, Error> {
+    let content_type = req
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
 
     if !content_type.contains("application/json") {
         return Ok(openai_error_response(
@@ -760,35 +2137,48 @@ async fn handle_chat_completions(
         ));
     }
 
-    let body_bytes = read_body_limited(req.into_body(), MAX_REQUEST_SIZE).await?;
-
-    let chat_request: ChatCompletionRequest = match serde_json::from_slice(&body_bytes) {
+    let chat_request: ChatCompletionRequest = match read_json_body(req).await {
         Ok(r) => r,
-        Err(e) => {
+        Err(Error::InvalidBody(e)) => {
             return Ok(openai_error_response(
                 StatusCode::BAD_REQUEST,
                 &format!("Invalid JSON: {}", e),
                 "invalid_request_error",
             ));
         }
+        Err(e) => return Err(e),
     };
 
-    // Check for unsupported n > 1
-    if chat_request.n.unwrap_or(1) > 1 {
-        return Ok(openai_error_response(
-            StatusCode::BAD_REQUEST,
-            "n > 1 is not supported",
-            "invalid_request_error",
-        ));
+    let config = get_config();
+
+    // n > 1 is only served by fanning out when explicitly enabled
+    let n = chat_request.n.unwrap_or(1);
+    if n > 1 {
+        let message = if !config.server.emulate_n {
+            Some("n > 1 is not supported (set [server] emulate_n = true to enable)".to_string())
+        } else if chat_request.stream {
+            Some("n > 1 is not supported with stream=true".to_string())
+        } else if n > MAX_EMULATED_N {
+            Some(format!("n must be at most {}", MAX_EMULATED_N))
+        } else {
+            None
+        };
+        if let Some(message) = message {
+            return Ok(openai_error_response(
+                StatusCode::BAD_REQUEST,
+                &message,
+                "invalid_request_error",
+            ));
+        }
     }
 
     let mut messages_request = crate::format::openai_to_anthropic(&chat_request);
 
     let original_model = messages_request.model.clone();
-    let config = get_config();
     messages_request.model = resolve_with_mappings(
         &messages_request.model,
         &config.mappings.rules,
+        &config.mappings.aliases,
         &config.mappings.background_task_model,
     );
 
@@ -798,31 +2188,42 @@ async fn handle_chat_completions(
         request_id = %request_id,
         "Model resolution (OpenAI)"
     );
+    handle_unknown_model(&mut messages_request.model, &config, request_id)?;
 
-    validate_request(&messages_request)?;
-
-    // Try the primary model first
-    let result = execute_openai_request(&messages_request, &state, request_id, false).await;
-
-    // Check if fallback is enabled and we got a quota exhaustion error
-    if config.accounts.fallback
-        && let Err(Error::Api(ApiError::QuotaExhausted { .. })) = &result
-        && let Some(fallback_model) = get_fallback_model(&messages_request.model)
-    {
-        warn!(
-            primary = %messages_request.model,
-            fallback = %fallback_model,
-            request_id = %request_id,
-            "Quota exhausted, falling back to alternate model (OpenAI API)"
-        );
+    apply_request_defaults(
+        &mut messages_request,
+        &config.defaults,
+        Some(crate::format::openai_convert::DEFAULT_MAX_TOKENS),
+    );
+    apply_server_policies(&mut messages_request, &config.server, request_id);
 
-        let mut fallback_request = messages_request.clone();
-        fallback_request.model = fallback_model.to_string();
+    validate_request(&messages_request, &config)?;
+    // Every fanned-out choice is an upstream request of its own
+    check_client_rate_limit(&state, &messages_request, client_ip, request_id, n)?;
 
-        return execute_openai_request(&fallback_request, &state, request_id, true).await;
+    if n > 1 {
+        return execute_openai_fan_out(&messages_request, &state, request_id, n).await;
     }
 
-    result
+    execute_openai_with_fallback(&messages_request, &state, request_id).await
+}
+
+/// Run an OpenAI-format request with model fallback.
+async fn execute_openai_with_fallback(
+    messages_request: &MessagesRequest,
+    state: &Arc<ServerState>,
+    request_id: &str,
+) -> Result<Response<ResponseBody>, Error> {
+    run_with_fallback(
+        messages_request,
+        &get_config(),
+        request_id,
+        "OpenAI",
+        |request, is_fallback| async move {
+            execute_openai_request(&request, state, request_id, is_fallback).await
+        },
+    )
+    .await
 }
 
 /// Execute an OpenAI-format request with the given model.
@@ -849,66 +2250,155 @@ async fn execute_openai_request(
 
     log_if_enabled(request_id, "OpenAI request", &messages_request);
 
-    let (access_token, project_id, account_id, account_email) =
-        get_account_credentials(state, model).await?;
-
-    let cc_request = build_request(messages_request, &project_id);
-    let request_body = Bytes::from(serde_json::to_vec(&cc_request)?);
+    execute_with_account_retry(state, model, messages_request, |attempt| async move {
+        if is_streaming {
+            handle_openai_streaming(
+                &state.cloudcode_client,
+                attempt.body,
+                &attempt.access_token,
+                model,
+                &attempt.request_id,
+                attempt.usage_sink,
+            )
+            .await
+        } else if use_streaming_endpoint(model) {
+            handle_openai_thinking_non_streaming(
+                state,
+                attempt.body,
+                &attempt.access_token,
+                model,
+                &attempt.request_id,
+                attempt.usage_sink,
+            )
+            .await
+        } else {
+            handle_openai_non_streaming(
+                state,
+                attempt.body,
+                &attempt.access_token,
+                model,
+                &attempt.request_id,
+                attempt.usage_sink,
+            )
+            .await
+        }
+    })
+    .await
+}
 
-    let is_thinking = is_thinking_model(model);
+/// Emulate OpenAI `n > 1` by running the request `n` times and merging the
+/// choices. Sub-requests run with bounded concurrency and each goes through
+/// normal account selection and model fallback, so they can spread across
+/// accounts. The merged response names the requested model in
+/// `X-AGCP-Fallback-From` if any choice came from the fallback model.
+async fn execute_openai_fan_out(
+    messages_request: &MessagesRequest,
+    state: &Arc<ServerState>,
+    request_id: &str,
+    n: u32,
+) -> Result<Response<ResponseBody>, Error> {
+    let mut sub_request = messages_request.clone();
+    sub_request.candidate_count = None;
+    let sub_request = Arc::new(sub_request);
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(EMULATED_N_CONCURRENCY));
+
+    debug!(n = n, request_id = %request_id, "Emulating n > 1 with parallel requests");
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for index in 0..n {
+        let sub_request = Arc::clone(&sub_request);
+        let state = Arc::clone(state);
+        let semaphore = Arc::clone(&semaphore);
+        let sub_request_id = format!("{}-{}", request_id, index);
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let result = execute_openai_with_fallback(&sub_request, &state, &sub_request_id).await;
+            (index, result)
+        });
+    }
 
-    let result = if is_streaming {
-        handle_openai_streaming(
-            &state.cloudcode_client,
-            request_body.clone(),
-            &access_token,
-            model,
-            &cc_request.request_id,
-        )
-        .await
-    } else if is_thinking {
-        handle_openai_thinking_non_streaming(
-            &state.cloudcode_client,
-            request_body.clone(),
-            &access_token,
-            model,
-            &cc_request.request_id,
-        )
-        .await
-    } else {
-        handle_openai_non_streaming(
-            &state.cloudcode_client,
-            request_body.clone(),
-            &access_token,
-            model,
-            &cc_request.request_id,
-        )
-        .await
-    };
+    // Dropping the JoinSet on an early error aborts the remaining sub-requests
+    let mut responses = Vec::with_capacity(n as usize);
+    let mut fallback_from = None;
+    while let Some(joined) = tasks.join_next().await {
+        let (index, result) = joined.map_err(|e| Error::Http(e.to_string()))?;
+        let response = result?;
+        if let Some(value) = response.headers().get("X-AGCP-Fallback-From") {
+            fallback_from = Some(value.clone());
+        }
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| Error::Http(e.to_string()))?
+            .to_bytes();
+        let response: ChatCompletionResponse = serde_json::from_slice(&body)?;
+        responses.push((index, response));
+    }
+    responses.sort_by_key(|(index, _)| *index);
+
+    let merged = merge_chat_completions(responses.into_iter().map(|(_, r)| r), request_id)
+        .ok_or_else(|| Error::Http("n > 1 emulation produced no responses".to_string()))?;
+    let body = serde_json::to_vec(&merged)?;
+    let mut response = json_ok_response(body, request_id, None);
+    if let Some(value) = fallback_from {
+        response.headers_mut().insert("X-AGCP-Fallback-From", value);
+    }
+    Ok(response)
+}
 
-    track_request_outcome(
-        state,
-        &account_id,
-        &account_email,
-        model,
-        &cc_request.request_id,
-        &result,
-    )
-    .await;
+/// Merge single-choice completions into one response with sequential choice
+/// indices. Usage is summed, since every choice consumed a full upstream request.
+fn merge_chat_completions(
+    responses: impl IntoIterator<Item = ChatCompletionResponse>,
+    request_id: &str,
+) -> Option<ChatCompletionResponse> {
+    let mut merged: Option<ChatCompletionResponse> = None;
+    for response in responses {
+        let Some(target) = merged.as_mut() else {
+            let mut first = response;
+            first.id = format!("chatcmpl-{}", request_id);
+            for (i, choice) in first.choices.iter_mut().enumerate() {
+                choice.index = i as u32;
+            }
+            merged = Some(first);
+            continue;
+        };
 
-    result
+        for mut choice in response.choices {
+            choice.index = target.choices.len() as u32;
+            target.choices.push(choice);
+        }
+        if let Some(usage) = response.usage {
+            match target.usage.as_mut() {
+                Some(total) => {
+                    total.prompt_tokens += usage.prompt_tokens;
+                    total.completion_tokens += usage.completion_tokens;
+                    total.total_tokens += usage.total_tokens;
+                }
+                None => target.usage = Some(usage),
+            }
+        }
+    }
+    merged
 }
 
 async fn handle_openai_non_streaming(
-    client: &CloudCodeClient,
+    state: &Arc<ServerState>,
     body: Bytes,
     access_token: &str,
     model: &str,
     request_id: &str,
+    usage_sink: UsageSink,
 ) -> Result<Response<ResponseBody>, Error> {
-    let response = client.send_request(body, access_token, model).await?;
-    let anthropic_response = parse_response(&response, model, request_id);
-    record_usage(model, &anthropic_response.usage);
+    let response = state
+        .cloudcode_client
+        .send_request(body.clone(), access_token, model)
+        .await?;
+    let anthropic_response =
+        debug_span!("convert_response").in_scope(|| parse_response(&response, model, request_id));
+    usage_sink.record(model, &anthropic_response.usage);
+    spawn_mirror_comparison(state, body, model, request_id, &anthropic_response);
 
     let openai_response =
         crate::format::anthropic_to_openai(&anthropic_response, model, request_id);
@@ -920,13 +2410,15 @@ async fn handle_openai_non_streaming(
 }
 
 async fn handle_openai_thinking_non_streaming(
-    client: &CloudCodeClient,
+    state: &Arc<ServerState>,
     body: Bytes,
     access_token: &str,
     model: &str,
     request_id: &str,
+    usage_sink: UsageSink,
 ) -> Result<Response<ResponseBody>, Error> {
-    let (events, _body_bytes) = collect_sse_events(client, body, access_token, model).await?;
+    let (events, _body_bytes) =
+        collect_sse_events(&state.cloudcode_client, body.clone(), access_token, model).await?;
 
     check_stream_errors(
         &events,
@@ -935,8 +2427,9 @@ async fn handle_openai_thinking_non_streaming(
         " (OpenAI thinking non-streaming)",
     )?;
 
-    let anthropic_response = crate::format::build_response_from_events(&events, model, request_id);
-    record_usage(model, &anthropic_response.usage);
+    let anthropic_response = assemble_streamed_response(&events, model, request_id)?;
+    usage_sink.record(model, &anthropic_response.usage);
+    spawn_mirror_comparison(state, body, model, request_id, &anthropic_response);
     let openai_response =
         crate::format::anthropic_to_openai(&anthropic_response, model, request_id);
 
@@ -956,6 +2449,7 @@ async fn handle_openai_streaming(
     access_token: &str,
     model: &str,
     request_id: &str,
+    usage_sink: UsageSink,
 ) -> Result<Response<ResponseBody>, Error> {
     let upstream = client
         .send_streaming_request(body, access_token, model)
@@ -969,7 +2463,7 @@ async fn handle_openai_streaming(
 
     tokio::spawn(async move {
         use crate::format::openai::{
-            ChatCompletionChunk, ChatUsage, ChunkChoice, ChunkDelta, ChunkFunction, ChunkToolCall,
+            ChatCompletionChunk, ChunkChoice, ChunkDelta, ChunkFunction, ChunkToolCall,
         };
         use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -980,7 +2474,8 @@ async fn handle_openai_streaming(
         let chunk_id = format!("chatcmpl-{}", request_id);
 
         let mut parser = SseParser::new(&model);
-        let mut input_tokens = 0u32;
+        // Input and cache token counts from message_start
+        let mut prompt_usage = crate::format::anthropic::Usage::default();
         let mut output_tokens = 0u32;
         let mut sent_role = false;
         let mut tool_call_index = 0u32;
@@ -996,13 +2491,13 @@ async fn handle_openai_streaming(
 
         let process_event = |event: &StreamEvent,
                              tx: &mpsc::Sender<Bytes>,
-                             input_tokens: &mut u32,
+                             prompt_usage: &mut crate::format::anthropic::Usage,
                              output_tokens: &mut u32,
                              sent_role: &mut bool,
                              tool_call_index: &mut u32| {
             match event {
                 StreamEvent::MessageStart { message } => {
-                    *input_tokens = message.usage.input_tokens;
+                    *prompt_usage = message.usage.clone();
                     let chunk = ChatCompletionChunk {
                         id: chunk_id.clone(),
                         object: "chat.completion.chunk".to_string(),
@@ -1165,11 +2660,12 @@ async fn handle_openai_streaming(
                             finish_reason,
                             logprobs: None,
                         }],
-                        usage: Some(ChatUsage {
-                            prompt_tokens: *input_tokens,
-                            completion_tokens: *output_tokens,
-                            total_tokens: *input_tokens + *output_tokens,
-                        }),
+                        usage: Some(crate::format::openai_convert::chat_usage(
+                            &crate::format::anthropic::Usage {
+                                output_tokens: *output_tokens,
+                                ..prompt_usage.clone()
+                            },
+                        )),
                         system_fingerprint: None,
                     };
                     send_chunk(tx, &chunk);
@@ -1186,12 +2682,11 @@ async fn handle_openai_streaming(
             match tokio::time::timeout(frame_timeout, incoming.frame()).await {
                 Ok(Some(Ok(frame))) => {
                     if let Ok(data) = frame.into_data() {
-                        let chunk_str = String::from_utf8_lossy(&data);
-                        for event in parser.feed(&chunk_str) {
+                        for event in parser.feed_bytes(&data) {
                             process_event(
                                 &event,
                                 &tx,
-                                &mut input_tokens,
+                                &mut prompt_usage,
                                 &mut output_tokens,
                                 &mut sent_role,
                                 &mut tool_call_index,
@@ -1215,14 +2710,20 @@ async fn handle_openai_streaming(
             process_event(
                 &event,
                 &tx,
-                &mut input_tokens,
+                &mut prompt_usage,
                 &mut output_tokens,
                 &mut sent_role,
                 &mut tool_call_index,
             );
         }
 
-        get_stats().record_token_usage(&model, input_tokens, output_tokens, 0);
+        usage_sink.record(
+            &model,
+            &crate::format::anthropic::Usage {
+                output_tokens,
+                ..prompt_usage
+            },
+        );
         let _ = tx.send(Bytes::from("data: [DONE]\n\n")).await;
     });
 
@@ -1230,13 +2731,14 @@ async fn handle_openai_streaming(
 }
 
 // ============================================================================
-// OpenAI Responses API handlers (used by Codex CLI)
+// OpenAI legacy Completions API handlers
 // ============================================================================
 
-async fn handle_responses(
+async fn handle_completions(
     req: Request<hyper::body::Incoming>,
     state: Arc<ServerState>,
     request_id: &str,
+    client_ip: IpAddr,
 ) -> Result<Response<ResponseBody>, Error> {
     let content_type = req
         .headers()
@@ -1245,45 +2747,48 @@ async fn handle_responses(
         .unwrap_or("");
 
     if !content_type.contains("application/json") {
-        return Ok(responses_error_response(
+        return Ok(openai_error_response(
             StatusCode::BAD_REQUEST,
             "Content-Type must be application/json",
             "invalid_request_error",
         ));
     }
 
-    let body_bytes = read_body_limited(req.into_body(), MAX_REQUEST_SIZE).await?;
-
-    let responses_request: crate::format::ResponsesRequest =
-        match serde_json::from_slice(&body_bytes) {
-            Ok(r) => r,
-            Err(e) => {
-                return Ok(responses_error_response(
-                    StatusCode::BAD_REQUEST,
-                    &format!("Invalid JSON: {}", e),
-                    "invalid_request_error",
-                ));
-            }
-        };
-
-    // Log the tools for debugging
-    if let Some(tools) = &responses_request.tools {
-        for tool in tools {
-            trace!(
-                tool_type = %tool.tool_type,
-                tool_name = ?tool.name,
-                "Responses API: tool in request"
-            );
+    let completion_request: CompletionRequest = match read_json_body(req).await {
+        Ok(r) => r,
+        Err(Error::InvalidBody(e)) => {
+            return Ok(openai_error_response(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid JSON: {}", e),
+                "invalid_request_error",
+            ));
         }
+        Err(e) => return Err(e),
+    };
+
+    if completion_request.n.unwrap_or(1) > 1 {
+        return Ok(openai_error_response(
+            StatusCode::BAD_REQUEST,
+            "n > 1 is not supported for /v1/completions",
+            "invalid_request_error",
+        ));
     }
 
-    let mut messages_request = crate::format::responses_to_anthropic(&responses_request);
+    let Some(mut messages_request) = crate::format::completion_to_anthropic(&completion_request)
+    else {
+        return Ok(openai_error_response(
+            StatusCode::BAD_REQUEST,
+            "prompt must be a string or an array with a single string",
+            "invalid_request_error",
+        ));
+    };
 
-    let original_model = messages_request.model.clone();
     let config = get_config();
+    let original_model = messages_request.model.clone();
     messages_request.model = resolve_with_mappings(
         &messages_request.model,
         &config.mappings.rules,
+        &config.mappings.aliases,
         &config.mappings.background_task_model,
     );
 
@@ -1291,131 +2796,454 @@ async fn handle_responses(
         original_model = %original_model,
         resolved_model = %messages_request.model,
         request_id = %request_id,
-        "Model resolution (Responses)"
-    );
-
-    if let Err(e) = validate_request(&messages_request) {
-        return Ok(responses_error_response(
-            StatusCode::BAD_REQUEST,
-            &e.to_string(),
-            "invalid_request_error",
-        ));
-    }
-
-    get_stats().record_request(&messages_request.model, "/v1/responses");
-
-    let is_streaming = messages_request.stream;
-    let model = &messages_request.model;
-
-    debug!(
-        model = %model,
-        streaming = is_streaming,
-        request_id = %request_id,
-        "Processing Responses API request"
+        "Model resolution (Completions)"
     );
+    handle_unknown_model(&mut messages_request.model, &config, request_id)?;
 
-    // Log streaming status explicitly
-    debug!(
-        request_id = %request_id,
-        streaming = is_streaming,
-        model = %model,
-        "Responses API: handling request"
+    apply_request_defaults(
+        &mut messages_request,
+        &config.defaults,
+        Some(crate::format::openai_convert::DEFAULT_MAX_TOKENS),
     );
+    apply_server_policies(&mut messages_request, &config.server, request_id);
 
-    log_if_enabled(request_id, "Responses API request", &messages_request);
+    validate_request(&messages_request, &config)?;
+    check_client_rate_limit(&state, &messages_request, client_ip, request_id, 1)?;
 
-    let (access_token, project_id, account_id, account_email) =
-        get_account_credentials(&state, model).await?;
-
-    let cc_request = build_request(&messages_request, &project_id);
-    let request_body = Bytes::from(serde_json::to_vec(&cc_request)?);
-
-    // Thinking models must use streaming endpoint even for non-streaming requests
-    let is_thinking = is_thinking_model(model);
-
-    let result = if is_streaming {
-        handle_responses_streaming(
-            &state.cloudcode_client,
-            request_body.clone(),
-            &access_token,
-            model,
-            request_id,
-        )
-        .await
-    } else if is_thinking {
-        // Use streaming endpoint but return non-streaming response
-        handle_responses_thinking_non_streaming(
-            &state.cloudcode_client,
-            request_body.clone(),
-            &access_token,
-            model,
-            request_id,
-        )
-        .await
-    } else {
-        handle_responses_non_streaming(
-            &state.cloudcode_client,
-            request_body.clone(),
-            &access_token,
-            model,
-            request_id,
-        )
-        .await
-    };
+    let echo_prompt = completion_request
+        .echo
+        .then(|| completion_request.prompt.text())
+        .flatten();
 
-    track_request_outcome(
-        &state,
-        &account_id,
-        &account_email,
-        model,
+    let state = &state;
+    run_with_fallback(
+        &messages_request,
+        &config,
         request_id,
-        &result,
+        "Completions",
+        |request, _| async move {
+            execute_completions_request(&request, state, request_id, echo_prompt).await
+        },
     )
-    .await;
-
-    result
+    .await
 }
 
-async fn handle_responses_non_streaming(
-    client: &CloudCodeClient,
-    body: Bytes,
-    access_token: &str,
-    model: &str,
+/// Execute a legacy Completions request through the Messages pipeline.
+async fn execute_completions_request(
+    messages_request: &MessagesRequest,
+    state: &Arc<ServerState>,
     request_id: &str,
+    echo_prompt: Option<&str>,
 ) -> Result<Response<ResponseBody>, Error> {
-    let response = client.send_request(body, access_token, model).await?;
-    let anthropic_response = parse_response(&response, model, request_id);
-    record_usage(model, &anthropic_response.usage);
+    let model = &messages_request.model;
 
-    let responses_response =
-        crate::format::anthropic_to_responses(&anthropic_response, model, request_id);
+    get_stats().record_request(model, "/v1/completions");
 
-    log_if_enabled(request_id, "Responses API response", &responses_response);
+    debug!(
+        model = %model,
+        streaming = messages_request.stream,
+        max_tokens = messages_request.max_tokens,
+        request_id = %request_id,
+        "Processing legacy completions request"
+    );
 
-    let body = serde_json::to_vec(&responses_response)?;
-    Ok(json_ok_response(body, request_id, None))
+    log_if_enabled(request_id, "Completions request", &messages_request);
+
+    execute_with_account_retry(state, model, messages_request, |attempt| async move {
+        let client = &state.cloudcode_client;
+        let UpstreamAttempt {
+            access_token,
+            request_id: cc_request_id,
+            body: request_body,
+            usage_sink,
+        } = attempt;
+
+        if messages_request.stream {
+            handle_completions_streaming(
+                client,
+                request_body,
+                &access_token,
+                model,
+                &cc_request_id,
+                echo_prompt,
+                usage_sink,
+            )
+            .await
+        } else {
+            let anthropic_response = if use_streaming_endpoint(model) {
+                collect_sse_events(client, request_body.clone(), &access_token, model)
+                    .await
+                    .and_then(|(events, _)| {
+                        check_stream_errors(
+                            &events,
+                            model,
+                            &cc_request_id,
+                            " (Completions non-streaming)",
+                        )?;
+                        assemble_streamed_response(&events, model, &cc_request_id)
+                    })
+            } else {
+                client
+                    .send_request(request_body.clone(), &access_token, model)
+                    .await
+                    .map(|response| parse_response(&response, model, &cc_request_id))
+            };
+
+            anthropic_response.and_then(|anthropic_response| {
+                usage_sink.record(model, &anthropic_response.usage);
+                spawn_mirror_comparison(
+                    state,
+                    request_body,
+                    model,
+                    &cc_request_id,
+                    &anthropic_response,
+                );
+                let completion = crate::format::anthropic_to_completion(
+                    &anthropic_response,
+                    model,
+                    &cc_request_id,
+                    echo_prompt,
+                );
+                log_if_enabled(request_id, "Completions response", &completion);
+                Ok(json_ok_response(
+                    serde_json::to_vec(&completion)?,
+                    &cc_request_id,
+                    None,
+                ))
+            })
+        }
+    })
+    .await
 }
 
-// Thinking models must use streaming endpoint but return non-streaming response
-async fn handle_responses_thinking_non_streaming(
+/// Stream text deltas as `text_completion.chunk` events.
+async fn handle_completions_streaming(
     client: &CloudCodeClient,
     body: Bytes,
     access_token: &str,
     model: &str,
     request_id: &str,
+    echo_prompt: Option<&str>,
+    usage_sink: UsageSink,
 ) -> Result<Response<ResponseBody>, Error> {
-    let (all_events, _body_bytes) = collect_sse_events(client, body, access_token, model).await?;
+    let upstream = client
+        .send_streaming_request(body, access_token, model)
+        .await?;
 
-    check_stream_errors(
+    let (tx, body) = streaming_body();
+    let response = sse_streaming_response(body, request_id);
+
+    let model = model.to_string();
+    let request_id = request_id.to_string();
+    let echo_prompt = echo_prompt.map(str::to_string);
+
+    tokio::spawn(async move {
+        let created = crate::format::completions::now_secs();
+        let mut parser = SseParser::new(&model);
+        // Input and cache token counts from message_start
+        let mut prompt_usage = crate::format::anthropic::Usage::default();
+        let mut output_tokens = 0u32;
+
+        let send = |text: String, finish_reason: Option<String>| {
+            let chunk = crate::format::completions::completion_chunk(
+                &request_id,
+                &model,
+                created,
+                text,
+                finish_reason,
+            );
+            let data = format!(
+                "data: {}\n\n",
+                serde_json::to_string(&chunk).unwrap_or_default()
+            );
+            tx.try_send(Bytes::from(data)).is_ok()
+        };
+
+        if let Some(prompt) = echo_prompt {
+            send(prompt, None);
+        }
+
+        let mut process_event = |event: StreamEvent| match event {
+            StreamEvent::MessageStart { message } => {
+                prompt_usage = message.usage;
+            }
+            StreamEvent::ContentBlockDelta {
+                delta: crate::format::ContentDelta::Text { text },
+                ..
+            } => {
+                send(text, None);
+            }
+            StreamEvent::MessageDelta { delta, usage } => {
+                output_tokens = usage.output_tokens;
+                let finish_reason = delta.stop_reason.map(|r| r.to_openai_str().to_string());
+                send(String::new(), finish_reason);
+            }
+            _ => {}
+        };
+
+        let mut incoming = upstream.into_body();
+        loop {
+            use http_body_util::BodyExt;
+            let frame_timeout = Duration::from_secs(STREAM_FRAME_TIMEOUT_SECS);
+            match tokio::time::timeout(frame_timeout, incoming.frame()).await {
+                Ok(Some(Ok(frame))) => {
+                    if let Ok(data) = frame.into_data() {
+                        parser
+                            .feed_bytes(&data)
+                            .into_iter()
+                            .for_each(&mut process_event);
+                    }
+                }
+                Ok(Some(Err(e))) => {
+                    warn!(error = %e, "Error reading upstream for Completions streaming");
+                    break;
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    warn!("Upstream frame timeout in Completions streaming");
+                    break;
+                }
+            }
+        }
+        parser.finish().into_iter().for_each(&mut process_event);
+
+        usage_sink.record(
+            &model,
+            &crate::format::anthropic::Usage {
+                output_tokens,
+                ..prompt_usage
+            },
+        );
+        let _ = tx.send(Bytes::from("data: [DONE]\n\n")).await;
+    });
+
+    Ok(response)
+}
+
+// ============================================================================
+// OpenAI Responses API handlers (used by Codex CLI)
+// ============================================================================
+
+async fn handle_responses(
+    req: Request<hyper::body::Incoming>,
+    state: Arc<ServerState>,
+    request_id: &str,
+    client_ip: IpAddr,
+) -> Result<Response<ResponseBody>, Error> {
+    let content_type = req
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    if !content_type.contains("application/json") {
+        return Ok(responses_error_response(
+            StatusCode::BAD_REQUEST,
+            "Content-Type must be application/json",
+            "invalid_request_error",
+        ));
+    }
+
+    let responses_request: crate::format::ResponsesRequest = match read_json_body(req).await {
+        Ok(r) => r,
+        Err(Error::InvalidBody(e)) => {
+            return Ok(responses_error_response(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid JSON: {}", e),
+                "invalid_request_error",
+            ));
+        }
+        Err(e) => return Err(e),
+    };
+
+    // Log the tools for debugging
+    if let Some(tools) = &responses_request.tools {
+        for tool in tools {
+            trace!(
+                tool_type = %tool.tool_type,
+                tool_name = ?tool.name,
+                "Responses API: tool in request"
+            );
+        }
+    }
+
+    let mut messages_request = crate::format::responses_to_anthropic(&responses_request);
+
+    let original_model = messages_request.model.clone();
+    let config = get_config();
+    messages_request.model = resolve_with_mappings(
+        &messages_request.model,
+        &config.mappings.rules,
+        &config.mappings.aliases,
+        &config.mappings.background_task_model,
+    );
+
+    debug!(
+        original_model = %original_model,
+        resolved_model = %messages_request.model,
+        request_id = %request_id,
+        "Model resolution (Responses)"
+    );
+    if let Err(e) = handle_unknown_model(&mut messages_request.model, &config, request_id) {
+        return Ok(responses_error_response(
+            StatusCode::BAD_REQUEST,
+            &e.to_string(),
+            "invalid_request_error",
+        ));
+    }
+
+    apply_request_defaults(
+        &mut messages_request,
+        &config.defaults,
+        Some(crate::format::responses_convert::DEFAULT_MAX_OUTPUT_TOKENS),
+    );
+    apply_server_policies(&mut messages_request, &config.server, request_id);
+
+    if let Err(e) = validate_request(&messages_request, &config) {
+        return Ok(responses_error_response(
+            StatusCode::BAD_REQUEST,
+            &e.to_string(),
+            "invalid_request_error",
+        ));
+    }
+    if let Err(e) = check_client_rate_limit(&state, &messages_request, client_ip, request_id, 1) {
+        let mut resp = responses_error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            &e.to_string(),
+            "rate_limit_error",
+        );
+        if let Some(secs) = retry_after_secs(&state, &e).await {
+            resp.headers_mut()
+                .insert(hyper::header::RETRY_AFTER, secs.into());
+        }
+        return Ok(resp);
+    }
+
+    let state = &state;
+    run_with_fallback(
+        &messages_request,
+        &config,
+        request_id,
+        "Responses",
+        |request, _| async move { execute_responses_request(&request, state, request_id).await },
+    )
+    .await
+}
+
+/// Execute a Responses API request with the given model.
+async fn execute_responses_request(
+    messages_request: &MessagesRequest,
+    state: &Arc<ServerState>,
+    request_id: &str,
+) -> Result<Response<ResponseBody>, Error> {
+    get_stats().record_request(&messages_request.model, "/v1/responses");
+
+    let is_streaming = messages_request.stream;
+    let model = &messages_request.model;
+
+    debug!(
+        model = %model,
+        streaming = is_streaming,
+        request_id = %request_id,
+        "Processing Responses API request"
+    );
+
+    // Log streaming status explicitly
+    debug!(
+        request_id = %request_id,
+        streaming = is_streaming,
+        model = %model,
+        "Responses API: handling request"
+    );
+
+    log_if_enabled(request_id, "Responses API request", messages_request);
+
+    execute_with_account_retry(state, model, messages_request, |attempt| async move {
+        // Thinking models must use streaming endpoint even for non-streaming requests
+        if is_streaming {
+            handle_responses_streaming(
+                &state.cloudcode_client,
+                attempt.body,
+                &attempt.access_token,
+                model,
+                request_id,
+                attempt.usage_sink,
+            )
+            .await
+        } else if use_streaming_endpoint(model) {
+            // Use streaming endpoint but return non-streaming response
+            handle_responses_thinking_non_streaming(
+                state,
+                attempt.body,
+                &attempt.access_token,
+                model,
+                request_id,
+                attempt.usage_sink,
+            )
+            .await
+        } else {
+            handle_responses_non_streaming(
+                state,
+                attempt.body,
+                &attempt.access_token,
+                model,
+                request_id,
+                attempt.usage_sink,
+            )
+            .await
+        }
+    })
+    .await
+}
+
+async fn handle_responses_non_streaming(
+    state: &Arc<ServerState>,
+    body: Bytes,
+    access_token: &str,
+    model: &str,
+    request_id: &str,
+    usage_sink: UsageSink,
+) -> Result<Response<ResponseBody>, Error> {
+    let response = state
+        .cloudcode_client
+        .send_request(body.clone(), access_token, model)
+        .await?;
+    let anthropic_response =
+        debug_span!("convert_response").in_scope(|| parse_response(&response, model, request_id));
+    usage_sink.record(model, &anthropic_response.usage);
+    spawn_mirror_comparison(state, body, model, request_id, &anthropic_response);
+
+    let responses_response =
+        crate::format::anthropic_to_responses(&anthropic_response, model, request_id);
+
+    log_if_enabled(request_id, "Responses API response", &responses_response);
+
+    let body = serde_json::to_vec(&responses_response)?;
+    Ok(json_ok_response(body, request_id, None))
+}
+
+// Thinking models must use streaming endpoint but return non-streaming response
+async fn handle_responses_thinking_non_streaming(
+    state: &Arc<ServerState>,
+    body: Bytes,
+    access_token: &str,
+    model: &str,
+    request_id: &str,
+    usage_sink: UsageSink,
+) -> Result<Response<ResponseBody>, Error> {
+    let (all_events, _body_bytes) =
+        collect_sse_events(&state.cloudcode_client, body.clone(), access_token, model).await?;
+
+    check_stream_errors(
         &all_events,
         model,
         request_id,
         " (Responses thinking non-streaming)",
     )?;
 
-    let anthropic_response =
-        crate::format::build_response_from_events(&all_events, model, request_id);
-    record_usage(model, &anthropic_response.usage);
+    let anthropic_response = assemble_streamed_response(&all_events, model, request_id)?;
+    usage_sink.record(model, &anthropic_response.usage);
+    spawn_mirror_comparison(state, body, model, request_id, &anthropic_response);
 
     let responses_response =
         crate::format::anthropic_to_responses(&anthropic_response, model, request_id);
@@ -1436,6 +3264,7 @@ async fn handle_responses_streaming(
     access_token: &str,
     model: &str,
     request_id: &str,
+    usage_sink: UsageSink,
 ) -> Result<Response<ResponseBody>, Error> {
     let upstream = client
         .send_streaming_request(body, access_token, model)
@@ -1449,8 +3278,8 @@ async fn handle_responses_streaming(
 
     tokio::spawn(async move {
         use crate::format::responses::{
-            InputTokensDetails, OutputTokensDetails, ResponseOutputContent, ResponseOutputItem,
-            ResponseStreamEvent, ResponseUsage, ResponsesResponse,
+            OutputTokensDetails, ResponseOutputContent, ResponseOutputItem, ResponseStreamEvent,
+            ResponseUsage, ResponsesResponse,
         };
         use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -1464,6 +3293,7 @@ async fn handle_responses_streaming(
         let mut input_tokens = 0u32;
         let mut output_tokens = 0u32;
         let mut cache_read_tokens = 0u32;
+        let mut cache_creation_tokens = 0u32;
         let mut reasoning_tokens = 0u32;
         let mut text_content = String::new();
         let mut reasoning_content = String::new();
@@ -1513,6 +3343,7 @@ async fn handle_responses_streaming(
                              input_tokens: &mut u32,
                              output_tokens: &mut u32,
                              cache_read_tokens: &mut u32,
+                             cache_creation_tokens: &mut u32,
                              reasoning_tokens: &mut u32,
                              text_content: &mut String,
                              reasoning_content: &mut String,
@@ -1527,6 +3358,7 @@ async fn handle_responses_streaming(
                 StreamEvent::MessageStart { message } => {
                     *input_tokens = message.usage.input_tokens;
                     *cache_read_tokens = message.usage.cache_read_input_tokens.unwrap_or(0);
+                    *cache_creation_tokens = message.usage.cache_creation_input_tokens.unwrap_or(0);
                     if !*sent_initial {
                         emit(
                             tx,
@@ -1667,14 +3499,14 @@ async fn handle_responses_streaming(
             match tokio::time::timeout(frame_timeout, incoming.frame()).await {
                 Ok(Some(Ok(frame))) => {
                     if let Ok(data) = frame.into_data() {
-                        let chunk_str = String::from_utf8_lossy(&data);
-                        for event in parser.feed(&chunk_str) {
+                        for event in parser.feed_bytes(&data) {
                             process_event(
                                 &event,
                                 &tx,
                                 &mut input_tokens,
                                 &mut output_tokens,
                                 &mut cache_read_tokens,
+                                &mut cache_creation_tokens,
                                 &mut reasoning_tokens,
                                 &mut text_content,
                                 &mut reasoning_content,
@@ -1708,6 +3540,7 @@ async fn handle_responses_streaming(
                 &mut input_tokens,
                 &mut output_tokens,
                 &mut cache_read_tokens,
+                &mut cache_creation_tokens,
                 &mut reasoning_tokens,
                 &mut text_content,
                 &mut reasoning_content,
@@ -1762,13 +3595,10 @@ async fn handle_responses_streaming(
             input_tokens,
             output_tokens,
             total_tokens: input_tokens + output_tokens,
-            input_tokens_details: if cache_read_tokens > 0 {
-                Some(InputTokensDetails {
-                    cached_tokens: cache_read_tokens,
-                })
-            } else {
-                None
-            },
+            input_tokens_details: crate::format::responses_convert::input_tokens_details(
+                cache_read_tokens,
+                cache_creation_tokens,
+            ),
             output_tokens_details: if reasoning_tokens > 0 {
                 Some(OutputTokensDetails { reasoning_tokens })
             } else {
@@ -1808,7 +3638,13 @@ async fn handle_responses_streaming(
             });
         }
 
-        get_stats().record_token_usage(&model, input_tokens, output_tokens, cache_read_tokens);
+        usage_sink.record_tokens(
+            &model,
+            input_tokens,
+            output_tokens,
+            cache_read_tokens,
+            cache_creation_tokens,
+        );
 
         emit(
             &tx,
@@ -1900,1118 +3736,4083 @@ fn should_bypass_cache(headers: &hyper::HeaderMap) -> bool {
     false
 }
 
-fn validate_request(req: &MessagesRequest) -> Result<(), Error> {
-    if req.max_tokens == 0 {
-        return Err(Error::Api(ApiError::InvalidRequest {
-            message: "max_tokens must be greater than 0".to_string(),
-        }));
-    }
+/// Per-request cache TTL from the `X-AGCP-Cache-TTL` header, in seconds.
+/// Zero means "don't cache"; unparseable values are ignored.
+fn cache_ttl_override(headers: &hyper::HeaderMap) -> Option<Duration> {
+    headers
+        .get("x-agcp-cache-ttl")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
 
-    if req.max_tokens > 200_000 {
-        return Err(Error::Api(ApiError::InvalidRequest {
-            message: "max_tokens cannot exceed 200000".to_string(),
-        }));
+/// Fill sampling parameters the client didn't specify from `[defaults]`.
+/// Explicit client values always win, including an explicit `max_tokens: 0`
+/// that validation then rejects. Unset defaults leave the field to upstream,
+/// except `max_tokens`, which falls back to the endpoint's `fallback_max_tokens`.
+fn apply_request_defaults(
+    req: &mut MessagesRequest,
+    defaults: &crate::config::DefaultsConfig,
+    fallback_max_tokens: Option<u32>,
+) {
+    if req.temperature.is_none() {
+        req.temperature = defaults.temperature;
     }
-
-    if req.model.is_empty() {
-        return Err(Error::Api(ApiError::InvalidRequest {
-            message: "model is required".to_string(),
-        }));
+    if req.top_p.is_none() {
+        req.top_p = defaults.top_p;
     }
-
-    if req.messages.is_empty() {
-        return Err(Error::Api(ApiError::InvalidRequest {
-            message: "messages array cannot be empty".to_string(),
-        }));
+    if req.top_k.is_none() {
+        req.top_k = defaults.top_k;
     }
+    if req.max_tokens.is_none() {
+        req.max_tokens = defaults.max_tokens.or(fallback_max_tokens);
+    }
+}
 
-    if let Some(temp) = req.temperature
-        && !(0.0..=2.0).contains(&temp)
+/// Apply operator policies from `[server]` that rewrite a request regardless
+/// of which client sent it.
+fn apply_server_policies(
+    req: &mut MessagesRequest,
+    server: &crate::config::ServerConfig,
+    request_id: &str,
+) {
+    use crate::format::anthropic::{ContentBlock, SystemPrompt};
+
+    // Clamp rather than reject, so clients that over-request keep working
+    if let Some(cap) = server.max_tokens_cap_for(&req.model)
+        && let Some(requested) = req.max_tokens
+        && requested > cap
     {
-        return Err(Error::Api(ApiError::InvalidRequest {
-            message: "temperature must be between 0.0 and 2.0".to_string(),
-        }));
+        info!(
+            model = %req.model,
+            requested = requested,
+            cap = cap,
+            request_id = %request_id,
+            "Clamping max_tokens to configured cap"
+        );
+        req.max_tokens = Some(cap);
     }
 
-    Ok(())
+    let non_empty = |s: &Option<String>| s.clone().filter(|s| !s.is_empty());
+
+    if let Some(system) = non_empty(&server.system_override) {
+        req.system = Some(SystemPrompt::Text(system));
+    } else if let Some(prefix) = non_empty(&server.system_prefix) {
+        req.system = Some(match req.system.take() {
+            None => SystemPrompt::Text(prefix),
+            Some(SystemPrompt::Text(text)) if text.is_empty() => SystemPrompt::Text(prefix),
+            Some(SystemPrompt::Text(text)) => SystemPrompt::Text(format!("{}\n\n{}", prefix, text)),
+            Some(SystemPrompt::Blocks(mut blocks)) => {
+                blocks.insert(
+                    0,
+                    ContentBlock::Text {
+                        text: prefix,
+                        cache_control: None,
+                    },
+                );
+                SystemPrompt::Blocks(blocks)
+            }
+        });
+    }
 }
 
-async fn read_body_limited(body: hyper::body::Incoming, max_size: usize) -> Result<Bytes, Error> {
-    let collected = body
-        .collect()
-        .await
-        .map_err(|e| Error::Http(e.to_string()))?;
+/// Stands in for a `tool_result` sent with no content.
+const EMPTY_TOOL_RESULT_PLACEHOLDER: &str = "(no output)";
 
-    let bytes = collected.to_bytes();
-    if bytes.len() > max_size {
-        return Err(Error::Api(ApiError::RequestTooLarge {
-            size: bytes.len(),
-            max: max_size,
-        }));
-    }
+/// Deal with empty content that Cloud Code rejects with an opaque error:
+/// blank text blocks, `tool_result`s with no content and messages with no
+/// content at all.
+///
+/// With `reject` set the first one found is reported as an invalid request.
+/// Otherwise blank text blocks are dropped, empty `tool_result`s get a
+/// placeholder (dropping them would orphan their `tool_use`) and an empty
+/// trailing assistant message (a blank prefill) is removed. Any other empty
+/// message is an error either way, since there is nothing to send for it.
+fn handle_empty_content(
+    messages: &mut Vec<crate::format::anthropic::Message>,
+    reject: bool,
+    request_id: &str,
+) -> Result<(), Error> {
+    use crate::format::anthropic::{ContentBlock, MessageContent, Role, ToolResultContent};
 
-    Ok(bytes)
-}
+    let invalid = |message: String| Err(Error::Api(ApiError::InvalidRequest { message }));
+    let is_blank_text = |block: &ContentBlock| matches!(block, ContentBlock::Text { text, .. } if text.trim().is_empty());
 
-async fn handle_non_streaming_messages(
-    client: &CloudCodeClient,
-    body: Bytes,
-    access_token: &str,
-    model: &str,
-    request_id: &str,
-    cache_key: Option<String>,
-    state: &Arc<ServerState>,
-) -> Result<Response<ResponseBody>, Error> {
-    let response = client.send_request(body, access_token, model).await?;
-    let anthropic_response = parse_response(&response, model, request_id);
-    record_usage(model, &anthropic_response.usage);
-
-    log_if_enabled(request_id, "Anthropic response", &anthropic_response);
+    let mut normalized = 0usize;
+    for (i, message) in messages.iter_mut().enumerate() {
+        let MessageContent::Blocks(blocks) = &mut message.content else {
+            continue;
+        };
 
-    let response_bytes = serde_json::to_vec(&anthropic_response)?;
+        for (j, block) in blocks.iter_mut().enumerate() {
+            let ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                ..
+            } = block
+            else {
+                continue;
+            };
+            let empty = match content {
+                ToolResultContent::Text(text) => text.trim().is_empty(),
+                ToolResultContent::Blocks(inner) => {
+                    if reject && inner.iter().any(is_blank_text) {
+                        return invalid(format!(
+                            "messages[{}].content[{}]: tool_result for '{}' contains an empty text block",
+                            i, j, tool_use_id
+                        ));
+                    }
+                    let before = inner.len();
+                    inner.retain(|b| !is_blank_text(b));
+                    normalized += before - inner.len();
+                    inner.is_empty()
+                }
+            };
+            if empty {
+                if reject {
+                    return invalid(format!(
+                        "messages[{}].content[{}]: tool_result for '{}' has empty content",
+                        i, j, tool_use_id
+                    ));
+                }
+                *content = ToolResultContent::Text(EMPTY_TOOL_RESULT_PLACEHOLDER.to_string());
+                normalized += 1;
+            }
+        }
 
-    if let Some(ref key) = cache_key {
-        let mut cache = state.cache.lock().await;
-        cache.put(key.clone(), response_bytes.clone());
-        debug!(model = %model, request_id = %request_id, "Cached response");
+        if let Some(j) = blocks.iter().position(is_blank_text) {
+            if reject {
+                return invalid(format!(
+                    "messages[{}].content[{}]: text content blocks must contain non-whitespace text",
+                    i, j
+                ));
+            }
+            let before = blocks.len();
+            blocks.retain(|b| !is_blank_text(b));
+            normalized += before - blocks.len();
+        }
     }
 
-    // Add X-Cache header: MISS if we have a cache key (means we tried cache but didn't hit)
-    let cache_header = if cache_key.is_some() {
-        "MISS"
-    } else {
-        "BYPASS"
+    let is_empty = |message: &crate::format::anthropic::Message| match &message.content {
+        MessageContent::Text(text) => text.trim().is_empty(),
+        MessageContent::Blocks(blocks) => blocks.is_empty(),
     };
 
-    Ok(json_ok_response(
-        response_bytes,
-        request_id,
-        Some(cache_header),
-    ))
-}
+    // A blank prefill is the same as no prefill
+    if !reject
+        && messages.len() > 1
+        && messages
+            .last()
+            .is_some_and(|m| m.role == Role::Assistant && is_empty(m))
+    {
+        messages.pop();
+        normalized += 1;
+    }
 
-// Thinking models must use streaming endpoint (doesn't rate-limit) but client may want non-streaming
-async fn handle_thinking_non_streaming_messages(
-    client: &CloudCodeClient,
-    body: Bytes,
-    access_token: &str,
-    model: &str,
-    request_id: &str,
-) -> Result<Response<ResponseBody>, Error> {
-    let (events, body_bytes) = collect_sse_events(client, body, access_token, model).await?;
+    if let Some(i) = messages.iter().position(is_empty) {
+        return invalid(format!("messages[{}]: content must not be empty", i));
+    }
 
-    // Log raw response for debugging empty/error responses
-    if body_bytes.len() < 2000 {
-        let body_str = String::from_utf8_lossy(&body_bytes);
+    if normalized > 0 {
         debug!(
-            model = %model,
-            request_id = %request_id,
-            body_len = body_bytes.len(),
-            body = %body_str,
-            "Raw SSE response from Google (thinking non-streaming)"
-        );
-    } else {
-        debug!(
-            model = %model,
+            normalized = normalized,
             request_id = %request_id,
-            body_len = body_bytes.len(),
-            "Raw SSE response from Google (thinking non-streaming, truncated)"
+            "Normalized empty content blocks"
         );
     }
 
-    check_stream_errors(&events, model, request_id, " (thinking non-streaming)")?;
-
-    // Check if we got an empty response (no content events)
-    let has_content = events.iter().any(|e| {
-        matches!(
-            e,
-            StreamEvent::ContentBlockStart { .. } | StreamEvent::ContentBlockDelta { .. }
-        )
-    });
-
-    if !has_content && !body_bytes.is_empty() {
-        let body_str = String::from_utf8_lossy(&body_bytes);
-        warn!(
-            model = %model,
-            request_id = %request_id,
-            body_len = body_bytes.len(),
-            "Empty response from Google API (thinking non-streaming) - model may be unavailable. Raw body: {}",
-            body_str.chars().take(500).collect::<String>()
-        );
+    Ok(())
+}
 
-        // Return an error instead of an empty response
-        return Err(Error::Api(ApiError::ServerError {
-            status: 502,
-            message: format!(
-                "Model {} returned empty response from Google API. The model may be unavailable. Raw: {}",
-                model,
-                body_str.chars().take(200).collect::<String>()
-            ),
-        }));
+/// Combine consecutive messages with the same role into one, as the
+/// Anthropic API does, so the turns alternate before conversion.
+fn merge_consecutive_roles(messages: &mut Vec<crate::format::anthropic::Message>) {
+    use crate::format::anthropic::{ContentBlock, MessageContent};
+
+    fn into_blocks(content: MessageContent) -> Vec<ContentBlock> {
+        match content {
+            MessageContent::Blocks(blocks) => blocks,
+            MessageContent::Text(text) => vec![ContentBlock::Text {
+                text,
+                cache_control: None,
+            }],
+        }
     }
 
-    let anthropic_response = crate::format::build_response_from_events(&events, model, request_id);
-    record_usage(model, &anthropic_response.usage);
-
-    log_if_enabled(request_id, "Anthropic response", &anthropic_response);
-
-    let response_body = serde_json::to_vec(&anthropic_response)?;
-    Ok(json_ok_response(response_body, request_id, Some("BYPASS")))
+    let mut merged: Vec<crate::format::anthropic::Message> = Vec::with_capacity(messages.len());
+    for message in messages.drain(..) {
+        match merged.last_mut() {
+            Some(last) if last.role == message.role => {
+                let content =
+                    std::mem::replace(&mut last.content, MessageContent::Text(String::new()));
+                let mut blocks = into_blocks(content);
+                blocks.extend(into_blocks(message.content));
+                last.content = MessageContent::Blocks(blocks);
+            }
+            _ => merged.push(message),
+        }
+    }
+    *messages = merged;
 }
 
-/// Handle Anthropic streaming messages with true SSE pass-through.
-///
-/// Returns the response immediately with a channel-backed body.  A background
-/// task reads chunks from the upstream Google response, parses them with
-/// `SseParser`, and forwards each Anthropic-format SSE event through the
-/// channel as it arrives.
-async fn handle_streaming_messages(
-    client: &CloudCodeClient,
-    body: Bytes,
-    access_token: &str,
-    model: &str,
-    request_id: &str,
-) -> Result<Response<ResponseBody>, Error> {
-    let upstream = client
-        .send_streaming_request(body, access_token, model)
-        .await?;
-
-    let (tx, body) = streaming_body();
-
-    let model = model.to_string();
-    let request_id_owned = request_id.to_string();
-
-    // Return the SSE response immediately; the background task will feed data.
-    let response = sse_streaming_response(body, request_id);
-
-    let request_id = request_id_owned;
-    tokio::spawn(async move {
-        let mut parser = SseParser::new(&model);
-        let mut input_tokens = 0u32;
-        let mut output_tokens = 0u32;
-        let mut cache_read_tokens = 0u32;
-        let mut has_content = false;
-        let mut body_len = 0usize;
-
-        let mut incoming = upstream.into_body();
-
-        // Read chunks from upstream as they arrive.
-        loop {
-            use http_body_util::BodyExt;
-            let frame_timeout = Duration::from_secs(STREAM_FRAME_TIMEOUT_SECS);
-            let frame = tokio::time::timeout(frame_timeout, incoming.frame()).await;
-            match frame {
-                Ok(Some(Ok(frame))) => {
-                    if let Ok(data) = frame.into_data() {
-                        body_len += data.len();
-                        let chunk_str = String::from_utf8_lossy(&data);
+/// Check Anthropic-format turn structure that Cloud Code would otherwise
+/// reject with an opaque error: the conversation starts with a user turn and
+/// every `tool_use` is answered by a `tool_result` in the next user turn (and
+/// every `tool_result` answers one). Expects same-role turns to have been
+/// merged by [`merge_consecutive_roles`].
+fn validate_conversation(messages: &[crate::format::anthropic::Message]) -> Result<(), Error> {
+    use crate::format::anthropic::{ContentBlock, MessageContent, Role};
+
+    let invalid = |message: String| Err(Error::Api(ApiError::InvalidRequest { message }));
+    fn blocks(content: &MessageContent) -> &[ContentBlock] {
+        match content {
+            MessageContent::Blocks(blocks) => blocks.as_slice(),
+            MessageContent::Text(_) => &[],
+        }
+    }
 
-                        for event in parser.feed(&chunk_str) {
-                            // Track tokens
-                            match &event {
-                                StreamEvent::MessageStart { message } => {
-                                    input_tokens = message.usage.input_tokens;
-                                    cache_read_tokens =
-                                        message.usage.cache_read_input_tokens.unwrap_or(0);
-                                }
-                                StreamEvent::MessageDelta { usage, .. } => {
-                                    output_tokens = usage.output_tokens;
-                                }
-                                StreamEvent::ContentBlockStart { .. }
-                                | StreamEvent::ContentBlockDelta { .. } => {
-                                    has_content = true;
-                                }
-                                StreamEvent::Error { error } => {
-                                    warn!(
-                                        model = %model,
-                                        request_id = %request_id,
-                                        error = %error.message,
-                                        "Google API error in SSE stream"
-                                    );
-                                }
-                                _ => {}
-                            }
+    if let Some(first) = messages.first()
+        && first.role != Role::User
+    {
+        return invalid("messages: first message must use the \"user\" role".to_string());
+    }
 
-                            let formatted = format_sse_event(&event);
-                            if tx.send(Bytes::from(formatted)).await.is_err() {
-                                // Client disconnected
-                                return;
-                            }
-                        }
+    // tool_use ids from the previous assistant turn that still need a result
+    let mut pending_tool_uses: Vec<&str> = Vec::new();
+
+    for (i, message) in messages.iter().enumerate() {
+        match message.role {
+            Role::Assistant => {
+                pending_tool_uses = blocks(&message.content)
+                    .iter()
+                    .filter_map(|block| match block {
+                        ContentBlock::ToolUse { id, .. } => Some(id.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+            }
+            Role::User => {
+                for block in blocks(&message.content) {
+                    if let ContentBlock::ToolResult { tool_use_id, .. } = block {
+                        let Some(pos) = pending_tool_uses.iter().position(|id| id == tool_use_id)
+                        else {
+                            return invalid(format!(
+                                "messages[{}]: tool_result for '{}' does not match a tool_use \
+                                 in the preceding assistant message",
+                                i, tool_use_id
+                            ));
+                        };
+                        pending_tool_uses.swap_remove(pos);
                     }
                 }
-                Ok(Some(Err(e))) => {
-                    warn!(
-                        model = %model,
-                        request_id = %request_id,
-                        error = %e,
-                        "Error reading upstream SSE stream"
-                    );
-                    break;
-                }
-                Ok(None) => break, // End of upstream stream
-                Err(_) => {
-                    warn!(
-                        model = %model,
-                        request_id = %request_id,
-                        "Upstream frame timeout in Anthropic streaming"
-                    );
-                    break;
+                if let Some(id) = pending_tool_uses.first() {
+                    return invalid(format!(
+                        "messages[{}]: missing tool_result for tool_use '{}' from messages[{}]",
+                        i,
+                        id,
+                        i - 1
+                    ));
                 }
             }
         }
+    }
 
-        // Flush any remaining events from the parser.
-        for event in parser.finish() {
-            match &event {
-                StreamEvent::MessageStart { message } => {
-                    input_tokens = message.usage.input_tokens;
-                    cache_read_tokens = message.usage.cache_read_input_tokens.unwrap_or(0);
-                }
-                StreamEvent::MessageDelta { usage, .. } => {
-                    output_tokens = usage.output_tokens;
-                }
-                StreamEvent::ContentBlockStart { .. } | StreamEvent::ContentBlockDelta { .. } => {
-                    has_content = true;
-                }
-                _ => {}
-            }
-            let formatted = format_sse_event(&event);
-            let _ = tx.send(Bytes::from(formatted)).await;
+    if let Some(id) = pending_tool_uses.first() {
+        return invalid(format!(
+            "messages: tool_use '{}' in the final assistant message has no tool_result; \
+             send its result in a following user message",
+            id
+        ));
+    }
+
+    Ok(())
+}
+
+/// Catch common tool-definition mistakes before they reach the upstream,
+/// which rejects them with far less specific errors. A schema without a
+/// `"type"` is taken as `"object"` (OpenAI no-argument tools send `{}`), as
+/// the conversion does.
+fn validate_tools(tools: &[crate::format::anthropic::Tool]) -> Result<(), Error> {
+    let invalid = |message: String| Err(Error::Api(ApiError::InvalidRequest { message }));
+    let mut seen = std::collections::HashSet::new();
+
+    for (i, tool) in tools.iter().enumerate() {
+        if tool.name.trim().is_empty() {
+            return invalid(format!("tools[{}]: name must not be empty", i));
+        }
+        if !seen.insert(tool.name.as_str()) {
+            return invalid(format!(
+                "tools[{}]: duplicate tool name '{}'; tool names must be unique",
+                i, tool.name
+            ));
         }
+        if !tool.input_schema.is_object() {
+            return invalid(format!(
+                "tools[{}] ('{}'): input_schema must be a JSON object",
+                i, tool.name
+            ));
+        }
+    }
 
-        // Send final message_stop event.
-        let stop_event = format_sse_event(&create_message_stop());
-        let _ = tx.send(Bytes::from(stop_event)).await;
+    Ok(())
+}
 
-        // Record token usage.
-        get_stats().record_token_usage(&model, input_tokens, output_tokens, cache_read_tokens);
+/// Apply `[models] unknown_model` to a resolved model name that isn't a
+/// known model: swap in the configured default model, or reject the request
+/// with the list of available models. Targets configured in `[mappings]`
+/// are always passed through.
+fn handle_unknown_model(
+    model: &mut String,
+    config: &crate::config::Config,
+    request_id: &str,
+) -> Result<(), Error> {
+    if model.is_empty() || Model::from_id(model).is_some() || config.mappings.is_target(model) {
+        return Ok(());
+    }
 
-        if !has_content && body_len > 0 {
-            warn!(
-                model = %model,
+    match config.models.unknown_model_fallback() {
+        Some(default_model) => {
+            debug!(
+                requested = %model,
+                default_model = %default_model,
                 request_id = %request_id,
-                body_len = body_len,
-                "Empty response from Google API (streaming) - model may be unavailable"
+                "Unknown model, using default_model"
             );
+            *model = default_model.to_string();
+            Ok(())
         }
-    });
-
-    Ok(response)
+        None => Err(Error::Api(ApiError::InvalidRequest {
+            message: format!(
+                "model '{}' not found. Available models: {}",
+                model,
+                all_target_models().join(", ")
+            ),
+        })),
+    }
 }
 
-async fn handle_models() -> Result<Response<ResponseBody>, Error> {
-    let models: Vec<ModelInfo> = Model::all()
-        .iter()
-        .map(|m| ModelInfo {
-            id: m.anthropic_id().to_string(),
-            model_type: "model".to_string(),
-            display_name: m.anthropic_id().to_string(),
-            created_at: "2025-01-01T00:00:00Z".to_string(),
-        })
-        .collect();
+fn validate_request(req: &MessagesRequest, config: &crate::config::Config) -> Result<(), Error> {
+    let models = &config.models;
 
-    let response = ModelsResponse { data: models };
-    let body = serde_json::to_vec(&response)?;
+    let Some(max_tokens) = req.max_tokens else {
+        return Err(Error::Api(ApiError::InvalidRequest {
+            message: "max_tokens is required".to_string(),
+        }));
+    };
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .body(full_body(Full::new(Bytes::from(body))))
-        .unwrap())
-}
-
-/// Estimate token count for a messages request.
-///
-/// Uses a chars/4 heuristic which is a reasonable approximation for most
-/// tokenizers (GPT, Claude, Gemini all average ~3.5-4.5 chars per token
-/// for English text). This avoids requiring a full tokenizer dependency.
-async fn handle_count_tokens(
-    req: Request<hyper::body::Incoming>,
-) -> Result<Response<ResponseBody>, Error> {
-    let body_bytes = read_body_limited(req.into_body(), MAX_REQUEST_SIZE).await?;
-
-    #[derive(serde::Deserialize)]
-    struct CountTokensRequest {
-        messages: Vec<crate::format::anthropic::Message>,
-        #[serde(default)]
-        system: Option<crate::format::anthropic::SystemPrompt>,
-        #[serde(default)]
-        tools: Option<Vec<crate::format::anthropic::Tool>>,
+    if max_tokens == 0 {
+        return Err(Error::Api(ApiError::InvalidRequest {
+            message: "max_tokens must be greater than 0".to_string(),
+        }));
     }
 
-    let request: CountTokensRequest = serde_json::from_slice(&body_bytes)?;
+    if max_tokens > 200_000 {
+        return Err(Error::Api(ApiError::InvalidRequest {
+            message: "max_tokens cannot exceed 200000".to_string(),
+        }));
+    }
 
-    let mut total_chars: usize = 0;
+    if req.model.is_empty() {
+        return Err(Error::Api(ApiError::InvalidRequest {
+            message: "model is required".to_string(),
+        }));
+    }
 
-    // Count system prompt chars
-    if let Some(system) = &request.system {
-        match system {
-            crate::format::anthropic::SystemPrompt::Text(text) => {
-                total_chars += text.len();
-            }
-            crate::format::anthropic::SystemPrompt::Blocks(blocks) => {
-                for block in blocks {
-                    total_chars += count_block_chars(block);
-                }
+    if is_model_disabled(&req.model, &models.disabled) {
+        // Suggest the configured fallback first, then any other enabled model
+        let mut alternatives: Vec<&str> = Vec::new();
+        for candidate in get_fallback_model(&req.model)
+            .into_iter()
+            .chain(Model::all().iter().map(|m| m.anthropic_id()))
+        {
+            if alternatives.len() < 3
+                && candidate != req.model
+                && !alternatives.contains(&candidate)
+                && !is_model_disabled(candidate, &models.disabled)
+            {
+                alternatives.push(candidate);
             }
         }
+        let mut message = format!("model '{}' is disabled on this server", req.model);
+        if !alternatives.is_empty() {
+            message.push_str(&format!(". Try one of: {}", alternatives.join(", ")));
+        }
+        return Err(Error::Api(ApiError::InvalidRequest { message }));
     }
 
-    // Count message chars
-    for msg in &request.messages {
-        match &msg.content {
-            crate::format::anthropic::MessageContent::Text(text) => {
-                total_chars += text.len();
-            }
-            crate::format::anthropic::MessageContent::Blocks(blocks) => {
-                for block in blocks {
-                    total_chars += count_block_chars(block);
-                }
-            }
-        }
+    if req.messages.is_empty() {
+        return Err(Error::Api(ApiError::InvalidRequest {
+            message: "messages array cannot be empty".to_string(),
+        }));
     }
 
-    // Count tool definitions
-    if let Some(tools) = &request.tools {
-        for tool in tools {
-            total_chars += tool.name.len();
-            if let Some(desc) = &tool.description {
-                total_chars += desc.len();
-            }
-            total_chars += tool.input_schema.to_string().len();
+    if let Some(temp) = req.temperature
+        && !(0.0..=2.0).contains(&temp)
+    {
+        return Err(Error::Api(ApiError::InvalidRequest {
+            message: "temperature must be between 0.0 and 2.0".to_string(),
+        }));
+    }
+
+    // Structurally huge requests can be small in bytes but slow upstream
+    if req.messages.len() > config.server.max_messages {
+        return Err(Error::Api(ApiError::InvalidRequest {
+            message: format!(
+                "messages: {} messages exceeds this server's limit of {}",
+                req.messages.len(),
+                config.server.max_messages
+            ),
+        }));
+    }
+
+    if let Some(tools) = &req.tools {
+        if tools.len() > config.server.max_tools {
+            return Err(Error::Api(ApiError::InvalidRequest {
+                message: format!(
+                    "tools: {} tools exceeds this server's limit of {}",
+                    tools.len(),
+                    config.server.max_tools
+                ),
+            }));
         }
+        validate_tools(tools)?;
     }
 
-    // Estimate: ~4 chars per token, with a minimum of 1
-    let input_tokens = (total_chars / 4).max(1) as u32;
+    validate_capabilities(req)?;
 
-    let response = serde_json::json!({
-        "input_tokens": input_tokens,
-    });
+    Ok(())
+}
 
-    let response_body = serde_json::to_vec(&response)?;
-    Ok(json_ok_response(response_body, "count_tokens", None))
+/// Reject request features the resolved model can't serve, which the
+/// upstream would otherwise answer with an opaque 400.
+fn validate_capabilities(req: &MessagesRequest) -> Result<(), Error> {
+    let Some(capabilities) = model_capabilities(&req.model) else {
+        return Ok(());
+    };
+    let unsupported = |feature: &str| {
+        Error::Api(ApiError::InvalidRequest {
+            message: format!("model {} does not support {}", req.model, feature),
+        })
+    };
+
+    if !capabilities.supports_thinking
+        && matches!(
+            req.thinking,
+            Some(crate::format::anthropic::ThinkingConfig::Enabled { .. })
+        )
+    {
+        return Err(unsupported("the thinking parameter"));
+    }
+
+    if !capabilities.supports_tools && req.tools.as_ref().is_some_and(|t| !t.is_empty()) {
+        return Err(unsupported("tools"));
+    }
+
+    if !capabilities.supports_vision
+        && req.messages.iter().any(|m| match &m.content {
+            crate::format::anthropic::MessageContent::Blocks(blocks) => has_image(blocks),
+            crate::format::anthropic::MessageContent::Text(_) => false,
+        })
+    {
+        return Err(unsupported("image input"));
+    }
+
+    Ok(())
 }
 
-/// Count approximate character length of a content block.
-fn count_block_chars(block: &crate::format::ContentBlock) -> usize {
-    match block {
-        crate::format::ContentBlock::Text { text, .. } => text.len(),
-        crate::format::ContentBlock::Image { .. } => 256, // Images counted as ~64 tokens
-        crate::format::ContentBlock::Document { .. } => 1024, // PDFs counted as ~256 tokens
-        crate::format::ContentBlock::ToolUse { name, input, .. } => {
-            name.len() + input.to_string().len()
+/// Whether `blocks` contain an image, including inside tool results.
+fn has_image(blocks: &[crate::format::ContentBlock]) -> bool {
+    use crate::format::ContentBlock;
+    use crate::format::anthropic::ToolResultContent;
+
+    blocks.iter().any(|block| match block {
+        ContentBlock::Image { .. } => true,
+        ContentBlock::ToolResult {
+            content: ToolResultContent::Blocks(inner),
+            ..
+        } => has_image(inner),
+        _ => false,
+    })
+}
+
+/// Read a request body, failing as soon as it grows past `max_size`.
+async fn read_body_limited(
+    mut body: hyper::body::Incoming,
+    max_size: usize,
+) -> Result<Bytes, Error> {
+    let mut buf = Vec::new();
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|e| Error::Http(e.to_string()))?;
+        if let Ok(data) = frame.into_data() {
+            check_body_size(buf.len() + data.len(), max_size)?;
+            buf.extend_from_slice(&data);
         }
-        crate::format::ContentBlock::ToolResult { content, .. } => match content {
-            crate::format::anthropic::ToolResultContent::Text(text) => text.len(),
-            crate::format::anthropic::ToolResultContent::Blocks(blocks) => {
-                blocks.iter().map(count_block_chars).sum()
-            }
-        },
-        crate::format::ContentBlock::Thinking { thinking, .. } => thinking.len(),
     }
+    Ok(Bytes::from(buf))
 }
 
-async fn handle_stats(state: &Arc<ServerState>) -> Result<Response<ResponseBody>, Error> {
-    let stats = get_stats().summary();
-    let cache_stats = state.cache.lock().await.stats();
-
-    let response = serde_json::json!({
-        "requests": stats.to_json(),
-        "cache": cache_stats,
-    });
+/// Buffer a request body until it ends or reaches `threshold` bytes. Returns
+/// the bytes read and whether that was the whole body.
+async fn read_body_prefix(
+    body: &mut hyper::body::Incoming,
+    threshold: usize,
+    max_size: usize,
+) -> Result<(Bytes, bool), Error> {
+    let mut buf = Vec::new();
+    while buf.len() < threshold {
+        let Some(frame) = body.frame().await else {
+            return Ok((Bytes::from(buf), true));
+        };
+        let frame = frame.map_err(|e| Error::Http(e.to_string()))?;
+        if let Ok(data) = frame.into_data() {
+            check_body_size(buf.len() + data.len(), max_size)?;
+            buf.extend_from_slice(&data);
+        }
+    }
+    Ok((Bytes::from(buf), false))
+}
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .body(full_body(Full::new(Bytes::from(response.to_string()))))
-        .unwrap())
+fn check_body_size(size: usize, max_size: usize) -> Result<(), Error> {
+    if size > max_size {
+        return Err(Error::Api(ApiError::RequestTooLarge {
+            size,
+            max: max_size,
+        }));
+    }
+    Ok(())
 }
 
-async fn handle_account_limits(state: &Arc<ServerState>) -> Result<Response<ResponseBody>, Error> {
-    // Get credentials using the existing pattern
-    let credentials = get_account_credentials(state, "claude-sonnet-4-5").await;
+/// Read and deserialize a JSON request body, recording its size.
+///
+/// Bodies of at least [`STREAMING_PARSE_THRESHOLD`] bytes are deserialized
+/// as they arrive rather than buffered first, so the raw bytes and the parsed
+/// request are never both held in full. Smaller bodies, and bodies without a
+/// Content-Length until they cross the threshold, are buffered on the async
+/// side, so a slow upload doesn't hold a blocking thread. Malformed JSON is
+/// reported as `Error::InvalidBody`.
+async fn read_json_body<T>(mut req: Request<hyper::body::Incoming>) -> Result<T, Error>
+where
+    T: serde::de::DeserializeOwned + Send + 'static,
+{
+    if let Some(BufferedBody(bytes)) = req.extensions_mut().remove() {
+        get_stats().record_request_size(bytes.len());
+        return crate::format::parse_json_body(&bytes).map_err(Error::InvalidBody);
+    }
 
-    let response = match credentials {
-        Ok((access_token, project_id, account_id, _account_email)) => {
-            match fetch_model_quotas(&state.http_client, &access_token, Some(&project_id)).await {
-                Ok(quotas) => {
-                    // Save quota data to the account for TUI display
-                    {
-                        let mut accounts = state.accounts.write().await;
-                        if let Some(account) = accounts.get_account_mut(&account_id) {
-                            for q in &quotas {
-                                // Parse ISO timestamp to Unix timestamp
-                                let reset_time = q
-                                    .reset_time
-                                    .as_ref()
-                                    .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
-                                    .map(|dt| dt.timestamp() as u64)
-                                    .unwrap_or(0);
+    let content_length = req
+        .headers()
+        .get("content-length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+
+    let mut body = req.into_body();
+    let mut prefix = Bytes::new();
+    if !content_length.is_some_and(|len| len >= STREAMING_PARSE_THRESHOLD) {
+        let (bytes, complete) =
+            read_body_prefix(&mut body, STREAMING_PARSE_THRESHOLD, MAX_REQUEST_SIZE).await?;
+        if complete {
+            get_stats().record_request_size(bytes.len());
+            return crate::format::parse_json_body(&bytes).map_err(Error::InvalidBody);
+        }
+        prefix = bytes;
+    }
 
-                                account.quota.insert(
-                                    q.model_id.clone(),
-                                    crate::auth::accounts::ModelQuota {
-                                        remaining_fraction: q.remaining_fraction,
-                                        reset_time,
-                                    },
-                                );
-                            }
-                            // Save to disk
-                            if let Err(e) = accounts.save() {
-                                warn!(error = %e, "Failed to save quota data");
-                            }
-                        }
-                    }
+    let (value, size) = parse_body_streaming(body, prefix, MAX_REQUEST_SIZE).await?;
+    get_stats().record_request_size(size);
+    Ok(value)
+}
 
-                    let models: Vec<serde_json::Value> = quotas
-                        .iter()
-                        .map(|q| {
-                            serde_json::json!({
-                                "model": q.model_id,
-                                "remaining_fraction": q.remaining_fraction,
-                                "reset_time": q.reset_time,
-                            })
-                        })
-                        .collect();
+/// Deserialize a body on a blocking thread while its frames are still
+/// arriving, starting with the already-read `prefix`. Returns the value and
+/// the number of bytes read.
+async fn parse_body_streaming<T>(
+    mut body: hyper::body::Incoming,
+    prefix: Bytes,
+    max_size: usize,
+) -> Result<(T, usize), Error>
+where
+    T: serde::de::DeserializeOwned + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel::<Bytes>(BODY_CHUNK_BUFFER);
+    let parser = tokio::task::spawn_blocking(move || {
+        crate::format::parse_json_reader::<T, _>(std::io::BufReader::new(ChunkReader::new(rx)))
+    });
 
-                    serde_json::json!({
-                        "status": "ok",
-                        "quotas": models,
-                    })
-                }
-                Err(e) => {
-                    serde_json::json!({
-                        "status": "error",
-                        "message": e,
-                    })
-                }
+    let mut size = prefix.len();
+    if !prefix.is_empty() {
+        // Can't fail: the parser hasn't read anything yet
+        let _ = tx.send(prefix).await;
+    }
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|e| Error::Http(e.to_string()))?;
+        if let Ok(data) = frame.into_data() {
+            size += data.len();
+            // Dropping `tx` on return ends the parser with an EOF error
+            check_body_size(size, max_size)?;
+            if tx.send(data).await.is_err() {
+                // The parser already stopped on malformed JSON
+                break;
             }
         }
-        Err(e) => {
-            serde_json::json!({
-                "status": "error",
-                "message": e.to_string(),
-            })
-        }
-    };
+    }
+    drop(tx);
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .body(full_body(Full::new(Bytes::from(response.to_string()))))
-        .unwrap())
+    let value = parser
+        .await
+        .map_err(|e| Error::Http(e.to_string()))?
+        .map_err(Error::InvalidBody)?;
+    Ok((value, size))
 }
 
-async fn handle_logs_stream() -> Result<Response<ResponseBody>, Error> {
-    // Get the log file path
-    let config_dir = std::env::var_os("HOME")
-        .map(|h| std::path::PathBuf::from(h).join(".config").join("agcp"))
-        .unwrap_or_else(|| std::path::PathBuf::from("."));
-    let log_path = config_dir.join("agcp.log");
+/// Blocking `Read` over body chunks sent through a channel.
+struct ChunkReader {
+    rx: mpsc::Receiver<Bytes>,
+    chunk: Bytes,
+}
 
-    // Read the last 100 lines from the log file efficiently (seek from end)
-    let lines = match std::fs::File::open(&log_path) {
-        Ok(mut file) => {
-            use std::io::{Read, Seek, SeekFrom};
-            let file_len = file.metadata().map(|m| m.len()).unwrap_or(0);
-            if file_len == 0 {
-                vec!["No log entries".to_string()]
-            } else {
-                const TAIL_COUNT: usize = 100;
-                const CHUNK_SIZE: u64 = 64 * 1024;
-                let mut collected: Vec<String> = Vec::new();
-                let mut remaining = file_len;
+impl ChunkReader {
+    fn new(rx: mpsc::Receiver<Bytes>) -> Self {
+        Self {
+            rx,
+            chunk: Bytes::new(),
+        }
+    }
+}
 
-                while remaining > 0 && collected.len() < TAIL_COUNT + 1 {
-                    let chunk = remaining.min(CHUNK_SIZE);
-                    let offset = remaining - chunk;
-                    let _ = file.seek(SeekFrom::Start(offset));
-                    let mut buf = vec![0u8; chunk as usize];
-                    if file.read_exact(&mut buf).is_err() {
-                        break;
-                    }
-                    let chunk_str = String::from_utf8_lossy(&buf);
-                    let mut chunk_lines: Vec<String> =
-                        chunk_str.lines().map(String::from).collect();
-                    if offset > 0 && !chunk_lines.is_empty() {
-                        let partial = chunk_lines.remove(0);
-                        if let Some(last) = collected.last_mut() {
-                            *last = format!("{}{}", partial, last);
-                        }
-                    }
-                    chunk_lines.append(&mut collected);
-                    collected = chunk_lines;
-                    remaining = offset;
-                }
-
-                let start = collected.len().saturating_sub(TAIL_COUNT);
-                collected[start..].to_vec()
+impl std::io::Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
             }
         }
-        Err(_) => vec!["No log file available".to_string()],
-    };
-
-    // Format as SSE events
-    let mut body = String::new();
-    for line in lines {
-        // Strip ANSI codes for cleaner output
-        let clean_line = strip_ansi_codes(&line);
-        body.push_str(&format!("data: {}\n\n", clean_line));
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk[..n]);
+        self.chunk = self.chunk.slice(n..);
+        Ok(n)
     }
-
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "text/event-stream")
-        .header("Cache-Control", "no-cache")
-        .header("Connection", "keep-alive")
-        .body(full_body(Full::new(Bytes::from(body))))
-        .unwrap())
 }
 
-fn strip_ansi_codes(s: &str) -> String {
-    let mut result = String::with_capacity(s.len());
-    let mut chars = s.chars().peekable();
+async fn handle_non_streaming_messages(
+    state: &Arc<ServerState>,
+    attempt: UpstreamAttempt,
+    model: &str,
+    cache_slot: Option<(String, Duration)>,
+) -> Result<Response<ResponseBody>, Error> {
+    let UpstreamAttempt {
+        access_token,
+        request_id,
+        body,
+        usage_sink,
+    } = attempt;
+    let request_id = request_id.as_str();
+    let response = state
+        .cloudcode_client
+        .send_request(body.clone(), &access_token, model)
+        .await?;
+    let anthropic_response =
+        debug_span!("convert_response").in_scope(|| parse_response(&response, model, request_id));
+    usage_sink.record(model, &anthropic_response.usage);
+    spawn_mirror_comparison(state, body, model, request_id, &anthropic_response);
 
-    while let Some(c) = chars.next() {
-        if c == '\x1b' {
-            // Skip escape sequence
-            if chars.peek() == Some(&'[') {
-                chars.next(); // consume '['
-                // Skip until we hit a letter (end of sequence)
-                while let Some(&next) = chars.peek() {
-                    chars.next();
-                    if next.is_ascii_alphabetic() {
-                        break;
-                    }
-                }
-            }
-        } else {
-            result.push(c);
-        }
-    }
+    log_if_enabled(request_id, "Anthropic response", &anthropic_response);
 
-    result
-}
+    let response_bytes = serde_json::to_vec(&anthropic_response)?;
 
-/// Helper to create a test server state with default (empty) accounts and in-memory cache.
-#[cfg(test)]
-fn test_server_state() -> Arc<ServerState> {
-    use crate::auth::accounts::AccountStore;
+    if let Some((key, ttl)) = &cache_slot {
+        let mut cache = state.cache.lock().await;
+        cache.put_with_ttl(key.clone(), response_bytes.clone(), *ttl);
+        debug!(model = %model, request_id = %request_id, "Cached response");
+    }
 
-    Arc::new(ServerState {
-        accounts: RwLock::new(AccountStore::default()),
-        http_client: HttpClient::default(),
-        cloudcode_client: CloudCodeClient::default(),
-        cache: Mutex::new(ResponseCache::new(true, 300, 100)),
-    })
+    // Add X-Cache header: MISS if we have a cache key (means we tried cache but didn't hit)
+    let cache_header = if cache_slot.is_some() {
+        "MISS"
+    } else {
+        "BYPASS"
+    };
+
+    Ok(json_ok_response(
+        response_bytes,
+        request_id,
+        Some(cache_header),
+    ))
 }
 
-/// Send a streaming request and collect all SSE events by parsing the full response body.
-///
-/// Returns `(events, body_bytes)` where `body_bytes` are the raw response bytes.
-/// Callers that need the body as a string for logging can convert lazily.
-async fn collect_sse_events(
-    client: &CloudCodeClient,
+/// Send the translated request to `[cloudcode] mirror_url` in the background
+/// and log how its response compares with the primary one. The client's
+/// response is never delayed or altered.
+fn spawn_mirror_comparison(
+    state: &Arc<ServerState>,
     body: Bytes,
-    access_token: &str,
     model: &str,
-) -> Result<(Vec<StreamEvent>, Bytes), Error> {
-    let response = client
-        .send_streaming_request(body, access_token, model)
-        .await?;
+    request_id: &str,
+    primary: &crate::format::MessagesResponse,
+) {
+    let Some(url) = get_config().cloudcode.mirror_url.clone() else {
+        return;
+    };
 
-    let mut parser = SseParser::new(model);
+    let state = Arc::clone(state);
+    let model = model.to_string();
+    let request_id = request_id.to_string();
+    let primary_text = response_text(primary);
+    let primary_usage = primary.usage.clone();
 
-    let body_bytes = response
-        .into_body()
-        .collect()
-        .await
-        .map_err(|e| Error::Http(e.to_string()))?
-        .to_bytes();
+    tokio::spawn(async move {
+        let start = std::time::Instant::now();
+        let mirror = state
+            .http_client
+            .post(&url, "application/json", &body)
+            .await
+            .and_then(|bytes| {
+                serde_json::from_slice::<crate::format::google::GenerateContentResponse>(&bytes)
+                    .map_err(|e| format!("invalid response JSON: {e}"))
+            });
+        let latency_ms = start.elapsed().as_millis() as u64;
 
-    // Parse directly from the byte slice (lossy), avoiding an owned String allocation
-    let body_str = String::from_utf8_lossy(&body_bytes);
+        let mirror = match mirror {
+            Ok(response) => parse_response(&response, &model, &request_id),
+            Err(e) => {
+                warn!(request_id = %request_id, error = %e, "Mirror request failed");
+                return;
+            }
+        };
+        let mirror_text = response_text(&mirror);
 
-    let mut events = Vec::new();
-    for event in parser.feed(&body_str) {
-        events.push(event);
-    }
-    for event in parser.finish() {
-        events.push(event);
-    }
+        info!(
+            request_id = %request_id,
+            model = %model,
+            mirror_latency_ms = latency_ms,
+            primary_input_tokens = primary_usage.input_tokens,
+            mirror_input_tokens = mirror.usage.input_tokens,
+            primary_output_tokens = primary_usage.output_tokens,
+            mirror_output_tokens = mirror.usage.output_tokens,
+            primary_chars = primary_text.chars().count(),
+            mirror_chars = mirror_text.chars().count(),
+            similarity = %format!("{:.2}", text_similarity(&primary_text, &mirror_text)),
+            "Mirror comparison"
+        );
+    });
+}
+
+/// Concatenated text blocks of a response.
+fn response_text(response: &crate::format::MessagesResponse) -> String {
+    response
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            crate::format::anthropic::ContentBlock::Text { text, .. } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-    Ok((events, body_bytes))
+/// Word-set Jaccard similarity in `0.0..=1.0` (1.0 when both are empty).
+fn text_similarity(a: &str, b: &str) -> f64 {
+    use std::collections::HashSet;
+
+    let words =
+        |s: &str| -> HashSet<String> { s.split_whitespace().map(|w| w.to_lowercase()).collect() };
+    let (a, b) = (words(a), words(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
 }
 
-/// Check SSE events for API errors and return an error if one is found.
-fn check_stream_errors(
+/// Build a non-streaming response from collected stream events, failing with
+/// a 502 instead of returning a partial response when a block can't be
+/// assembled (e.g. truncated tool input JSON).
+fn assemble_streamed_response(
     events: &[StreamEvent],
     model: &str,
     request_id: &str,
-    context: &str,
-) -> Result<(), Error> {
-    let api_error = events.iter().find_map(|e| {
-        if let StreamEvent::Error { error } = e {
-            Some(error.message.clone())
-        } else {
-            None
-        }
-    });
+) -> Result<crate::format::MessagesResponse, Error> {
+    debug_span!("convert_response")
+        .in_scope(|| crate::format::build_response_from_events(events, model, request_id))
+        .map_err(|message| {
+            warn!(
+                model = %model,
+                request_id = %request_id,
+                error = %message,
+                "Failed to assemble response from stream events"
+            );
+            Error::Api(ApiError::ServerError {
+                status: 502,
+                message: format!("Model {} returned a malformed response: {}", model, message),
+            })
+        })
+}
 
-    if let Some(error_message) = api_error {
+/// Whether an upstream event shows the model actually responded.
+///
+/// Any content block counts, including tool-only and thinking-only replies.
+/// So does a started message with no blocks at all, which is a deliberately
+/// empty completion. The parser's closing `message_delta` is emitted even
+/// when upstream sent nothing, so it does not count.
+fn is_response_content(event: &StreamEvent) -> bool {
+    matches!(
+        event,
+        StreamEvent::MessageStart { .. }
+            | StreamEvent::ContentBlockStart { .. }
+            | StreamEvent::ContentBlockDelta { .. }
+    )
+}
+
+// Thinking models must use streaming endpoint (doesn't rate-limit) but client may want non-streaming
+async fn handle_thinking_non_streaming_messages(
+    state: &Arc<ServerState>,
+    body: Bytes,
+    access_token: &str,
+    model: &str,
+    request_id: &str,
+    usage_sink: UsageSink,
+) -> Result<Response<ResponseBody>, Error> {
+    let (events, body_bytes) =
+        collect_sse_events(&state.cloudcode_client, body.clone(), access_token, model).await?;
+
+    // Log raw response for debugging empty/error responses
+    if body_bytes.len() < 2000 {
+        let body_str = String::from_utf8_lossy(&body_bytes);
+        debug!(
+            model = %model,
+            request_id = %request_id,
+            body_len = body_bytes.len(),
+            body = %body_str,
+            "Raw SSE response from Google (thinking non-streaming)"
+        );
+    } else {
+        debug!(
+            model = %model,
+            request_id = %request_id,
+            body_len = body_bytes.len(),
+            "Raw SSE response from Google (thinking non-streaming, truncated)"
+        );
+    }
+
+    check_stream_errors(&events, model, request_id, " (thinking non-streaming)")?;
+
+    // Only a response with no message at all is an upstream failure; an
+    // empty or tool-only completion is returned as is
+    let has_content = events.iter().any(is_response_content);
+
+    if !has_content && !body_bytes.is_empty() {
+        let body_str = String::from_utf8_lossy(&body_bytes);
         warn!(
             model = %model,
             request_id = %request_id,
-            error = %error_message,
-            "Google API returned error in SSE stream{context}"
+            body_len = body_bytes.len(),
+            "Empty response from Google API (thinking non-streaming) - model may be unavailable. Raw body: {}",
+            body_str.chars().take(500).collect::<String>()
         );
+
+        // Return an error instead of an empty response
         return Err(Error::Api(ApiError::ServerError {
             status: 502,
-            message: error_message,
+            message: format!(
+                "Model {} returned empty response from Google API. The model may be unavailable. Raw: {}",
+                model,
+                body_str.chars().take(200).collect::<String>()
+            ),
         }));
     }
 
-    Ok(())
-}
+    let anthropic_response = assemble_streamed_response(&events, model, request_id)?;
+    usage_sink.record(model, &anthropic_response.usage);
+    spawn_mirror_comparison(state, body, model, request_id, &anthropic_response);
 
-fn json_response(status: StatusCode, body: &str) -> Response<ResponseBody> {
-    Response::builder()
-        .status(status)
-        .header("Content-Type", "application/json")
-        .header("Access-Control-Allow-Origin", "*")
-        .header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
-        .header(
-            "Access-Control-Allow-Headers",
-            "Content-Type, Authorization, X-API-Key, X-No-Cache, Cache-Control",
-        )
-        .body(full_body(Full::new(Bytes::from(body.to_string()))))
-        .unwrap()
+    log_if_enabled(request_id, "Anthropic response", &anthropic_response);
+
+    let response_body = serde_json::to_vec(&anthropic_response)?;
+    Ok(json_ok_response(response_body, request_id, Some("BYPASS")))
 }
 
-/// CORS preflight response.
-fn cors_preflight_response() -> Response<ResponseBody> {
-    Response::builder()
-        .status(StatusCode::NO_CONTENT)
-        .header("Access-Control-Allow-Origin", "*")
-        .header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
-        .header(
-            "Access-Control-Allow-Headers",
-            "Content-Type, Authorization, X-API-Key, X-No-Cache, Cache-Control",
-        )
-        .header("Access-Control-Max-Age", "86400")
-        .body(full_body(Full::new(Bytes::new())))
-        .unwrap()
+/// Estimates output tokens from streamed deltas and emits a running-usage
+/// `message_delta` every `interval` tokens (`[server] stream_usage_interval`).
+///
+/// The events carry no stop reason; clients that only read the final
+/// `message_delta` are unaffected.
+struct UsageMeter {
+    interval: u32,
+    chars: usize,
+    reported: u32,
 }
 
-/// Log a serializable value as pretty-printed JSON if request logging is enabled.
-fn log_if_enabled<T: serde::Serialize>(request_id: &str, label: &str, value: &T) {
-    if get_config().logging.log_requests
-        && let Ok(json) = serde_json::to_string_pretty(value)
-    {
-        info!(request_id = %request_id, "{}:\n{}", label, json);
+impl UsageMeter {
+    fn new(interval: u32) -> Option<Self> {
+        (interval > 0).then_some(Self {
+            interval,
+            chars: 0,
+            reported: 0,
+        })
     }
-}
 
-/// Build a JSON OK response with request tracking headers.
-fn json_ok_response(
-    body: impl Into<Bytes>,
-    request_id: &str,
-    cache: Option<&str>,
-) -> Response<ResponseBody> {
-    let mut builder = Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .header("X-Request-Id", request_id)
-        .header("Access-Control-Allow-Origin", "*");
+    /// Count a streamed event, returning a usage event once another
+    /// `interval` tokens have gone by.
+    fn observe(&mut self, event: &StreamEvent) -> Option<StreamEvent> {
+        let StreamEvent::ContentBlockDelta { delta, .. } = event else {
+            return None;
+        };
+        self.chars += match delta {
+            crate::format::ContentDelta::Text { text } => text.len(),
+            crate::format::ContentDelta::Thinking { thinking } => thinking.len(),
+            crate::format::ContentDelta::InputJson { partial_json } => partial_json.len(),
+            crate::format::ContentDelta::Signature { .. } => 0,
+        };
 
-    if let Some(cache_status) = cache {
-        builder = builder.header("X-Cache", cache_status);
+        // Same chars/4 estimate as count_tokens
+        let tokens = (self.chars / 4) as u32;
+        if tokens < self.reported + self.interval {
+            return None;
+        }
+        self.reported = tokens;
+        Some(StreamEvent::MessageDelta {
+            delta: crate::format::MessageDeltaData {
+                stop_reason: None,
+                stop_sequence: None,
+            },
+            usage: crate::format::MessageDeltaUsage {
+                output_tokens: tokens,
+            },
+        })
     }
+}
 
-    builder.body(full_body(Full::new(body.into()))).unwrap()
+/// Drops thinking blocks from an Anthropic event stream for
+/// `[server] strip_thinking`, renumbering the blocks after them so indices
+/// stay contiguous. Signatures were already cached by the parser.
+struct ThinkingStripper {
+    enabled: bool,
+    /// Index of the thinking block currently being dropped
+    skipping: Option<u32>,
+    /// Thinking blocks dropped so far
+    dropped: u32,
 }
 
-/// Build a true SSE streaming response backed by a channel body.
-fn sse_streaming_response(body: ResponseBody, request_id: &str) -> Response<ResponseBody> {
-    Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "text/event-stream")
-        .header("Cache-Control", "no-cache")
-        .header("Connection", "keep-alive")
-        .header("X-Request-Id", request_id)
-        .header("X-Cache", "BYPASS")
-        .header("Access-Control-Allow-Origin", "*")
-        .body(body)
-        .unwrap()
+impl ThinkingStripper {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            skipping: None,
+            dropped: 0,
+        }
+    }
+
+    /// The event to send in place of `event`, if any.
+    fn apply(&mut self, event: StreamEvent) -> Option<StreamEvent> {
+        if !self.enabled {
+            return Some(event);
+        }
+        match event {
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block: crate::format::ContentBlock::Thinking { .. },
+            } => {
+                self.skipping = Some(index);
+                None
+            }
+            StreamEvent::ContentBlockDelta { index, .. } if self.skipping == Some(index) => None,
+            StreamEvent::ContentBlockStop { index } if self.skipping == Some(index) => {
+                self.skipping = None;
+                self.dropped += 1;
+                None
+            }
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => Some(StreamEvent::ContentBlockStart {
+                index: index.saturating_sub(self.dropped),
+                content_block,
+            }),
+            StreamEvent::ContentBlockDelta { index, delta } => {
+                Some(StreamEvent::ContentBlockDelta {
+                    index: index.saturating_sub(self.dropped),
+                    delta,
+                })
+            }
+            StreamEvent::ContentBlockStop { index } => Some(StreamEvent::ContentBlockStop {
+                index: index.saturating_sub(self.dropped),
+            }),
+            other => Some(other),
+        }
+    }
 }
 
-/// Build a buffered SSE response with standard headers (used for non-true-streaming paths).
-#[allow(dead_code)]
-fn sse_ok_response(body: String, request_id: &str) -> Response<ResponseBody> {
-    Response::builder()
+/// Per-request stream handling for [`handle_streaming_messages`].
+struct StreamOptions {
+    /// Ends the stream when the client cancels the request
+    cancellation: StreamCancellation,
+    /// Drop thinking blocks (`[server] strip_thinking`)
+    strip_thinking: bool,
+}
+
+/// Handle Anthropic streaming messages with true SSE pass-through.
+///
+/// Returns the response immediately with a channel-backed body.  A background
+/// task reads chunks from the upstream Google response, parses them with
+/// `SseParser`, and forwards each Anthropic-format SSE event through the
+/// channel as it arrives.
+///
+/// `model` is the model actually serving the request (the fallback model when
+/// fallback kicked in), so `message_start` reports the model that answered.
+async fn handle_streaming_messages(
+    client: &CloudCodeClient,
+    attempt: UpstreamAttempt,
+    model: &str,
+    options: StreamOptions,
+) -> Result<Response<ResponseBody>, Error> {
+    let UpstreamAttempt {
+        access_token,
+        request_id,
+        body,
+        usage_sink,
+    } = attempt;
+    let StreamOptions {
+        mut cancellation,
+        strip_thinking,
+    } = options;
+    let request_id = request_id.as_str();
+    let upstream = client
+        .send_streaming_request(body, &access_token, model)
+        .await?;
+
+    let (tx, body) = streaming_body();
+
+    let model = model.to_string();
+    let request_id_owned = request_id.to_string();
+
+    // Return the SSE response immediately; the background task will feed data.
+    let response = sse_streaming_response(body, request_id);
+
+    let request_id = request_id_owned;
+    let mut usage_meter = get_config()
+        .server
+        .stream_usage_interval
+        .and_then(UsageMeter::new);
+    let mut stripper = ThinkingStripper::new(strip_thinking);
+    tokio::spawn(async move {
+        let mut parser = SseParser::new(&model);
+        let mut input_tokens = 0u32;
+        let mut output_tokens = 0u32;
+        let mut cache_read_tokens = 0u32;
+        let mut cache_creation_tokens = 0u32;
+        let mut has_content = false;
+        let mut body_len = 0usize;
+
+        let mut incoming = upstream.into_body();
+
+        // Read chunks from upstream as they arrive.
+        loop {
+            use http_body_util::BodyExt;
+            let frame_timeout = Duration::from_secs(STREAM_FRAME_TIMEOUT_SECS);
+            let frame = tokio::select! {
+                frame = tokio::time::timeout(frame_timeout, incoming.frame()) => frame,
+                () = cancellation.cancelled() => {
+                    info!(
+                        model = %model,
+                        request_id = %request_id,
+                        "Stream cancelled by client"
+                    );
+                    break;
+                }
+            };
+            match frame {
+                Ok(Some(Ok(frame))) => {
+                    if let Ok(data) = frame.into_data() {
+                        body_len += data.len();
+                        for event in parser.feed_bytes(&data) {
+                            has_content |= is_response_content(&event);
+                            // Track tokens
+                            match &event {
+                                StreamEvent::MessageStart { message } => {
+                                    input_tokens = message.usage.input_tokens;
+                                    cache_read_tokens =
+                                        message.usage.cache_read_input_tokens.unwrap_or(0);
+                                    cache_creation_tokens =
+                                        message.usage.cache_creation_input_tokens.unwrap_or(0);
+                                }
+                                StreamEvent::MessageDelta { usage, .. } => {
+                                    output_tokens = usage.output_tokens;
+                                }
+                                StreamEvent::Error { error } => {
+                                    warn!(
+                                        model = %model,
+                                        request_id = %request_id,
+                                        error = %error.message,
+                                        "Google API error in SSE stream"
+                                    );
+                                }
+                                _ => {}
+                            }
+
+                            let Some(event) = stripper.apply(event) else {
+                                continue;
+                            };
+                            let formatted = format_sse_event(&event);
+                            if tx.send(Bytes::from(formatted)).await.is_err() {
+                                // Client disconnected
+                                return;
+                            }
+
+                            if let Some(usage) =
+                                usage_meter.as_mut().and_then(|meter| meter.observe(&event))
+                                && tx
+                                    .send(Bytes::from(format_sse_event(&usage)))
+                                    .await
+                                    .is_err()
+                            {
+                                return;
+                            }
+                        }
+                    }
+                }
+                Ok(Some(Err(e))) => {
+                    warn!(
+                        model = %model,
+                        request_id = %request_id,
+                        error = %e,
+                        "Error reading upstream SSE stream"
+                    );
+                    break;
+                }
+                Ok(None) => break, // End of upstream stream
+                Err(_) => {
+                    warn!(
+                        model = %model,
+                        request_id = %request_id,
+                        "Upstream frame timeout in Anthropic streaming"
+                    );
+                    break;
+                }
+            }
+        }
+
+        // Flush any remaining events from the parser.
+        for event in parser.finish() {
+            has_content |= is_response_content(&event);
+            match &event {
+                StreamEvent::MessageStart { message } => {
+                    input_tokens = message.usage.input_tokens;
+                    cache_read_tokens = message.usage.cache_read_input_tokens.unwrap_or(0);
+                    cache_creation_tokens = message.usage.cache_creation_input_tokens.unwrap_or(0);
+                }
+                StreamEvent::MessageDelta { usage, .. } => {
+                    output_tokens = usage.output_tokens;
+                }
+                _ => {}
+            }
+            let Some(event) = stripper.apply(event) else {
+                continue;
+            };
+            let formatted = format_sse_event(&event);
+            let _ = tx.send(Bytes::from(formatted)).await;
+        }
+
+        // Send final message_stop event.
+        let stop_event = format_sse_event(&create_message_stop());
+        let _ = tx.send(Bytes::from(stop_event)).await;
+
+        // Record token usage.
+        usage_sink.record_tokens(
+            &model,
+            input_tokens,
+            output_tokens,
+            cache_read_tokens,
+            cache_creation_tokens,
+        );
+
+        if !has_content && body_len > 0 {
+            warn!(
+                model = %model,
+                request_id = %request_id,
+                body_len = body_len,
+                "Empty response from Google API (streaming) - model may be unavailable"
+            );
+        }
+    });
+
+    Ok(response)
+}
+
+async fn handle_models() -> Result<Response<ResponseBody>, Error> {
+    let config = get_config();
+    let mut models: Vec<ModelInfo> = Model::all()
+        .iter()
+        .filter(|m| !is_model_disabled(m.anthropic_id(), &config.models.disabled))
+        .map(|m| ModelInfo {
+            id: m.anthropic_id().to_string(),
+            model_type: "model".to_string(),
+            display_name: m.anthropic_id().to_string(),
+            created_at: "2025-01-01T00:00:00Z".to_string(),
+        })
+        .collect();
+
+    // Configured aliases are requestable names too
+    for (alias, target) in &config.mappings.aliases {
+        let resolved = crate::models::resolve_model_alias(target);
+        if is_model_disabled(resolved, &config.models.disabled) {
+            continue;
+        }
+        models.push(ModelInfo {
+            id: alias.clone(),
+            model_type: "model".to_string(),
+            display_name: format!("{} ({})", alias, resolved),
+            created_at: "2025-01-01T00:00:00Z".to_string(),
+        });
+    }
+
+    let response = ModelsResponse { data: models };
+    let body = serde_json::to_vec(&response)?;
+
+    Ok(Response::builder()
         .status(StatusCode::OK)
-        .header("Content-Type", "text/event-stream")
-        .header("Cache-Control", "no-cache")
-        .header("Connection", "keep-alive")
-        .header("X-Request-Id", request_id)
-        .header("X-Cache", "BYPASS")
+        .header("Content-Type", "application/json")
         .body(full_body(Full::new(Bytes::from(body))))
-        .unwrap()
+        .unwrap())
 }
 
-fn error_to_response(error: &Error, request_id: &str) -> Response<ResponseBody> {
-    let (status, error_type, message) = match error {
-        Error::Auth(AuthError::TokenExpired) => (
-            StatusCode::UNAUTHORIZED,
-            "authentication_error",
-            "Token expired".to_string(),
-        ),
-        Error::Auth(e) => (
-            StatusCode::UNAUTHORIZED,
-            "authentication_error",
-            e.to_string(),
-        ),
-        Error::Api(ApiError::RateLimited { retry_after }) => (
-            StatusCode::TOO_MANY_REQUESTS,
-            "rate_limit_error",
-            format!("Rate limited. Retry after {:?}", retry_after),
-        ),
-        Error::Api(ApiError::QuotaExhausted { model, reset_time }) => (
-            StatusCode::TOO_MANY_REQUESTS,
-            "invalid_request_error",
-            format!(
-                "You have exhausted your capacity on {model}. Quota will reset after {reset_time}."
-            ),
-        ),
-        Error::Api(ApiError::InvalidRequest { message }) => (
-            StatusCode::BAD_REQUEST,
-            "invalid_request_error",
-            message.clone(),
-        ),
-        Error::Api(ApiError::ServerError { status, message }) => (
-            StatusCode::from_u16(*status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
-            "api_error",
-            message.clone(),
-        ),
-        Error::Api(ApiError::CapacityExhausted) => (
-            StatusCode::SERVICE_UNAVAILABLE,
-            "overloaded_error",
-            "Model capacity exhausted".to_string(),
-        ),
-        Error::Api(ApiError::RequestTooLarge { size, max }) => (
-            StatusCode::PAYLOAD_TOO_LARGE,
-            "invalid_request_error",
-            format!(
-                "Request body too large: {} bytes (max: {} bytes)",
-                size, max
-            ),
-        ),
-        Error::Io(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "api_error",
-            e.to_string(),
-        ),
-        Error::Json(e) => (
-            StatusCode::BAD_REQUEST,
-            "invalid_request_error",
-            e.to_string(),
-        ),
-        Error::Http(msg) => (StatusCode::BAD_GATEWAY, "api_error", msg.clone()),
-        Error::Timeout(d) => (
-            StatusCode::GATEWAY_TIMEOUT,
-            "timeout_error",
-            format!("Request timed out after {:?}", d),
-        ),
+/// Estimate token count for a messages request.
+///
+/// Uses a chars/4 heuristic which is a reasonable approximation for most
+/// tokenizers (GPT, Claude, Gemini all average ~3.5-4.5 chars per token
+/// for English text). This avoids requiring a full tokenizer dependency.
+async fn handle_count_tokens(
+    mut req: Request<hyper::body::Incoming>,
+) -> Result<Response<ResponseBody>, Error> {
+    let body_bytes = match req.extensions_mut().remove() {
+        Some(BufferedBody(bytes)) => bytes,
+        None => read_body_limited(req.into_body(), MAX_REQUEST_SIZE).await?,
+    };
+    get_stats().record_request_size(body_bytes.len());
+
+    #[derive(serde::Deserialize)]
+    struct CountTokensRequest {
+        messages: Vec<crate::format::anthropic::Message>,
+        #[serde(default)]
+        system: Option<crate::format::anthropic::SystemPrompt>,
+        #[serde(default)]
+        tools: Option<Vec<crate::format::anthropic::Tool>>,
+    }
+
+    let request: CountTokensRequest = serde_json::from_slice(&body_bytes)?;
+
+    let mut total_chars: usize = 0;
+
+    // Count system prompt chars
+    if let Some(system) = &request.system {
+        match system {
+            crate::format::anthropic::SystemPrompt::Text(text) => {
+                total_chars += text.len();
+            }
+            crate::format::anthropic::SystemPrompt::Blocks(blocks) => {
+                for block in blocks {
+                    total_chars += count_block_chars(block);
+                }
+            }
+        }
+    }
+
+    // Count message chars
+    for msg in &request.messages {
+        match &msg.content {
+            crate::format::anthropic::MessageContent::Text(text) => {
+                total_chars += text.len();
+            }
+            crate::format::anthropic::MessageContent::Blocks(blocks) => {
+                for block in blocks {
+                    total_chars += count_block_chars(block);
+                }
+            }
+        }
+    }
+
+    // Count tool definitions
+    if let Some(tools) = &request.tools {
+        for tool in tools {
+            total_chars += tool.name.len();
+            if let Some(desc) = &tool.description {
+                total_chars += desc.len();
+            }
+            total_chars += tool.input_schema.to_string().len();
+        }
+    }
+
+    // Estimate: ~4 chars per token, with a minimum of 1
+    let input_tokens = (total_chars / 4).max(1) as u32;
+
+    let response = serde_json::json!({
+        "input_tokens": input_tokens,
+    });
+
+    let response_body = serde_json::to_vec(&response)?;
+    Ok(json_ok_response(response_body, "count_tokens", None))
+}
+
+/// Count approximate character length of a content block.
+fn count_block_chars(block: &crate::format::ContentBlock) -> usize {
+    match block {
+        crate::format::ContentBlock::Text { text, .. } => text.len(),
+        crate::format::ContentBlock::Image { .. } => 256, // Images counted as ~64 tokens
+        crate::format::ContentBlock::Document { .. } => 1024, // PDFs counted as ~256 tokens
+        crate::format::ContentBlock::ToolUse { name, input, .. } => {
+            name.len() + input.to_string().len()
+        }
+        crate::format::ContentBlock::ToolResult { content, .. } => match content {
+            crate::format::anthropic::ToolResultContent::Text(text) => text.len(),
+            crate::format::anthropic::ToolResultContent::Blocks(blocks) => {
+                blocks.iter().map(count_block_chars).sum()
+            }
+        },
+        crate::format::ContentBlock::Thinking { thinking, .. } => thinking.len(),
+    }
+}
+
+/// Operational account state for `/stats`: selection strategy, the active
+/// account, and how many accounts are enabled or currently rate-limited.
+fn accounts_snapshot(store: &AccountStore) -> serde_json::Value {
+    let enabled: Vec<_> = store
+        .accounts
+        .iter()
+        .filter(|a| a.enabled && !a.is_invalid)
+        .collect();
+    let active = store
+        .active_account_id
+        .as_ref()
+        .and_then(|id| store.accounts.iter().find(|a| &a.id == id))
+        .map(|a| serde_json::json!({ "id": a.id, "email": a.email }));
+
+    serde_json::json!({
+        "strategy": store.strategy,
+        "active": active,
+        "total": store.accounts.len(),
+        "enabled": enabled.len(),
+        "rate_limited": enabled.iter().filter(|a| a.is_rate_limited_any()).count(),
+    })
+}
+
+/// Per-account view of the in-memory store the server is routing on.
+///
+/// Unlike `agcp accounts list`, which reads `accounts.json` from disk, this
+/// reflects health scores, token buckets and rate limits as they are now.
+fn debug_accounts_snapshot(store: &AccountStore, now: u64) -> serde_json::Value {
+    let accounts: Vec<_> = store
+        .accounts
+        .iter()
+        .map(|a| {
+            let rate_limits: serde_json::Map<_, _> = a
+                .rate_limits
+                .iter()
+                .filter(|(_, limit)| limit.until > now)
+                .map(|(model, limit)| {
+                    (
+                        model.clone(),
+                        serde_json::json!({
+                            "until": limit.until,
+                            "remaining_secs": limit.until - now,
+                        }),
+                    )
+                })
+                .collect();
+            let quota: serde_json::Map<_, _> = a
+                .quota
+                .iter()
+                .map(|(model, q)| {
+                    (
+                        model.clone(),
+                        serde_json::json!({
+                            "remaining_fraction": q.remaining_fraction,
+                            "reset_time": q.reset_time,
+                        }),
+                    )
+                })
+                .collect();
+            serde_json::json!({
+                "id": a.id.chars().take(8).collect::<String>(),
+                "email": a.email,
+                "active": store.active_account_id.as_deref() == Some(a.id.as_str()),
+                "enabled": a.enabled,
+                "invalid": a.is_invalid,
+                "invalid_reason": a.invalid_reason,
+                "health_score": a.health_score,
+                "tokens_available": a.tokens_available,
+                "last_used": a.last_used,
+                "subscription_tier": a.subscription_tier,
+                "project_id": a.effective_project_id(),
+                "rate_limits": rate_limits,
+                "quota": quota,
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "strategy": store.strategy,
+        "now": now,
+        "accounts": accounts,
+    })
+}
+
+async fn handle_debug_accounts(state: &Arc<ServerState>) -> Result<Response<ResponseBody>, Error> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let snapshot = debug_accounts_snapshot(&*state.accounts.read().await, now);
+    Ok(json_response(StatusCode::OK, &snapshot.to_string()))
+}
+
+/// Extract `{id}` from `/v1/messages/{id}/cancel`.
+fn cancel_request_id(path: &str) -> Option<&str> {
+    path.strip_prefix("/v1/messages/")?
+        .strip_suffix("/cancel")
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+}
+
+fn handle_cancel_stream(
+    state: &Arc<ServerState>,
+    path: &str,
+) -> Result<Response<ResponseBody>, Error> {
+    let request_id = cancel_request_id(path).unwrap_or_default();
+    if !state.active_streams.cancel(request_id) {
+        return Ok(json_response(
+            StatusCode::NOT_FOUND,
+            &serde_json::json!({
+                "type": "error",
+                "error": {
+                    "type": "not_found_error",
+                    "message": format!("No streaming request in progress with id '{}'", request_id),
+                },
+            })
+            .to_string(),
+        ));
+    }
+
+    Ok(json_response(
+        StatusCode::OK,
+        &serde_json::json!({ "status": "cancelled", "request_id": request_id }).to_string(),
+    ))
+}
+
+/// The `[server] enabled_endpoints` group a path belongs to. Health, stats
+/// and other internal endpoints have none and are always served.
+fn endpoint_group(path: &str) -> Option<&'static str> {
+    match path {
+        "/v1/messages" | "/messages" => Some("messages"),
+        p if p.starts_with("/v1/messages/") => Some("messages"),
+        "/v1/chat/completions" => Some("chat_completions"),
+        "/v1/completions" => Some("completions"),
+        "/v1/responses" => Some("responses"),
+        "/v1/models" => Some("models"),
+        _ => None,
+    }
+}
+
+/// Build metadata for `GET /version`, captured by build.rs.
+fn version_info() -> serde_json::Value {
+    let build_time = env!("AGCP_BUILD_TIME")
+        .parse::<i64>()
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|t| t.to_rfc3339());
+
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": env!("AGCP_GIT_SHA"),
+        "build_time": build_time,
+        "rustc": env!("AGCP_RUSTC_VERSION"),
+    })
+}
+
+async fn handle_stats(state: &Arc<ServerState>) -> Result<Response<ResponseBody>, Error> {
+    let stats = get_stats().summary();
+    let cache_stats = state.cache.lock().await.stats();
+    let accounts = accounts_snapshot(&*state.accounts.read().await);
+
+    let response = serde_json::json!({
+        "requests": stats.to_json(),
+        "cache": cache_stats,
+        "accounts": accounts,
+        "in_flight": {
+            "accounts": state.account_load.snapshot(),
+            "max_concurrent_requests": get_config().cloudcode.max_concurrent_requests,
+        },
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(full_body(Full::new(Bytes::from(response.to_string()))))
+        .unwrap())
+}
+
+async fn handle_account_limits(state: &Arc<ServerState>) -> Result<Response<ResponseBody>, Error> {
+    let ttl = Duration::from_secs(get_config().server.account_limits_ttl_secs);
+    let (response, cache_status) = match state.account_limits.get(ttl, std::time::Instant::now()) {
+        Some((payload, false)) => (payload, "HIT"),
+        Some((payload, true)) => {
+            let state = state.clone();
+            tokio::spawn(async move {
+                // Released on drop, so a failed refresh doesn't block the next
+                if let Ok(_fetching) = state.account_limits.fetching.claim(ACCOUNT_LIMITS_FETCH) {
+                    refresh_account_limits(&state).await;
+                }
+            });
+            (payload, "STALE")
+        }
+        None if ttl.is_zero() => (refresh_account_limits(state).await, "MISS"),
+        None => loop {
+            match state.account_limits.fetching.claim(ACCOUNT_LIMITS_FETCH) {
+                Ok(_fetching) => break (refresh_account_limits(state).await, "MISS"),
+                Err(mut done) => {
+                    let _ = done.changed().await;
+                    // Reuse what the other fetch stored; if it failed, fetch here
+                    if let Some((payload, _)) =
+                        state.account_limits.get(ttl, std::time::Instant::now())
+                    {
+                        break (payload, "HIT");
+                    }
+                }
+            }
+        },
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("X-Cache", cache_status)
+        .body(full_body(Full::new(Bytes::from(response.to_string()))))
+        .unwrap())
+}
+
+/// Fetch quotas for `/account-limits`, saving them to the account and, when
+/// the fetch succeeded, to the [`AccountLimitsCache`].
+async fn refresh_account_limits(state: &Arc<ServerState>) -> serde_json::Value {
+    // Get credentials using the existing pattern
+    let credentials = get_account_credentials(state, "claude-sonnet-4-5").await;
+
+    let response = match credentials {
+        Ok((access_token, project_id, account_id, _account_email)) => {
+            match fetch_model_quotas(&state.http_client, &access_token, Some(&project_id)).await {
+                Ok(quotas) => {
+                    // Save quota data to the account for TUI display
+                    {
+                        let mut accounts = state.accounts.write().await;
+                        if let Some(account) = accounts.get_account_mut(&account_id) {
+                            for q in &quotas {
+                                // Parse ISO timestamp to Unix timestamp
+                                let reset_time = q
+                                    .reset_time
+                                    .as_ref()
+                                    .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
+                                    .map(|dt| dt.timestamp() as u64)
+                                    .unwrap_or(0);
+
+                                let previous = account
+                                    .quota
+                                    .get(&q.model_id)
+                                    .map(|quota| quota.remaining_fraction);
+                                crate::notify::quota_updated(
+                                    &account.email,
+                                    &q.model_id,
+                                    previous,
+                                    q.remaining_fraction,
+                                );
+
+                                account.quota.insert(
+                                    q.model_id.clone(),
+                                    crate::auth::accounts::ModelQuota {
+                                        remaining_fraction: q.remaining_fraction,
+                                        reset_time,
+                                    },
+                                );
+                            }
+                            // Save to disk
+                            if !state.accounts_read_only.load(Ordering::Relaxed) {
+                                match accounts.save() {
+                                    Ok(()) => {}
+                                    Err(Error::Io(e)) => {
+                                        note_account_save_error(state, &AccountStore::path(), &e)
+                                    }
+                                    Err(e) => warn!(error = %e, "Failed to save quota data"),
+                                }
+                            }
+                        }
+                    }
+
+                    let models: Vec<serde_json::Value> = quotas
+                        .iter()
+                        .map(|q| {
+                            serde_json::json!({
+                                "model": q.model_id,
+                                "remaining_fraction": q.remaining_fraction,
+                                "reset_time": q.reset_time,
+                            })
+                        })
+                        .collect();
+
+                    serde_json::json!({
+                        "status": "ok",
+                        "quotas": models,
+                    })
+                }
+                Err(e) => {
+                    serde_json::json!({
+                        "status": "error",
+                        "message": e,
+                    })
+                }
+            }
+        }
+        Err(e) => {
+            serde_json::json!({
+                "status": "error",
+                "message": e.to_string(),
+            })
+        }
     };
 
-    // Add suggestion if available
-    let message_with_suggestion = if let Some(suggestion) = error.suggestion() {
-        format!("{}. {}", message, suggestion)
-    } else {
-        message
-    };
+    if response["status"] == "ok" {
+        state
+            .account_limits
+            .store(response.clone(), std::time::Instant::now());
+    }
+    response
+}
+
+async fn handle_logs_stream() -> Result<Response<ResponseBody>, Error> {
+    // Get the log file path
+    let config_dir = std::env::var_os("HOME")
+        .map(|h| std::path::PathBuf::from(h).join(".config").join("agcp"))
+        .unwrap_or_else(|| std::path::PathBuf::from("."));
+    let log_path = config_dir.join("agcp.log");
+
+    // Read the last 100 lines from the log file efficiently (seek from end)
+    let lines = match std::fs::File::open(&log_path) {
+        Ok(mut file) => {
+            use std::io::{Read, Seek, SeekFrom};
+            let file_len = file.metadata().map(|m| m.len()).unwrap_or(0);
+            if file_len == 0 {
+                vec!["No log entries".to_string()]
+            } else {
+                const TAIL_COUNT: usize = 100;
+                const CHUNK_SIZE: u64 = 64 * 1024;
+                let mut collected: Vec<String> = Vec::new();
+                let mut remaining = file_len;
+
+                while remaining > 0 && collected.len() < TAIL_COUNT + 1 {
+                    let chunk = remaining.min(CHUNK_SIZE);
+                    let offset = remaining - chunk;
+                    let _ = file.seek(SeekFrom::Start(offset));
+                    let mut buf = vec![0u8; chunk as usize];
+                    if file.read_exact(&mut buf).is_err() {
+                        break;
+                    }
+                    let chunk_str = String::from_utf8_lossy(&buf);
+                    let mut chunk_lines: Vec<String> =
+                        chunk_str.lines().map(String::from).collect();
+                    if offset > 0 && !chunk_lines.is_empty() {
+                        let partial = chunk_lines.remove(0);
+                        if let Some(last) = collected.last_mut() {
+                            *last = format!("{}{}", partial, last);
+                        }
+                    }
+                    chunk_lines.append(&mut collected);
+                    collected = chunk_lines;
+                    remaining = offset;
+                }
+
+                let start = collected.len().saturating_sub(TAIL_COUNT);
+                collected[start..].to_vec()
+            }
+        }
+        Err(_) => vec!["No log file available".to_string()],
+    };
+
+    // Format as SSE events
+    let mut body = String::new();
+    for line in lines {
+        // Strip ANSI codes for cleaner output
+        let clean_line = strip_ansi_codes(&line);
+        body.push_str(&format!("data: {}\n\n", clean_line));
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("Connection", "keep-alive")
+        .body(full_body(Full::new(Bytes::from(body))))
+        .unwrap())
+}
+
+/// Remove ANSI color escape sequences from a log line.
+pub fn strip_ansi_codes(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip escape sequence
+            if chars.peek() == Some(&'[') {
+                chars.next(); // consume '['
+                // Skip until we hit a letter (end of sequence)
+                while let Some(&next) = chars.peek() {
+                    chars.next();
+                    if next.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+/// Helper to create a test server state with default (empty) accounts and in-memory cache.
+#[cfg(test)]
+fn test_server_state() -> Arc<ServerState> {
+    use crate::auth::accounts::AccountStore;
+
+    Arc::new(ServerState {
+        accounts: RwLock::new(AccountStore::default()),
+        http_client: HttpClient::default(),
+        cloudcode_client: CloudCodeClient::default(),
+        cache: Mutex::new(ResponseCache::new(true, 300, 100)),
+        accounts_read_only: AtomicBool::new(false),
+        in_flight: InFlightRequests::default(),
+        account_load: AccountLoad::default(),
+        active_streams: ActiveStreams::default(),
+        api_activity: ApiActivity::default(),
+        idempotency: IdempotencyCache::default(),
+        client_limits: ClientRateLimiter::default(),
+        account_limits: AccountLimitsCache::default(),
+    })
+}
+
+/// Send a streaming request and collect all SSE events from the response body.
+///
+/// Returns `(events, body_bytes)` where `body_bytes` are the raw response bytes.
+/// Callers that need the body as a string for logging can convert lazily.
+///
+/// Buffering stops at `[cloudcode] max_collected_events` / `max_collected_bytes`;
+/// the response is then finished early with a `max_tokens` stop reason (see
+/// [`collect_sse_body`]).
+async fn collect_sse_events(
+    client: &CloudCodeClient,
+    body: Bytes,
+    access_token: &str,
+    model: &str,
+) -> Result<(Vec<StreamEvent>, Bytes), Error> {
+    let response = client
+        .send_streaming_request(body, access_token, model)
+        .await?;
+
+    let config = get_config();
+    collect_sse_body(
+        response.into_body(),
+        model,
+        config.cloudcode.max_collected_events,
+        config.cloudcode.max_collected_bytes,
+    )
+    .await
+}
+
+/// Parse an SSE body into events, keeping at most `max_events` upstream
+/// events and `max_bytes` of body. Past either limit the rest of the body is
+/// left unread and the events are closed off by
+/// [`SseParser::finish_truncated`].
+async fn collect_sse_body<B>(
+    mut incoming: B,
+    model: &str,
+    max_events: usize,
+    max_bytes: usize,
+) -> Result<(Vec<StreamEvent>, Bytes), Error>
+where
+    B: hyper::body::Body<Data = Bytes> + Unpin,
+    B::Error: std::fmt::Display,
+{
+    let mut parser = SseParser::new(model);
+    let mut body_bytes = Vec::new();
+    let mut events = Vec::new();
+
+    while let Some(frame) = incoming.frame().await {
+        let frame = frame.map_err(|e| Error::Http(e.to_string()))?;
+        let Ok(data) = frame.into_data() else {
+            continue;
+        };
+
+        // Cut the frame at the limits rather than checking after appending it
+        let byte_room = max_bytes.saturating_sub(body_bytes.len());
+        let mut truncated = data.len() > byte_room;
+        let data = &data[..data.len().min(byte_room)];
+        body_bytes.extend_from_slice(data);
+
+        let mut new_events = parser.feed_bytes(data);
+        let event_room = max_events.saturating_sub(events.len());
+        if new_events.len() > event_room {
+            new_events.truncate(event_room);
+            truncated = true;
+        }
+        events.extend(new_events);
+
+        if truncated {
+            warn!(
+                model = %model,
+                events = events.len(),
+                bytes = body_bytes.len(),
+                max_events = max_events,
+                max_bytes = max_bytes,
+                "Upstream response exceeded the collection limit, truncating"
+            );
+            events.extend(parser.finish_truncated());
+            return Ok((events, Bytes::from(body_bytes)));
+        }
+    }
+    events.extend(parser.finish());
+
+    Ok((events, Bytes::from(body_bytes)))
+}
+
+/// Check SSE events for API errors and return an error if one is found.
+fn check_stream_errors(
+    events: &[StreamEvent],
+    model: &str,
+    request_id: &str,
+    context: &str,
+) -> Result<(), Error> {
+    let api_error = events.iter().find_map(|e| {
+        if let StreamEvent::Error { error } = e {
+            Some(error.message.clone())
+        } else {
+            None
+        }
+    });
+
+    if let Some(error_message) = api_error {
+        warn!(
+            model = %model,
+            request_id = %request_id,
+            error = %error_message,
+            "Google API returned error in SSE stream{context}"
+        );
+        return Err(Error::Api(ApiError::ServerError {
+            status: 502,
+            message: error_message,
+        }));
+    }
+
+    Ok(())
+}
+
+fn json_response(status: StatusCode, body: &str) -> Response<ResponseBody> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
+        .header(
+            "Access-Control-Allow-Headers",
+            "Content-Type, Authorization, X-API-Key, X-No-Cache, X-AGCP-Cache-TTL, Cache-Control",
+        )
+        .body(full_body(Full::new(Bytes::from(body.to_string()))))
+        .unwrap()
+}
+
+/// CORS preflight response.
+fn cors_preflight_response() -> Response<ResponseBody> {
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
+        .header(
+            "Access-Control-Allow-Headers",
+            "Content-Type, Authorization, X-API-Key, X-No-Cache, X-AGCP-Cache-TTL, Cache-Control",
+        )
+        .header("Access-Control-Max-Age", "86400")
+        .body(full_body(Full::new(Bytes::new())))
+        .unwrap()
+}
+
+/// Log a serializable value as pretty-printed JSON if request logging is enabled,
+/// applying the `[logging]` redaction settings. With `trace_file` set the
+/// body goes there instead of the main log.
+fn log_if_enabled<T: serde::Serialize>(request_id: &str, label: &str, value: &T) {
+    let config = get_config();
+    let trace_path = config.logging.trace_path();
+    if !config.logging.log_requests && trace_path.is_none() {
+        return;
+    }
+
+    let redaction = crate::format::Redaction {
+        messages: config.logging.redact,
+        system: config.logging.redact_system,
+        tools: config.logging.redact_tools,
+    };
+    let Ok(mut json) = serde_json::to_value(value) else {
+        return;
+    };
+    if !redaction.is_empty() {
+        redaction.apply(&mut json);
+    }
+    let Ok(json) = serde_json::to_string_pretty(&json) else {
+        return;
+    };
+    match trace_path {
+        Some(path) => {
+            let entry = format!(
+                "{} request_id={} {}:\n{}\n\n",
+                chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                request_id,
+                label,
+                json
+            );
+            let max_bytes = config
+                .logging
+                .trace_file_max_bytes
+                .unwrap_or(DEFAULT_TRACE_FILE_MAX_BYTES);
+            queue_trace(path, max_bytes, entry);
+        }
+        None => info!(request_id = %request_id, "{}:\n{}", label, json),
+    }
+}
+
+/// Rotation size for `[logging] trace_file` when `trace_file_max_bytes` is unset.
+const DEFAULT_TRACE_FILE_MAX_BYTES: u64 = 50 * 1024 * 1024;
+
+/// The open `[logging] trace_file` and its current size.
+struct TraceFile {
+    path: PathBuf,
+    file: std::fs::File,
+    len: u64,
+}
+
+/// Sender to the thread that owns the trace file, so request handlers never
+/// block a runtime worker on file I/O.
+static TRACE_WRITER: std::sync::LazyLock<std::sync::mpsc::Sender<(PathBuf, u64, String)>> =
+    std::sync::LazyLock::new(|| {
+        let (tx, rx) = std::sync::mpsc::channel::<(PathBuf, u64, String)>();
+        std::thread::spawn(move || {
+            let mut trace = None;
+            for (path, max_bytes, entry) in rx {
+                write_trace(&mut trace, &path, max_bytes, &entry);
+            }
+        });
+        tx
+    });
+
+/// Hand `entry` to the trace writer thread.
+fn queue_trace(path: PathBuf, max_bytes: u64, entry: String) {
+    let _ = TRACE_WRITER.send((path, max_bytes, entry));
+}
+
+fn open_trace_file(path: &Path) -> Option<TraceFile> {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    match std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+    {
+        Ok(file) => Some(TraceFile {
+            path: path.to_path_buf(),
+            len: file.metadata().map(|m| m.len()).unwrap_or(0),
+            file,
+        }),
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "Failed to open trace file");
+            None
+        }
+    }
+}
+
+/// Append `entry` to the trace file, first rotating it to `<path>.old` if
+/// the entry would take it past `max_bytes`. `trace` is the open file,
+/// reopened when `path` changes.
+fn write_trace(trace: &mut Option<TraceFile>, path: &Path, max_bytes: u64, entry: &str) {
+    use std::io::Write;
+
+    if trace.as_ref().is_none_or(|t| t.path != path) {
+        *trace = open_trace_file(path);
+    }
+    let Some(current) = trace.as_ref() else {
+        return;
+    };
+
+    if current.len > 0 && current.len + entry.len() as u64 > max_bytes {
+        let mut old = path.as_os_str().to_owned();
+        old.push(".old");
+        *trace = None;
+        let _ = std::fs::rename(path, &old);
+        *trace = open_trace_file(path);
+    }
+
+    if let Some(current) = trace.as_mut()
+        && current.file.write_all(entry.as_bytes()).is_ok()
+    {
+        current.len += entry.len() as u64;
+    }
+}
+
+/// Build a JSON OK response with request tracking headers.
+fn json_ok_response(
+    body: impl Into<Bytes>,
+    request_id: &str,
+    cache: Option<&str>,
+) -> Response<ResponseBody> {
+    let mut builder = Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("X-Request-Id", request_id)
+        .header("Access-Control-Allow-Origin", "*");
+
+    if let Some(cache_status) = cache {
+        builder = builder.header("X-Cache", cache_status);
+    }
+
+    builder.body(full_body(Full::new(body.into()))).unwrap()
+}
+
+/// Build a true SSE streaming response backed by a channel body.
+fn sse_streaming_response(body: ResponseBody, request_id: &str) -> Response<ResponseBody> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("Connection", "keep-alive")
+        .header("X-Request-Id", request_id)
+        .header("X-Cache", "BYPASS")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(body)
+        .unwrap()
+}
+
+/// Build a buffered SSE response with standard headers (used for non-true-streaming paths).
+#[allow(dead_code)]
+fn sse_ok_response(body: String, request_id: &str) -> Response<ResponseBody> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("Connection", "keep-alive")
+        .header("X-Request-Id", request_id)
+        .header("X-Cache", "BYPASS")
+        .body(full_body(Full::new(Bytes::from(body))))
+        .unwrap()
+}
+
+/// `Retry-After` seconds for rate-limit errors: when the soonest account can
+/// serve the model again, else the upstream's own hint.
+async fn retry_after_secs(state: &ServerState, error: &Error) -> Option<u64> {
+    match error {
+        Error::Api(ApiError::QuotaExhausted { model, reset_time }) => {
+            let from_accounts = state.accounts.read().await.retry_after_secs(model);
+            from_accounts.or_else(|| {
+                let reset = chrono::DateTime::parse_from_rfc3339(reset_time).ok()?;
+                let secs = (reset.timestamp() - chrono::Utc::now().timestamp()).max(1);
+                Some(secs as u64)
+            })
+        }
+        Error::Api(ApiError::RateLimited { retry_after }) => {
+            Some((retry_after.as_secs_f64().ceil() as u64).max(1))
+        }
+        _ => None,
+    }
+}
+
+fn error_to_response(error: &Error, request_id: &str) -> Response<ResponseBody> {
+    let (status, error_type, message) = match error {
+        Error::Auth(AuthError::TokenExpired) => (
+            StatusCode::UNAUTHORIZED,
+            "authentication_error",
+            "Token expired".to_string(),
+        ),
+        Error::Auth(e) => (
+            StatusCode::UNAUTHORIZED,
+            "authentication_error",
+            e.to_string(),
+        ),
+        Error::Api(ApiError::RateLimited { retry_after }) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limit_error",
+            format!("Rate limited. Retry after {:?}", retry_after),
+        ),
+        Error::Api(ApiError::QuotaExhausted { model, reset_time }) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            "invalid_request_error",
+            format!(
+                "You have exhausted your capacity on {model}. Quota will reset after {reset_time}."
+            ),
+        ),
+        Error::Api(ApiError::InvalidRequest { message }) => (
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            message.clone(),
+        ),
+        Error::Api(ApiError::ServerError { status, message }) => (
+            StatusCode::from_u16(*status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            "api_error",
+            message.clone(),
+        ),
+        Error::Api(ApiError::CapacityExhausted) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "overloaded_error",
+            "Model capacity exhausted".to_string(),
+        ),
+        Error::Api(ApiError::RequestTooLarge { size, max }) => (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "invalid_request_error",
+            format!(
+                "Request body too large: {} bytes (max: {} bytes)",
+                size, max
+            ),
+        ),
+        Error::Io(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "api_error",
+            e.to_string(),
+        ),
+        Error::Json(e) => (
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            e.to_string(),
+        ),
+        Error::InvalidBody(msg) => (
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            msg.clone(),
+        ),
+        Error::Http(msg) => (StatusCode::BAD_GATEWAY, "api_error", msg.clone()),
+        Error::Timeout(d) => (
+            StatusCode::GATEWAY_TIMEOUT,
+            "timeout_error",
+            format!("Request timed out after {:?}", d),
+        ),
+    };
+
+    // Add suggestion if available
+    let message_with_suggestion = if let Some(suggestion) = error.suggestion() {
+        format!("{}. {}", message, suggestion)
+    } else {
+        message
+    };
+
+    let body = serde_json::json!({
+        "type": "error",
+        "error": {
+            "type": error_type,
+            "code": error.code(),
+            "message": message_with_suggestion
+        },
+        "request_id": request_id
+    })
+    .to_string();
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("X-Request-Id", request_id)
+        .body(full_body(Full::new(Bytes::from(body))))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Spin up the server on a random port and return the bound address.
+    async fn spawn_test_server() -> SocketAddr {
+        spawn_test_server_with_state(test_server_state()).await
+    }
+
+    /// Like [`spawn_test_server`], but serving the given state.
+    async fn spawn_test_server_with_state(state: Arc<ServerState>) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                if let Ok((stream, remote_addr)) = listener.accept().await {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        let _ = handle_connection(stream, remote_addr, state).await;
+                    });
+                }
+            }
+        });
+
+        addr
+    }
+
+    /// Send a raw HTTP/1.1 request and return (status_code, body).
+    async fn http_request(addr: SocketAddr, request: &str) -> (u16, String) {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        stream.flush().await.unwrap();
+
+        // Read the full response (server closes connection due to Connection: close)
+        let mut buf = Vec::new();
+        let _ = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buf)).await;
+
+        let response = String::from_utf8_lossy(&buf).to_string();
+
+        // Parse status code from first line: "HTTP/1.1 200 OK"
+        let status_code = response
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|code| code.parse::<u16>().ok())
+            .unwrap_or(0);
+
+        // Extract body: everything after the blank line (\r\n\r\n)
+        // For chunked encoding, just grab everything for assertion matching
+        let body = response
+            .split("\r\n\r\n")
+            .skip(1)
+            .collect::<Vec<_>>()
+            .join("");
+
+        (status_code, body)
+    }
+
+    // -- Health check --
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let addr = spawn_test_server().await;
+        let (status, body) = http_request(
+            addr,
+            "GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert_eq!(status, 200, "body: {body}");
+        assert!(body.contains(r#""status":"ok"#), "body: {body}");
+    }
+
+    #[tokio::test]
+    async fn test_root_get_health() {
+        let addr = spawn_test_server().await;
+        let (status, body) = http_request(
+            addr,
+            "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert_eq!(status, 200, "body: {body}");
+        assert!(body.contains(r#""status":"ok"#), "body: {body}");
+    }
+
+    // -- Models --
+
+    #[tokio::test]
+    async fn test_models_endpoint() {
+        let addr = spawn_test_server().await;
+        let (status, body) = http_request(
+            addr,
+            "GET /v1/models HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert_eq!(status, 200, "body: {body}");
+        assert!(
+            body.contains("claude-opus-4-6-thinking"),
+            "body should list Claude models: {body}"
+        );
+        assert!(
+            body.contains("gemini-3-flash"),
+            "body should list Gemini models: {body}"
+        );
+    }
+
+    // -- 404 --
+
+    #[tokio::test]
+    async fn test_not_found() {
+        let addr = spawn_test_server().await;
+        let (status, body) = http_request(
+            addr,
+            "GET /nonexistent HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert_eq!(status, 404, "body: {body}");
+        assert!(body.contains("not_found"), "body: {body}");
+    }
+
+    // -- Token counting --
+
+    #[tokio::test]
+    async fn test_count_tokens() {
+        let addr = spawn_test_server().await;
+        let payload = r#"{"messages":[{"role":"user","content":"Hello, world!"}]}"#;
+        let req = format!(
+            "POST /v1/messages/count_tokens HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            payload.len(),
+            payload
+        );
+        let (status, body) = http_request(addr, &req).await;
+        assert_eq!(status, 200, "body: {body}");
+        assert!(body.contains("input_tokens"), "body: {body}");
+    }
+
+    // -- Event logging batch --
+
+    #[tokio::test]
+    async fn test_event_logging_batch() {
+        let addr = spawn_test_server().await;
+        let (status, body) = http_request(
+            addr,
+            "POST /api/event_logging/batch HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 2\r\n\r\n{}",
+        )
+        .await;
+        assert_eq!(status, 200, "body: {body}");
+        assert!(body.contains(r#""status":"ok"#), "body: {body}");
+    }
+
+    // -- POST / heartbeat --
+
+    #[tokio::test]
+    async fn test_post_root_heartbeat() {
+        let addr = spawn_test_server().await;
+        let (status, body) = http_request(
+            addr,
+            "POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 2\r\n\r\n{}",
+        )
+        .await;
+        assert_eq!(status, 200, "body: {body}");
+        assert!(body.contains(r#""status":"ok"#), "body: {body}");
+    }
+
+    // -- Cache endpoints --
+
+    #[tokio::test]
+    async fn test_cache_stats() {
+        let addr = spawn_test_server().await;
+        let (status, body) = http_request(
+            addr,
+            "GET /cache/stats HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert_eq!(status, 200, "body: {body}");
+    }
+
+    #[tokio::test]
+    async fn test_cache_clear() {
+        let addr = spawn_test_server().await;
+        let (status, body) = http_request(
+            addr,
+            "POST /cache/clear HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+        )
+        .await;
+        assert_eq!(status, 200, "body: {body}");
+        assert!(body.contains("cleared"), "body: {body}");
+    }
+
+    // -- Stats endpoint --
+
+    #[tokio::test]
+    async fn test_stats_endpoint() {
+        let addr = spawn_test_server().await;
+        let (status, _body) = http_request(
+            addr,
+            "GET /stats HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert_eq!(status, 200);
+    }
+
+    // -- Messages endpoint: validation errors --
+
+    #[tokio::test]
+    async fn test_messages_invalid_json() {
+        let addr = spawn_test_server().await;
+        let (status, _body) = http_request(
+            addr,
+            "POST /v1/messages HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: 14\r\n\r\nnot valid json",
+        )
+        .await;
+        assert_eq!(status, 400, "expected 400 for bad JSON");
+    }
+
+    #[tokio::test]
+    async fn test_messages_chunked_body_reports_field_path() {
+        // Without a Content-Length, small bodies are buffered and large ones
+        // switch to the streaming parser; both report the failing field
+        let chunked = |body: &str| {
+            let mut request = "POST /v1/messages HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\nTransfer-Encoding: chunked\r\n\r\n".to_string();
+            for chunk in body.as_bytes().chunks(64 * 1024) {
+                request.push_str(&format!("{:x}\r\n", chunk.len()));
+                request.push_str(std::str::from_utf8(chunk).unwrap());
+                request.push_str("\r\n");
+            }
+            request.push_str("0\r\n\r\n");
+            request
+        };
+        let addr = spawn_test_server().await;
+
+        for padding in [0, STREAMING_PARSE_THRESHOLD + 1] {
+            let body = format!(
+                r#"{{"model":"claude-sonnet-4-5","max_tokens":10,"padding":"{}","messages":[{{"role":"user","content":5}}]}}"#,
+                "x".repeat(padding)
+            );
+            let (status, response) = http_request(addr, &chunked(&body)).await;
+            assert_eq!(status, 400, "body: {response}");
+            assert!(response.contains("messages[0].content"), "body: {response}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_messages_empty_model() {
+        let addr = spawn_test_server().await;
+        let payload =
+            r#"{"model":"","max_tokens":100,"messages":[{"role":"user","content":"hi"}]}"#;
+        let req = format!(
+            "POST /v1/messages HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{payload}",
+            payload.len()
+        );
+        let (status, body) = http_request(addr, &req).await;
+        assert_eq!(status, 400, "expected 400 for empty model, body: {body}");
+        assert!(
+            body.contains(r#""code":"invalid_request""#),
+            "expected stable error code, body: {body}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_messages_empty_messages_array() {
+        let addr = spawn_test_server().await;
+        let payload = r#"{"model":"claude-sonnet-4-5","max_tokens":100,"messages":[]}"#;
+        let req = format!(
+            "POST /v1/messages HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{payload}",
+            payload.len()
+        );
+        let (status, body) = http_request(addr, &req).await;
+        assert_eq!(status, 400, "expected 400 for empty messages, body: {body}");
+    }
+
+    #[tokio::test]
+    async fn test_messages_zero_max_tokens() {
+        let addr = spawn_test_server().await;
+        let payload = r#"{"model":"claude-sonnet-4-5","max_tokens":0,"messages":[{"role":"user","content":"hi"}]}"#;
+        let req = format!(
+            "POST /v1/messages HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{payload}",
+            payload.len()
+        );
+        let (status, body) = http_request(addr, &req).await;
+        assert_eq!(status, 400, "expected 400 for max_tokens=0, body: {body}");
+    }
+
+    #[test]
+    fn test_is_response_content() {
+        let parse = |data: &str| {
+            let mut parser = SseParser::new("claude-sonnet-4-5");
+            let mut events = parser.feed(data);
+            events.extend(parser.finish());
+            events
+        };
+
+        // A tool-only reply has no text but is a real completion
+        let tool_only = parse(
+            "data: {\"response\":{\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"functionCall\":{\"name\":\"get_weather\",\"args\":{\"city\":\"Paris\"}}}]},\"finishReason\":\"STOP\"}]}}\n\n",
+        );
+        assert!(tool_only.iter().any(is_response_content));
+        let response = crate::format::build_response_from_events(&tool_only, "m", "r").unwrap();
+        assert!(matches!(
+            response.content.as_slice(),
+            [crate::format::ContentBlock::ToolUse { .. }]
+        ));
+
+        // No candidate content at all still counts as empty
+        let empty =
+            parse("data: {\"response\":{\"candidates\":[{\"finishReason\":\"STOP\"}]}}\n\n");
+        assert!(!empty.iter().any(is_response_content));
+        assert!(!parse("").iter().any(is_response_content));
+    }
+
+    #[test]
+    fn test_handle_empty_content() {
+        let run = |messages: serde_json::Value, reject: bool| {
+            let mut messages: Vec<crate::format::anthropic::Message> =
+                serde_json::from_value(messages).unwrap();
+            handle_empty_content(&mut messages, reject, "test")
+                .map(|()| serde_json::to_value(&messages).unwrap())
+                .map_err(|e| e.to_string())
+        };
+
+        let blank_text = serde_json::json!([
+            {"role": "user", "content": [
+                {"type": "text", "text": "hello"},
+                {"type": "text", "text": "  "}
+            ]}
+        ]);
+        let normalized = run(blank_text.clone(), false).unwrap();
+        assert_eq!(normalized[0]["content"].as_array().unwrap().len(), 1);
+        let err = run(blank_text, true).unwrap_err();
+        assert!(err.contains("messages[0].content[1]"), "{err}");
+
+        // Empty tool results get a placeholder so the tool_use stays answered
+        let empty_result = serde_json::json!([
+            {"role": "user", "content": "list files"},
+            {"role": "assistant", "content": [{"type": "tool_use", "id": "tu_1", "name": "ls", "input": {}}]},
+            {"role": "user", "content": [
+                {"type": "tool_result", "tool_use_id": "tu_1", "content": []}
+            ]}
+        ]);
+        let normalized = run(empty_result.clone(), false).unwrap();
+        assert_eq!(
+            normalized[2]["content"][0]["content"],
+            EMPTY_TOOL_RESULT_PLACEHOLDER
+        );
+        let err = run(empty_result, true).unwrap_err();
+        assert!(
+            err.contains("tool_result for 'tu_1' has empty content"),
+            "{err}"
+        );
+
+        let blank_inner = serde_json::json!([
+            {"role": "user", "content": [
+                {"type": "tool_result", "tool_use_id": "tu_1", "content": [
+                    {"type": "text", "text": ""},
+                    {"type": "text", "text": "ok"}
+                ]}
+            ]}
+        ]);
+        let normalized = run(blank_inner.clone(), false).unwrap();
+        assert_eq!(
+            normalized[0]["content"][0]["content"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(
+            run(blank_inner, true)
+                .unwrap_err()
+                .contains("empty text block")
+        );
+
+        // A blank prefill is dropped; an empty user turn can't be fixed
+        let normalized = run(
+            serde_json::json!([
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": []}
+            ]),
+            false,
+        )
+        .unwrap();
+        assert_eq!(normalized.as_array().unwrap().len(), 1);
+        let err = run(serde_json::json!([{"role": "user", "content": []}]), false).unwrap_err();
+        assert!(
+            err.contains("messages[0]: content must not be empty"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_validate_conversation() {
+        let check = |messages: serde_json::Value| {
+            let mut messages: Vec<crate::format::anthropic::Message> =
+                serde_json::from_value(messages).unwrap();
+            merge_consecutive_roles(&mut messages);
+            validate_conversation(&messages).map_err(|e| e.to_string())
+        };
+
+        let tool_use = serde_json::json!({
+            "role": "assistant",
+            "content": [{"type": "tool_use", "id": "tu_1", "name": "ls", "input": {}}]
+        });
+        let tool_result = serde_json::json!({
+            "role": "user",
+            "content": [{"type": "tool_result", "tool_use_id": "tu_1", "content": "ok"}]
+        });
+
+        assert!(
+            check(serde_json::json!([
+                {"role": "user", "content": "list files"},
+                tool_use,
+                tool_result,
+                {"role": "assistant", "content": "done"}
+            ]))
+            .is_ok()
+        );
+
+        let err = check(serde_json::json!([{"role": "assistant", "content": "hi"}])).unwrap_err();
+        assert!(err.contains("first message"), "{err}");
+
+        assert!(
+            check(serde_json::json!([
+                {"role": "user", "content": "a"},
+                {"role": "user", "content": "b"}
+            ]))
+            .is_ok()
+        );
+
+        // A tool_result followed by a separate user turn still answers the tool_use
+        assert!(
+            check(serde_json::json!([
+                {"role": "user", "content": "list files"},
+                tool_use,
+                tool_result,
+                {"role": "user", "content": "thanks"}
+            ]))
+            .is_ok()
+        );
+
+        let err = check(serde_json::json!([
+            {"role": "user", "content": "list files"},
+            tool_use,
+            {"role": "user", "content": "never mind"}
+        ]))
+        .unwrap_err();
+        assert!(
+            err.contains("missing tool_result for tool_use 'tu_1'"),
+            "{err}"
+        );
 
-    let body = serde_json::json!({
-        "type": "error",
-        "error": {
-            "type": error_type,
-            "message": message_with_suggestion
-        },
-        "request_id": request_id
-    })
-    .to_string();
+        let err = check(serde_json::json!([
+            {"role": "user", "content": "hi"},
+            {"role": "assistant", "content": "hello"},
+            tool_result
+        ]))
+        .unwrap_err();
+        assert!(err.contains("does not match a tool_use"), "{err}");
+
+        let err = check(serde_json::json!([
+            {"role": "user", "content": "list files"},
+            tool_use
+        ]))
+        .unwrap_err();
+        assert!(err.contains("final assistant message"), "{err}");
+    }
 
-    Response::builder()
-        .status(status)
-        .header("Content-Type", "application/json")
-        .header("X-Request-Id", request_id)
-        .body(full_body(Full::new(Bytes::from(body))))
-        .unwrap()
-}
+    #[test]
+    fn test_merge_consecutive_roles() {
+        use crate::format::anthropic::{ContentBlock, MessageContent, Role};
+
+        let mut messages: Vec<crate::format::anthropic::Message> =
+            serde_json::from_value(serde_json::json!([
+                {"role": "user", "content": "a"},
+                {"role": "user", "content": [{"type": "text", "text": "b"}]},
+                {"role": "assistant", "content": "c"},
+                {"role": "user", "content": "d"}
+            ]))
+            .unwrap();
+        merge_consecutive_roles(&mut messages);
+
+        let roles: Vec<Role> = messages.iter().map(|m| m.role).collect();
+        assert_eq!(roles, [Role::User, Role::Assistant, Role::User]);
+        let MessageContent::Blocks(blocks) = &messages[0].content else {
+            panic!("merged content should be blocks");
+        };
+        let texts: Vec<&str> = blocks
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts, ["a", "b"]);
+        // Unmerged messages keep their original content
+        assert!(matches!(&messages[1].content, MessageContent::Text(t) if t == "c"));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    #[test]
+    fn test_text_similarity() {
+        assert_eq!(text_similarity("", ""), 1.0);
+        assert_eq!(text_similarity("The cat sat", "the CAT sat"), 1.0);
+        assert_eq!(text_similarity("a b", "c d"), 0.0);
+        assert_eq!(text_similarity("a b c", "a b d"), 0.5);
+    }
 
-    /// Spin up the server on a random port and return the bound address.
-    async fn spawn_test_server() -> SocketAddr {
-        let state = test_server_state();
-        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let addr = listener.local_addr().unwrap();
+    #[tokio::test]
+    async fn test_fallback_stream_reports_effective_model() {
+        use crate::auth::accounts::Account;
+        use std::sync::atomic::AtomicUsize;
 
+        // Upstream that reports exhausted quota for the first request only
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
         tokio::spawn(async move {
-            loop {
-                if let Ok((stream, remote_addr)) = listener.accept().await {
-                    let state = state.clone();
-                    tokio::spawn(async move {
-                        let _ = handle_connection(stream, remote_addr, state).await;
-                    });
-                }
+            while let Ok((stream, _)) = listener.accept().await {
+                let requests = Arc::clone(&requests);
+                let service = hyper::service::service_fn(move |_req| {
+                    let first = requests.fetch_add(1, Ordering::SeqCst) == 0;
+                    async move {
+                        let response = if first {
+                            Response::builder().status(StatusCode::TOO_MANY_REQUESTS).body(
+                                Full::new(Bytes::from_static(
+                                    br#"{"error":{"code":429,"message":"Resource has been exhausted"}}"#,
+                                )),
+                            )
+                        } else {
+                            Response::builder()
+                                .header("content-type", "text/event-stream")
+                                .body(Full::new(Bytes::from_static(
+                                    b"data: {\"response\":{\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hi\"}]}}]}}\n\n",
+                                )))
+                        };
+                        Ok::<_, std::convert::Infallible>(response.unwrap())
+                    }
+                });
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service),
+                );
             }
         });
 
-        addr
+        let mut state = test_server_state();
+        Arc::get_mut(&mut state).unwrap().cloudcode_client =
+            CloudCodeClient::with_endpoints(vec![upstream]);
+        state.accounts_read_only.store(true, Ordering::Relaxed);
+        {
+            let mut account = Account::new("a@example.com".to_string(), "rt-a".to_string());
+            account.access_token = Some("token-a".to_string());
+            account.access_token_expires = Some(u64::MAX / 2);
+            state.accounts.write().await.add_account(account);
+        }
+        let account_id = state.accounts.read().await.accounts[0].id.clone();
+        let mut config = crate::config::Config::default();
+        config.accounts.fallback = true;
+        config.accounts.fallback_backoff_ms = 0;
+
+        let requested = "claude-opus-4-6-thinking";
+        let effective = get_fallback_model(requested).unwrap();
+        let request: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": requested,
+            "max_tokens": 10,
+            "stream": true,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+
+        let response = tokio::time::timeout(
+            Duration::from_secs(10),
+            execute_with_fallback(&request, &state, &config, "req", false, None),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(response.headers()["x-agcp-fallback-from"], requested);
+        assert_eq!(response.headers()["x-agcp-account"], account_id.as_str());
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let start = body
+            .split("\n\n")
+            .find(|event| event.starts_with("event: message_start"))
+            .and_then(|event| event.lines().find_map(|line| line.strip_prefix("data: ")))
+            .unwrap_or_else(|| panic!("no message_start in: {}", body));
+        let start: serde_json::Value = serde_json::from_str(start).unwrap();
+        assert_eq!(start["message"]["model"], effective);
     }
 
-    /// Send a raw HTTP/1.1 request and return (status_code, body).
-    async fn http_request(addr: SocketAddr, request: &str) -> (u16, String) {
-        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
-        stream.write_all(request.as_bytes()).await.unwrap();
-        stream.flush().await.unwrap();
+    #[tokio::test]
+    async fn test_openai_stream_reports_cached_tokens() {
+        use crate::auth::accounts::Account;
 
-        // Read the full response (server closes connection due to Connection: close)
-        let mut buf = Vec::new();
-        let _ = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buf)).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let service = hyper::service::service_fn(|_req| async {
+                    Ok::<_, std::convert::Infallible>(
+                        Response::builder()
+                            .header("content-type", "text/event-stream")
+                            .body(Full::new(Bytes::from_static(
+                                b"data: {\"response\":{\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hi\"}]},\"finishReason\":\"STOP\"}],\"usageMetadata\":{\"promptTokenCount\":10,\"candidatesTokenCount\":2,\"cachedContentTokenCount\":4}}}\n\n",
+                            )))
+                            .unwrap(),
+                    )
+                });
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service),
+                );
+            }
+        });
 
-        let response = String::from_utf8_lossy(&buf).to_string();
+        let mut state = test_server_state();
+        Arc::get_mut(&mut state).unwrap().cloudcode_client =
+            CloudCodeClient::with_endpoints(vec![upstream]);
+        state.accounts_read_only.store(true, Ordering::Relaxed);
+        {
+            let mut account = Account::new("a@example.com".to_string(), "rt-a".to_string());
+            account.access_token = Some("token-a".to_string());
+            account.access_token_expires = Some(u64::MAX / 2);
+            state.accounts.write().await.add_account(account);
+        }
+        let addr = spawn_test_server_with_state(state).await;
 
-        // Parse status code from first line: "HTTP/1.1 200 OK"
-        let status_code = response
+        let payload = r#"{"model":"claude-sonnet-4-5","stream":true,"messages":[{"role":"user","content":"hi"}]}"#;
+        let (status, body) = http_request(
+            addr,
+            &format!(
+                "POST /v1/chat/completions HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                payload.len(),
+                payload
+            ),
+        )
+        .await;
+        assert_eq!(status, 200, "body: {body}");
+
+        let usage = body
             .lines()
-            .next()
-            .and_then(|line| line.split_whitespace().nth(1))
-            .and_then(|code| code.parse::<u16>().ok())
-            .unwrap_or(0);
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter_map(|data| serde_json::from_str::<serde_json::Value>(data).ok())
+            .find_map(|chunk| chunk.get("usage").filter(|u| !u.is_null()).cloned())
+            .unwrap_or_else(|| panic!("no usage chunk in: {body}"));
+        assert_eq!(
+            usage["prompt_tokens_details"]["cached_tokens"], 4,
+            "{usage}"
+        );
+    }
 
-        // Extract body: everything after the blank line (\r\n\r\n)
-        // For chunked encoding, just grab everything for assertion matching
-        let body = response
-            .split("\r\n\r\n")
-            .skip(1)
-            .collect::<Vec<_>>()
-            .join("");
+    #[test]
+    fn test_usage_meter_reports_every_interval() {
+        assert!(UsageMeter::new(0).is_none());
+        let mut meter = UsageMeter::new(10).unwrap();
+        let delta = |text: &str| StreamEvent::ContentBlockDelta {
+            index: 0,
+            delta: crate::format::ContentDelta::Text {
+                text: text.to_string(),
+            },
+        };
+        let reported = |event: Option<StreamEvent>| match event {
+            Some(StreamEvent::MessageDelta { delta, usage }) => {
+                assert!(delta.stop_reason.is_none());
+                Some(usage.output_tokens)
+            }
+            _ => None,
+        };
 
-        (status_code, body)
+        assert_eq!(reported(meter.observe(&delta(&"a".repeat(36)))), None);
+        assert_eq!(reported(meter.observe(&delta("abcd"))), Some(10));
+        assert_eq!(reported(meter.observe(&delta(&"a".repeat(20)))), None);
+        assert_eq!(reported(meter.observe(&StreamEvent::Ping)), None);
+        assert_eq!(reported(meter.observe(&delta(&"a".repeat(24)))), Some(21));
     }
 
-    // -- Health check --
+    #[test]
+    fn test_thinking_stripper_renumbers_blocks() {
+        use crate::format::{ContentBlock, ContentDelta};
+
+        let events = vec![
+            StreamEvent::ContentBlockStart {
+                index: 0,
+                content_block: ContentBlock::Thinking {
+                    thinking: String::new(),
+                    signature: None,
+                },
+            },
+            StreamEvent::ContentBlockDelta {
+                index: 0,
+                delta: ContentDelta::Thinking {
+                    thinking: "hmm".to_string(),
+                },
+            },
+            StreamEvent::ContentBlockDelta {
+                index: 0,
+                delta: ContentDelta::Signature {
+                    signature: "sig".to_string(),
+                },
+            },
+            StreamEvent::ContentBlockStop { index: 0 },
+            StreamEvent::ContentBlockStart {
+                index: 1,
+                content_block: ContentBlock::Text {
+                    text: String::new(),
+                    cache_control: None,
+                },
+            },
+            StreamEvent::ContentBlockDelta {
+                index: 1,
+                delta: ContentDelta::Text {
+                    text: "answer".to_string(),
+                },
+            },
+            StreamEvent::ContentBlockStop { index: 1 },
+            StreamEvent::MessageStop,
+        ];
+
+        let mut stripper = ThinkingStripper::new(true);
+        let kept: Vec<_> = events
+            .clone()
+            .into_iter()
+            .filter_map(|e| stripper.apply(e))
+            .collect();
+        assert_eq!(kept.len(), 4);
+        assert!(matches!(
+            &kept[0],
+            StreamEvent::ContentBlockStart {
+                index: 0,
+                content_block: ContentBlock::Text { .. }
+            }
+        ));
+        assert!(matches!(
+            &kept[1],
+            StreamEvent::ContentBlockDelta {
+                index: 0,
+                delta: ContentDelta::Text { text }
+            } if text == "answer"
+        ));
+        assert!(matches!(
+            kept[2],
+            StreamEvent::ContentBlockStop { index: 0 }
+        ));
+
+        // Disabled, everything passes through untouched
+        let mut passthrough = ThinkingStripper::new(false);
+        let len = events.len();
+        assert_eq!(
+            events
+                .into_iter()
+                .filter_map(|e| passthrough.apply(e))
+                .count(),
+            len
+        );
+    }
+
+    #[test]
+    fn test_strip_thinking_blocks() {
+        let body = serde_json::json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "content": [
+                {"type": "thinking", "thinking": "hmm", "signature": "sig"},
+                {"type": "text", "text": "answer"}
+            ],
+            "model": "claude-opus-4-6-thinking",
+            "stop_reason": "end_turn",
+            "usage": {"input_tokens": 1, "output_tokens": 2}
+        });
+        let stripped = strip_thinking_blocks(body.to_string().as_bytes()).unwrap();
+        let stripped: serde_json::Value = serde_json::from_slice(&stripped).unwrap();
+        assert_eq!(
+            stripped["content"],
+            serde_json::json!([{"type": "text", "text": "answer"}])
+        );
+
+        // Nothing to remove, or not a Messages response at all
+        assert!(strip_thinking_blocks(stripped.to_string().as_bytes()).is_none());
+        assert!(strip_thinking_blocks(br#"{"type":"error"}"#).is_none());
+
+        let mut headers = hyper::HeaderMap::new();
+        assert_eq!(strip_thinking_override(&headers), None);
+        headers.insert("x-agcp-strip-thinking", "TRUE".parse().unwrap());
+        assert_eq!(strip_thinking_override(&headers), Some(true));
+        headers.insert("x-agcp-strip-thinking", "0".parse().unwrap());
+        assert_eq!(strip_thinking_override(&headers), Some(false));
+    }
+
+    #[test]
+    fn test_ignored_betas() {
+        let model = "claude-sonnet-4-5";
+        let mut headers = hyper::HeaderMap::new();
+        assert!(ignored_betas(&requested_betas(&headers), model, "req").is_empty());
+
+        headers.append(
+            "anthropic-beta",
+            "token-efficient-tools-2025-02-19, output-128k-2025-02-19"
+                .parse()
+                .unwrap(),
+        );
+        headers.append(
+            "anthropic-beta",
+            "some-future-beta,output-128k-2025-02-19".parse().unwrap(),
+        );
+        assert_eq!(
+            ignored_betas(&requested_betas(&headers), model, "req"),
+            vec!["output-128k-2025-02-19", "some-future-beta"]
+        );
+    }
+
+    #[test]
+    fn test_interleaved_thinking_beta_follows_model() {
+        let betas = vec![INTERLEAVED_THINKING_BETA.to_string()];
+        // Sent upstream for Claude thinking models, so it's in effect there
+        assert!(ignored_betas(&betas, "claude-opus-4-6-thinking", "req").is_empty());
+        assert_eq!(
+            ignored_betas(&betas, "claude-sonnet-4-5", "req"),
+            vec![INTERLEAVED_THINKING_BETA]
+        );
+        assert_eq!(
+            ignored_betas(&betas, "gemini-3-flash", "req"),
+            vec![INTERLEAVED_THINKING_BETA]
+        );
+    }
+
+    fn sse_text_events(count: usize) -> Bytes {
+        let mut body = String::new();
+        for i in 0..count {
+            body.push_str(&format!(
+                "data: {{\"response\":{{\"candidates\":[{{\"content\":{{\"role\":\"model\",\"parts\":[{{\"text\":\"chunk {} \"}}]}}}}]}}}}\n\n",
+                i
+            ));
+        }
+        Bytes::from(body)
+    }
+
+    fn assert_truncated(events: &[StreamEvent]) {
+        match events.last() {
+            Some(StreamEvent::MessageDelta { delta, .. }) => {
+                assert_eq!(
+                    delta.stop_reason,
+                    Some(crate::format::StopReason::MaxTokens)
+                );
+            }
+            other => panic!("Expected MessageDelta, got {:?}", other),
+        }
+    }
 
     #[tokio::test]
-    async fn test_health_check() {
+    async fn test_collect_sse_body_caps_events_within_a_frame() {
+        // Every event arrives in one frame, so the cap must apply mid-frame
+        let body = Full::new(sse_text_events(20));
+        let (events, _) = collect_sse_body(body, "claude-sonnet-4-5", 5, usize::MAX)
+            .await
+            .unwrap();
+
+        // The kept upstream events plus the closing block stop and message_delta
+        assert_eq!(events.len(), 5 + 2);
+        assert!(matches!(
+            events[5],
+            StreamEvent::ContentBlockStop { index: 0 }
+        ));
+        assert_truncated(&events);
+
+        let response =
+            crate::format::build_response_from_events(&events, "claude-sonnet-4-5", "req").unwrap();
+        assert_eq!(
+            response.stop_reason,
+            Some(crate::format::StopReason::MaxTokens)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_sse_body_caps_bytes() {
+        let full = sse_text_events(20);
+        let max_bytes = full.len() / 2;
+        let (events, body) =
+            collect_sse_body(Full::new(full), "claude-sonnet-4-5", usize::MAX, max_bytes)
+                .await
+                .unwrap();
+        assert_eq!(body.len(), max_bytes);
+        assert_truncated(&events);
+
+        // Under both limits the stream finishes normally
+        let (events, _) = collect_sse_body(
+            Full::new(sse_text_events(3)),
+            "claude-sonnet-4-5",
+            100,
+            1 << 20,
+        )
+        .await
+        .unwrap();
+        match events.last() {
+            Some(StreamEvent::MessageDelta { delta, .. }) => {
+                assert_eq!(delta.stop_reason, Some(crate::format::StopReason::EndTurn));
+            }
+            other => panic!("Expected MessageDelta, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chunk_reader_parses_split_body() {
+        let (tx, rx) = mpsc::channel(4);
+        let parser = tokio::task::spawn_blocking(move || {
+            crate::format::parse_json_reader::<MessagesRequest, _>(ChunkReader::new(rx))
+        });
+        for chunk in [
+            r#"{"model": "gem"#,
+            r#"ini-3-flash", "max_tokens""#,
+            r#": 16, "messages": [{"role": "user", "content": "hi"}]}"#,
+        ] {
+            tx.send(Bytes::from_static(chunk.as_bytes())).await.unwrap();
+        }
+        drop(tx);
+
+        let request = parser.await.unwrap().unwrap();
+        assert_eq!(request.model, "gemini-3-flash");
+        assert_eq!(request.max_tokens, Some(16));
+        assert_eq!(request.messages.len(), 1);
+
+        assert!(check_body_size(MAX_REQUEST_SIZE, MAX_REQUEST_SIZE).is_ok());
+        assert!(matches!(
+            check_body_size(MAX_REQUEST_SIZE + 1, MAX_REQUEST_SIZE),
+            Err(Error::Api(ApiError::RequestTooLarge { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_validate_request_model_capabilities() {
+        let config = crate::config::Config::default();
+        let request = |model: &str, extra: serde_json::Value| -> MessagesRequest {
+            let mut json = serde_json::json!({
+                "model": model,
+                "max_tokens": 100,
+                "messages": [{"role": "user", "content": "hi"}]
+            });
+            json.as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            serde_json::from_value(json).unwrap()
+        };
+        let thinking = serde_json::json!({"thinking": {"type": "enabled", "budget_tokens": 1024}});
+        let image = serde_json::json!({"messages": [{"role": "user", "content": [
+            {"type": "tool_result", "tool_use_id": "t1", "content": [
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AA=="}}
+            ]}
+        ]}]});
+
+        let err = validate_request(&request("gpt-oss-120b-medium", thinking.clone()), &config)
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("model gpt-oss-120b-medium does not support the thinking parameter"),
+            "{err}"
+        );
+        let err =
+            validate_request(&request("gpt-oss-120b-medium", image.clone()), &config).unwrap_err();
+        assert!(
+            err.to_string().contains("does not support image input"),
+            "{err}"
+        );
+
+        // Supported, disabled thinking, and unknown models pass
+        assert!(validate_request(&request("claude-sonnet-4-5", thinking), &config).is_ok());
+        assert!(validate_request(&request("gemini-3-flash", image.clone()), &config).is_ok());
+        let disabled = serde_json::json!({"thinking": {"type": "disabled"}});
+        assert!(validate_request(&request("gpt-oss-120b-medium", disabled), &config).is_ok());
+        assert!(validate_request(&request("custom-model", image), &config).is_ok());
+    }
+
+    #[test]
+    fn test_handle_unknown_model() {
+        let mut config = crate::config::Config::default();
+
+        let mut model = "gemini-3-flash".to_string();
+        assert!(handle_unknown_model(&mut model, &config, "req").is_ok());
+        assert_eq!(model, "gemini-3-flash");
+
+        let mut model = "gemini-3-flsah".to_string();
+        let err = handle_unknown_model(&mut model, &config, "req").unwrap_err();
+        assert!(
+            err.to_string().contains(
+                "model 'gemini-3-flsah' not found. Available models: claude-opus-4-6-thinking"
+            ),
+            "{err}"
+        );
+
+        // Custom mapping and alias targets pass through under "reject"
+        config.mappings.rules.push(crate::config::MappingRule {
+            from: "claude-*".to_string(),
+            to: "custom-rule-target".to_string(),
+        });
+        config
+            .mappings
+            .aliases
+            .insert("mine".to_string(), "custom-alias-target".to_string());
+        for target in ["custom-rule-target", "custom-alias-target"] {
+            let mut custom = target.to_string();
+            assert!(handle_unknown_model(&mut custom, &config, "req").is_ok());
+            assert_eq!(custom, target);
+        }
+
+        config.models.unknown_model = Some("fallback".to_string());
+        config.models.default_model = Some("sonnet".to_string());
+        assert!(handle_unknown_model(&mut model, &config, "req").is_ok());
+        assert_eq!(model, "claude-sonnet-4-5");
+    }
+
+    #[test]
+    fn test_validate_request_size_limits() {
+        let mut config = crate::config::Config::default();
+        config.server.max_tools = 1;
+        config.server.max_messages = 2;
+
+        let request = |messages: usize, tools: usize| -> MessagesRequest {
+            let turns: Vec<_> = (0..messages)
+                .map(|i| {
+                    let role = if i % 2 == 0 { "user" } else { "assistant" };
+                    serde_json::json!({"role": role, "content": "hi"})
+                })
+                .collect();
+            let tools: Vec<_> = (0..tools)
+                .map(|i| serde_json::json!({"name": format!("tool_{i}")}))
+                .collect();
+            serde_json::from_value(serde_json::json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": 100,
+                "messages": turns,
+                "tools": tools,
+            }))
+            .unwrap()
+        };
+
+        assert!(validate_request(&request(2, 1), &config).is_ok());
+        let err = validate_request(&request(3, 1), &config).unwrap_err();
+        assert!(err.to_string().contains("limit of 2"), "{err}");
+        let err = validate_request(&request(1, 2), &config).unwrap_err();
+        assert!(err.to_string().contains("limit of 1"), "{err}");
+    }
+
+    #[test]
+    fn test_validate_tools() {
+        let check = |tools: serde_json::Value| {
+            let tools: Vec<crate::format::anthropic::Tool> = serde_json::from_value(tools).unwrap();
+            validate_tools(&tools).map_err(|e| e.to_string())
+        };
+        let schema = serde_json::json!({"type": "object", "properties": {}});
+
+        assert!(
+            check(serde_json::json!([
+                {"name": "ls", "input_schema": schema},
+                {"name": "cat", "input_schema": schema}
+            ]))
+            .is_ok()
+        );
+
+        let err = check(serde_json::json!([{"name": " ", "input_schema": schema}])).unwrap_err();
+        assert!(err.contains("tools[0]: name must not be empty"), "{err}");
+
+        let err = check(serde_json::json!([
+            {"name": "ls", "input_schema": schema},
+            {"name": "ls", "input_schema": schema}
+        ]))
+        .unwrap_err();
+        assert!(err.contains("tools[1]: duplicate tool name 'ls'"), "{err}");
+
+        let err = check(serde_json::json!([{"name": "ls", "input_schema": "object"}])).unwrap_err();
+        assert!(
+            err.contains("('ls'): input_schema must be a JSON object"),
+            "{err}"
+        );
+
+        // No "type" defaults to "object", e.g. an OpenAI tool with `parameters: {}`
+        assert!(check(serde_json::json!([{"name": "ls", "input_schema": {}}])).is_ok());
+        assert!(
+            check(serde_json::json!([{"name": "ls", "input_schema": {"properties": {}}}])).is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_messages_excessive_max_tokens() {
         let addr = spawn_test_server().await;
-        let (status, body) = http_request(
-            addr,
-            "GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        let payload = r#"{"model":"claude-sonnet-4-5","max_tokens":999999,"messages":[{"role":"user","content":"hi"}]}"#;
+        let req = format!(
+            "POST /v1/messages HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{payload}",
+            payload.len()
+        );
+        let (status, body) = http_request(addr, &req).await;
+        assert_eq!(
+            status, 400,
+            "expected 400 for excessive max_tokens, body: {body}"
+        );
+    }
+
+    #[test]
+    fn test_apply_server_policies_system_prompt() {
+        use crate::format::anthropic::{ContentBlock, SystemPrompt};
+
+        let parse = |json: &str| -> MessagesRequest { serde_json::from_str(json).unwrap() };
+        let server = crate::config::ServerConfig {
+            system_prefix: Some("Be concise.".to_string()),
+            ..Default::default()
+        };
+
+        let mut none = parse(r#"{"model":"m","messages":[{"role":"user","content":"hi"}]}"#);
+        apply_server_policies(&mut none, &server, "test");
+        assert!(matches!(none.system, Some(SystemPrompt::Text(ref t)) if t == "Be concise."));
+
+        let mut text = parse(
+            r#"{"model":"m","system":"You are Bob.","messages":[{"role":"user","content":"hi"}]}"#,
+        );
+        apply_server_policies(&mut text, &server, "test");
+        assert!(
+            matches!(text.system, Some(SystemPrompt::Text(ref t)) if t == "Be concise.\n\nYou are Bob.")
+        );
+
+        let mut blocks = parse(
+            r#"{"model":"m","system":[{"type":"text","text":"You are Bob."}],"messages":[{"role":"user","content":"hi"}]}"#,
+        );
+        apply_server_policies(&mut blocks, &server, "test");
+        let Some(SystemPrompt::Blocks(blocks)) = blocks.system else {
+            panic!("expected blocks");
+        };
+        assert_eq!(blocks.len(), 2);
+        assert!(matches!(&blocks[0], ContentBlock::Text { text, .. } if text == "Be concise."));
+
+        let server = crate::config::ServerConfig {
+            system_prefix: Some("Be concise.".to_string()),
+            system_override: Some("House rules.".to_string()),
+            ..Default::default()
+        };
+        let mut text = parse(
+            r#"{"model":"m","system":"You are Bob.","messages":[{"role":"user","content":"hi"}]}"#,
+        );
+        apply_server_policies(&mut text, &server, "test");
+        assert!(matches!(text.system, Some(SystemPrompt::Text(ref t)) if t == "House rules."));
+    }
+
+    #[test]
+    fn test_apply_server_policies_max_tokens_cap() {
+        let server = crate::config::ServerConfig {
+            max_tokens_cap: Some(8192),
+            model_max_tokens_cap: vec![crate::config::ModelMaxTokensRule {
+                model: "claude-opus-*".to_string(),
+                max_tokens: 4096,
+            }],
+            ..Default::default()
+        };
+        let request = |model: &str, max_tokens: u32| -> MessagesRequest {
+            serde_json::from_value(serde_json::json!({
+                "model": model,
+                "max_tokens": max_tokens,
+                "messages": [{"role": "user", "content": "hi"}]
+            }))
+            .unwrap()
+        };
+
+        let mut over = request("claude-sonnet-4-5", 999_999);
+        apply_server_policies(&mut over, &server, "test");
+        assert_eq!(over.max_tokens, Some(8192));
+
+        let mut under = request("claude-sonnet-4-5", 100);
+        apply_server_policies(&mut under, &server, "test");
+        assert_eq!(under.max_tokens, Some(100));
+
+        let mut per_model = request("claude-opus-4-6-thinking", 8000);
+        apply_server_policies(&mut per_model, &server, "test");
+        assert_eq!(per_model.max_tokens, Some(4096));
+
+        let mut uncapped = request("claude-sonnet-4-5", 999_999);
+        apply_server_policies(&mut uncapped, &Default::default(), "test");
+        assert_eq!(uncapped.max_tokens, Some(999_999));
+    }
+
+    #[test]
+    fn test_apply_request_defaults_respects_client_values() {
+        let defaults = crate::config::DefaultsConfig {
+            temperature: Some(0.0),
+            top_p: Some(0.9),
+            top_k: Some(40),
+            max_tokens: Some(8192),
+        };
+
+        let mut omitted: MessagesRequest = serde_json::from_str(
+            r#"{"model":"claude-sonnet-4-5","messages":[{"role":"user","content":"hi"}]}"#,
+        )
+        .unwrap();
+        apply_request_defaults(&mut omitted, &defaults, None);
+        assert_eq!(omitted.temperature, Some(0.0));
+        assert_eq!(omitted.top_p, Some(0.9));
+        assert_eq!(omitted.top_k, Some(40));
+        assert_eq!(omitted.max_tokens, Some(8192));
+
+        let mut explicit: MessagesRequest = serde_json::from_str(
+            r#"{"model":"claude-sonnet-4-5","max_tokens":100,"temperature":0.7,"top_k":5,"messages":[{"role":"user","content":"hi"}]}"#,
+        )
+        .unwrap();
+        apply_request_defaults(&mut explicit, &defaults, None);
+        assert_eq!(explicit.temperature, Some(0.7));
+        assert_eq!(explicit.top_p, Some(0.9));
+        assert_eq!(explicit.top_k, Some(5));
+        assert_eq!(explicit.max_tokens, Some(100));
+
+        // An explicit 0 is kept so validation rejects it
+        let mut zero: MessagesRequest = serde_json::from_str(
+            r#"{"model":"claude-sonnet-4-5","max_tokens":0,"messages":[{"role":"user","content":"hi"}]}"#,
+        )
+        .unwrap();
+        apply_request_defaults(&mut zero, &defaults, Some(4096));
+        assert_eq!(zero.max_tokens, Some(0));
+
+        // Without a configured default the endpoint fallback applies
+        let mut fallback: MessagesRequest = serde_json::from_str(
+            r#"{"model":"claude-sonnet-4-5","messages":[{"role":"user","content":"hi"}]}"#,
         )
-        .await;
-        assert_eq!(status, 200, "body: {body}");
-        assert!(body.contains(r#""status":"ok"#), "body: {body}");
+        .unwrap();
+        apply_request_defaults(&mut fallback, &Default::default(), Some(4096));
+        assert_eq!(fallback.max_tokens, Some(4096));
     }
 
-    #[tokio::test]
-    async fn test_root_get_health() {
-        let addr = spawn_test_server().await;
-        let (status, body) = http_request(
-            addr,
-            "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    #[test]
+    fn test_merge_chat_completions_reindexes_and_sums_usage() {
+        let completion = |text: &str| -> ChatCompletionResponse {
+            serde_json::from_value(serde_json::json!({
+                "id": "chatcmpl-sub",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "claude-sonnet-4-5",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": text},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15}
+            }))
+            .unwrap()
+        };
+
+        let merged = merge_chat_completions(
+            vec![completion("a"), completion("b"), completion("c")],
+            "req",
         )
-        .await;
-        assert_eq!(status, 200, "body: {body}");
-        assert!(body.contains(r#""status":"ok"#), "body: {body}");
+        .unwrap();
+        assert_eq!(merged.id, "chatcmpl-req");
+        assert_eq!(merged.choices.len(), 3);
+        for (i, choice) in merged.choices.iter().enumerate() {
+            assert_eq!(choice.index, i as u32);
+        }
+        assert_eq!(merged.choices[2].message.content.as_deref(), Some("c"));
+        let usage = merged.usage.unwrap();
+        assert_eq!(usage.prompt_tokens, 30);
+        assert_eq!(usage.completion_tokens, 15);
+        assert_eq!(usage.total_tokens, 45);
+
+        assert!(merge_chat_completions(Vec::new(), "req").is_none());
     }
 
-    // -- Models --
+    #[test]
+    fn test_fallback_backoff_bounds() {
+        assert_eq!(fallback_backoff(0, 0), Duration::ZERO);
 
-    #[tokio::test]
-    async fn test_models_endpoint() {
-        let addr = spawn_test_server().await;
-        let (status, body) = http_request(
-            addr,
-            "GET /v1/models HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
-        )
-        .await;
-        assert_eq!(status, 200, "body: {body}");
-        assert!(
-            body.contains("claude-opus-4-6-thinking"),
-            "body should list Claude models: {body}"
+        for _ in 0..50 {
+            let first = fallback_backoff(0, 400);
+            assert!(first >= Duration::from_millis(200) && first <= Duration::from_millis(400));
+
+            let second = fallback_backoff(1, 400);
+            assert!(second >= Duration::from_millis(400) && second <= Duration::from_millis(800));
+
+            let capped = fallback_backoff(30, 400);
+            assert!(capped <= Duration::from_millis(MAX_FALLBACK_BACKOFF_MS));
+            assert!(capped >= Duration::from_millis(MAX_FALLBACK_BACKOFF_MS / 2));
+        }
+    }
+
+    #[test]
+    fn test_fallback_header_only_on_success() {
+        let response = |status: StatusCode| {
+            Ok(Response::builder()
+                .status(status)
+                .body(full_body(Full::new(Bytes::new())))
+                .unwrap())
+        };
+
+        let ok = with_fallback_header(response(StatusCode::OK), "claude-opus-4-6-thinking");
+        assert_eq!(
+            ok.unwrap().headers()["x-agcp-fallback-from"],
+            "claude-opus-4-6-thinking"
+        );
+
+        let failed = with_fallback_header(
+            response(StatusCode::TOO_MANY_REQUESTS),
+            "claude-opus-4-6-thinking",
         );
         assert!(
-            body.contains("gemini-3-flash"),
-            "body should list Gemini models: {body}"
+            !failed
+                .unwrap()
+                .headers()
+                .contains_key("x-agcp-fallback-from")
         );
     }
 
-    // -- 404 --
+    #[test]
+    fn test_triggers_fallback() {
+        assert!(triggers_fallback(&Error::Api(ApiError::QuotaExhausted {
+            model: "gemini-3-flash".to_string(),
+            reset_time: "unknown".to_string(),
+        })));
+        assert!(triggers_fallback(&Error::Timeout(Duration::from_secs(30))));
+        assert!(!triggers_fallback(&Error::Api(ApiError::InvalidRequest {
+            message: "bad".to_string(),
+        })));
+    }
+
+    #[test]
+    fn test_cache_ttl_override_header() {
+        let mut headers = hyper::HeaderMap::new();
+        assert_eq!(cache_ttl_override(&headers), None);
+
+        headers.insert("x-agcp-cache-ttl", "120".parse().unwrap());
+        assert_eq!(cache_ttl_override(&headers), Some(Duration::from_secs(120)));
+
+        headers.insert("x-agcp-cache-ttl", "0".parse().unwrap());
+        assert_eq!(cache_ttl_override(&headers), Some(Duration::ZERO));
+
+        headers.insert("x-agcp-cache-ttl", "soon".parse().unwrap());
+        assert_eq!(cache_ttl_override(&headers), None);
+    }
 
     #[tokio::test]
-    async fn test_not_found() {
+    async fn test_active_streams_cancel() {
+        let streams = ActiveStreams::default();
+        assert_eq!(
+            cancel_request_id("/v1/messages/req-1/cancel"),
+            Some("req-1")
+        );
+        assert_eq!(cancel_request_id("/v1/messages//cancel"), None);
+        assert!(!streams.cancel("req-1"));
+
+        let mut handle = streams.register(&["req-1", "agent-1"]);
+        // Reusing an ID replaces the entry; dropping the newer handle must not
+        // unregister the older stream's other ID
+        drop(streams.register(&["req-1"]));
+        assert!(!streams.cancel("req-1"));
+        assert!(streams.cancel("agent-1"));
+        tokio::time::timeout(Duration::from_secs(1), handle.cancelled())
+            .await
+            .expect("cancellation should fire");
+
+        drop(handle);
+        assert!(!streams.cancel("agent-1"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_unknown_stream() {
         let addr = spawn_test_server().await;
-        let (status, body) = http_request(
-            addr,
-            "GET /nonexistent HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
-        )
-        .await;
+        let req = "POST /v1/messages/nope/cancel HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+        let (status, body) = http_request(addr, req).await;
         assert_eq!(status, 404, "body: {body}");
-        assert!(body.contains("not_found"), "body: {body}");
+        assert!(body.contains("not_found_error"));
     }
 
-    // -- Token counting --
-
     #[tokio::test]
-    async fn test_count_tokens() {
+    async fn test_completions_rejects_batched_prompt() {
         let addr = spawn_test_server().await;
-        let payload = r#"{"messages":[{"role":"user","content":"Hello, world!"}]}"#;
+        let body = r#"{"model":"gemini-3-flash","prompt":["a","b"]}"#;
         let req = format!(
-            "POST /v1/messages/count_tokens HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
-            payload.len(),
-            payload
+            "POST /v1/completions HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
         );
         let (status, body) = http_request(addr, &req).await;
-        assert_eq!(status, 200, "body: {body}");
-        assert!(body.contains("input_tokens"), "body: {body}");
+        assert_eq!(status, 400, "body: {body}");
+        assert!(body.contains("single string"));
     }
 
-    // -- Event logging batch --
+    #[tokio::test]
+    async fn test_retry_after_secs() {
+        let state = test_server_state();
+        let rate_limited = Error::Api(ApiError::RateLimited {
+            retry_after: Duration::from_millis(1500),
+        });
+        assert_eq!(retry_after_secs(&state, &rate_limited).await, Some(2));
+
+        let mut account = crate::auth::Account::new("a@example.com".into(), "token".into());
+        account.set_rate_limit(
+            "claude-sonnet-4-5",
+            chrono::Utc::now().timestamp() as u64 + 90,
+        );
+        state.accounts.write().await.add_account(account);
+        let exhausted = Error::Api(ApiError::QuotaExhausted {
+            model: "claude-sonnet-4-5".to_string(),
+            reset_time: "unknown".to_string(),
+        });
+        let secs = retry_after_secs(&state, &exhausted).await.unwrap();
+        assert!((89..=90).contains(&secs), "got {secs}");
+
+        assert_eq!(
+            retry_after_secs(&state, &Error::Http("boom".into())).await,
+            None
+        );
+    }
 
     #[tokio::test]
-    async fn test_event_logging_batch() {
+    async fn test_version_endpoint() {
         let addr = spawn_test_server().await;
-        let (status, body) = http_request(
-            addr,
-            "POST /api/event_logging/batch HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 2\r\n\r\n{}",
-        )
-        .await;
+        let req = "GET /version HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+        let (status, body) = http_request(addr, req).await;
         assert_eq!(status, 200, "body: {body}");
-        assert!(body.contains(r#""status":"ok"#), "body: {body}");
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert!(json["git_sha"].is_string());
+        assert!(json["build_time"].is_string());
+        assert!(json["rustc"].is_string());
     }
 
-    // -- POST / heartbeat --
+    #[tokio::test]
+    async fn test_in_flight_requests_single_flight() {
+        let in_flight = InFlightRequests::default();
+
+        let guard = in_flight.claim("key").unwrap();
+        let Err(mut waiter) = in_flight.claim("key") else {
+            panic!("expected a waiter");
+        };
+        assert!(in_flight.claim("other").is_ok());
+
+        drop(guard);
+        // Channel closed: waiter wakes immediately
+        assert!(waiter.changed().await.is_err());
+        assert!(in_flight.claim("key").is_ok());
+    }
 
     #[tokio::test]
-    async fn test_post_root_heartbeat() {
-        let addr = spawn_test_server().await;
-        let (status, body) = http_request(
-            addr,
-            "POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 2\r\n\r\n{}",
-        )
+    async fn test_fatal_account_error_retries_once_on_another_account() {
+        use crate::auth::accounts::Account;
+
+        let state = test_server_state();
+        state.accounts_read_only.store(true, Ordering::Relaxed);
+        {
+            let mut accounts = state.accounts.write().await;
+            for name in ["a", "b", "c"] {
+                let mut account = Account::new(format!("{name}@example.com"), format!("rt-{name}"));
+                account.access_token = Some(format!("token-{name}"));
+                account.access_token_expires = Some(u64::MAX / 2);
+                accounts.add_account(account);
+            }
+        }
+        let request: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 10,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        let fatal = || {
+            Err(Error::Api(ApiError::ServerError {
+                status: 403,
+                message: r#"{"error":{"code":403,"status":"PERMISSION_DENIED","details":[{"reason":"SERVICE_DISABLED"}]}}"#.to_string(),
+            }))
+        };
+        let invalid_tokens = |accounts: &AccountStore| -> Vec<String> {
+            accounts
+                .accounts
+                .iter()
+                .filter(|a| a.is_invalid)
+                .filter_map(|a| a.access_token.clone())
+                .collect()
+        };
+
+        // The rejected account is marked invalid and another one serves the request
+        let tokens = parking_lot::Mutex::new(Vec::new());
+        let result = execute_with_account_retry(&state, "claude-sonnet-4-5", &request, |attempt| {
+            let mut tokens = tokens.lock();
+            tokens.push(attempt.access_token);
+            let first = tokens.len() == 1;
+            async move {
+                if first {
+                    fatal()
+                } else {
+                    Ok(json_ok_response(b"{}".to_vec(), "test", None))
+                }
+            }
+        })
         .await;
-        assert_eq!(status, 200, "body: {body}");
-        assert!(body.contains(r#""status":"ok"#), "body: {body}");
+        assert!(result.is_ok());
+        let tokens = tokens.into_inner();
+        assert_eq!(tokens.len(), 2);
+        assert_ne!(tokens[0], tokens[1]);
+        assert_eq!(
+            invalid_tokens(&*state.accounts.read().await),
+            vec![tokens[0].clone()]
+        );
+
+        // Only one retry, even with another account left to try
+        let attempts = std::sync::atomic::AtomicUsize::new(0);
+        let result = execute_with_account_retry(&state, "claude-sonnet-4-5", &request, |_| {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            async { fatal() }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+        assert_eq!(invalid_tokens(&*state.accounts.read().await).len(), 3);
     }
 
-    // -- Cache endpoints --
+    #[test]
+    fn test_accounts_snapshot() {
+        use crate::auth::accounts::Account;
+
+        let mut store = AccountStore::default();
+        let mut limited = Account::new("a@example.com".into(), "rt-a".into());
+        limited.set_rate_limit("claude-sonnet-4-5", u64::MAX);
+        let mut disabled = Account::new("b@example.com".into(), "rt-b".into());
+        disabled.enabled = false;
+        let active = Account::new("c@example.com".into(), "rt-c".into());
+        store.active_account_id = Some(active.id.clone());
+        store.accounts = vec![limited, disabled, active];
+
+        let snapshot = accounts_snapshot(&store);
+        assert_eq!(snapshot["strategy"], "hybrid");
+        assert_eq!(snapshot["active"]["email"], "c@example.com");
+        assert_eq!(snapshot["total"], 3);
+        assert_eq!(snapshot["enabled"], 2);
+        assert_eq!(snapshot["rate_limited"], 1);
+    }
+
+    #[test]
+    fn test_debug_accounts_snapshot() {
+        use crate::auth::accounts::Account;
+
+        let mut store = AccountStore::default();
+        let mut account = Account::new("a@example.com".into(), "rt-a".into());
+        account.set_rate_limit("claude-sonnet-4-5", 1_060);
+        account.set_rate_limit("claude-opus-4-5", 900);
+        store.active_account_id = Some(account.id.clone());
+        store.accounts = vec![account];
+
+        let snapshot = debug_accounts_snapshot(&store, 1_000);
+        let entry = &snapshot["accounts"][0];
+        assert_eq!(entry["email"], "a@example.com");
+        assert_eq!(entry["active"], true);
+        assert!(entry["id"].as_str().unwrap().len() <= 8);
+        // Expired windows are left out
+        let limits = entry["rate_limits"].as_object().unwrap();
+        assert_eq!(limits.len(), 1);
+        assert_eq!(limits["claude-sonnet-4-5"]["remaining_secs"], 60);
+    }
 
     #[tokio::test]
-    async fn test_cache_stats() {
-        let addr = spawn_test_server().await;
-        let (status, body) = http_request(
-            addr,
-            "GET /cache/stats HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
-        )
-        .await;
-        assert_eq!(status, 200, "body: {body}");
+    async fn test_idempotency_cache_replays_stored_response() {
+        let cache = IdempotencyCache::default();
+        let digest: [u8; 32] = Sha256::digest(b"body").into();
+        let response = json_response(StatusCode::OK, r#"{"id":"msg_1"}"#);
+        let returned = cache
+            .remember("/v1/messages k1".to_string(), digest, response)
+            .await;
+        let body = returned.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], br#"{"id":"msg_1"}"#);
+
+        let replay = cache
+            .replay("/v1/messages k1", "/v1/messages", &digest)
+            .unwrap();
+        assert_eq!(replay.status(), StatusCode::OK);
+        assert_eq!(replay.headers()["x-idempotent-replay"], "true");
+        assert_eq!(replay.headers()["content-type"], "application/json");
+        let body = replay.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], br#"{"id":"msg_1"}"#);
+        assert!(
+            cache
+                .replay("/v1/messages k2", "/v1/messages", &digest)
+                .is_none()
+        );
+
+        // The same key with a different body is rejected, not replayed
+        let other: [u8; 32] = Sha256::digest(b"other body").into();
+        let mismatch = cache
+            .replay("/v1/messages k1", "/v1/messages", &other)
+            .unwrap();
+        assert_eq!(mismatch.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(!mismatch.headers().contains_key("x-idempotent-replay"));
+        let body: serde_json::Value =
+            serde_json::from_slice(&mismatch.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+
+        // Each endpoint gets the mismatch in its own error format
+        let chat = cache
+            .replay("/v1/messages k1", "/v1/chat/completions", &other)
+            .unwrap();
+        let body: serde_json::Value =
+            serde_json::from_slice(&chat.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert!(body.get("type").is_none());
+        assert!(body["error"]["param"].is_null());
+        let responses = cache
+            .replay("/v1/messages k1", "/v1/responses", &other)
+            .unwrap();
+        let body: serde_json::Value =
+            serde_json::from_slice(&responses.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+        assert_eq!(body["error"]["code"], "invalid_request_error");
+
+        // Entries expire after the TTL
+        let later = std::time::Instant::now() + IDEMPOTENCY_TTL + Duration::from_secs(1);
+        evict_expired_idempotency(&mut cache.entries.lock(), later);
+        assert!(
+            cache
+                .replay("/v1/messages k1", "/v1/messages", &digest)
+                .is_none()
+        );
     }
 
     #[tokio::test]
-    async fn test_cache_clear() {
-        let addr = spawn_test_server().await;
-        let (status, body) = http_request(
-            addr,
-            "POST /cache/clear HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
-        )
-        .await;
-        assert_eq!(status, 200, "body: {body}");
-        assert!(body.contains("cleared"), "body: {body}");
+    async fn test_idempotency_duplicate_waits_for_first_response() {
+        let cache = IdempotencyCache::default();
+        let digest = [7u8; 32];
+        let Ok(guard) = cache
+            .claim_or_replay("/v1/messages k1", "/v1/messages", &digest)
+            .await
+        else {
+            panic!("first request should claim the key");
+        };
+
+        let first = async {
+            tokio::task::yield_now().await;
+            let response = json_response(StatusCode::OK, r#"{"id":"msg_1"}"#);
+            cache
+                .remember("/v1/messages k1".to_string(), digest, response)
+                .await;
+            drop(guard);
+        };
+        let (_, duplicate) = tokio::join!(
+            first,
+            cache.claim_or_replay("/v1/messages k1", "/v1/messages", &digest)
+        );
+        let Err(replay) = duplicate else {
+            panic!("duplicate should replay the stored response");
+        };
+        assert_eq!(replay.headers()["x-idempotent-replay"], "true");
+        let body = replay.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], br#"{"id":"msg_1"}"#);
+
+        // A failed request stores nothing, so the next one runs itself
+        let Ok(guard) = cache
+            .claim_or_replay("/v1/messages k2", "/v1/messages", &digest)
+            .await
+        else {
+            panic!("first request should claim the key");
+        };
+        let first = async {
+            tokio::task::yield_now().await;
+            drop(guard);
+        };
+        let (_, retry) = tokio::join!(
+            first,
+            cache.claim_or_replay("/v1/messages k2", "/v1/messages", &digest)
+        );
+        assert!(retry.is_ok());
     }
 
-    // -- Stats endpoint --
+    #[test]
+    fn test_write_trace_rotates() {
+        let dir = std::env::temp_dir().join(format!("agcp-trace-{}", std::process::id()));
+        let path = dir.join("trace.log");
 
-    #[tokio::test]
-    async fn test_stats_endpoint() {
-        let addr = spawn_test_server().await;
-        let (status, _body) = http_request(
-            addr,
-            "GET /stats HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
-        )
-        .await;
-        assert_eq!(status, 200);
+        let mut trace = None;
+        write_trace(&mut trace, &path, 20, "first entry\n");
+        write_trace(&mut trace, &path, 20, "second entry\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second entry\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("trace.log.old")).unwrap(),
+            "first entry\n"
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
     }
 
-    // -- Messages endpoint: validation errors --
+    #[test]
+    fn test_queue_trace_writes_on_writer_thread() {
+        let dir = std::env::temp_dir().join(format!("agcp-trace-queue-{}", std::process::id()));
+        let path = dir.join("trace.log");
 
-    #[tokio::test]
-    async fn test_messages_invalid_json() {
-        let addr = spawn_test_server().await;
-        let (status, _body) = http_request(
-            addr,
-            "POST /v1/messages HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: 14\r\n\r\nnot valid json",
-        )
-        .await;
-        assert_eq!(status, 400, "expected 400 for bad JSON");
+        queue_trace(path.clone(), 1024, "queued entry\n".to_string());
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while std::fs::read_to_string(&path).unwrap_or_default() != "queued entry\n" {
+            assert!(std::time::Instant::now() < deadline, "entry never written");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
     }
 
-    #[tokio::test]
-    async fn test_messages_empty_model() {
-        let addr = spawn_test_server().await;
-        let payload =
-            r#"{"model":"","max_tokens":100,"messages":[{"role":"user","content":"hi"}]}"#;
-        let req = format!(
-            "POST /v1/messages HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{payload}",
-            payload.len()
+    #[test]
+    fn test_account_limits_cache_freshness() {
+        let cache = AccountLimitsCache::default();
+        let ttl = Duration::from_secs(60);
+        let start = std::time::Instant::now();
+        assert!(cache.get(ttl, start).is_none());
+
+        let payload = serde_json::json!({"status": "ok", "quotas": []});
+        cache.store(payload.clone(), start);
+        assert_eq!(
+            cache.get(ttl, start + Duration::from_secs(59)),
+            Some((payload.clone(), false))
         );
-        let (status, body) = http_request(addr, &req).await;
-        assert_eq!(status, 400, "expected 400 for empty model, body: {body}");
+        assert_eq!(
+            cache.get(ttl, start + Duration::from_secs(60)),
+            Some((payload, true))
+        );
+
+        // A zero TTL turns caching off
+        assert!(cache.get(Duration::ZERO, start).is_none());
     }
 
     #[tokio::test]
-    async fn test_messages_empty_messages_array() {
-        let addr = spawn_test_server().await;
-        let payload = r#"{"model":"claude-sonnet-4-5","max_tokens":100,"messages":[]}"#;
-        let req = format!(
-            "POST /v1/messages HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{payload}",
-            payload.len()
+    async fn test_account_limits_cold_cache_shares_one_fetch() {
+        let state = test_server_state();
+        let fetching = state
+            .account_limits
+            .fetching
+            .claim(ACCOUNT_LIMITS_FETCH)
+            .unwrap();
+
+        // A cold-cache request waits for the fetch already running
+        let waiter = tokio::spawn({
+            let state = state.clone();
+            async move { handle_account_limits(&state).await.unwrap() }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+
+        let payload = serde_json::json!({"status": "ok", "quotas": []});
+        state
+            .account_limits
+            .store(payload.clone(), std::time::Instant::now());
+        drop(fetching);
+
+        let response = waiter.await.unwrap();
+        assert_eq!(response.headers()["x-cache"], "HIT");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            payload
         );
-        let (status, body) = http_request(addr, &req).await;
-        assert_eq!(status, 400, "expected 400 for empty messages, body: {body}");
     }
 
-    #[tokio::test]
-    async fn test_messages_zero_max_tokens() {
-        let addr = spawn_test_server().await;
-        let payload = r#"{"model":"claude-sonnet-4-5","max_tokens":0,"messages":[{"role":"user","content":"hi"}]}"#;
-        let req = format!(
-            "POST /v1/messages HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{payload}",
-            payload.len()
+    #[test]
+    fn test_client_rate_limiter_windows() {
+        let limiter = ClientRateLimiter::default();
+        let start = std::time::Instant::now();
+
+        assert!(limiter.check("user:a", 1, 2, start).is_ok());
+        assert!(limiter.check("user:a", 1, 2, start).is_ok());
+        let retry = limiter
+            .check("user:a", 1, 2, start + Duration::from_secs(20))
+            .unwrap_err();
+        assert_eq!(retry, Duration::from_secs(40));
+        // Other clients have their own budget
+        assert!(limiter.check("user:b", 1, 2, start).is_ok());
+        // A new window starts once the old one has passed
+        assert!(
+            limiter
+                .check("user:a", 1, 2, start + CLIENT_RATE_WINDOW)
+                .is_ok()
         );
-        let (status, body) = http_request(addr, &req).await;
-        assert_eq!(status, 400, "expected 400 for max_tokens=0, body: {body}");
+
+        // A fanned-out request uses up as much budget as it makes calls
+        let later = start + CLIENT_RATE_WINDOW * 2;
+        assert!(limiter.check("user:c", 3, 4, later).is_ok());
+        assert!(limiter.check("user:c", 1, 4, later).is_ok());
+        assert!(limiter.check("user:c", 1, 4, later).is_err());
     }
 
-    #[tokio::test]
-    async fn test_messages_excessive_max_tokens() {
-        let addr = spawn_test_server().await;
-        let payload = r#"{"model":"claude-sonnet-4-5","max_tokens":999999,"messages":[{"role":"user","content":"hi"}]}"#;
-        let req = format!(
-            "POST /v1/messages HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{payload}",
-            payload.len()
+    #[test]
+    fn test_rate_limit_headers() {
+        let mut account = crate::auth::Account::new("a@example.com".into(), "token".into());
+        account.tokens_available = 42;
+        assert_eq!(
+            rate_limit_headers(&account, "gemini-3-flash", 1000),
+            vec![
+                ("X-RateLimit-Limit", TOKEN_BUCKET_CAPACITY.to_string()),
+                ("X-RateLimit-Remaining", "42".to_string()),
+            ]
+        );
+
+        account.quota.insert(
+            "gemini-3-flash".to_string(),
+            crate::auth::accounts::ModelQuota {
+                remaining_fraction: 0.256,
+                reset_time: 1300,
+            },
         );
-        let (status, body) = http_request(addr, &req).await;
         assert_eq!(
-            status, 400,
-            "expected 400 for excessive max_tokens, body: {body}"
+            rate_limit_headers(&account, "gemini-3-flash", 1000),
+            vec![
+                ("X-RateLimit-Limit", QUOTA_PERCENT_LIMIT.to_string()),
+                ("X-RateLimit-Remaining", "26".to_string()),
+                ("X-RateLimit-Reset", "300".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_client_key_prefers_user_id() {
+        let ip: IpAddr = "10.0.0.7".parse().unwrap();
+        let parse =
+            |json: serde_json::Value| -> MessagesRequest { serde_json::from_value(json).unwrap() };
+        let base = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 100,
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+
+        assert_eq!(client_key(&parse(base.clone()), ip), "ip:10.0.0.7");
+
+        let mut with_user = base.clone();
+        with_user["metadata"] = serde_json::json!({"user_id": "alice"});
+        assert_eq!(client_key(&parse(with_user), ip), "user:alice");
+
+        let mut empty_user = base;
+        empty_user["metadata"] = serde_json::json!({"user_id": ""});
+        assert_eq!(client_key(&parse(empty_user), ip), "ip:10.0.0.7");
+    }
+
+    #[test]
+    fn test_reports_account_only_on_request() {
+        let mut headers = hyper::HeaderMap::new();
+        assert!(!reports_account(&headers));
+        headers.insert("x-agcp-report-account", "false".parse().unwrap());
+        assert!(!reports_account(&headers));
+        headers.insert("x-agcp-report-account", "TRUE".parse().unwrap());
+        assert!(reports_account(&headers));
+    }
+
+    #[test]
+    fn test_idempotency_key_scoping() {
+        let ip: IpAddr = "10.0.0.7".parse().unwrap();
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("idempotency-key", "abc".parse().unwrap());
+        assert_eq!(
+            idempotency_key(&Method::POST, "/v1/messages", &headers, ip).as_deref(),
+            Some("ip:10.0.0.7 /v1/messages abc")
+        );
+        assert_ne!(
+            idempotency_key(&Method::POST, "/v1/messages", &headers, ip),
+            idempotency_key(
+                &Method::POST,
+                "/v1/messages",
+                &headers,
+                "10.0.0.8".parse().unwrap()
+            )
+        );
+        assert_eq!(
+            idempotency_key(&Method::GET, "/v1/models", &headers, ip),
+            None
+        );
+        assert_eq!(
+            idempotency_key(&Method::POST, "/cache/clear", &headers, ip),
+            None
+        );
+        assert_eq!(
+            idempotency_key(&Method::POST, "/v1/messages", &hyper::HeaderMap::new(), ip),
+            None
         );
+
+        // With an API key the client is identified by it, not its IP
+        headers.insert("x-api-key", "secret-a".parse().unwrap());
+        let with_key_a = idempotency_key(&Method::POST, "/v1/messages", &headers, ip).unwrap();
+        assert!(with_key_a.starts_with("key:"));
+        assert!(!with_key_a.contains("secret-a"));
+        headers.insert("x-api-key", "secret-b".parse().unwrap());
+        let with_key_b = idempotency_key(&Method::POST, "/v1/messages", &headers, ip).unwrap();
+        assert_ne!(with_key_a, with_key_b);
+    }
+
+    #[test]
+    fn test_account_load_counts_until_stream_dropped() {
+        let load = AccountLoad::default();
+        let first = load.start("acct-a");
+        let second = load.start("acct-a");
+        let _other = load.start("acct-b");
+        assert_eq!(load.snapshot().get("acct-a"), Some(&2));
+
+        drop(first);
+        assert_eq!(load.snapshot().get("acct-a"), Some(&1));
+
+        // A streaming response keeps the account busy until its body is dropped
+        let (_tx, body) = streaming_body();
+        let response = hold_account_load(Response::new(body), second);
+        assert_eq!(load.snapshot().get("acct-a"), Some(&1));
+        drop(response);
+        assert!(!load.snapshot().contains_key("acct-a"));
+        assert_eq!(load.snapshot().get("acct-b"), Some(&1));
     }
 }
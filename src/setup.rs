@@ -1,4 +1,7 @@
-//! Interactive setup command for configuring AI coding tools to use AGCP proxy.
+//! Setup command for configuring AI coding tools to use AGCP proxy.
+//!
+//! Runs an interactive picker by default; `--tool` and `--non-interactive`
+//! configure tools without prompts for scripted installs.
 
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -29,6 +32,8 @@ fn xdg_config_dir() -> PathBuf {
 /// Tool configuration definition
 struct Tool {
     name: &'static str,
+    /// Short identifier accepted by `--tool` (e.g. `claude-code`)
+    id: &'static str,
     config_path: PathBuf,
     backup_name: &'static str,
     /// Check if tool is detected (config exists or can be created)
@@ -39,12 +44,6 @@ struct Tool {
     configure: fn(&Path, &str) -> Result<(), String>,
 }
 
-/// Get the AGCP proxy URL based on the running daemon's address, falling back to config
-fn get_proxy_url() -> String {
-    let (host, port) = crate::config::get_daemon_host_port();
-    format!("http://{}:{}", host, port)
-}
-
 /// Get the backups directory
 fn get_backups_dir() -> PathBuf {
     dirs::config_dir()
@@ -53,10 +52,10 @@ fn get_backups_dir() -> PathBuf {
         .join("backups")
 }
 
-/// Backup a config file
-fn backup_config(config_path: &Path, backup_name: &str) -> Result<(), String> {
+/// Backup a config file, returning where the backup was written
+fn backup_config(config_path: &Path, backup_name: &str) -> Result<Option<PathBuf>, String> {
     if !config_path.exists() {
-        return Ok(()); // Nothing to backup
+        return Ok(None); // Nothing to backup
     }
 
     let backups_dir = get_backups_dir();
@@ -66,7 +65,7 @@ fn backup_config(config_path: &Path, backup_name: &str) -> Result<(), String> {
     fs::copy(config_path, &backup_path)
         .map_err(|e| format!("Failed to backup {}: {}", config_path.display(), e))?;
 
-    Ok(())
+    Ok(Some(backup_path))
 }
 
 /// Restore a config file from backup
@@ -573,6 +572,7 @@ fn get_tools() -> Vec<Tool> {
     vec![
         Tool {
             name: "Claude Code",
+            id: "claude-code",
             config_path: claude_code_config_path(),
             backup_name: "claude-code.json",
             detect: detect_claude_code,
@@ -581,6 +581,7 @@ fn get_tools() -> Vec<Tool> {
         },
         Tool {
             name: "Codex",
+            id: "codex",
             config_path: codex_config_path(),
             backup_name: "codex.json",
             detect: detect_codex,
@@ -589,6 +590,7 @@ fn get_tools() -> Vec<Tool> {
         },
         Tool {
             name: "OpenCode",
+            id: "opencode",
             config_path: opencode_config_path(),
             backup_name: "opencode.json",
             detect: detect_opencode,
@@ -597,6 +599,7 @@ fn get_tools() -> Vec<Tool> {
         },
         Tool {
             name: "Crush",
+            id: "crush",
             config_path: crush_config_path(),
             backup_name: "crush.json",
             detect: detect_crush,
@@ -605,6 +608,7 @@ fn get_tools() -> Vec<Tool> {
         },
         Tool {
             name: "Zed",
+            id: "zed",
             config_path: zed_config_path(),
             backup_name: "zed-settings.json",
             detect: detect_zed,
@@ -614,6 +618,117 @@ fn get_tools() -> Vec<Tool> {
     ]
 }
 
+/// Parsed `agcp setup` flags
+#[derive(Debug, Default, PartialEq)]
+struct SetupArgs {
+    /// Tools named with `--tool`, configured without the picker
+    tools: Vec<String>,
+    /// Proxy port to write instead of the running daemon's
+    port: Option<u16>,
+    /// Never prompt; configure every detected tool when no `--tool` is given
+    non_interactive: bool,
+}
+
+fn parse_setup_args(args: &[String]) -> Result<SetupArgs, String> {
+    let mut parsed = SetupArgs::default();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--tool" | "-t" => {
+                let value = iter.next().ok_or("--tool requires a tool name")?;
+                // Allow `--tool codex,zed` as well as repeating the flag
+                parsed.tools.extend(
+                    value
+                        .split(',')
+                        .map(|t| t.trim().to_string())
+                        .filter(|t| !t.is_empty()),
+                );
+            }
+            "--port" | "-p" => {
+                let value = iter.next().ok_or("--port requires a value")?;
+                let port = value
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|p| *p != 0)
+                    .ok_or_else(|| format!("Invalid port: {}", value))?;
+                parsed.port = Some(port);
+            }
+            "--non-interactive" | "-y" => parsed.non_interactive = true,
+            other => return Err(format!("Unknown option: {}", other)),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Find a tool by its id or display name, ignoring case, spaces and dashes
+fn find_tool<'a>(tools: &'a [Tool], name: &str) -> Option<&'a Tool> {
+    let normalize = |s: &str| {
+        s.chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '_'))
+            .collect::<String>()
+            .to_lowercase()
+    };
+    let wanted = normalize(name);
+    tools
+        .iter()
+        .find(|t| normalize(t.id) == wanted || normalize(t.name) == wanted)
+}
+
+/// Back up and configure a single tool, printing the outcome.
+/// Returns false if the tool could not be configured.
+fn configure_tool(tool: &Tool, proxy_url: &str) -> bool {
+    print!("  {} ", tool.name);
+
+    // Backup first
+    let backup_path = match backup_config(&tool.config_path, tool.backup_name) {
+        Ok(path) => path,
+        Err(e) => {
+            println!("{}✗ backup failed: {}{}", YELLOW, e, RESET);
+            return false;
+        }
+    };
+
+    // Configure
+    match (tool.configure)(&tool.config_path, proxy_url) {
+        Ok(()) => {
+            println!("{}✓{} configured", GREEN, RESET);
+            say!("      {}Wrote {}{}", DIM, tool.config_path.display(), RESET);
+            if let Some(backup_path) = backup_path {
+                say!("      {}Backup {}{}", DIM, backup_path.display(), RESET);
+            }
+            // Show extra instructions for OpenCode
+            if tool.name == "OpenCode" {
+                say!(
+                    "      {}Note: OpenCode requires ANTHROPIC_API_KEY env var{}",
+                    DIM,
+                    RESET
+                );
+                say!("      {}Run: export ANTHROPIC_API_KEY=agcp{}", DIM, RESET);
+            }
+            // Show extra instructions for Zed
+            if tool.name == "Zed" {
+                say!(
+                    "      {}Note: Set any Anthropic API key in Zed's settings{}",
+                    DIM,
+                    RESET
+                );
+                say!(
+                    "      {}Zed > Settings > Anthropic > API Key (any value works){}",
+                    DIM,
+                    RESET
+                );
+            }
+            true
+        }
+        Err(e) => {
+            println!("{}✗ {}{}", YELLOW, e, RESET);
+            false
+        }
+    }
+}
+
 /// Run the setup command
 pub fn run_setup_command(args: &[String]) {
     // Check for --undo flag
@@ -622,131 +737,161 @@ pub fn run_setup_command(args: &[String]) {
         return;
     }
 
-    println!();
-    println!("{}{}AGCP Setup{}", BOLD, GREEN, RESET);
-    println!();
-
-    let proxy_url = get_proxy_url();
-    println!("  Proxy URL: {}{}{}", CYAN, proxy_url, RESET);
-
-    // Warn if daemon is running on a different port than the config file
-    let config = Config::load().unwrap_or_default();
-    let config_url = format!("http://{}:{}", config.host(), config.port());
-    if proxy_url != config_url {
-        println!(
-            "  {}Note: Daemon is running on {}, which differs from config ({}){}\n",
-            YELLOW, proxy_url, config_url, RESET
-        );
-    }
-    println!();
-    let tools = get_tools();
-
-    // Detect installed tools
-    let detected: Vec<_> = tools
-        .iter()
-        .filter(|t| (t.detect)(&t.config_path))
-        .collect();
-
-    if detected.is_empty() {
-        println!("{}No supported tools detected.{}", DIM, RESET);
-        println!();
-        println!("Supported tools:");
-        println!("  • Claude Code  ~/.claude/settings.json");
-        println!("  • Codex        ~/.codex/config.json");
-        println!("  • OpenCode     ~/.config/opencode/opencode.json");
-        println!("  • Crush        ~/.config/crush/crush.json");
-        println!("  • Zed          ~/.config/zed/settings.json");
-        println!();
+    if args.iter().any(|a| a == "--list" || a == "-l") {
+        run_list();
         return;
     }
 
-    // Build selection items with status
-    let items: Vec<String> = detected
-        .iter()
-        .map(|t| {
-            let configured = (t.is_configured)(&t.config_path, &proxy_url);
-            let status = if configured { " (configured)" } else { "" };
-            format!("{:<12} {}{}{}", t.name, DIM, t.config_path.display(), RESET) + status
-        })
-        .collect();
-
-    // Pre-select tools that aren't configured yet
-    let defaults: Vec<bool> = detected
-        .iter()
-        .map(|t| !(t.is_configured)(&t.config_path, &proxy_url))
-        .collect();
-
-    // Show interactive selection
-    let selections = match MultiSelect::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select tools to configure")
-        .items(&items)
-        .defaults(&defaults)
-        .interact_opt()
-    {
-        Ok(Some(s)) => s,
-        Ok(None) => {
-            println!("{}Cancelled.{}", DIM, RESET);
-            return;
-        }
+    let opts = match parse_setup_args(args) {
+        Ok(opts) => opts,
         Err(e) => {
-            eprintln!("{}Error: {}{}", YELLOW, e, RESET);
-            return;
+            eprintln!("{}Error: {}{}", RED, e, RESET);
+            eprintln!(
+                "{}Usage: agcp setup [--tool <name>] [--port <port>] [--non-interactive] [--list] [--undo]{}",
+                DIM, RESET
+            );
+            std::process::exit(1);
         }
     };
 
-    if selections.is_empty() {
-        println!("{}No tools selected.{}", DIM, RESET);
-        return;
+    let tools = get_tools();
+
+    // Resolve named tools up front so a typo fails before anything is written
+    let mut named: Vec<&Tool> = Vec::new();
+    for name in &opts.tools {
+        match find_tool(&tools, name) {
+            Some(tool) => {
+                if !named.iter().any(|t| t.id == tool.id) {
+                    named.push(tool);
+                }
+            }
+            None => {
+                let valid: Vec<_> = tools.iter().map(|t| t.id).collect();
+                eprintln!(
+                    "{}Error: Unknown tool '{}'. Valid tools: {}{}",
+                    RED,
+                    name,
+                    valid.join(", "),
+                    RESET
+                );
+                std::process::exit(1);
+            }
+        }
     }
 
-    println!();
-    println!("Configuring {} tool(s)...", selections.len());
+    say!();
+    say!("{}{}AGCP Setup{}", BOLD, GREEN, RESET);
+    say!();
 
-    // Configure selected tools
-    for idx in &selections {
-        let tool = detected[*idx];
-        print!("  {} ", tool.name);
+    let (host, daemon_port) = crate::config::get_daemon_host_port();
+    let port = opts.port.unwrap_or(daemon_port);
+    let proxy_url = format!("http://{}:{}", host, port);
+    say!("  Proxy URL: {}{}{}", CYAN, proxy_url, RESET);
 
-        // Backup first
-        if let Err(e) = backup_config(&tool.config_path, tool.backup_name) {
-            println!("{}✗ backup failed: {}{}", YELLOW, e, RESET);
-            continue;
+    // Warn if daemon is running on a different port than the config file
+    let config = Config::load().unwrap_or_default();
+    let config_url = format!("http://{}:{}", config.host(), config.port());
+    if opts.port.is_none() && proxy_url != config_url {
+        say!(
+            "  {}Note: Daemon is running on {}, which differs from config ({}){}\n",
+            YELLOW,
+            proxy_url,
+            config_url,
+            RESET
+        );
+    }
+    say!();
+
+    let selected: Vec<&Tool> = if !named.is_empty() {
+        named
+    } else {
+        // Detect installed tools
+        let detected: Vec<_> = tools
+            .iter()
+            .filter(|t| (t.detect)(&t.config_path))
+            .collect();
+
+        if detected.is_empty() {
+            println!("{}No supported tools detected.{}", DIM, RESET);
+            println!();
+            println!("Supported tools:");
+            for tool in &tools {
+                println!("  • {:<12} {}", tool.name, display_path(&tool.config_path));
+            }
+            println!();
+            return;
         }
 
-        // Configure
-        match (tool.configure)(&tool.config_path, &proxy_url) {
-            Ok(()) => {
-                println!("{}✓{} configured", GREEN, RESET);
-                // Show extra instructions for OpenCode
-                if tool.name == "OpenCode" {
-                    println!(
-                        "      {}Note: OpenCode requires ANTHROPIC_API_KEY env var{}",
-                        DIM, RESET
-                    );
-                    println!("      {}Run: export ANTHROPIC_API_KEY=agcp{}", DIM, RESET);
+        if opts.non_interactive {
+            // Without prompts, configure every detected tool that isn't set up yet
+            let pending: Vec<_> = detected
+                .into_iter()
+                .filter(|t| !(t.is_configured)(&t.config_path, &proxy_url))
+                .collect();
+            if pending.is_empty() {
+                say!("{}All detected tools are already configured.{}", DIM, RESET);
+                say!();
+                return;
+            }
+            pending
+        } else {
+            // Build selection items with status
+            let items: Vec<String> = detected
+                .iter()
+                .map(|t| {
+                    let configured = (t.is_configured)(&t.config_path, &proxy_url);
+                    let status = if configured { " (configured)" } else { "" };
+                    format!("{:<12} {}{}{}", t.name, DIM, t.config_path.display(), RESET) + status
+                })
+                .collect();
+
+            // Pre-select tools that aren't configured yet
+            let defaults: Vec<bool> = detected
+                .iter()
+                .map(|t| !(t.is_configured)(&t.config_path, &proxy_url))
+                .collect();
+
+            // Show interactive selection
+            let selections = match MultiSelect::with_theme(&ColorfulTheme::default())
+                .with_prompt("Select tools to configure")
+                .items(&items)
+                .defaults(&defaults)
+                .interact_opt()
+            {
+                Ok(Some(s)) => s,
+                Ok(None) => {
+                    println!("{}Cancelled.{}", DIM, RESET);
+                    return;
                 }
-                // Show extra instructions for Zed
-                if tool.name == "Zed" {
-                    println!(
-                        "      {}Note: Set any Anthropic API key in Zed's settings{}",
-                        DIM, RESET
-                    );
-                    println!(
-                        "      {}Zed > Settings > Anthropic > API Key (any value works){}",
-                        DIM, RESET
-                    );
+                Err(e) => {
+                    eprintln!("{}Error: {}{}", YELLOW, e, RESET);
+                    return;
                 }
+            };
+
+            if selections.is_empty() {
+                println!("{}No tools selected.{}", DIM, RESET);
+                return;
             }
-            Err(e) => {
-                println!("{}✗ {}{}", YELLOW, e, RESET);
-            }
+
+            selections.into_iter().map(|idx| detected[idx]).collect()
+        }
+    };
+
+    say!();
+    say!("Configuring {} tool(s)...", selected.len());
+
+    // Configure selected tools
+    let mut failed = 0;
+    for tool in &selected {
+        if !configure_tool(tool, &proxy_url) {
+            failed += 1;
         }
     }
 
-    println!();
+    say!();
 
     // Verify daemon is reachable at the configured URL
-    let (host, port) = crate::config::get_daemon_host_port();
     let addr = format!("{}:{}", host, port);
     let reachable = addr
         .parse::<std::net::SocketAddr>()
@@ -757,18 +902,77 @@ pub fn run_setup_command(args: &[String]) {
         .is_some();
 
     if reachable {
-        println!("{}✓{} Daemon is running at {}", GREEN, RESET, proxy_url);
+        say!("{}✓{} Daemon is running at {}", GREEN, RESET, proxy_url);
     } else {
         println!(
             "{}!{} Daemon is not running at {}",
             YELLOW, RESET, proxy_url
         );
-        println!("  {}Start it with: agcp{}", DIM, RESET);
+        if opts.port.is_some_and(|p| p != daemon_port) {
+            say!("  {}Start it with: agcp --port {}{}", DIM, port, RESET);
+        } else {
+            say!("  {}Start it with: agcp{}", DIM, RESET);
+        }
+    }
+
+    say!();
+    say!(
+        "{}Done! Run 'agcp setup --undo' to restore previous configs.{}",
+        DIM,
+        RESET
+    );
+    say!();
+
+    // Let provisioning scripts notice partial failures
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Shorten a path under the home directory to `~/...` for display
+fn display_path(path: &Path) -> String {
+    match dirs::home_dir().and_then(|home| path.strip_prefix(home).ok().map(PathBuf::from)) {
+        // Join with '/' so the `~/` prefix doesn't mix separators on Windows
+        Some(rest) => {
+            let parts: Vec<_> = rest
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            format!("~/{}", parts.join("/"))
+        }
+        None => path.display().to_string(),
+    }
+}
+
+/// List the tools setup can configure, without changing anything
+fn run_list() {
+    let (host, port) = crate::config::get_daemon_host_port();
+    let proxy_url = format!("http://{}:{}", host, port);
+
+    println!();
+    println!("{}Supported tools{}", BOLD, RESET);
+    println!();
+
+    let tools = get_tools();
+    for tool in &tools {
+        let status = if (tool.is_configured)(&tool.config_path, &proxy_url) {
+            format!("{}configured{}", GREEN, RESET)
+        } else if (tool.detect)(&tool.config_path) {
+            format!("{}detected{}", CYAN, RESET)
+        } else {
+            format!("{}not detected{}", DIM, RESET)
+        };
+        println!("  {:<12} {:<12} {}", tool.name, tool.id, status);
+        println!("  {}{}{}", DIM, display_path(&tool.config_path), RESET);
     }
 
     println!();
     println!(
-        "{}Done! Run 'agcp setup --undo' to restore previous configs.{}",
+        "{}\"configured\" means the config already points at {}{}",
+        DIM, proxy_url, RESET
+    );
+    println!(
+        "{}Configure one with: agcp setup --tool <name>{}",
         DIM, RESET
     );
     println!();
@@ -776,9 +980,9 @@ pub fn run_setup_command(args: &[String]) {
 
 /// Run the undo command
 fn run_undo() {
-    println!();
-    println!("{}{}Restoring configurations...{}", BOLD, GREEN, RESET);
-    println!();
+    say!();
+    say!("{}{}Restoring configurations...{}", BOLD, GREEN, RESET);
+    say!();
 
     let tools = get_tools();
     let mut restored = 0;
@@ -800,22 +1004,71 @@ fn run_undo() {
         }
     }
 
-    println!();
+    say!();
     if restored > 0 {
-        println!(
+        say!(
             "{}Done! Restored {} configuration(s).{}",
-            DIM, restored, RESET
+            DIM,
+            restored,
+            RESET
         );
     } else {
         println!("{}No backups found to restore.{}", DIM, RESET);
     }
-    println!();
+    say!();
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_setup_args() {
+        let parsed = parse_setup_args(&args(&[
+            "--tool",
+            "claude-code,zed",
+            "--tool",
+            "codex",
+            "--port",
+            "9090",
+            "--non-interactive",
+        ]))
+        .unwrap();
+        assert_eq!(parsed.tools, vec!["claude-code", "zed", "codex"]);
+        assert_eq!(parsed.port, Some(9090));
+        assert!(parsed.non_interactive);
+
+        assert_eq!(parse_setup_args(&[]).unwrap(), SetupArgs::default());
+        assert!(parse_setup_args(&args(&["--tool"])).is_err());
+        assert!(parse_setup_args(&args(&["--port", "0"])).is_err());
+        assert!(parse_setup_args(&args(&["--port", "http"])).is_err());
+        assert!(parse_setup_args(&args(&["--bogus"])).is_err());
+    }
+
+    #[test]
+    fn test_display_path() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(
+            display_path(&home.join(".claude").join("settings.json")),
+            "~/.claude/settings.json"
+        );
+        assert_eq!(display_path(Path::new("/etc/agcp.json")), "/etc/agcp.json");
+    }
+
+    #[test]
+    fn test_find_tool() {
+        let tools = get_tools();
+        assert_eq!(find_tool(&tools, "claude-code").unwrap().id, "claude-code");
+        assert_eq!(find_tool(&tools, "Claude Code").unwrap().id, "claude-code");
+        assert_eq!(find_tool(&tools, "OPENCODE").unwrap().id, "opencode");
+        assert_eq!(find_tool(&tools, "zed").unwrap().id, "zed");
+        assert!(find_tool(&tools, "vim").is_none());
+    }
+
     #[test]
     fn test_strip_jsonc_comments() {
         // Line comments
@@ -10,6 +10,18 @@ const RATE_HISTORY_SIZE: usize = 60;
 /// Maximum number of token events to keep for time-series display
 const MAX_TOKEN_EVENTS: usize = 1000;
 
+/// Upper bounds (inclusive, in bytes) of the payload size histogram buckets.
+/// Sizes above the last bound fall into a final overflow bucket.
+const SIZE_BUCKET_BOUNDS: [u64; 7] = [
+    1024,
+    4 * 1024,
+    16 * 1024,
+    64 * 1024,
+    256 * 1024,
+    1024 * 1024,
+    4 * 1024 * 1024,
+];
+
 /// Global stats instance
 static STATS: std::sync::LazyLock<Stats> = std::sync::LazyLock::new(Stats::new);
 
@@ -36,6 +48,8 @@ struct PersistentTokenCounters {
     input: u64,
     output: u64,
     cache_read: u64,
+    #[serde(default)]
+    cache_creation: u64,
 }
 
 /// Per-model token counters (atomic for lock-free reads)
@@ -43,6 +57,7 @@ struct TokenCounters {
     input_tokens: AtomicU64,
     output_tokens: AtomicU64,
     cache_read_tokens: AtomicU64,
+    cache_creation_tokens: AtomicU64,
 }
 
 impl TokenCounters {
@@ -51,6 +66,7 @@ impl TokenCounters {
             input_tokens: AtomicU64::new(0),
             output_tokens: AtomicU64::new(0),
             cache_read_tokens: AtomicU64::new(0),
+            cache_creation_tokens: AtomicU64::new(0),
         }
     }
 }
@@ -69,6 +85,50 @@ pub struct TokenEvent {
     pub output_tokens: u32,
     /// Cache read tokens
     pub cache_read_tokens: u32,
+    /// Cache creation tokens
+    pub cache_creation_tokens: u32,
+}
+
+/// Bucketed histogram of payload sizes (lock-free)
+struct SizeHistogram {
+    /// One counter per bound in `SIZE_BUCKET_BOUNDS`, plus an overflow bucket
+    buckets: [AtomicU64; SIZE_BUCKET_BOUNDS.len() + 1],
+    /// Number of recorded samples
+    count: AtomicU64,
+    /// Sum of all recorded sizes in bytes
+    total_bytes: AtomicU64,
+}
+
+impl SizeHistogram {
+    fn new() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            total_bytes: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, bytes: u64) {
+        let idx = SIZE_BUCKET_BOUNDS
+            .iter()
+            .position(|&bound| bytes <= bound)
+            .unwrap_or(SIZE_BUCKET_BOUNDS.len());
+        self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> SizeHistogramSummary {
+        SizeHistogramSummary {
+            buckets: self
+                .buckets
+                .iter()
+                .map(|b| b.load(Ordering::Relaxed))
+                .collect(),
+            count: self.count.load(Ordering::Relaxed),
+            total_bytes: self.total_bytes.load(Ordering::Relaxed),
+        }
+    }
 }
 
 /// Request/response statistics
@@ -85,6 +145,10 @@ pub struct Stats {
     token_counters: RwLock<HashMap<String, TokenCounters>>,
     /// Time-series of token events for graphing
     token_events: RwLock<VecDeque<TokenEvent>>,
+    /// Request body sizes in bytes
+    request_sizes: SizeHistogram,
+    /// Response body sizes in bytes (buffered responses only)
+    response_sizes: SizeHistogram,
 }
 
 /// Tracks requests per second over time
@@ -167,6 +231,8 @@ impl Stats {
             rate_history: RwLock::new(RateHistory::new()),
             token_counters: RwLock::new(HashMap::new()),
             token_events: RwLock::new(VecDeque::with_capacity(MAX_TOKEN_EVENTS)),
+            request_sizes: SizeHistogram::new(),
+            response_sizes: SizeHistogram::new(),
         };
         stats.load_persistent();
         stats
@@ -207,6 +273,9 @@ impl Stats {
                 entry
                     .cache_read_tokens
                     .fetch_add(tc.cache_read, Ordering::Relaxed);
+                entry
+                    .cache_creation_tokens
+                    .fetch_add(tc.cache_creation, Ordering::Relaxed);
             }
         }
     }
@@ -238,6 +307,7 @@ impl Stats {
                         input: v.input_tokens.load(Ordering::Relaxed),
                         output: v.output_tokens.load(Ordering::Relaxed),
                         cache_read: v.cache_read_tokens.load(Ordering::Relaxed),
+                        cache_creation: v.cache_creation_tokens.load(Ordering::Relaxed),
                     },
                 )
             })
@@ -281,6 +351,7 @@ impl Stats {
         input_tokens: u32,
         output_tokens: u32,
         cache_read_tokens: u32,
+        cache_creation_tokens: u32,
     ) {
         // Update per-model cumulative counters
         {
@@ -292,6 +363,8 @@ impl Stats {
                     .fetch_add(output_tokens as u64, Ordering::Relaxed);
                 c.cache_read_tokens
                     .fetch_add(cache_read_tokens as u64, Ordering::Relaxed);
+                c.cache_creation_tokens
+                    .fetch_add(cache_creation_tokens as u64, Ordering::Relaxed);
                 // Fall through to record event
             } else {
                 drop(counters);
@@ -308,6 +381,9 @@ impl Stats {
                 entry
                     .cache_read_tokens
                     .fetch_add(cache_read_tokens as u64, Ordering::Relaxed);
+                entry
+                    .cache_creation_tokens
+                    .fetch_add(cache_creation_tokens as u64, Ordering::Relaxed);
             }
         }
 
@@ -319,6 +395,7 @@ impl Stats {
             input_tokens,
             output_tokens,
             cache_read_tokens,
+            cache_creation_tokens,
         };
         let mut events = self.token_events.write();
         if events.len() >= MAX_TOKEN_EVENTS {
@@ -327,6 +404,16 @@ impl Stats {
         events.push_back(event);
     }
 
+    /// Record the size of a request body in bytes
+    pub fn record_request_size(&self, bytes: usize) {
+        self.request_sizes.record(bytes as u64);
+    }
+
+    /// Record the size of a response body in bytes
+    pub fn record_response_size(&self, bytes: usize) {
+        self.response_sizes.record(bytes as u64);
+    }
+
     /// Get uptime
     pub fn uptime(&self) -> Duration {
         self.start_time.elapsed()
@@ -353,6 +440,8 @@ impl Stats {
             endpoints: self.get_endpoint_stats(),
             rate_history: self.get_rate_history(),
             token_usage: self.get_token_usage(),
+            request_sizes: self.request_sizes.snapshot(),
+            response_sizes: self.response_sizes.snapshot(),
         }
     }
 
@@ -362,21 +451,24 @@ impl Stats {
         requests
             .iter()
             .map(|(model, count)| {
-                let (input, output, cache_read) = if let Some(tc) = token_counters.get(model) {
-                    (
-                        tc.input_tokens.load(Ordering::Relaxed),
-                        tc.output_tokens.load(Ordering::Relaxed),
-                        tc.cache_read_tokens.load(Ordering::Relaxed),
-                    )
-                } else {
-                    (0, 0, 0)
-                };
+                let (input, output, cache_read, cache_creation) =
+                    if let Some(tc) = token_counters.get(model) {
+                        (
+                            tc.input_tokens.load(Ordering::Relaxed),
+                            tc.output_tokens.load(Ordering::Relaxed),
+                            tc.cache_read_tokens.load(Ordering::Relaxed),
+                            tc.cache_creation_tokens.load(Ordering::Relaxed),
+                        )
+                    } else {
+                        (0, 0, 0, 0)
+                    };
                 ModelStats {
                     model: model.clone(),
                     requests: count.load(Ordering::Relaxed),
                     input_tokens: input,
                     output_tokens: output,
                     cache_read_tokens: cache_read,
+                    cache_creation_tokens: cache_creation,
                 }
             })
             .collect()
@@ -398,15 +490,18 @@ impl Stats {
         let mut total_input = 0u64;
         let mut total_output = 0u64;
         let mut total_cache_read = 0u64;
+        let mut total_cache_creation = 0u64;
         for tc in counters.values() {
             total_input += tc.input_tokens.load(Ordering::Relaxed);
             total_output += tc.output_tokens.load(Ordering::Relaxed);
             total_cache_read += tc.cache_read_tokens.load(Ordering::Relaxed);
+            total_cache_creation += tc.cache_creation_tokens.load(Ordering::Relaxed);
         }
         TokenUsageSummary {
             total_input_tokens: total_input,
             total_output_tokens: total_output,
             total_cache_read_tokens: total_cache_read,
+            total_cache_creation_tokens: total_cache_creation,
         }
     }
 
@@ -439,6 +534,8 @@ pub struct StatsSummary {
     pub endpoints: Vec<EndpointStats>,
     pub rate_history: Vec<u64>,
     pub token_usage: TokenUsageSummary,
+    pub request_sizes: SizeHistogramSummary,
+    pub response_sizes: SizeHistogramSummary,
 }
 
 #[derive(Debug, Clone)]
@@ -448,6 +545,7 @@ pub struct ModelStats {
     pub input_tokens: u64,
     pub output_tokens: u64,
     pub cache_read_tokens: u64,
+    pub cache_creation_tokens: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -461,6 +559,38 @@ pub struct TokenUsageSummary {
     pub total_input_tokens: u64,
     pub total_output_tokens: u64,
     pub total_cache_read_tokens: u64,
+    pub total_cache_creation_tokens: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SizeHistogramSummary {
+    /// Counts per bucket, aligned with `SIZE_BUCKET_BOUNDS` plus overflow
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub total_bytes: u64,
+}
+
+impl SizeHistogramSummary {
+    fn to_json(&self) -> serde_json::Value {
+        let buckets: Vec<_> = self
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                serde_json::json!({
+                    "le_bytes": SIZE_BUCKET_BOUNDS.get(i),
+                    "count": count,
+                })
+            })
+            .collect();
+        let average = self.total_bytes.checked_div(self.count).unwrap_or(0);
+        serde_json::json!({
+            "count": self.count,
+            "total_bytes": self.total_bytes,
+            "average_bytes": average,
+            "buckets": buckets,
+        })
+    }
 }
 
 impl StatsSummary {
@@ -475,6 +605,7 @@ impl StatsSummary {
                 "input_tokens": m.input_tokens,
                 "output_tokens": m.output_tokens,
                 "cache_read_tokens": m.cache_read_tokens,
+                "cache_creation_tokens": m.cache_creation_tokens,
             })).collect::<Vec<_>>(),
             "endpoints": self.endpoints.iter().map(|e| serde_json::json!({
                 "endpoint": e.endpoint,
@@ -485,6 +616,11 @@ impl StatsSummary {
                 "total_input_tokens": self.token_usage.total_input_tokens,
                 "total_output_tokens": self.token_usage.total_output_tokens,
                 "total_cache_read_tokens": self.token_usage.total_cache_read_tokens,
+                "total_cache_creation_tokens": self.token_usage.total_cache_creation_tokens,
+            },
+            "sizes": {
+                "request": self.request_sizes.to_json(),
+                "response": self.response_sizes.to_json(),
             },
         })
     }
@@ -503,6 +639,8 @@ mod tests {
             rate_history: RwLock::new(RateHistory::new()),
             token_counters: RwLock::new(HashMap::new()),
             token_events: RwLock::new(VecDeque::with_capacity(MAX_TOKEN_EVENTS)),
+            request_sizes: SizeHistogram::new(),
+            response_sizes: SizeHistogram::new(),
         }
     }
 
@@ -542,9 +680,9 @@ mod tests {
     fn test_stats_token_usage() {
         let stats = fresh_stats();
         stats.record_request("claude-sonnet-4-5", "/v1/messages");
-        stats.record_token_usage("claude-sonnet-4-5", 100, 200, 50);
-        stats.record_token_usage("claude-sonnet-4-5", 150, 300, 0);
-        stats.record_token_usage("gemini-3-flash", 80, 160, 0);
+        stats.record_token_usage("claude-sonnet-4-5", 100, 200, 50, 20);
+        stats.record_token_usage("claude-sonnet-4-5", 150, 300, 0, 0);
+        stats.record_token_usage("gemini-3-flash", 80, 160, 0, 0);
 
         let summary = stats.summary();
 
@@ -557,11 +695,13 @@ mod tests {
         assert_eq!(sonnet.input_tokens, 250);
         assert_eq!(sonnet.output_tokens, 500);
         assert_eq!(sonnet.cache_read_tokens, 50);
+        assert_eq!(sonnet.cache_creation_tokens, 20);
 
         // Check totals
         assert_eq!(summary.token_usage.total_input_tokens, 330);
         assert_eq!(summary.token_usage.total_output_tokens, 660);
         assert_eq!(summary.token_usage.total_cache_read_tokens, 50);
+        assert_eq!(summary.token_usage.total_cache_creation_tokens, 20);
 
         // Check events
         let events = stats.get_token_events();
@@ -574,7 +714,7 @@ mod tests {
     fn test_stats_token_json() {
         let stats = fresh_stats();
         stats.record_request("test-model", "/v1/messages");
-        stats.record_token_usage("test-model", 100, 200, 0);
+        stats.record_token_usage("test-model", 100, 200, 0, 0);
 
         let json = stats.summary().to_json();
         let token_usage = &json["token_usage"];
@@ -585,4 +725,27 @@ mod tests {
         assert_eq!(model["input_tokens"].as_u64(), Some(100));
         assert_eq!(model["output_tokens"].as_u64(), Some(200));
     }
+
+    #[test]
+    fn test_stats_size_histogram() {
+        let stats = fresh_stats();
+        stats.record_request_size(500);
+        stats.record_request_size(1024);
+        stats.record_request_size(2048);
+        stats.record_response_size(10 * 1024 * 1024);
+
+        let json = stats.summary().to_json();
+        let request = &json["sizes"]["request"];
+        assert_eq!(request["count"].as_u64(), Some(3));
+        assert_eq!(request["total_bytes"].as_u64(), Some(3572));
+        assert_eq!(request["buckets"][0]["le_bytes"].as_u64(), Some(1024));
+        assert_eq!(request["buckets"][0]["count"].as_u64(), Some(2));
+        assert_eq!(request["buckets"][1]["count"].as_u64(), Some(1));
+
+        // Oversized payloads land in the overflow bucket
+        let response = &json["sizes"]["response"];
+        let overflow = &response["buckets"][SIZE_BUCKET_BOUNDS.len()];
+        assert!(overflow["le_bytes"].is_null());
+        assert_eq!(overflow["count"].as_u64(), Some(1));
+    }
 }
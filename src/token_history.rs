@@ -0,0 +1,351 @@
+//! Per-model token usage history.
+//!
+//! Persisted to `token_history.json` by the server so headless runs keep a
+//! usage history, and read by the TUI's Usage chart.
+
+/// Maximum number of data points to keep in the token history
+/// With change-only recording, this covers many hours of active use
+const TOKEN_HISTORY_MAX_POINTS: usize = 5000;
+
+/// A single timestamped snapshot of cumulative token usage
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TokenSnapshot {
+    /// Unix timestamp (seconds since epoch)
+    pub timestamp: u64,
+    /// Per-model cumulative totals: (model_name, total_tokens)
+    pub models: Vec<(String, u64)>,
+}
+
+/// Persistent time-series of cumulative token usage per model.
+/// The server takes a snapshot on an interval and persists it to disk, so it
+/// survives restarts and is recorded even when no TUI is attached; the TUI
+/// only reads it.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TokenHistory {
+    /// Ordered list of snapshots
+    pub snapshots: Vec<TokenSnapshot>,
+    /// Unix timestamp of the quota period start (set from quota reset_time - 24h)
+    #[serde(default)]
+    pub period_start: Option<u64>,
+    /// Per-model offsets to add when the server restarts (so the line never drops)
+    #[serde(default)]
+    offsets: std::collections::HashMap<String, u64>,
+    /// Per-model peak stored values — survives model disappearing from stats after
+    /// server restart so we can still detect drops when the model reappears.
+    #[serde(default)]
+    peak_values: std::collections::HashMap<String, u64>,
+    /// Unix timestamp when this TUI session started (not persisted).
+    #[serde(skip)]
+    session_start: Option<u64>,
+}
+
+impl Default for TokenHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TokenHistory {
+    pub fn new() -> Self {
+        Self {
+            snapshots: Vec::new(),
+            period_start: None,
+            offsets: std::collections::HashMap::new(),
+            peak_values: std::collections::HashMap::new(),
+            session_start: None,
+        }
+    }
+
+    /// Load from the persistence file, or return a new empty history
+    pub fn load() -> Self {
+        let path = Self::path();
+        let mut history: Self = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
+            Err(_) => return Self::new(),
+        };
+
+        // Rebuild peak_values from snapshots if empty (backward compat with old files)
+        if history.peak_values.is_empty() {
+            for snap in &history.snapshots {
+                for (name, val) in &snap.models {
+                    let peak = history.peak_values.entry(name.clone()).or_insert(0);
+                    *peak = (*peak).max(*val);
+                }
+            }
+        }
+
+        // Deduplicate consecutive snapshots with identical model values
+        history.snapshots.dedup_by(|b, a| {
+            a.models.len() == b.models.len()
+                && a.models.iter().all(|(name, val)| {
+                    b.models
+                        .iter()
+                        .find(|(n, _)| n == name)
+                        .map(|(_, t)| t == val)
+                        .unwrap_or(false)
+                })
+        });
+
+        history.session_start = Some(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        );
+
+        history
+    }
+
+    /// Re-read the persistence file, keeping this session's start time
+    pub fn reload(&mut self) {
+        let session_start = self.session_start;
+        *self = Self::load();
+        self.session_start = session_start;
+    }
+
+    /// Save to the persistence file. Written atomically since the TUI reads
+    /// it while the server writes it.
+    pub fn save(&self) {
+        let path = Self::path();
+        if let Ok(json) = serde_json::to_string(self) {
+            let _ = crate::auth::accounts::write_atomic(&path, json.as_bytes());
+        }
+    }
+
+    /// Path to the persistence file
+    fn path() -> std::path::PathBuf {
+        crate::config::Config::dir().join("token_history.json")
+    }
+
+    /// Record a new snapshot of per-model cumulative totals (input + output
+    /// tokens) as reported by the server's stats.
+    /// Returns true if the snapshot was added (i.e., there's new data).
+    pub fn push(&mut self, raw_models: Vec<(String, u64)>) -> bool {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        // Skip if no token data
+        if raw_models.is_empty() || raw_models.iter().all(|(_, t)| *t == 0) {
+            return false;
+        }
+
+        // Detect server restart: if any model's raw value + current offset
+        // is less than the peak stored value, the server counters reset.
+        // We use peak_values (not last_snap) so models that disappear after
+        // a restart and later reappear still get correct offset treatment.
+        for (name, raw_total) in &raw_models {
+            let peak = self.peak_values.get(name).copied().unwrap_or(0);
+            let current_offset = self.offsets.get(name).copied().unwrap_or(0);
+
+            if peak > 0 && raw_total + current_offset < peak {
+                // Server restarted — set offset so new usage builds on previous peak
+                self.offsets.insert(name.clone(), peak);
+            }
+        }
+
+        // Apply offsets and enforce monotonicity (line never goes down)
+        let models: Vec<(String, u64)> = raw_models
+            .into_iter()
+            .map(|(name, raw)| {
+                let offset = self.offsets.get(&name).copied().unwrap_or(0);
+                let value = raw + offset;
+
+                // Extra safety: clamp to at least the peak stored value
+                let peak = self.peak_values.get(&name).copied().unwrap_or(0);
+                (name, value.max(peak))
+            })
+            .collect();
+
+        // Skip if values haven't changed since the last snapshot (idle period)
+        if let Some(last) = self.snapshots.last() {
+            let unchanged = models.len() == last.models.len()
+                && models.iter().all(|(name, val)| {
+                    last.models
+                        .iter()
+                        .find(|(n, _)| n == name)
+                        .map(|(_, t)| t == val)
+                        .unwrap_or(false)
+                });
+            if unchanged {
+                return false;
+            }
+        }
+
+        // Update peak values
+        for (name, val) in &models {
+            let peak = self.peak_values.entry(name.clone()).or_insert(0);
+            *peak = (*peak).max(*val);
+        }
+
+        self.snapshots.push(TokenSnapshot {
+            timestamp: now,
+            models,
+        });
+
+        // Trim old snapshots
+        if self.snapshots.len() > TOKEN_HISTORY_MAX_POINTS {
+            let excess = self.snapshots.len() - TOKEN_HISTORY_MAX_POINTS;
+            self.snapshots.drain(..excess);
+        }
+
+        true
+    }
+
+    /// Update the quota period start time from quota data.
+    /// Assumes a 24-hour quota period ending at `reset_ts` (Unix seconds).
+    pub fn set_period_from_reset_time(&mut self, reset_ts: u64) {
+        // Quota periods are typically 24 hours
+        self.period_start = Some(reset_ts.saturating_sub(86400));
+    }
+
+    /// Record the server's current token totals: start a new period if the
+    /// quota has reset since the first snapshot, then take a snapshot.
+    /// `reset_ts` is the earliest known quota reset time (Unix seconds).
+    pub fn record(&mut self, raw_models: Vec<(String, u64)>, reset_ts: Option<u64>) -> bool {
+        if let Some(reset_ts) = reset_ts {
+            if self.should_reset(reset_ts) {
+                self.snapshots.clear();
+                self.offsets.clear();
+                self.peak_values.clear();
+                self.set_period_from_reset_time(reset_ts);
+            } else if self.period_start.is_none() {
+                self.set_period_from_reset_time(reset_ts);
+            }
+        }
+        self.push(raw_models)
+    }
+
+    /// Reset the history (clear all snapshots). Called when quota period resets.
+    pub fn reset(&mut self) {
+        self.snapshots.clear();
+        self.offsets.clear();
+        self.peak_values.clear();
+        self.save();
+    }
+
+    /// Check if the quota period has ended (current time > reset_time).
+    /// If reset_time is in the past, we should reset.
+    pub fn should_reset(&self, reset_ts: u64) -> bool {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        // Only reset if we have snapshots that are from before the reset time
+        // and the reset has already happened
+        if now >= reset_ts
+            && let Some(first) = self.snapshots.first()
+        {
+            return first.timestamp < reset_ts;
+        }
+        false
+    }
+
+    /// Get per-model cumulative data for the chart.
+    /// Returns Vec of (model_name, Vec<(x, y)>) where:
+    /// - x = minutes since session start
+    /// - y = cumulative total tokens
+    ///
+    /// Only includes snapshots from the current session. The last snapshot
+    /// before session start is included as a baseline at x=0 so lines begin
+    /// at the correct cumulative Y value.
+    pub fn get_cumulative_series(&self) -> Vec<(String, Vec<(f64, f64)>)> {
+        if self.snapshots.is_empty() {
+            return Vec::new();
+        }
+
+        let origin = self.session_start.unwrap_or(self.snapshots[0].timestamp);
+
+        // Find the baseline: last snapshot at or before session start, or 0
+        let start_idx = self
+            .snapshots
+            .iter()
+            .rposition(|s| s.timestamp <= origin)
+            .unwrap_or(0);
+
+        let session_snapshots = &self.snapshots[start_idx..];
+
+        // Collect all unique model names from session snapshots
+        let mut model_names: Vec<String> = Vec::new();
+        for snap in session_snapshots {
+            for (name, _) in &snap.models {
+                if !model_names.contains(name) {
+                    model_names.push(name.clone());
+                }
+            }
+        }
+
+        let mut result = Vec::new();
+        for model_name in &model_names {
+            let mut points = Vec::new();
+            for snap in session_snapshots {
+                // Clamp to 0 so the baseline snapshot sits at x=0
+                let x = (snap.timestamp.saturating_sub(origin)) as f64 / 60.0;
+                let y = snap
+                    .models
+                    .iter()
+                    .find(|(n, _)| n == model_name)
+                    .map(|(_, t)| *t as f64)
+                    .unwrap_or(0.0);
+                points.push((x, y));
+            }
+            // Only include models with non-zero data
+            if points.iter().any(|(_, y)| *y > 0.0) {
+                result.push((model_name.clone(), points));
+            }
+        }
+        result
+    }
+
+    /// Get the time range in minutes for the X axis.
+    /// Scoped to the current TUI session so the chart doesn't stretch across
+    /// days of persisted history.
+    pub fn get_time_range_minutes(&self) -> f64 {
+        let origin = self
+            .session_start
+            .unwrap_or_else(|| self.snapshots.first().map(|s| s.timestamp).unwrap_or(0));
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let range = (now.saturating_sub(origin)) as f64 / 60.0;
+        range.max(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_builds_monotonic_history() {
+        let mut history = TokenHistory::new();
+        assert!(history.record(vec![("model-a".to_string(), 100)], None));
+        // Unchanged totals are not recorded again
+        assert!(!history.record(vec![("model-a".to_string(), 100)], None));
+
+        // A server restart resets the counters; the series keeps climbing
+        assert!(history.record(vec![("model-a".to_string(), 40)], None));
+        let last = history.snapshots.last().unwrap();
+        assert_eq!(last.models, vec![("model-a".to_string(), 140)]);
+    }
+
+    #[test]
+    fn test_record_starts_new_period_after_quota_reset() {
+        let mut history = TokenHistory::new();
+        history.snapshots.push(TokenSnapshot {
+            timestamp: 1_000,
+            models: vec![("model-a".to_string(), 500)],
+        });
+
+        // Reset time already passed and the history predates it
+        assert!(history.record(vec![("model-a".to_string(), 10)], Some(2_000)));
+        assert_eq!(history.snapshots.len(), 1);
+        assert_eq!(
+            history.snapshots[0].models,
+            vec![("model-a".to_string(), 10)]
+        );
+        assert_eq!(history.period_start, Some(0));
+    }
+}
@@ -53,7 +53,8 @@ fn detect_runtime_warning_message(entries: &[super::data::LogEntry]) -> Option<&
 }
 
 /// Available tabs in the TUI
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Tab {
     #[default]
     Overview,
@@ -80,6 +81,14 @@ impl Tab {
         ]
     }
 
+    /// Look up a tab by its display name, case-insensitively.
+    pub fn from_name(name: &str) -> Option<Tab> {
+        Tab::all()
+            .iter()
+            .copied()
+            .find(|tab| tab.name().eq_ignore_ascii_case(name))
+    }
+
     pub fn name(&self) -> &'static str {
         match self {
             Tab::Overview => "Overview",
@@ -135,10 +144,113 @@ pub enum UpdateStatus {
     Error(String),
 }
 
+/// Lifecycle of the test request modal
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestRequestStatus {
+    /// Typing the prompt
+    Editing,
+    /// Waiting for / receiving the streamed response
+    Sending,
+    /// Response finished
+    Done,
+    /// Request failed
+    Failed(String),
+}
+
+/// State of the "Test request" modal opened from the Overview tab
+pub struct TestRequest {
+    pub prompt: String,
+    pub response: String,
+    pub status: TestRequestStatus,
+    /// ID of the account that served the response, as reported by the server
+    pub account_id: Option<String>,
+    /// ID of the account that was active when the modal was opened
+    pub active_account_id: Option<String>,
+    /// Scroll offset (lines) into the response
+    pub scroll: u16,
+    /// Receiver for streamed response events
+    receiver: Option<mpsc::Receiver<super::data::TestRequestEvent>>,
+}
+
+impl TestRequest {
+    fn new(active_account_id: Option<String>) -> Self {
+        Self {
+            prompt: String::new(),
+            response: String::new(),
+            status: TestRequestStatus::Editing,
+            account_id: None,
+            active_account_id,
+            scroll: 0,
+            receiver: None,
+        }
+    }
+
+    /// Whether the server reported serving the request from an account other
+    /// than the active one (the strategy or a retry picked another)
+    pub fn served_by_other_account(&self) -> bool {
+        matches!(
+            (&self.account_id, &self.active_account_id),
+            (Some(served), Some(active)) if served != active
+        )
+    }
+
+    /// Send the prompt to the local server on a background thread
+    fn send(&mut self) {
+        let prompt = self.prompt.trim().to_string();
+        if prompt.is_empty() {
+            return;
+        }
+        self.response.clear();
+        self.account_id = None;
+        self.scroll = 0;
+        self.status = TestRequestStatus::Sending;
+
+        let (tx, rx) = mpsc::channel();
+        self.receiver = Some(rx);
+        std::thread::spawn(move || {
+            super::data::DataProvider::stream_test_request(&prompt, &tx);
+        });
+    }
+
+    /// Drain streamed events into the response buffer
+    fn poll(&mut self) {
+        use super::data::TestRequestEvent;
+
+        let Some(ref receiver) = self.receiver else {
+            return;
+        };
+        loop {
+            match receiver.try_recv() {
+                Ok(TestRequestEvent::Account(id)) => self.account_id = Some(id),
+                Ok(TestRequestEvent::Text(text)) => self.response.push_str(&text),
+                Ok(TestRequestEvent::Done) => {
+                    self.status = TestRequestStatus::Done;
+                    self.receiver = None;
+                    return;
+                }
+                Ok(TestRequestEvent::Error(e)) => {
+                    self.status = TestRequestStatus::Failed(e);
+                    self.receiver = None;
+                    return;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.status =
+                        TestRequestStatus::Failed("Test request ended unexpectedly".to_string());
+                    self.receiver = None;
+                    return;
+                }
+                Err(mpsc::TryRecvError::Empty) => return,
+            }
+        }
+    }
+}
+
 /// Sort mode for accounts list
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AccountSort {
     /// Default order (as loaded)
+    #[default]
     Default,
     /// Email A-Z
     EmailAsc,
@@ -176,6 +288,48 @@ impl AccountSort {
     }
 }
 
+/// UI preferences persisted across TUI sessions (no data, only view settings)
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct TuiState {
+    tab: Tab,
+    account_sort: AccountSort,
+    /// Debug, Info, Warn, Error
+    log_level_filter: [bool; 4],
+}
+
+impl Default for TuiState {
+    fn default() -> Self {
+        Self {
+            tab: Tab::default(),
+            account_sort: AccountSort::default(),
+            log_level_filter: [true; 4],
+        }
+    }
+}
+
+impl TuiState {
+    /// Load from the persistence file, falling back to defaults
+    fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save to the persistence file
+    fn save(&self) {
+        if let Ok(json) = serde_json::to_string_pretty(self) {
+            let _ = std::fs::write(Self::path(), json);
+        }
+    }
+
+    /// Path to the persistence file
+    fn path() -> std::path::PathBuf {
+        crate::config::Config::dir().join("tui-state.json")
+    }
+}
+
 /// Main application state
 pub struct App {
     pub running: bool,
@@ -189,7 +343,8 @@ pub struct App {
     pub log_scroll: usize,
     /// Scrollbar state for logs view
     pub log_scrollbar_state: ScrollbarState,
-    /// Whether to auto-scroll to bottom when new logs arrive
+    /// Follow mode: stay at the bottom as new logs arrive. When off, the view
+    /// is frozen on the lines currently shown.
     pub log_auto_scroll: bool,
     /// Log tailer for following new log entries
     pub log_tailer: LogTailer,
@@ -275,6 +430,8 @@ pub struct App {
     pub show_startup_warnings: bool,
     /// Runtime warning popup message for high-priority live errors
     pub runtime_warning_message: Option<String>,
+    /// Test request modal (open when Some)
+    pub test_request: Option<TestRequest>,
     /// Receiver for background startup warnings collection
     startup_warnings_receiver: Option<mpsc::Receiver<Vec<super::widgets::StartupWarning>>>,
     /// About page: cached inner area for mouse detection
@@ -319,8 +476,8 @@ pub struct App {
     pub token_history: super::data::TokenHistory,
     /// Last time token stats were fetched
     last_token_stats_refresh: Instant,
-    /// Last time token history was saved to disk
-    last_token_history_save: Instant,
+    /// Last time token history was re-read from disk
+    last_token_history_load: Instant,
     /// Last tab area width used for tab_areas calculation (for invalidation)
     cached_tabs_area: Rect,
     // Log filtering and search state
@@ -332,6 +489,10 @@ pub struct App {
     pub log_search_active: bool,
     /// Search query string
     pub log_search_query: String,
+    /// Highlight search matches in place instead of filtering to them
+    pub log_search_highlight: bool,
+    /// Log line (index into self.logs) selected by n/N search navigation
+    pub log_search_match: Option<usize>,
     /// Cached filtered indices into self.logs (indices of entries that pass filters)
     pub log_filtered_indices: Vec<usize>,
     /// Whether the account filter dropdown is open
@@ -378,6 +539,8 @@ pub struct App {
     /// Status message for daemon control (e.g. "Started", "Stopped", error)
     /// (message, is_error, timestamp for auto-clear)
     pub daemon_status_message: Option<(String, bool, Instant)>,
+    /// Transient footer message (e.g. "Copied"), (message, is_warning, timestamp)
+    pub footer_message: Option<(String, bool, Instant)>,
 }
 
 impl App {
@@ -395,10 +558,11 @@ impl App {
         let log_count = logs.len();
 
         let data = super::data::DataProvider::new();
+        let state = TuiState::load();
 
-        Self {
+        let mut app = Self {
             running: true,
-            current_tab: Tab::default(),
+            current_tab: state.tab,
             effects: EffectManager::default(),
             data,
             logs,
@@ -410,7 +574,7 @@ impl App {
             account_selected: 0,
             account_search_active: false,
             account_search_query: String::new(),
-            account_sort: AccountSort::Default,
+            account_sort: state.account_sort,
             account_display_indices: Vec::new(),
             show_help: false,
             startup_done: false,
@@ -447,6 +611,7 @@ impl App {
             startup_warnings: Vec::new(),
             show_startup_warnings: false,
             runtime_warning_message,
+            test_request: None,
             startup_warnings_receiver: None,
             about_area: Rect::default(),
             about_link_hovered: false,
@@ -475,12 +640,14 @@ impl App {
             token_anim_start_ms: 0,
             token_history: super::data::TokenHistory::load(),
             last_token_stats_refresh: Instant::now() - Duration::from_secs(10),
-            last_token_history_save: Instant::now(),
+            last_token_history_load: Instant::now(),
             cached_tabs_area: Rect::default(),
-            log_level_filter: [true; 4],
+            log_level_filter: state.log_level_filter,
             log_account_filter: None,
             log_search_active: false,
             log_search_query: String::new(),
+            log_search_highlight: false,
+            log_search_match: None,
             log_filtered_indices: Vec::new(),
             log_account_dropdown_open: false,
             log_account_dropdown_selected: 0,
@@ -518,7 +685,26 @@ impl App {
             mapping_status: None,
             mapping_dirty: false,
             daemon_status_message: None,
+            footer_message: None,
+        };
+
+        if app.has_active_account_filter() {
+            app.refilter_accounts();
         }
+        if app.has_active_log_filter() {
+            app.refilter_logs();
+        }
+        app
+    }
+
+    /// Persist UI preferences (active tab, account sort, log level filters)
+    pub fn save_state(&self) {
+        TuiState {
+            tab: self.current_tab,
+            account_sort: self.account_sort,
+            log_level_filter: self.log_level_filter,
+        }
+        .save();
     }
 
     /// Refresh logs from file and update cached stats
@@ -528,8 +714,13 @@ impl App {
             self.runtime_warning_message = Some(message.to_string());
         }
         let new_count = new_entries.len();
+        let old_len = self.logs.len();
         super::log_reader::append_entries(&mut self.logs, new_entries);
 
+        // Keep the search selection on the same line as old entries are dropped
+        let dropped = (old_len + new_count).saturating_sub(self.logs.len());
+        self.log_search_match = self.log_search_match.and_then(|i| i.checked_sub(dropped));
+
         // If we were at the bottom (auto-scroll) and new logs came in, stay at bottom
         if new_count > 0 {
             if self.log_auto_scroll {
@@ -558,6 +749,30 @@ impl App {
         if self.has_active_log_filter() {
             self.refilter_logs();
         }
+
+        // Frozen: keep the same lines on screen as new ones arrive below
+        if new_count > 0 && !self.log_auto_scroll {
+            let first_new = self.logs.len().saturating_sub(new_count);
+            let added = if self.has_active_log_filter() {
+                self.log_filtered_indices
+                    .iter()
+                    .filter(|&&i| i >= first_new)
+                    .count()
+            } else {
+                new_count.min(self.logs.len())
+            };
+            self.log_scroll = (self.log_scroll + added).min(self.log_view_len().saturating_sub(1));
+            self.update_scrollbar();
+        }
+    }
+
+    /// Number of log lines in the current (possibly filtered) view
+    fn log_view_len(&self) -> usize {
+        if self.has_active_log_filter() {
+            self.log_filtered_indices.len()
+        } else {
+            self.logs.len()
+        }
     }
 
     /// Recompute overview stats from logs (called once per log refresh, not per frame)
@@ -600,44 +815,14 @@ impl App {
 
         self.cached_token_stats = new_stats;
 
-        // Update quota period from quota data
-        if self.token_history.period_start.is_none()
-            && let Some(reset_time) = self.get_earliest_reset_time()
-        {
-            self.token_history.set_period_from_reset_time(&reset_time);
-        }
-
-        // Check for quota period reset
-        if let Some(reset_time) = self.get_earliest_reset_time()
-            && self.token_history.should_reset(&reset_time)
-        {
-            self.token_history.reset();
-            // Update period_start for new period
-            self.token_history.set_period_from_reset_time(&reset_time);
-        }
-
-        // Push new snapshot
-        if let Some(ref stats) = self.cached_token_stats {
-            self.token_history.push(stats);
-        }
-
-        // Save to disk periodically (every 30 seconds) to avoid I/O overhead
-        if self.last_token_history_save.elapsed() >= Duration::from_secs(30) {
-            self.last_token_history_save = Instant::now();
-            self.token_history.save();
+        // The server records and persists token history; pick up its
+        // latest snapshots periodically to avoid I/O overhead
+        if self.last_token_history_load.elapsed() >= Duration::from_secs(30) {
+            self.last_token_history_load = Instant::now();
+            self.token_history.reload();
         }
     }
 
-    /// Get the earliest quota reset time from quota data
-    fn get_earliest_reset_time(&self) -> Option<String> {
-        self.quota_data
-            .values()
-            .flat_map(|quotas| quotas.iter())
-            .filter_map(|q| q.reset_time.as_ref())
-            .min()
-            .cloned()
-    }
-
     /// Duration of the token count-up animation in milliseconds
     const TOKEN_ANIM_DURATION_MS: u64 = 400;
 
@@ -700,8 +885,8 @@ impl App {
                 }
             }
 
-            // Check search query
-            if !self.log_search_query.is_empty() {
+            // Check search query (unless matches are only highlighted)
+            if !self.log_search_query.is_empty() && !self.log_search_highlight {
                 let query_lower = self.log_search_query.to_lowercase();
                 if !entry.line.to_lowercase().contains(&query_lower) {
                     continue;
@@ -716,7 +901,84 @@ impl App {
     pub fn has_active_log_filter(&self) -> bool {
         !self.log_level_filter.iter().all(|&v| v)
             || self.log_account_filter.is_some()
-            || !self.log_search_query.is_empty()
+            || (!self.log_search_query.is_empty() && !self.log_search_highlight)
+    }
+
+    /// Switch search between filtering the view and highlighting matches in place
+    pub fn toggle_log_search_highlight(&mut self) {
+        self.log_search_highlight = !self.log_search_highlight;
+        self.refilter_logs();
+        self.log_scroll = self.log_scroll.min(self.log_view_len().saturating_sub(1));
+        self.update_scrollbar();
+    }
+
+    /// Select the next (or previous) line in the current view that matches the
+    /// search query and scroll it to the bottom of the view
+    pub fn jump_to_log_match(&mut self, forward: bool) {
+        if self.log_search_query.is_empty() {
+            return;
+        }
+        let len = self.log_view_len();
+        let Some(bottom) = len.checked_sub(1 + self.log_scroll.min(len.saturating_sub(1))) else {
+            return;
+        };
+        // Search from the selected match if it's still in view; otherwise from
+        // the bottom line, including the bottom line itself when going back
+        let start = self
+            .log_search_match
+            .and_then(|idx| self.log_view_position(idx))
+            .unwrap_or(if forward { bottom } else { bottom + 1 });
+
+        let query = self.log_search_query.to_lowercase();
+        let found = next_match_position(len, start, forward, |pos| {
+            self.log_at_view_position(pos)
+                .is_some_and(|entry| entry.line.to_lowercase().contains(&query))
+        });
+
+        match found {
+            Some((pos, wrapped)) => {
+                self.log_search_match = self.log_index_at_view_position(pos);
+                self.log_scroll = len - 1 - pos;
+                self.log_auto_scroll = false;
+                self.update_scrollbar();
+                if wrapped {
+                    self.footer_message = Some((
+                        if forward {
+                            "Search wrapped to top".to_string()
+                        } else {
+                            "Search wrapped to bottom".to_string()
+                        },
+                        false,
+                        Instant::now(),
+                    ));
+                }
+            }
+            None => {
+                self.footer_message = Some(("No matches".to_string(), true, Instant::now()));
+            }
+        }
+    }
+
+    /// Index into self.logs of the line at a position in the current view
+    fn log_index_at_view_position(&self, pos: usize) -> Option<usize> {
+        if self.has_active_log_filter() {
+            self.log_filtered_indices.get(pos).copied()
+        } else {
+            (pos < self.logs.len()).then_some(pos)
+        }
+    }
+
+    fn log_at_view_position(&self, pos: usize) -> Option<&super::data::LogEntry> {
+        self.logs.get(self.log_index_at_view_position(pos)?)
+    }
+
+    /// Position in the current view of a log line, if it passes the filters
+    fn log_view_position(&self, index: usize) -> Option<usize> {
+        if self.has_active_log_filter() {
+            self.log_filtered_indices.binary_search(&index).ok()
+        } else {
+            (index < self.logs.len()).then_some(index)
+        }
     }
 
     /// Get the list of unique account emails (from cached accounts + log entries)
@@ -812,6 +1074,13 @@ impl App {
         }
     }
 
+    /// Poll the test request modal for streamed response text
+    fn poll_test_request(&mut self) {
+        if let Some(ref mut test) = self.test_request {
+            test.poll();
+        }
+    }
+
     /// Spawn startup warnings collection in a background thread
     fn spawn_startup_warnings(&mut self) {
         let (tx, rx) = mpsc::channel();
@@ -884,6 +1153,58 @@ impl App {
         });
     }
 
+    /// Copy text to the system clipboard and report the outcome in the footer
+    fn copy_to_clipboard(&mut self, label: &str, text: &str) {
+        self.footer_message = Some(match super::clipboard::copy(text) {
+            Ok(()) => (format!("Copied {}", label), false, Instant::now()),
+            Err(e) => (e, true, Instant::now()),
+        });
+    }
+
+    /// The selected search match if it's still in view, otherwise the entry
+    /// at the current scroll position
+    fn selected_log(&self) -> Option<&super::data::LogEntry> {
+        match self.log_search_match {
+            Some(idx) if self.log_view_position(idx).is_some() => self.logs.get(idx),
+            _ => self.bottom_visible_log(),
+        }
+    }
+
+    /// The log entry at the bottom of the logs view (newest visible line)
+    fn bottom_visible_log(&self) -> Option<&super::data::LogEntry> {
+        let total = if self.has_active_log_filter() {
+            self.log_filtered_indices.len()
+        } else {
+            self.logs.len()
+        };
+        let pos = total.checked_sub(1)?.saturating_sub(self.log_scroll);
+        if self.has_active_log_filter() {
+            self.logs.get(self.log_filtered_indices[pos])
+        } else {
+            self.logs.get(pos)
+        }
+    }
+
+    /// In-flight requests for an account and the concurrency cap, when the
+    /// server is reachable
+    pub fn account_in_flight(&self, account_id: &str) -> Option<(u64, u64)> {
+        let load = self.cached_token_stats.as_ref()?.account_load.as_ref()?;
+        let count = load.accounts.get(account_id).copied().unwrap_or(0);
+        Some((count, load.max_concurrent_requests))
+    }
+
+    /// Fetch quota on the next tick instead of waiting out the polling interval
+    pub fn force_quota_refresh(&mut self) {
+        if !self.quota_fetch_pending {
+            self.last_quota_refresh = Instant::now() - Duration::from_secs(120);
+        }
+    }
+
+    /// Whether a background quota fetch is in progress
+    pub fn is_quota_refreshing(&self) -> bool {
+        self.quota_fetch_pending
+    }
+
     /// Get cached server status, refreshing every 2 seconds
     pub fn get_cached_server_status(&mut self) -> super::data::ServerStatus {
         if self.last_status_refresh.elapsed() >= Duration::from_secs(2) {
@@ -1030,7 +1351,7 @@ impl App {
                 match crate::cloudcode::fetch_model_quotas(
                     &http_client,
                     &access_token,
-                    account.project_id.as_deref(),
+                    account.effective_project_id(),
                 )
                 .await
                 {
@@ -1234,6 +1555,22 @@ impl App {
         }
     }
 
+    /// Move the selected account up or down in selection priority order
+    fn move_selected_account(&mut self, up: bool) {
+        // Display order differs from storage order while searching or sorting
+        if self.has_active_account_filter() {
+            return;
+        }
+        if let Some(acc) = self.accounts.get(self.account_selected)
+            && let Ok(mut store) = crate::auth::accounts::AccountStore::load()
+            && let Some(new_idx) = store.move_account(&acc.id, up)
+        {
+            let _ = store.save();
+            self.refresh_accounts();
+            self.account_selected = new_idx;
+        }
+    }
+
     /// Set selected account as active
     fn set_active_account(&mut self) {
         let real_idx = self.account_real_index(self.account_selected);
@@ -1314,6 +1651,44 @@ impl App {
             return;
         }
 
+        // Handle test request modal (blocks other input)
+        if let Some(ref mut test) = self.test_request {
+            match code {
+                KeyCode::Esc => {
+                    // Dropping the receiver stops the background stream
+                    self.test_request = None;
+                }
+                KeyCode::Enter => match test.status {
+                    TestRequestStatus::Editing => test.send(),
+                    TestRequestStatus::Sending => {}
+                    TestRequestStatus::Done | TestRequestStatus::Failed(_) => {
+                        // Edit the prompt again for another run
+                        test.status = TestRequestStatus::Editing;
+                    }
+                },
+                KeyCode::Backspace if test.status == TestRequestStatus::Editing => {
+                    test.prompt.pop();
+                }
+                KeyCode::Char(c) if test.status == TestRequestStatus::Editing => {
+                    test.prompt.push(c);
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    test.scroll = test.scroll.saturating_sub(1);
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    test.scroll = test.scroll.saturating_add(1);
+                }
+                KeyCode::PageUp => {
+                    test.scroll = test.scroll.saturating_sub(10);
+                }
+                KeyCode::PageDown => {
+                    test.scroll = test.scroll.saturating_add(10);
+                }
+                _ => {}
+            }
+            return;
+        }
+
         // Handle account dropdown when open (blocks other Logs input)
         if self.log_account_dropdown_open {
             match code {
@@ -1359,10 +1734,12 @@ impl App {
                 }
                 KeyCode::Backspace => {
                     self.log_search_query.pop();
+                    self.log_search_match = None;
                     self.refilter_logs();
                 }
                 KeyCode::Char(c) => {
                     self.log_search_query.push(c);
+                    self.log_search_match = None;
                     self.refilter_logs();
                 }
                 _ => {}
@@ -1547,6 +1924,18 @@ impl App {
                     self.last_status_refresh = Instant::now() - std::time::Duration::from_secs(10);
                 }
             }
+            // Force a quota fetch (`r` restarts the daemon on Overview)
+            KeyCode::Char('u') if self.current_tab == Tab::Overview => {
+                self.force_quota_refresh();
+            }
+            // Open the test request modal
+            KeyCode::Char('t') if self.current_tab == Tab::Overview => {
+                let active = self.accounts.iter().find(|a| a.is_active);
+                self.test_request = Some(TestRequest::new(active.map(|a| a.id.clone())));
+            }
+            KeyCode::Char('r') if self.current_tab == Tab::Quota => {
+                self.force_quota_refresh();
+            }
             // Usage tab controls
             KeyCode::Char('r') if self.current_tab == Tab::Usage => {
                 self.token_history.reset();
@@ -1571,6 +1960,13 @@ impl App {
             KeyCode::Char('e') if self.current_tab == Tab::Accounts => {
                 self.toggle_account_enabled();
             }
+            // Reorder accounts (selection priority)
+            KeyCode::Char('K') if self.current_tab == Tab::Accounts => {
+                self.move_selected_account(true);
+            }
+            KeyCode::Char('J') if self.current_tab == Tab::Accounts => {
+                self.move_selected_account(false);
+            }
             // Set as active account
             KeyCode::Enter if self.current_tab == Tab::Accounts => {
                 self.set_active_account();
@@ -1595,6 +1991,20 @@ impl App {
                 self.account_sort = AccountSort::Default;
                 self.account_display_indices.clear();
             }
+            // Copy selected account id / email
+            KeyCode::Char(c @ ('y' | 'Y')) if self.current_tab == Tab::Accounts => {
+                if let Some(acc) = self
+                    .account_real_index(self.account_selected)
+                    .and_then(|idx| self.accounts.get(idx))
+                {
+                    let (label, text) = if c == 'y' {
+                        ("account id", acc.id.clone())
+                    } else {
+                        ("email", acc.email.clone())
+                    };
+                    self.copy_to_clipboard(label, &text);
+                }
+            }
             // Log scrolling (when on Logs tab)
             KeyCode::Up | KeyCode::Char('k') if self.current_tab == Tab::Logs => {
                 self.scroll_logs_up(1);
@@ -1608,15 +2018,24 @@ impl App {
             KeyCode::PageDown if self.current_tab == Tab::Logs => {
                 self.scroll_logs_down(10);
             }
-            KeyCode::Home if self.current_tab == Tab::Logs => {
-                self.log_scroll = self.logs.len().saturating_sub(1);
-                self.log_auto_scroll = false;
-                self.update_scrollbar();
+            KeyCode::Home | KeyCode::Char('g') if self.current_tab == Tab::Logs => {
+                self.scroll_logs_to_top();
             }
-            KeyCode::End if self.current_tab == Tab::Logs => {
-                self.log_scroll = 0;
-                self.log_auto_scroll = true;
-                self.update_scrollbar();
+            KeyCode::End | KeyCode::Char('G') if self.current_tab == Tab::Logs => {
+                self.scroll_logs_to_bottom();
+            }
+            KeyCode::Char('f') if self.current_tab == Tab::Logs => {
+                self.toggle_log_follow();
+            }
+            // Search match navigation
+            KeyCode::Char('n') if self.current_tab == Tab::Logs => {
+                self.jump_to_log_match(true);
+            }
+            KeyCode::Char('N') if self.current_tab == Tab::Logs => {
+                self.jump_to_log_match(false);
+            }
+            KeyCode::Char('m') if self.current_tab == Tab::Logs => {
+                self.toggle_log_search_highlight();
             }
             // Log filter/search keybindings
             KeyCode::Char('/') if self.current_tab == Tab::Logs => {
@@ -1648,12 +2067,18 @@ impl App {
                     self.log_account_dropdown_selected = 0;
                 }
             }
+            KeyCode::Char('y') if self.current_tab == Tab::Logs => {
+                if let Some(line) = self.selected_log().map(|entry| entry.line.clone()) {
+                    self.copy_to_clipboard("log line", &line);
+                }
+            }
             KeyCode::Char('c') if self.current_tab == Tab::Logs => {
                 // Clear all filters
                 self.log_level_filter = [true; 4];
                 self.log_account_filter = None;
                 self.log_search_query.clear();
                 self.log_search_active = false;
+                self.log_search_match = None;
                 self.log_filtered_indices.clear();
             }
             // Config navigation (when on Config tab and not editing)
@@ -1770,16 +2195,36 @@ impl App {
         self.update_scrollbar();
     }
 
-    /// Scroll logs down (towards newer entries)
+    /// Scroll logs down (towards newer entries). Reaching the bottom doesn't
+    /// resume following; that takes `f`, `G` or End.
     pub fn scroll_logs_down(&mut self, amount: usize) {
         self.log_scroll = self.log_scroll.saturating_sub(amount);
-        // Re-enable auto-scroll if we're at the bottom
-        if self.log_scroll == 0 {
-            self.log_auto_scroll = true;
-        }
         self.update_scrollbar();
     }
 
+    /// Jump to the oldest log line and freeze the view
+    pub fn scroll_logs_to_top(&mut self) {
+        self.log_scroll = self.log_view_len().saturating_sub(1);
+        self.log_auto_scroll = false;
+        self.update_scrollbar();
+    }
+
+    /// Jump to the newest log line and follow new ones
+    pub fn scroll_logs_to_bottom(&mut self) {
+        self.log_scroll = 0;
+        self.log_auto_scroll = true;
+        self.update_scrollbar();
+    }
+
+    /// Toggle between following new log lines and freezing the view in place
+    pub fn toggle_log_follow(&mut self) {
+        if self.log_auto_scroll {
+            self.log_auto_scroll = false;
+        } else {
+            self.scroll_logs_to_bottom();
+        }
+    }
+
     /// Scroll activity up (towards older entries)
     pub fn scroll_activity_up(&mut self, amount: usize) {
         let max_scroll = self.logs.len().saturating_sub(1);
@@ -1910,6 +2355,9 @@ impl App {
                 ("logging", "log_requests") => {
                     config.logging.log_requests = field.value == "true";
                 }
+                ("logging", "redact") => {
+                    config.logging.redact = field.value == "true";
+                }
                 ("accounts", "strategy") => {
                     config.accounts.strategy = field.value.clone();
                 }
@@ -2523,8 +2971,10 @@ impl App {
         // Invert because scrollbar top = oldest (high scroll), bottom = newest (low scroll)
         self.log_scroll = ((1.0 - ratio) * max_scroll as f64) as usize;
 
-        // Update auto-scroll state
-        self.log_auto_scroll = self.log_scroll == 0;
+        // Dragging away from the bottom freezes the view
+        if self.log_scroll > 0 {
+            self.log_auto_scroll = false;
+        }
         self.update_scrollbar();
     }
 
@@ -2586,7 +3036,7 @@ impl Default for App {
 }
 
 /// Run the TUI application
-pub fn run() -> io::Result<()> {
+pub fn run(start_tab: Option<Tab>) -> io::Result<()> {
     // Setup terminal with mouse capture
     enable_raw_mode()?;
     io::stdout().execute(EnterAlternateScreen)?;
@@ -2595,6 +3045,9 @@ pub fn run() -> io::Result<()> {
 
     // Create app state
     let mut app = App::new();
+    if let Some(tab) = start_tab {
+        app.current_tab = tab;
+    }
     app.spawn_tier_refresh();
     app.spawn_startup_warnings();
     let mut last_frame = Instant::now();
@@ -2615,7 +3068,7 @@ pub fn run() -> io::Result<()> {
         app.maybe_refresh_quota();
 
         // Refresh token usage stats periodically (every 5 seconds, only on relevant tabs)
-        if matches!(app.current_tab, Tab::Usage | Tab::Overview) {
+        if matches!(app.current_tab, Tab::Usage | Tab::Overview | Tab::Accounts) {
             app.maybe_refresh_token_stats();
         }
 
@@ -2625,6 +3078,9 @@ pub fn run() -> io::Result<()> {
         // Poll for background startup warnings
         app.poll_startup_warnings();
 
+        // Poll for streamed test request output
+        app.poll_test_request();
+
         // Draw
         terminal.draw(|frame| {
             render(frame, &mut app, elapsed);
@@ -2644,6 +3100,8 @@ pub fn run() -> io::Result<()> {
         }
     }
 
+    app.save_state();
+
     // Restore terminal
     io::stdout().execute(DisableMouseCapture)?;
     disable_raw_mode()?;
@@ -2749,7 +3207,12 @@ fn render(frame: &mut Frame, app: &mut App, elapsed: Duration) {
         }
         Tab::Config => super::views::config::render(frame, content_area, app),
         Tab::Mappings => super::views::mappings::render(frame, content_area, app),
-        Tab::Quota => super::views::quota::render(frame, content_area, app.get_active_quota_data()),
+        Tab::Quota => super::views::quota::render(
+            frame,
+            content_area,
+            app.get_active_quota_data(),
+            app.is_quota_refreshing(),
+        ),
         Tab::Usage => super::views::usage::render(frame, content_area, app),
         Tab::About => {
             // Trigger update check on first visit to About tab
@@ -2768,7 +3231,15 @@ fn render(frame: &mut Frame, app: &mut App, elapsed: Duration) {
     }
 
     // Footer
-    let footer = super::widgets::Footer::for_tab(app.current_tab);
+    let mut footer =
+        super::widgets::Footer::for_tab(app.current_tab).with_message(app.footer_message.as_ref());
+    if app.current_tab == Tab::Logs {
+        footer = footer.with_status(if app.log_auto_scroll {
+            ("FOLLOW", theme::success())
+        } else {
+            ("FROZEN", theme::warning())
+        });
+    }
     frame.render_widget(footer, chunks[3]);
 
     // Help overlay
@@ -2781,6 +3252,15 @@ fn render(frame: &mut Frame, app: &mut App, elapsed: Duration) {
         super::widgets::startup_warnings::render(frame, area, &app.startup_warnings);
     } else if let Some(message) = &app.runtime_warning_message {
         super::widgets::runtime_warning::render(frame, area, message);
+    } else if let Some(test) = &app.test_request {
+        // The account the server reported, by email when it's one we know
+        let account = test.account_id.as_deref().map(|id| {
+            app.accounts
+                .iter()
+                .find(|a| a.id == id)
+                .map_or(id, |a| a.email.as_str())
+        });
+        super::widgets::test_request::render(frame, area, test, account);
     }
 
     // Process effects
@@ -2813,6 +3293,27 @@ fn calculate_tab_areas(tabs_area: Rect) -> Vec<Rect> {
     areas
 }
 
+/// Find the next position after `start` (or before it, going backwards) in a
+/// view of `len` lines for which `is_match` holds, wrapping around the ends.
+/// `start` may be `len` to search backwards from the end. Returns the
+/// position and whether the search wrapped.
+fn next_match_position(
+    len: usize,
+    start: usize,
+    forward: bool,
+    is_match: impl Fn(usize) -> bool,
+) -> Option<(usize, bool)> {
+    (1..=len).find_map(|step| {
+        let pos = if forward {
+            (start + step) % len
+        } else {
+            (start + 2 * len - step) % len
+        };
+        let wrapped = if forward { pos <= start } else { pos >= start };
+        is_match(pos).then_some((pos, wrapped))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2852,4 +3353,109 @@ mod tests {
         let warning = detect_runtime_warning_message(&entries);
         assert!(warning.is_none());
     }
+
+    #[test]
+    fn test_test_request_records_serving_account() {
+        use super::super::data::TestRequestEvent;
+
+        let (tx, rx) = mpsc::channel();
+        let mut test = TestRequest::new(Some("acct-1".to_string()));
+        test.receiver = Some(rx);
+        tx.send(TestRequestEvent::Account("acct-1".to_string()))
+            .unwrap();
+        tx.send(TestRequestEvent::Text("Hi".to_string())).unwrap();
+        tx.send(TestRequestEvent::Done).unwrap();
+        test.poll();
+
+        assert_eq!(test.account_id.as_deref(), Some("acct-1"));
+        assert!(!test.served_by_other_account());
+        assert_eq!(test.response, "Hi");
+        assert_eq!(test.status, TestRequestStatus::Done);
+
+        // A request the server served from another account is flagged
+        let (tx, rx) = mpsc::channel();
+        let mut test = TestRequest::new(Some("acct-1".to_string()));
+        test.receiver = Some(rx);
+        tx.send(TestRequestEvent::Account("acct-2".to_string()))
+            .unwrap();
+        tx.send(TestRequestEvent::Done).unwrap();
+        test.poll();
+        assert!(test.served_by_other_account());
+
+        // Nothing to compare against without a reported account
+        let test = TestRequest::new(Some("acct-1".to_string()));
+        assert!(!test.served_by_other_account());
+    }
+
+    #[test]
+    fn test_parse_test_sse_line() {
+        use super::super::data::{TestRequestEvent, parse_test_sse_line};
+
+        assert_eq!(
+            parse_test_sse_line(
+                r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}"#
+            ),
+            Some(TestRequestEvent::Text("Hi".to_string()))
+        );
+        assert_eq!(
+            parse_test_sse_line(r#"data: {"type":"message_stop"}"#),
+            Some(TestRequestEvent::Done)
+        );
+        assert_eq!(
+            parse_test_sse_line(
+                r#"data: {"type":"error","error":{"type":"overloaded_error","message":"busy"}}"#
+            ),
+            Some(TestRequestEvent::Error("busy".to_string()))
+        );
+        assert_eq!(parse_test_sse_line("event: ping"), None);
+        assert_eq!(
+            parse_test_sse_line(
+                r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"x"}}"#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_next_match_position_wraps() {
+        let matches = [1, 4];
+        let is_match = |pos: usize| matches.contains(&pos);
+
+        assert_eq!(next_match_position(6, 1, true, is_match), Some((4, false)));
+        assert_eq!(next_match_position(6, 4, true, is_match), Some((1, true)));
+        assert_eq!(next_match_position(6, 4, false, is_match), Some((1, false)));
+        assert_eq!(next_match_position(6, 1, false, is_match), Some((4, true)));
+        // Searching back from past the end includes the last line
+        assert_eq!(
+            next_match_position(5, 5, false, |pos| pos == 4),
+            Some((4, false))
+        );
+        assert_eq!(next_match_position(6, 0, true, |_| false), None);
+        assert_eq!(next_match_position(0, 0, true, |_| true), None);
+    }
+
+    #[test]
+    fn test_tab_from_name_is_case_insensitive() {
+        assert_eq!(Tab::from_name("usage"), Some(Tab::Usage));
+        assert_eq!(Tab::from_name("QUOTA"), Some(Tab::Quota));
+        assert_eq!(Tab::from_name("Overview"), Some(Tab::Overview));
+        assert_eq!(Tab::from_name("nope"), None);
+    }
+
+    #[test]
+    fn test_tui_state_round_trip_and_partial_file() {
+        let state = TuiState {
+            tab: Tab::Logs,
+            account_sort: AccountSort::QuotaAsc,
+            log_level_filter: [false, true, true, true],
+        };
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<TuiState>(&json).unwrap(), state);
+
+        // Missing fields fall back to defaults
+        let partial: TuiState = serde_json::from_str(r#"{"tab":"quota"}"#).unwrap();
+        assert_eq!(partial.tab, Tab::Quota);
+        assert_eq!(partial.account_sort, AccountSort::Default);
+        assert_eq!(partial.log_level_filter, [true; 4]);
+    }
 }
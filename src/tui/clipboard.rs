@@ -0,0 +1,46 @@
+//! System clipboard access via the platform's clipboard command.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Clipboard commands to try, in order of preference
+#[cfg(target_os = "macos")]
+const COMMANDS: &[(&str, &[&str])] = &[("pbcopy", &[])];
+
+#[cfg(target_os = "windows")]
+const COMMANDS: &[(&str, &[&str])] = &[("clip", &[])];
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+];
+
+/// Copy text to the system clipboard.
+///
+/// Returns an error message when no clipboard is available (e.g. over SSH or
+/// in a headless session) so the caller can show it instead of failing.
+pub fn copy(text: &str) -> Result<(), String> {
+    for (program, args) in COMMANDS {
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+
+        let written = child
+            .stdin
+            .take()
+            .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        if child.wait().is_ok_and(|status| status.success()) && written {
+            return Ok(());
+        }
+    }
+
+    Err("No clipboard available".to_string())
+}
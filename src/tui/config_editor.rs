@@ -159,6 +159,13 @@ pub fn build_config_fields(config: &Config) -> Vec<ConfigField> {
             config.logging.log_requests.to_string(),
             "Log each API request with model, status, and duration",
         ),
+        ConfigField::new(
+            "logging",
+            "redact",
+            FieldType::Bool,
+            config.logging.redact.to_string(),
+            "Replace message text in logged bodies with length placeholders",
+        ),
         // Accounts section
         ConfigField::new(
             "accounts",
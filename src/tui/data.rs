@@ -2,10 +2,12 @@
 
 use std::collections::VecDeque;
 use std::path::PathBuf;
-use std::sync::LazyLock;
+use std::sync::{LazyLock, mpsc};
 
 use regex_lite::Regex;
 
+pub use crate::token_history::TokenHistory;
+
 /// Regex to strip ANSI escape codes
 static ANSI_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"\x1b\[[0-9;]*m").expect("valid regex"));
@@ -62,6 +64,10 @@ pub struct AccountInfo {
     pub enabled: bool,
     pub is_invalid: bool,
     pub subscription_tier: Option<String>,
+    /// Requests left in today's budget (None means no request budget)
+    pub requests_remaining: Option<u64>,
+    /// Tokens left in today's budget (None means no token budget)
+    pub tokens_remaining: Option<u64>,
 }
 
 /// Model usage statistics
@@ -147,36 +153,21 @@ impl LogEntry {
     }
 }
 
-/// Parse timestamp "2026-02-05T21:25:01" to seconds since epoch
+/// Parse timestamp "2026-02-05T21:25:01" (UTC) to seconds since the Unix epoch
 fn parse_timestamp(s: &str) -> Option<u64> {
-    // Simple parsing: YYYY-MM-DDTHH:MM:SS
-    let parts: Vec<&str> = s.split('T').collect();
-    if parts.len() != 2 {
-        return None;
-    }
-
-    let date_parts: Vec<u32> = parts[0].split('-').filter_map(|p| p.parse().ok()).collect();
-    let time_parts: Vec<u32> = parts[1].split(':').filter_map(|p| p.parse().ok()).collect();
-
-    if date_parts.len() != 3 || time_parts.len() != 3 {
-        return None;
-    }
+    chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .and_then(|dt| u64::try_from(dt.and_utc().timestamp()).ok())
+}
 
-    // Calculate seconds since a reference point (Jan 1, 2020)
-    // This doesn't need to be accurate epoch time, just consistent for rate calculation
-    let year = date_parts[0];
-    let month = date_parts[1];
-    let day = date_parts[2];
-    let hour = time_parts[0];
-    let min = time_parts[1];
-    let sec = time_parts[2];
-
-    // Approximate days since 2020-01-01
-    let years_since_2020 = year.saturating_sub(2020);
-    let days = years_since_2020 * 365 + (month - 1) * 30 + day;
-    let secs = (days as u64) * 86400 + (hour as u64) * 3600 + (min as u64) * 60 + (sec as u64);
-
-    Some(secs)
+/// Parse the timestamp that starts a log line
+/// (e.g. "2026-02-05T21:25:01.034804Z  INFO Request completed").
+pub fn parse_log_line_timestamp(line: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    let clean = ANSI_REGEX.replace_all(line, "");
+    let token = clean.split_whitespace().next()?;
+    chrono::DateTime::parse_from_rfc3339(token)
+        .ok()
+        .map(|dt| dt.with_timezone(&chrono::Utc))
 }
 
 /// Build rate history from log entries (requests per second for last 60 seconds)
@@ -299,74 +290,14 @@ pub fn parse_daemon_start_from_line(line: &str) -> Option<u64> {
     None
 }
 
-/// Get current time as seconds (same format as parse_timestamp)
+/// Get current time as seconds since the Unix epoch (as parse_timestamp)
 pub fn current_time_secs() -> u64 {
     use std::time::{SystemTime, UNIX_EPOCH};
 
-    let now = SystemTime::now()
+    SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
-        .as_secs();
-
-    // Convert Unix timestamp to UTC components
-    // This must exactly match parse_timestamp's calculation
-
-    const SECS_PER_MIN: u64 = 60;
-    const SECS_PER_HOUR: u64 = 3600;
-    const SECS_PER_DAY: u64 = 86400;
-
-    // Days and time of day
-    let days_since_1970 = now / SECS_PER_DAY;
-    let time_of_day = now % SECS_PER_DAY;
-    let hour = (time_of_day / SECS_PER_HOUR) as u32;
-    let min = ((time_of_day % SECS_PER_HOUR) / SECS_PER_MIN) as u32;
-    let sec = (time_of_day % SECS_PER_MIN) as u32;
-
-    // Convert days since 1970 to year/month/day
-    // Using the same approximate logic as parse_timestamp
-    let mut remaining_days = days_since_1970 as i64;
-    let mut year = 1970u32;
-
-    loop {
-        let days_in_year = if is_leap_year(year) { 366 } else { 365 };
-        if remaining_days < days_in_year {
-            break;
-        }
-        remaining_days -= days_in_year;
-        year += 1;
-    }
-
-    // Now remaining_days is day of year (0-based)
-    let day_of_year = remaining_days as u32;
-
-    // Convert to month/day (1-based)
-    let (month, day) = day_of_year_to_month_day(day_of_year, is_leap_year(year));
-
-    // Now use EXACTLY the same formula as parse_timestamp
-    let years_since_2020 = year.saturating_sub(2020);
-    let days = years_since_2020 * 365 + (month - 1) * 30 + day;
-    (days as u64) * 86400 + (hour as u64) * 3600 + (min as u64) * 60 + (sec as u64)
-}
-
-fn is_leap_year(year: u32) -> bool {
-    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
-}
-
-fn day_of_year_to_month_day(day_of_year: u32, leap: bool) -> (u32, u32) {
-    let days_in_months: [u32; 12] = if leap {
-        [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
-    } else {
-        [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
-    };
-
-    let mut remaining = day_of_year;
-    for (i, &days) in days_in_months.iter().enumerate() {
-        if remaining < days {
-            return ((i + 1) as u32, remaining + 1);
-        }
-        remaining -= days;
-    }
-    (12, 31) // Fallback to Dec 31
+        .as_secs()
 }
 
 /// Data provider for the TUI
@@ -452,18 +383,30 @@ impl DataProvider {
 
     /// Get list of accounts
     pub fn get_accounts(&self) -> Vec<AccountInfo> {
+        let reset_hour = crate::config::get_config().accounts.budget_reset_hour_utc;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let day = crate::auth::accounts::budget_day(now, reset_hour);
+
         match crate::auth::accounts::AccountStore::load() {
             Ok(store) => store
                 .accounts
                 .iter()
-                .map(|acc| AccountInfo {
-                    id: acc.id.clone(),
-                    email: acc.email.clone(),
-                    quota_fraction: acc.get_average_quota_fraction(),
-                    is_active: store.active_account_id.as_ref() == Some(&acc.id),
-                    enabled: acc.enabled,
-                    is_invalid: acc.is_invalid,
-                    subscription_tier: acc.subscription_tier.clone(),
+                .map(|acc| {
+                    let (requests_remaining, tokens_remaining) = acc.remaining_daily_budget(day);
+                    AccountInfo {
+                        id: acc.id.clone(),
+                        email: acc.email.clone(),
+                        quota_fraction: acc.get_average_quota_fraction(),
+                        is_active: store.active_account_id.as_ref() == Some(&acc.id),
+                        enabled: acc.enabled,
+                        is_invalid: acc.is_invalid,
+                        subscription_tier: acc.subscription_tier.clone(),
+                        requests_remaining,
+                        tokens_remaining,
+                    }
                 })
                 .collect(),
             Err(_) => vec![],
@@ -544,12 +487,186 @@ impl DataProvider {
         let total_output = token_usage["total_output_tokens"].as_u64().unwrap_or(0);
         let total_cache = token_usage["total_cache_read_tokens"].as_u64().unwrap_or(0);
 
+        let cache = &json["cache"];
+        let response_cache = cache["enabled"].as_bool().map(|enabled| CacheMetrics {
+            enabled,
+            entries: cache["entries"].as_u64().unwrap_or(0),
+            max_entries: cache["max_entries"].as_u64().unwrap_or(0),
+            hit_rate: cache["hit_rate"].as_f64().unwrap_or(0.0),
+            bytes_stored: cache["bytes_stored"].as_u64().unwrap_or(0),
+            bytes_served: cache["bytes_served"].as_u64().unwrap_or(0),
+            upstream_calls_saved: cache["upstream_calls_saved"].as_u64().unwrap_or(0),
+            tokens_saved: cache["tokens_saved"].as_u64().unwrap_or(0),
+            evictions: cache["evictions"].as_u64().unwrap_or(0),
+        });
+
+        let in_flight = &json["in_flight"];
+        let account_load = in_flight["accounts"]
+            .as_object()
+            .map(|accounts| AccountLoad {
+                accounts: accounts
+                    .iter()
+                    .filter_map(|(id, count)| Some((id.clone(), count.as_u64()?)))
+                    .collect(),
+                max_concurrent_requests: in_flight["max_concurrent_requests"].as_u64().unwrap_or(0),
+            });
+
         Some(TokenStats {
             models,
             total_input_tokens: total_input,
             total_output_tokens: total_output,
             total_cache_read_tokens: total_cache,
+            response_cache,
+            account_load,
+        })
+    }
+
+    /// Send a streaming test prompt to the running server and forward the
+    /// response over `tx` as it arrives. The server's account selection picks
+    /// the account, as for any client request, and reports it back first as
+    /// an `Account` event. Always finishes with `Done` or
+    /// `Error` unless the receiver has been dropped.
+    pub fn stream_test_request(prompt: &str, tx: &mpsc::Sender<TestRequestEvent>) {
+        if let Err(e) = Self::try_stream_test_request(prompt, tx) {
+            let _ = tx.send(TestRequestEvent::Error(e));
+        }
+    }
+
+    fn try_stream_test_request(
+        prompt: &str,
+        tx: &mpsc::Sender<TestRequestEvent>,
+    ) -> Result<(), String> {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpStream;
+        use std::time::Duration;
+
+        let config = crate::config::get_config();
+        let addr = crate::config::get_daemon_addr();
+        let sock_addr = addr
+            .parse()
+            .map_err(|_| format!("Invalid server address: {}", addr))?;
+        let mut stream = TcpStream::connect_timeout(&sock_addr, Duration::from_secs(2))
+            .map_err(|_| "Server is not running".to_string())?;
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(120)));
+        let _ = stream.set_write_timeout(Some(Duration::from_secs(5)));
+
+        let body = serde_json::json!({
+            "model": config.mappings.background_task_model,
+            "max_tokens": TEST_REQUEST_MAX_TOKENS,
+            "stream": true,
+            "messages": [{ "role": "user", "content": prompt }],
         })
+        .to_string();
+        let auth = config
+            .server
+            .api_key
+            .as_ref()
+            .map(|key| format!("x-api-key: {}\r\n", key))
+            .unwrap_or_default();
+        // HTTP/1.0 keeps the body unchunked so SSE lines can be read directly.
+        // Bypass the response cache so the request really reaches upstream,
+        // and ask the server to name the account that served it.
+        let request = format!(
+            "POST /v1/messages HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nx-no-cache: true\r\nx-agcp-report-account: true\r\n{}\r\n{}",
+            addr,
+            body.len(),
+            auth,
+            body
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| format!("Failed to send request: {}", e))?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader
+            .read_line(&mut status_line)
+            .map_err(|e| format!("Failed to read response: {}", e))?;
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse::<u16>().ok())
+            .ok_or_else(|| "Malformed response from server".to_string())?;
+
+        // Skip response headers, keeping the account the server picked
+        let mut account_id = None;
+        loop {
+            let mut line = String::new();
+            let n = reader
+                .read_line(&mut line)
+                .map_err(|e| format!("Failed to read response: {}", e))?;
+            if n == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':')
+                && name.trim().eq_ignore_ascii_case("x-agcp-account")
+            {
+                account_id = Some(value.trim().to_string());
+            }
+        }
+
+        if status != 200 {
+            let mut body = String::new();
+            let _ = reader.read_to_string(&mut body);
+            let message = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|json| json["error"]["message"].as_str().map(str::to_string))
+                .unwrap_or_else(|| format!("Server returned HTTP {}", status));
+            return Err(message);
+        }
+        if let Some(id) = account_id {
+            let _ = tx.send(TestRequestEvent::Account(id));
+        }
+
+        for line in reader.lines() {
+            let line = line.map_err(|e| format!("Stream interrupted: {}", e))?;
+            let Some(event) = parse_test_sse_line(&line) else {
+                continue;
+            };
+            let finished = matches!(event, TestRequestEvent::Done | TestRequestEvent::Error(_));
+            if tx.send(event).is_err() || finished {
+                // Receiver gone (modal closed) or stream complete
+                return Ok(());
+            }
+        }
+
+        Err("Stream ended before the response completed".to_string())
+    }
+}
+
+/// Output budget for TUI test requests
+const TEST_REQUEST_MAX_TOKENS: u32 = 1024;
+
+/// Progress of a streaming test request sent from the TUI
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestRequestEvent {
+    /// ID of the account the server used, from its `X-AGCP-Account` header
+    Account(String),
+    /// A chunk of response text
+    Text(String),
+    /// The response finished
+    Done,
+    /// The request failed
+    Error(String),
+}
+
+/// Parse one SSE line from a streaming Messages response into a test request event.
+/// Returns None for lines that carry nothing to display.
+pub fn parse_test_sse_line(line: &str) -> Option<TestRequestEvent> {
+    let data = line.strip_prefix("data:")?.trim();
+    let json: serde_json::Value = serde_json::from_str(data).ok()?;
+    match json["type"].as_str()? {
+        "content_block_delta" if json["delta"]["type"] == "text_delta" => json["delta"]["text"]
+            .as_str()
+            .map(|text| TestRequestEvent::Text(text.to_string())),
+        "message_stop" => Some(TestRequestEvent::Done),
+        "error" => Some(TestRequestEvent::Error(
+            json["error"]["message"]
+                .as_str()
+                .unwrap_or("Unknown error")
+                .to_string(),
+        )),
+        _ => None,
     }
 }
 
@@ -570,301 +687,31 @@ pub struct TokenStats {
     pub total_input_tokens: u64,
     pub total_output_tokens: u64,
     pub total_cache_read_tokens: u64,
+    /// Response cache efficiency, if the server reported it
+    pub response_cache: Option<CacheMetrics>,
+    /// Per-account in-flight requests, if the server reported them
+    pub account_load: Option<AccountLoad>,
 }
 
-/// Maximum number of data points to keep in the token history
-/// With change-only recording, this covers many hours of active use
-const TOKEN_HISTORY_MAX_POINTS: usize = 5000;
-
-/// A single timestamped snapshot of cumulative token usage
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct TokenSnapshot {
-    /// Unix timestamp (seconds since epoch)
-    pub timestamp: u64,
-    /// Per-model cumulative totals: (model_name, total_tokens)
-    pub models: Vec<(String, u64)>,
-}
-
-/// Persistent time-series of cumulative token usage per model.
-/// Snapshots are taken every poll interval and persisted to disk
-/// so they survive TUI/server restarts.
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct TokenHistory {
-    /// Ordered list of snapshots
-    pub snapshots: Vec<TokenSnapshot>,
-    /// Unix timestamp of the quota period start (set from quota reset_time - 24h)
-    #[serde(default)]
-    pub period_start: Option<u64>,
-    /// Per-model offsets to add when the server restarts (so the line never drops)
-    #[serde(default)]
-    offsets: std::collections::HashMap<String, u64>,
-    /// Per-model peak stored values — survives model disappearing from stats after
-    /// server restart so we can still detect drops when the model reappears.
-    #[serde(default)]
-    peak_values: std::collections::HashMap<String, u64>,
-    /// Unix timestamp when this TUI session started (not persisted).
-    #[serde(skip)]
-    session_start: Option<u64>,
-}
-
-impl Default for TokenHistory {
-    fn default() -> Self {
-        Self::new()
-    }
+/// In-flight upstream requests per account from the server's /stats endpoint
+#[derive(Debug, Clone)]
+pub struct AccountLoad {
+    /// Account ID -> requests in flight (idle accounts are omitted)
+    pub accounts: std::collections::HashMap<String, u64>,
+    /// Upstream concurrency cap shared by all accounts
+    pub max_concurrent_requests: u64,
 }
 
-impl TokenHistory {
-    pub fn new() -> Self {
-        Self {
-            snapshots: Vec::new(),
-            period_start: None,
-            offsets: std::collections::HashMap::new(),
-            peak_values: std::collections::HashMap::new(),
-            session_start: None,
-        }
-    }
-
-    /// Load from the persistence file, or return a new empty history
-    pub fn load() -> Self {
-        let path = Self::path();
-        let mut history: Self = match std::fs::read_to_string(&path) {
-            Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
-            Err(_) => return Self::new(),
-        };
-
-        // Rebuild peak_values from snapshots if empty (backward compat with old files)
-        if history.peak_values.is_empty() {
-            for snap in &history.snapshots {
-                for (name, val) in &snap.models {
-                    let peak = history.peak_values.entry(name.clone()).or_insert(0);
-                    *peak = (*peak).max(*val);
-                }
-            }
-        }
-
-        // Deduplicate consecutive snapshots with identical model values
-        history.snapshots.dedup_by(|b, a| {
-            a.models.len() == b.models.len()
-                && a.models.iter().all(|(name, val)| {
-                    b.models
-                        .iter()
-                        .find(|(n, _)| n == name)
-                        .map(|(_, t)| t == val)
-                        .unwrap_or(false)
-                })
-        });
-
-        history.session_start = Some(
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs(),
-        );
-
-        history
-    }
-
-    /// Save to the persistence file
-    pub fn save(&self) {
-        let path = Self::path();
-        if let Ok(json) = serde_json::to_string(self) {
-            let _ = std::fs::write(&path, json);
-        }
-    }
-
-    /// Path to the persistence file
-    fn path() -> std::path::PathBuf {
-        crate::config::Config::dir().join("token_history.json")
-    }
-
-    /// Record a new snapshot from the current TokenStats.
-    /// Returns true if the snapshot was added (i.e., there's new data).
-    pub fn push(&mut self, stats: &TokenStats) -> bool {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-
-        let raw_models: Vec<(String, u64)> = stats
-            .models
-            .iter()
-            .map(|m| (m.model.clone(), m.input_tokens + m.output_tokens))
-            .collect();
-
-        // Skip if no token data
-        if raw_models.is_empty() || raw_models.iter().all(|(_, t)| *t == 0) {
-            return false;
-        }
-
-        // Detect server restart: if any model's raw value + current offset
-        // is less than the peak stored value, the server counters reset.
-        // We use peak_values (not last_snap) so models that disappear after
-        // a restart and later reappear still get correct offset treatment.
-        for (name, raw_total) in &raw_models {
-            let peak = self.peak_values.get(name).copied().unwrap_or(0);
-            let current_offset = self.offsets.get(name).copied().unwrap_or(0);
-
-            if peak > 0 && raw_total + current_offset < peak {
-                // Server restarted — set offset so new usage builds on previous peak
-                self.offsets.insert(name.clone(), peak);
-            }
-        }
-
-        // Apply offsets and enforce monotonicity (line never goes down)
-        let models: Vec<(String, u64)> = raw_models
-            .into_iter()
-            .map(|(name, raw)| {
-                let offset = self.offsets.get(&name).copied().unwrap_or(0);
-                let value = raw + offset;
-
-                // Extra safety: clamp to at least the peak stored value
-                let peak = self.peak_values.get(&name).copied().unwrap_or(0);
-                (name, value.max(peak))
-            })
-            .collect();
-
-        // Skip if values haven't changed since the last snapshot (idle period)
-        if let Some(last) = self.snapshots.last() {
-            let unchanged = models.len() == last.models.len()
-                && models.iter().all(|(name, val)| {
-                    last.models
-                        .iter()
-                        .find(|(n, _)| n == name)
-                        .map(|(_, t)| t == val)
-                        .unwrap_or(false)
-                });
-            if unchanged {
-                return false;
-            }
-        }
-
-        // Update peak values
-        for (name, val) in &models {
-            let peak = self.peak_values.entry(name.clone()).or_insert(0);
-            *peak = (*peak).max(*val);
-        }
-
-        self.snapshots.push(TokenSnapshot {
-            timestamp: now,
-            models,
-        });
-
-        // Trim old snapshots
-        if self.snapshots.len() > TOKEN_HISTORY_MAX_POINTS {
-            let excess = self.snapshots.len() - TOKEN_HISTORY_MAX_POINTS;
-            self.snapshots.drain(..excess);
-        }
-
-        true
-    }
-
-    /// Update the quota period start time from quota data.
-    /// Assumes a 24-hour quota period ending at reset_time.
-    pub fn set_period_from_reset_time(&mut self, reset_time_str: &str) {
-        if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(reset_time_str) {
-            let reset_ts = dt.timestamp() as u64;
-            // Quota periods are typically 24 hours
-            let period_start = reset_ts.saturating_sub(86400);
-            self.period_start = Some(period_start);
-        }
-    }
-
-    /// Reset the history (clear all snapshots). Called when quota period resets.
-    pub fn reset(&mut self) {
-        self.snapshots.clear();
-        self.offsets.clear();
-        self.peak_values.clear();
-        self.save();
-    }
-
-    /// Check if the quota period has ended (current time > reset_time).
-    /// If reset_time is in the past, we should reset.
-    pub fn should_reset(&self, reset_time_str: &str) -> bool {
-        if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(reset_time_str) {
-            let reset_ts = dt.timestamp() as u64;
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-            // Only reset if we have snapshots that are from before the reset time
-            // and the reset has already happened
-            if now >= reset_ts
-                && let Some(first) = self.snapshots.first()
-            {
-                return first.timestamp < reset_ts;
-            }
-        }
-        false
-    }
-
-    /// Get per-model cumulative data for the chart.
-    /// Returns Vec of (model_name, Vec<(x, y)>) where:
-    /// - x = minutes since session start
-    /// - y = cumulative total tokens
-    ///
-    /// Only includes snapshots from the current session. The last snapshot
-    /// before session start is included as a baseline at x=0 so lines begin
-    /// at the correct cumulative Y value.
-    pub fn get_cumulative_series(&self) -> Vec<(String, Vec<(f64, f64)>)> {
-        if self.snapshots.is_empty() {
-            return Vec::new();
-        }
-
-        let origin = self.session_start.unwrap_or(self.snapshots[0].timestamp);
-
-        // Find the baseline: last snapshot at or before session start, or 0
-        let start_idx = self
-            .snapshots
-            .iter()
-            .rposition(|s| s.timestamp <= origin)
-            .unwrap_or(0);
-
-        let session_snapshots = &self.snapshots[start_idx..];
-
-        // Collect all unique model names from session snapshots
-        let mut model_names: Vec<String> = Vec::new();
-        for snap in session_snapshots {
-            for (name, _) in &snap.models {
-                if !model_names.contains(name) {
-                    model_names.push(name.clone());
-                }
-            }
-        }
-
-        let mut result = Vec::new();
-        for model_name in &model_names {
-            let mut points = Vec::new();
-            for snap in session_snapshots {
-                // Clamp to 0 so the baseline snapshot sits at x=0
-                let x = (snap.timestamp.saturating_sub(origin)) as f64 / 60.0;
-                let y = snap
-                    .models
-                    .iter()
-                    .find(|(n, _)| n == model_name)
-                    .map(|(_, t)| *t as f64)
-                    .unwrap_or(0.0);
-                points.push((x, y));
-            }
-            // Only include models with non-zero data
-            if points.iter().any(|(_, y)| *y > 0.0) {
-                result.push((model_name.clone(), points));
-            }
-        }
-        result
-    }
-
-    /// Get the time range in minutes for the X axis.
-    /// Scoped to the current TUI session so the chart doesn't stretch across
-    /// days of persisted history.
-    pub fn get_time_range_minutes(&self) -> f64 {
-        let origin = self
-            .session_start
-            .unwrap_or_else(|| self.snapshots.first().map(|s| s.timestamp).unwrap_or(0));
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        let range = (now.saturating_sub(origin)) as f64 / 60.0;
-        range.max(1.0)
-    }
+/// Response cache efficiency numbers from the server's /stats endpoint
+#[derive(Debug, Clone)]
+pub struct CacheMetrics {
+    pub enabled: bool,
+    pub entries: u64,
+    pub max_entries: u64,
+    pub hit_rate: f64,
+    pub bytes_stored: u64,
+    pub bytes_served: u64,
+    pub upstream_calls_saved: u64,
+    pub tokens_saved: u64,
+    pub evictions: u64,
 }
@@ -3,7 +3,7 @@
 use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use super::data::LogEntry;
 
@@ -108,58 +108,138 @@ pub fn read_last_lines_and_start(
     (entries, server_start_line)
 }
 
-/// Read new lines from a log file (for follow mode)
-pub struct LogTailer {
+/// Follows a file by path like `tail -F`: lines appended to the open file
+/// are returned as they arrive, and when the path is rotated (replaced by a
+/// new file) or truncated in place, it is reopened and read from the start.
+pub struct FollowedFile {
+    path: PathBuf,
     reader: Option<BufReader<File>>,
-    path: std::path::PathBuf,
+    identity: Option<FileIdentity>,
+    /// Bytes read from the current file
+    pos: u64,
+    /// Unterminated line waiting for the rest of its bytes
+    partial: String,
 }
 
-impl LogTailer {
+impl FollowedFile {
+    /// Start following `path` from its current end.
     pub fn new(path: &Path) -> Self {
-        let reader = File::open(path).ok().map(|mut f| {
-            let _ = f.seek(SeekFrom::End(0));
-            BufReader::new(f)
-        });
-
-        Self {
-            reader,
+        let mut follower = Self {
             path: path.to_path_buf(),
-        }
+            reader: None,
+            identity: None,
+            pos: 0,
+            partial: String::new(),
+        };
+        follower.open(true);
+        follower
     }
 
-    /// Read any new lines since last call
-    pub fn read_new_lines(&mut self) -> Vec<LogEntry> {
-        let mut entries = Vec::new();
-
-        let reader = match &mut self.reader {
-            Some(r) => r,
-            None => {
-                // Try to open the file if it wasn't available before
-                if let Ok(mut f) = File::open(&self.path) {
-                    let _ = f.seek(SeekFrom::End(0));
-                    self.reader = Some(BufReader::new(f));
-                }
-                return entries;
-            }
+    fn open(&mut self, at_end: bool) -> bool {
+        let Ok(mut file) = File::open(&self.path) else {
+            return false;
         };
+        self.identity = file.metadata().ok().as_ref().and_then(file_identity);
+        self.pos = if at_end {
+            file.seek(SeekFrom::End(0)).unwrap_or(0)
+        } else {
+            0
+        };
+        self.reader = Some(BufReader::new(file));
+        true
+    }
+
+    /// Complete lines (without line endings) appended since the last call.
+    pub fn read_new_lines(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
 
-        // Reuse a single buffer across iterations instead of allocating per line
+        // A file that didn't exist yet is new, so read it from the start
+        if self.reader.is_none() && !self.open(false) {
+            return lines;
+        }
+
+        self.read_available(&mut lines);
+        if self.rotated() {
+            // The old file is drained; its last line won't be finished now
+            if !self.partial.is_empty() {
+                lines.push(std::mem::take(&mut self.partial));
+            }
+            if self.open(false) {
+                self.read_available(&mut lines);
+            }
+        }
+        lines
+    }
+
+    fn read_available(&mut self, lines: &mut Vec<String>) {
+        let Some(reader) = &mut self.reader else {
+            return;
+        };
         let mut line = String::new();
         loop {
             line.clear();
             match reader.read_line(&mut line) {
-                Ok(0) => break, // No new data
-                Ok(_) => {
-                    let trimmed = line.trim_end();
-                    if !trimmed.is_empty() {
-                        entries.push(LogEntry::new(trimmed.to_string()));
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    self.pos += n as u64;
+                    if !line.ends_with('\n') {
+                        self.partial.push_str(&line);
+                        break;
                     }
+                    let mut complete = std::mem::take(&mut self.partial);
+                    complete.push_str(line.trim_end_matches(['\n', '\r']));
+                    lines.push(complete);
                 }
-                Err(_) => break,
             }
         }
+    }
 
-        entries
+    /// Whether the path now names a different file, or a file shorter than
+    /// what was read from it (truncated in place, e.g. `copytruncate`). A
+    /// deleted path that hasn't been recreated yet keeps the old handle.
+    fn rotated(&self) -> bool {
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            return false;
+        };
+        metadata.len() < self.pos || file_identity(&metadata) != self.identity
+    }
+}
+
+/// Device and inode, which change when a path is replaced by a new file.
+type FileIdentity = (u64, u64);
+
+#[cfg(unix)]
+fn file_identity(metadata: &std::fs::Metadata) -> Option<FileIdentity> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Without inodes, rotation is only detected through the size check.
+#[cfg(not(unix))]
+fn file_identity(_metadata: &std::fs::Metadata) -> Option<FileIdentity> {
+    None
+}
+
+/// Read new lines from a log file (for follow mode)
+pub struct LogTailer {
+    file: FollowedFile,
+}
+
+impl LogTailer {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            file: FollowedFile::new(path),
+        }
+    }
+
+    /// Read any new lines since last call
+    pub fn read_new_lines(&mut self) -> Vec<LogEntry> {
+        self.file
+            .read_new_lines()
+            .into_iter()
+            .filter(|line| !line.trim_end().is_empty())
+            .map(|line| LogEntry::new(line.trim_end().to_string()))
+            .collect()
     }
 }
 
@@ -172,3 +252,48 @@ pub fn append_entries(logs: &mut VecDeque<LogEntry>, new_entries: Vec<LogEntry>)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn append(path: &Path, text: &str) {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_followed_file_survives_rotation() {
+        let dir = std::env::temp_dir().join(format!("agcp-follow-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("agcp.log");
+        std::fs::write(&path, "old line\n").unwrap();
+
+        let mut follower = FollowedFile::new(&path);
+        assert!(follower.read_new_lines().is_empty());
+
+        // Partial lines wait for their newline
+        append(&path, "first\nsec");
+        assert_eq!(follower.read_new_lines(), vec!["first"]);
+        append(&path, "ond\n");
+        assert_eq!(follower.read_new_lines(), vec!["second"]);
+
+        // Rename-and-recreate rotation
+        std::fs::rename(&path, dir.join("agcp.log.old")).unwrap();
+        assert!(follower.read_new_lines().is_empty());
+        append(&path, "after rotation\n");
+        assert_eq!(follower.read_new_lines(), vec!["after rotation"]);
+
+        // Truncation in place
+        std::fs::write(&path, "").unwrap();
+        append(&path, "x\n");
+        assert_eq!(follower.read_new_lines(), vec!["x"]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,4 +1,5 @@
 mod app;
+mod clipboard;
 pub mod config_editor;
 mod data;
 mod effects;
@@ -7,4 +8,6 @@ mod theme;
 mod views;
 mod widgets;
 
-pub use app::run;
+pub use app::{Tab, run};
+pub use data::parse_log_line_timestamp;
+pub use log_reader::FollowedFile;
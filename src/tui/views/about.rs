@@ -90,7 +90,7 @@ pub fn render(
 
     lines.push(Line::from(""));
 
-    let version_text = format!("Version {}", VERSION);
+    let version_text = format!("Version {} ({})", VERSION, env!("AGCP_GIT_SHA"));
     let version_padding = (inner.width as usize).saturating_sub(version_text.len()) / 2;
     lines.push(Line::from(vec![
         Span::raw(" ".repeat(version_padding)),
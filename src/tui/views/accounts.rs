@@ -129,7 +129,7 @@ pub fn render(frame: &mut Frame, area: Rect, app: &mut App) {
             let email_display = truncate_email(&acc.email, 32);
             let email_padding = " ".repeat(32_usize.saturating_sub(email_display.len()));
 
-            Line::from(vec![
+            let mut spans = vec![
                 Span::raw(selector),
                 Span::styled(status_icon.0, status_icon.1),
                 Span::raw(" "),
@@ -140,7 +140,49 @@ pub fn render(frame: &mut Frame, area: Rect, app: &mut App) {
                 Span::raw(" "),
                 Span::styled(quota_bar, quota_style),
                 Span::styled(format!(" {:>3.0}%", quota * 100.0), theme::dim()),
-            ])
+            ];
+
+            // Live in-flight requests against the upstream concurrency cap
+            if let Some((in_flight, cap)) = app.account_in_flight(&acc.id) {
+                let style = if in_flight == 0 {
+                    theme::dim()
+                } else if cap > 0 && in_flight >= cap {
+                    theme::warning()
+                } else {
+                    theme::success()
+                };
+                spans.push(Span::raw("  "));
+                spans.push(Span::styled(
+                    render_load_gauge(in_flight, cap.max(1), 5),
+                    style,
+                ));
+                spans.push(Span::styled(format!(" {}/{}", in_flight, cap), style));
+            }
+
+            // Remaining daily budget, when one is set
+            if acc.requests_remaining.is_some() || acc.tokens_remaining.is_some() {
+                let exhausted =
+                    acc.requests_remaining == Some(0) || acc.tokens_remaining == Some(0);
+                let style = if exhausted {
+                    theme::warning()
+                } else {
+                    theme::dim()
+                };
+                let mut parts = Vec::new();
+                if let Some(requests) = acc.requests_remaining {
+                    parts.push(format!("{} req", requests));
+                }
+                if let Some(tokens) = acc.tokens_remaining {
+                    parts.push(format!("{} tok", format_budget_tokens(tokens)));
+                }
+                spans.push(Span::raw("  "));
+                spans.push(Span::styled(
+                    format!("{} left today", parts.join(" / ")),
+                    style,
+                ));
+            }
+
+            Line::from(spans)
         })
         .collect();
 
@@ -225,6 +267,17 @@ fn render_quota_bar(fraction: f64, width: usize) -> String {
     format!("{}{}", "\u{2588}".repeat(filled), "\u{2591}".repeat(empty)) // █ and ░
 }
 
+/// One cell per in-flight request, up to `width` cells
+fn render_load_gauge(in_flight: u64, cap: u64, width: usize) -> String {
+    let cells = (cap as usize).min(width);
+    let filled = (in_flight as usize).min(cells);
+    format!(
+        "{}{}",
+        "\u{25a0}".repeat(filled),
+        "\u{25a1}".repeat(cells - filled)
+    ) // ■ and □
+}
+
 fn quota_color(fraction: f64) -> Style {
     // For quota, LOW remaining = bad (red), HIGH remaining = good (green)
     if fraction <= 0.1 {
@@ -235,3 +288,14 @@ fn quota_color(fraction: f64) -> Style {
         theme::success()
     }
 }
+
+/// Compact token count for the budget column
+fn format_budget_tokens(count: u64) -> String {
+    if count >= 1_000_000 {
+        format!("{:.1}M", count as f64 / 1_000_000.0)
+    } else if count >= 1_000 {
+        format!("{:.1}K", count as f64 / 1_000.0)
+    } else {
+        count.to_string()
+    }
+}
@@ -64,21 +64,30 @@ pub fn render(frame: &mut Frame, area: Rect, app: &mut App) {
         .saturating_sub(visible_height)
         .saturating_sub(app.log_scroll);
 
-    // Build styled text only for the visible window
+    // Build styled text only for the visible window, marking search matches
+    let query = app.log_search_query.to_lowercase();
+    let render_line = |idx: usize| {
+        let entry = app.logs.get(idx)?;
+        let mut line = highlight_log_line(&entry.line, entry.level);
+        if !query.is_empty() {
+            line = highlight_matches(line, &query);
+        }
+        if app.log_search_match == Some(idx) {
+            line = line.patch_style(Style::default().add_modifier(Modifier::REVERSED));
+        }
+        Some(line)
+    };
     let lines: Vec<Line> = if has_filter {
         app.log_filtered_indices
             .iter()
             .skip(scroll_offset)
             .take(visible_height)
-            .filter_map(|&idx| app.logs.get(idx))
-            .map(|entry| highlight_log_line(&entry.line, entry.level))
+            .filter_map(|&idx| render_line(idx))
             .collect()
     } else {
-        app.logs
-            .iter()
-            .skip(scroll_offset)
+        (scroll_offset..app.logs.len())
             .take(visible_height)
-            .map(|entry| highlight_log_line(&entry.line, entry.level))
+            .filter_map(render_line)
             .collect()
     };
 
@@ -317,6 +326,14 @@ fn render_toolbar(frame: &mut Frame, area: Rect, app: &mut App) {
                 Style::default().fg(theme::PRIMARY),
             ));
         }
+        search_spans.push(Span::styled(
+            if app.log_search_highlight {
+                "  highlight (m)  n/N next/prev"
+            } else {
+                "  filter (m)  n/N next/prev"
+            },
+            theme::dim(),
+        ));
 
         frame.render_widget(Paragraph::new(Line::from(search_spans)), search_row);
     } else {
@@ -440,6 +457,48 @@ fn render_account_dropdown(frame: &mut Frame, area: Rect, app: &mut App) {
     frame.render_widget(content, inner);
 }
 
+/// Highlight occurrences of `query` (already lowercased) across the spans of
+/// a rendered log line. Matching ignores ASCII case only, so byte offsets in
+/// the line stay valid.
+fn highlight_matches(line: Line<'static>, query: &str) -> Line<'static> {
+    let text: String = line.spans.iter().map(|s| s.content.as_ref()).collect();
+    let ranges: Vec<(usize, usize)> = text
+        .to_ascii_lowercase()
+        .match_indices(query)
+        .map(|(start, m)| (start, start + m.len()))
+        .collect();
+    if ranges.is_empty() {
+        return line;
+    }
+
+    let match_style = Style::default().fg(Color::Black).bg(theme::WARNING);
+    let mut spans = Vec::new();
+    let mut offset = 0;
+    for span in &line.spans {
+        let end = offset + span.content.len();
+        let mut cut = offset;
+        for &(start, stop) in ranges.iter().filter(|&&(s, e)| s < end && e > offset) {
+            let (start, stop) = (start.max(offset), stop.min(end));
+            if start > cut {
+                spans.push(Span::styled(text[cut..start].to_string(), span.style));
+            }
+            spans.push(Span::styled(
+                text[start..stop].to_string(),
+                span.style.patch(match_style),
+            ));
+            cut = stop;
+        }
+        if cut < end {
+            spans.push(Span::styled(text[cut..end].to_string(), span.style));
+        }
+        offset = end;
+    }
+
+    let mut highlighted = Line::from(spans).style(line.style);
+    highlighted.alignment = line.alignment;
+    highlighted
+}
+
 /// Highlight a log line with syntax coloring
 /// Note: `line` is expected to already be ANSI-stripped (LogEntry::new handles this)
 pub fn highlight_log_line(line: &str, level: LogLevel) -> Line<'static> {
@@ -83,7 +83,8 @@ pub fn render(frame: &mut Frame, area: Rect, app: &mut App) {
         .split(main_chunks[2]);
 
     // Account panel - pass active account's live quota data
-    let account_panel = AccountPanel::new(accounts, app.get_active_quota_data());
+    let account_panel = AccountPanel::new(accounts, app.get_active_quota_data())
+        .refreshing(app.is_quota_refreshing());
     frame.render_widget(account_panel, mid_chunks[0]);
 
     // Model Usage panel - uses model info parsed from logs
@@ -7,9 +7,14 @@ use crate::tui::theme;
 use crate::tui::widgets::QuotaDonut;
 
 /// Render the quota view with donut charts and visual bars for each model
-pub fn render(frame: &mut Frame, area: Rect, quotas: &[ModelQuota]) {
+pub fn render(frame: &mut Frame, area: Rect, quotas: &[ModelQuota], refreshing: bool) {
+    let title = if refreshing {
+        " Model Quotas (refreshing...) "
+    } else {
+        " Model Quotas "
+    };
     let block = Block::default()
-        .title(" Model Quotas ")
+        .title(title)
         .title_style(theme::primary())
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
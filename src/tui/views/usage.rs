@@ -156,7 +156,53 @@ fn render_summary(frame: &mut Frame, area: Rect, app: &App) {
         ));
     }
 
-    let lines = vec![Line::from(spans), Line::from(model_spans)];
+    let mut lines = vec![Line::from(spans), Line::from(model_spans)];
+
+    // Third line: response cache efficiency
+    if let Some(cache) = stats.response_cache.as_ref().filter(|c| c.enabled) {
+        let label = |text: &'static str| {
+            Span::styled(
+                text,
+                Style::default()
+                    .fg(theme::TEXT)
+                    .add_modifier(Modifier::BOLD),
+            )
+        };
+        lines.push(Line::from(vec![
+            label("  Cache: "),
+            Span::styled(
+                format!(
+                    "{} hits ({:.0}%)",
+                    cache.upstream_calls_saved,
+                    cache.hit_rate * 100.0
+                ),
+                Style::default().fg(theme::SUCCESS),
+            ),
+            Span::raw("    "),
+            label("Saved: "),
+            Span::styled(
+                format!("~{} tokens", format_tokens(cache.tokens_saved)),
+                Style::default().fg(theme::SUCCESS),
+            ),
+            Span::raw("    "),
+            label("Served: "),
+            Span::styled(format_bytes(cache.bytes_served), theme::dim()),
+            Span::raw("    "),
+            label("Stored: "),
+            Span::styled(
+                format!(
+                    "{}/{} ({})",
+                    cache.entries,
+                    cache.max_entries,
+                    format_bytes(cache.bytes_stored)
+                ),
+                theme::dim(),
+            ),
+            Span::raw("    "),
+            label("Evictions: "),
+            Span::styled(cache.evictions.to_string(), theme::dim()),
+        ]));
+    }
 
     let text_area = Rect::new(inner.x, inner.y, inner.width, inner.height.min(3));
     frame.render_widget(Paragraph::new(lines), text_area);
@@ -324,6 +370,17 @@ fn format_tokens(count: u64) -> String {
     }
 }
 
+/// Format a byte count with a binary unit suffix
+fn format_bytes(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
 /// Shorter format for axis labels
 fn format_tokens_short(count: u64) -> String {
     if count >= 1_000_000 {
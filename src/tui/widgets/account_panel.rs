@@ -10,6 +10,7 @@ use crate::tui::theme;
 pub struct AccountPanel<'a> {
     pub accounts: &'a [AccountInfo],
     pub quota_data: &'a [ModelQuota],
+    pub refreshing: bool,
 }
 
 impl<'a> AccountPanel<'a> {
@@ -17,9 +18,16 @@ impl<'a> AccountPanel<'a> {
         Self {
             accounts,
             quota_data,
+            refreshing: false,
         }
     }
 
+    /// Show a "refreshing..." indicator while quota is being fetched
+    pub fn refreshing(mut self, refreshing: bool) -> Self {
+        self.refreshing = refreshing;
+        self
+    }
+
     /// Calculate average quota from live quota data
     fn get_live_quota_fraction(&self) -> f64 {
         if self.quota_data.is_empty() {
@@ -34,8 +42,13 @@ impl<'a> AccountPanel<'a> {
 
 impl Widget for AccountPanel<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = if self.refreshing {
+            " Active Account (refreshing...) "
+        } else {
+            " Active Account "
+        };
         let block = Block::default()
-            .title(" Active Account ")
+            .title(title)
             .title_style(theme::primary())
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
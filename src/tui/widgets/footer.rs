@@ -1,15 +1,42 @@
+use std::time::{Duration, Instant};
+
 use ratatui::prelude::*;
 use ratatui::widgets::Paragraph;
 
 use crate::tui::theme;
 
+/// Duration after which a footer status message auto-clears
+const MESSAGE_TTL: Duration = Duration::from_secs(3);
+
 pub struct Footer {
     pub keybinds: Vec<(&'static str, &'static str)>,
+    /// Transient (message, is_warning) shown on the right
+    pub message: Option<(String, bool)>,
+    /// Mode badge shown before the keybinds (e.g. log follow state)
+    pub status: Option<(&'static str, Style)>,
 }
 
 impl Footer {
     pub fn new(keybinds: Vec<(&'static str, &'static str)>) -> Self {
-        Self { keybinds }
+        Self {
+            keybinds,
+            message: None,
+            status: None,
+        }
+    }
+
+    /// Show a mode badge before the keybinds
+    pub fn with_status(mut self, status: (&'static str, Style)) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Show a transient status message (if within TTL)
+    pub fn with_message(mut self, message: Option<&(String, bool, Instant)>) -> Self {
+        self.message = message
+            .filter(|(_, _, created)| created.elapsed() < MESSAGE_TTL)
+            .map(|(msg, is_warning, _)| (msg.clone(), *is_warning));
+        self
     }
 
     /// Get context-sensitive keybinds for a specific tab
@@ -22,6 +49,7 @@ impl Footer {
         match tab {
             Tab::Accounts => {
                 binds.insert(2, ("c", "Clear"));
+                binds.insert(2, ("y", "Copy"));
                 binds.insert(2, ("s", "Sort"));
                 binds.insert(2, ("/", "Search"));
                 binds.insert(2, ("r", "Refresh"));
@@ -30,7 +58,10 @@ impl Footer {
             }
             Tab::Logs => {
                 binds.insert(2, ("c", "Clear"));
+                binds.insert(2, ("y", "Copy"));
                 binds.insert(2, ("a", "Account"));
+                binds.insert(2, ("f", "Follow"));
+                binds.insert(2, ("n/N", "Match"));
                 binds.insert(2, ("/", "Search"));
                 binds.insert(2, ("d/i/w/e", "Levels"));
             }
@@ -59,6 +90,8 @@ impl Footer {
                 ]);
             }
             Tab::Overview => {
+                binds.insert(2, ("t", "Test"));
+                binds.insert(2, ("u", "Quota"));
                 binds.insert(2, ("r", "Restart"));
                 binds.insert(2, ("x", "Stop"));
                 binds.insert(2, ("s", "Start"));
@@ -66,6 +99,9 @@ impl Footer {
             Tab::Usage => {
                 binds.insert(2, ("r", "Reset"));
             }
+            Tab::Quota => {
+                binds.insert(2, ("r", "Refresh"));
+            }
             _ => {}
         }
 
@@ -75,23 +111,39 @@ impl Footer {
 
 impl Widget for Footer {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let spans: Vec<Span> = self
-            .keybinds
-            .iter()
-            .enumerate()
-            .flat_map(|(i, (key, desc))| {
-                let mut s = vec![
-                    Span::styled(*key, theme::primary()),
-                    Span::raw(" "),
-                    Span::styled(*desc, theme::dim()),
-                ];
-                if i < self.keybinds.len() - 1 {
-                    s.push(Span::raw("  "));
-                }
-                s
-            })
-            .collect();
+        let mut spans: Vec<Span> = Vec::new();
+        if let Some((label, style)) = self.status {
+            spans.push(Span::styled(format!("[{}]", label), style));
+            spans.push(Span::raw("  "));
+        }
+        spans.extend(
+            self.keybinds
+                .iter()
+                .enumerate()
+                .flat_map(|(i, (key, desc))| {
+                    let mut s = vec![
+                        Span::styled(*key, theme::primary()),
+                        Span::raw(" "),
+                        Span::styled(*desc, theme::dim()),
+                    ];
+                    if i < self.keybinds.len() - 1 {
+                        s.push(Span::raw("  "));
+                    }
+                    s
+                }),
+        );
 
         Paragraph::new(Line::from(spans)).render(area, buf);
+
+        if let Some((msg, is_warning)) = self.message {
+            let style = if is_warning {
+                theme::warning()
+            } else {
+                theme::success()
+            };
+            Paragraph::new(Span::styled(format!("{} ", msg), style))
+                .alignment(Alignment::Right)
+                .render(area, buf);
+        }
     }
 }
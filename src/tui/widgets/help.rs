@@ -7,7 +7,7 @@ use crate::tui::theme;
 pub fn render(frame: &mut Frame, area: Rect) {
     // Two-column layout: wider but shorter
     let popup_width = 80.min(area.width.saturating_sub(4));
-    let popup_height = 25.min(area.height.saturating_sub(4));
+    let popup_height = 30.min(area.height.saturating_sub(4));
 
     let popup_area = Rect {
         x: area.x + (area.width.saturating_sub(popup_width)) / 2,
@@ -47,20 +47,29 @@ pub fn render(frame: &mut Frame, area: Rect) {
         Line::from("  s             Start daemon"),
         Line::from("  x             Stop daemon"),
         Line::from("  r             Restart daemon"),
+        Line::from("  u             Refresh quota"),
+        Line::from("  t             Test request via server"),
         Line::from(""),
         Line::from(Span::styled("Logs Tab", theme::primary())),
         Line::from("  /             Search logs"),
+        Line::from("  n / N         Next / previous match"),
+        Line::from("  m             Filter or highlight matches"),
+        Line::from("  f             Follow / freeze"),
+        Line::from("  g / G         Jump to top / bottom"),
         Line::from("  d i w e       Toggle log levels"),
         Line::from("  a             Filter by account"),
+        Line::from("  y             Copy selected line"),
         Line::from("  c             Clear filters"),
         Line::from(""),
         Line::from(Span::styled("Accounts Tab", theme::primary())),
         Line::from("  Enter         Set as active"),
         Line::from("  e             Toggle enabled"),
+        Line::from("  J / K         Move down / up in order"),
         Line::from("  /             Search accounts"),
         Line::from("  s             Cycle sort"),
         Line::from("  c             Clear filters"),
         Line::from("  r             Refresh"),
+        Line::from("  y / Y         Copy id / email"),
     ];
 
     // Right column: Config, Mappings, Usage, General
@@ -83,6 +92,9 @@ pub fn render(frame: &mut Frame, area: Rect) {
         Line::from(Span::styled("Usage Tab", theme::primary())),
         Line::from("  r             Reset history"),
         Line::from(""),
+        Line::from(Span::styled("Quota Tab", theme::primary())),
+        Line::from("  r             Refresh quota"),
+        Line::from(""),
         Line::from(Span::styled("General", theme::primary())),
         Line::from("  ?             Toggle help"),
         Line::from("  q / Esc       Quit"),
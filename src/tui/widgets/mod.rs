@@ -8,6 +8,7 @@ pub mod startup_warnings;
 mod stats_panel;
 mod status_panel;
 mod tabs;
+pub mod test_request;
 
 pub use account_panel::AccountPanel;
 pub use donut_chart::QuotaDonut;
@@ -97,11 +97,19 @@ pub fn collect_startup_warnings() -> Vec<StartupWarning> {
 /// Check config file for syntax and validation errors
 /// Returns true if config is valid
 fn check_config(warnings: &mut Vec<StartupWarning>) -> bool {
-    if let Err(e) = crate::config::Config::load() {
-        warnings.push(StartupWarning::error("Config Error", e.to_string()));
-        false
-    } else {
-        true
+    match crate::config::Config::load() {
+        Ok(config) => {
+            for conflict in
+                crate::models::mapping_conflicts(&config.mappings.rules, &config.mappings.aliases)
+            {
+                warnings.push(StartupWarning::warning("Mapping Conflict", conflict));
+            }
+            true
+        }
+        Err(e) => {
+            warnings.push(StartupWarning::error("Config Error", e.to_string()));
+            false
+        }
     }
 }
 
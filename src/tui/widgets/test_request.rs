@@ -0,0 +1,90 @@
+//! Test request modal widget.
+//!
+//! Shows a prompt input and the streamed response of a test request sent
+//! through the local server, which picks the account as for any client and
+//! reports which one it used, flagging it when that isn't the active account.
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Wrap};
+
+use crate::tui::app::{TestRequest, TestRequestStatus};
+use crate::tui::theme;
+
+/// Render the centered test request modal. `account` is the account that
+/// served the response, once the server has reported it.
+pub fn render(frame: &mut Frame, area: Rect, test: &TestRequest, account: Option<&str>) {
+    let popup_width = 90.min(area.width.saturating_sub(4));
+    let popup_height = 24.min(area.height.saturating_sub(4));
+
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+
+    frame.render_widget(Clear, popup_area);
+
+    let title = match account {
+        Some(account) if test.served_by_other_account() => {
+            format!(" Test Request ({}, not the active account) ", account)
+        }
+        Some(account) => format!(" Test Request ({}) ", account),
+        None => " Test Request (via server) ".to_string(),
+    };
+    let block = Block::default()
+        .title(title)
+        .title_style(theme::primary().add_modifier(Modifier::BOLD))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(theme::primary())
+        .style(theme::surface());
+
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(2), // Prompt
+            Constraint::Min(1),    // Response
+            Constraint::Length(1), // Status / hints
+        ])
+        .split(inner);
+
+    // Prompt line with a cursor while editing
+    let editing = test.status == TestRequestStatus::Editing;
+    let mut prompt = vec![
+        Span::styled("Prompt: ", theme::primary()),
+        Span::styled(test.prompt.as_str(), Style::default().fg(theme::TEXT)),
+    ];
+    if editing {
+        prompt.push(Span::styled("█", theme::primary()));
+    }
+    frame.render_widget(Paragraph::new(Line::from(prompt)), chunks[0]);
+
+    // Streamed response (or error)
+    let response = match &test.status {
+        TestRequestStatus::Failed(e) if test.response.is_empty() => {
+            Paragraph::new(Span::styled(e.as_str(), theme::error()))
+        }
+        _ => Paragraph::new(test.response.as_str()).style(Style::default().fg(theme::TEXT)),
+    };
+    frame.render_widget(
+        response.wrap(Wrap { trim: false }).scroll((test.scroll, 0)),
+        chunks[1],
+    );
+
+    let status = match &test.status {
+        TestRequestStatus::Editing => Span::styled("Enter send · Esc close", theme::dim()),
+        TestRequestStatus::Sending => Span::styled("Streaming… · Esc cancel", theme::warning()),
+        TestRequestStatus::Done => Span::styled(
+            "Done · ↑↓ scroll · Enter new prompt · Esc close",
+            theme::success(),
+        ),
+        TestRequestStatus::Failed(_) => {
+            Span::styled("Failed · Enter edit prompt · Esc close", theme::error())
+        }
+    };
+    frame.render_widget(Paragraph::new(Line::from(status)), chunks[2]);
+}
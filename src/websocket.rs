@@ -0,0 +1,188 @@
+//! Server side of the WebSocket streaming endpoint.
+//!
+//! hyper performs the HTTP upgrade; this module validates the upgrade request
+//! and wraps the upgraded connection in a `tokio-tungstenite` stream, which
+//! handles the RFC 6455 framing, masking, fragmentation and control frames.
+
+use futures_util::{SinkExt, StreamExt};
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::error::ProtocolError;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Role, WebSocketConfig};
+use tokio_tungstenite::tungstenite::{Error, Message};
+
+/// Close code for a normal closure.
+pub const CLOSE_NORMAL: CloseCode = CloseCode::Normal;
+/// Close code for an error that prevented the request from completing.
+pub const CLOSE_INTERNAL_ERROR: CloseCode = CloseCode::Error;
+
+/// Return the client's `Sec-WebSocket-Key` if the headers form a valid
+/// WebSocket upgrade request.
+pub fn handshake_key(headers: &hyper::HeaderMap) -> Option<String> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    let upgrade = header("upgrade")?;
+    let connection = header("connection")?;
+    if !upgrade.eq_ignore_ascii_case("websocket")
+        || !connection
+            .split(',')
+            .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+        || header("sec-websocket-version") != Some("13")
+    {
+        return None;
+    }
+
+    header("sec-websocket-key").map(|key| key.trim().to_string())
+}
+
+/// Compute the `Sec-WebSocket-Accept` value for a client key.
+pub fn accept_key(client_key: &str) -> String {
+    derive_accept_key(client_key.as_bytes())
+}
+
+/// Wrap an upgraded connection as the server end of a WebSocket, rejecting
+/// client messages larger than `max_size` bytes.
+pub async fn server_stream<S>(io: S, max_size: usize) -> WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(max_size))
+        .max_frame_size(Some(max_size));
+    WebSocketStream::from_raw_socket(io, Role::Server, Some(config)).await
+}
+
+/// Read one complete text message from the client.
+///
+/// Returns `None` if the client closes or drops the connection before sending
+/// one.
+/// Ping and pong frames are skipped; binary messages are rejected.
+pub async fn read_text_message<S>(ws: &mut WebSocketStream<S>) -> Result<Option<String>, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    while let Some(message) = ws.next().await {
+        match message {
+            Ok(Message::Text(text)) => return Ok(Some(text.to_string())),
+            Ok(Message::Close(_))
+            | Err(Error::ConnectionClosed)
+            | Err(Error::Protocol(ProtocolError::ResetWithoutClosingHandshake)) => {
+                return Ok(None);
+            }
+            Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)) => continue,
+            Ok(Message::Binary(_)) => {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "expected a text message",
+                )));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+/// Send a text message.
+pub async fn send_text<S>(ws: &mut WebSocketStream<S>, text: &str) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    ws.send(Message::text(text)).await
+}
+
+/// Send a close frame with `code` and no reason.
+pub async fn close<S>(ws: &mut WebSocketStream<S>, code: CloseCode) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    ws.close(Some(CloseFrame {
+        code,
+        reason: "".into(),
+    }))
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+
+    /// A connected (client, server) pair over an in-memory pipe.
+    async fn pair(
+        max_size: usize,
+    ) -> (WebSocketStream<DuplexStream>, WebSocketStream<DuplexStream>) {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        (client, server_stream(server, max_size).await)
+    }
+
+    #[test]
+    fn test_accept_key_rfc_example() {
+        // Example from RFC 6455 section 1.3
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_handshake_key_requires_upgrade_headers() {
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("upgrade", "websocket".parse().unwrap());
+        headers.insert("connection", "keep-alive, Upgrade".parse().unwrap());
+        headers.insert("sec-websocket-version", "13".parse().unwrap());
+        headers.insert("sec-websocket-key", "abc==".parse().unwrap());
+        assert_eq!(handshake_key(&headers).as_deref(), Some("abc=="));
+
+        headers.insert("sec-websocket-version", "8".parse().unwrap());
+        assert!(handshake_key(&headers).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_message_round_trip() {
+        let (mut client, mut server) = pair(1024).await;
+
+        client.send(Message::Ping("hi".into())).await.unwrap();
+        client.send(Message::text("{\"model\":1}")).await.unwrap();
+        assert_eq!(
+            read_text_message(&mut server).await.unwrap().as_deref(),
+            Some("{\"model\":1}")
+        );
+
+        send_text(&mut server, "event").await.unwrap();
+        close(&mut server, CLOSE_NORMAL).await.unwrap();
+
+        let mut received = Vec::new();
+        while let Some(Ok(message)) = client.next().await {
+            let closed = message.is_close();
+            received.push(message);
+            if closed {
+                break;
+            }
+        }
+        assert!(received.contains(&Message::text("event")));
+        assert!(matches!(
+            received.last(),
+            Some(Message::Close(Some(frame))) if frame.code == CLOSE_NORMAL
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_message_limits() {
+        let (mut client, mut server) = pair(4).await;
+        client.send(Message::text("too long")).await.unwrap();
+        assert!(read_text_message(&mut server).await.is_err());
+
+        let (mut client, mut server) = pair(4).await;
+        client.close(None).await.unwrap();
+        assert!(read_text_message(&mut server).await.unwrap().is_none());
+
+        // Hanging up without a close frame is also treated as a close
+        let (client, mut server) = pair(4).await;
+        drop(client);
+        assert!(read_text_message(&mut server).await.unwrap().is_none());
+    }
+}